@@ -1,24 +1,196 @@
-use std::{fmt, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::tag::TagId;
+use crate::{filter::Filter, tag::TagId};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Command {
-    Play { filter: Option<String> },
-    PlayPause { filter: Option<String> },
-    Shuffle { filter: Option<String> },
+    Play {
+        filter: Option<Filter>,
+    },
+    PlayPause {
+        filter: Option<Filter>,
+    },
+    Shuffle {
+        filter: Option<Filter>,
+    },
+    Enqueue {
+        filter: Option<Filter>,
+    },
+    PlayFile {
+        path: PathBuf,
+    },
+    /// Replaces the queue with the tracks of an M3U or PLS playlist, in order.
+    PlayPlaylist {
+        path: PathBuf,
+    },
     Stop,
+    /// Pauses playback; does nothing when nothing plays or it is already paused.
+    Pause,
+    /// Resumes a paused track, or starts the current queue track when stopped.
+    Resume,
     Next,
     Prev,
+    /// Jumps to the first track of the next run of queue tracks from another directory.
+    NextDir,
+    /// Jumps to the first track of the previous directory in the queue.
+    PrevDir,
     TrackDone,
     VolumeUp,
     VolumeDown,
+    SetVolume {
+        level: f32,
+    },
+    Mute,
     ClearQueue,
+    Rescan,
+    ShuffleQueue,
+    /// Several commands run in order, written `VOLUME 0.3; SHUFFLE kids/*`.
+    Sequence(Vec<Command>),
+    JumpTo {
+        index: usize,
+    },
+    RemoveFromQueue {
+        index: usize,
+    },
+    MoveInQueue {
+        from: usize,
+        to: usize,
+    },
     Shutdown,
-    AssignTag { id: TagId, command: Option<String> },
-    Tag { id: TagId },
+    ResetLimit,
+    Seek {
+        target: SeekTarget,
+    },
+    Repeat {
+        mode: RepeatMode,
+    },
+    Interject {
+        path: PathBuf,
+    },
+    AssignTag {
+        id: TagId,
+        command: Option<String>,
+    },
+    AdjustTrackVolume {
+        path: PathBuf,
+        delta: i32,
+    },
+    Tag {
+        id: TagId,
+    },
+    /// Playback rate, e.g. `SPEED 0.8` to slow down; clamped by the player.
+    Speed {
+        factor: f32,
+    },
+    /// `SLEEP 30` stops playback after 30 minutes; `SLEEP OFF` (a zero duration) cancels.
+    SleepTimer {
+        duration: Duration,
+    },
+    /// Logs every tag mapping.
+    ListTags,
+    /// Removes every tag mapping and saves the empty set.
+    ClearTags,
+}
+
+/// What happens when a track finishes: stop at the end of the queue, repeat the
+/// current track, or wrap around to the start of the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatMode {
+    Off,
+    One,
+    #[default]
+    All,
+}
+
+impl FromStr for RepeatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "OFF" => Ok(RepeatMode::Off),
+            "ONE" => Ok(RepeatMode::One),
+            "ALL" => Ok(RepeatMode::All),
+            _ => Err(format!("Invalid repeat mode '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for RepeatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RepeatMode::Off => "OFF",
+            RepeatMode::One => "ONE",
+            RepeatMode::All => "ALL",
+        })
+    }
+}
+
+impl Serialize for RepeatMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RepeatMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        RepeatMode::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Where a `SEEK` command jumps to: `SEEK 90` is absolute, `SEEK +30` / `SEEK -10` relative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekTarget {
+    Absolute(Duration),
+    Relative(i64),
+}
+
+impl SeekTarget {
+    pub fn resolve(self, current: Duration) -> Duration {
+        match self {
+            SeekTarget::Absolute(position) => position,
+            SeekTarget::Relative(offset) if offset >= 0 => {
+                current + Duration::from_secs(offset.unsigned_abs())
+            }
+            SeekTarget::Relative(offset) => {
+                current.saturating_sub(Duration::from_secs(offset.unsigned_abs()))
+            }
+        }
+    }
+}
+
+impl FromStr for SeekTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with(['+', '-']) {
+            s.parse::<i64>()
+                .map(SeekTarget::Relative)
+                .map_err(|err| format!("Invalid seek offset '{s}': {err}"))
+        } else {
+            s.parse::<u64>()
+                .map(|secs| SeekTarget::Absolute(Duration::from_secs(secs)))
+                .map_err(|err| format!("Invalid seek position '{s}': {err}"))
+        }
+    }
+}
+
+impl fmt::Display for SeekTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeekTarget::Absolute(position) => write!(f, "{}", position.as_secs()),
+            SeekTarget::Relative(offset) => write!(f, "{offset:+}"),
+        }
+    }
 }
 
 impl FromStr for Command {
@@ -32,21 +204,56 @@ impl FromStr for Command {
 impl fmt::Display for Command {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Command::Play { filter } => write_name_with_filter(f, "PLAY", filter.as_deref()),
+            Command::Play { filter } => write_name_with_filter(f, "PLAY", filter.as_ref()),
             Command::PlayPause { filter } => {
-                write_name_with_filter(f, "PLAYPAUSE", filter.as_deref())
+                write_name_with_filter(f, "PLAYPAUSE", filter.as_ref())
             }
-            Command::Shuffle { filter } => write_name_with_filter(f, "SHUFFLE", filter.as_deref()),
+            Command::Shuffle { filter } => write_name_with_filter(f, "SHUFFLE", filter.as_ref()),
+            Command::Enqueue { filter } => write_name_with_filter(f, "ENQUEUE", filter.as_ref()),
+            Command::PlayFile { path } => write!(f, "PLAYFILE {}", path.display()),
+            Command::PlayPlaylist { path } => write!(f, "PLAYLIST {}", path.display()),
             Command::Stop => f.write_str("STOP"),
+            Command::Pause => f.write_str("PAUSE"),
+            Command::Resume => f.write_str("RESUME"),
             Command::Next => f.write_str("NEXT"),
             Command::Prev => f.write_str("PREV"),
+            Command::NextDir => f.write_str("NEXTDIR"),
+            Command::PrevDir => f.write_str("PREVDIR"),
             Command::TrackDone => f.write_str("TRACKDONE"),
             Command::VolumeUp => f.write_str("VOLUMEUP"),
             Command::VolumeDown => f.write_str("VOLUMEDOWN"),
+            Command::SetVolume { level } => write!(f, "VOLUME {level}"),
+            Command::Mute => f.write_str("MUTE"),
             Command::ClearQueue => f.write_str("CLEARQUEUE"),
+            Command::Rescan => f.write_str("RESCAN"),
+            Command::ShuffleQueue => f.write_str("SHUFFLE_QUEUE"),
+            Command::Sequence(commands) => {
+                for (idx, command) in commands.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str("; ")?;
+                    }
+                    write!(f, "{command}")?;
+                }
+                Ok(())
+            }
+            Command::JumpTo { index } => write!(f, "JUMP {index}"),
+            Command::RemoveFromQueue { index } => write!(f, "REMOVE_FROM_QUEUE {index}"),
+            Command::MoveInQueue { from, to } => write!(f, "MOVE {from} {to}"),
             Command::Shutdown => f.write_str("SHUTDOWN"),
+            Command::ResetLimit => f.write_str("RESET_LIMIT"),
+            Command::Seek { target } => write!(f, "SEEK {target}"),
+            Command::Repeat { mode } => write!(f, "REPEAT {mode}"),
+            Command::Interject { path } => write!(f, "INTERJECT {}", path.display()),
             Command::AssignTag { id, .. } => write!(f, "ASSIGN_TAG {id}"),
+            Command::AdjustTrackVolume { path, delta } => {
+                write!(f, "TRACK_VOLUME {} {delta}", path.display())
+            }
             Command::Tag { id } => write!(f, "TAG {id}"),
+            Command::Speed { factor } => write!(f, "SPEED {factor}"),
+            Command::SleepTimer { duration } if duration.is_zero() => f.write_str("SLEEP OFF"),
+            Command::SleepTimer { duration } => write!(f, "SLEEP {}", duration.as_secs() / 60),
+            Command::ListTags => f.write_str("LIST_TAGS"),
+            Command::ClearTags => f.write_str("CLEAR_TAGS"),
         }
     }
 }
@@ -62,25 +269,123 @@ impl<'de> Deserialize<'de> for Command {
 }
 
 pub fn parse_command(input: &str) -> Option<Command> {
+    if input.contains(';') {
+        return parse_sequence(input);
+    }
+
     let mut parts = input.trim().splitn(2, char::is_whitespace);
     let command = parts.next()?.to_ascii_uppercase();
-    let filter = parts
-        .next()
-        .map(str::trim)
-        .filter(|s| !s.is_empty())
-        .map(str::to_owned);
+    let argument = parts.next().map(str::trim).filter(|s| !s.is_empty());
+    let filter = argument.map(Filter::parse);
 
     match command.as_str() {
         "PLAY" => Some(Command::Play { filter }),
         "PLAYPAUSE" => Some(Command::PlayPause { filter }),
         "SHUFFLE" => Some(Command::Shuffle { filter }),
+        "ENQUEUE" => Some(Command::Enqueue { filter }),
+        "PLAYFILE" => argument.map(|path| Command::PlayFile {
+            path: PathBuf::from(path),
+        }),
+        "PLAYLIST" => argument.map(|path| Command::PlayPlaylist {
+            path: PathBuf::from(path),
+        }),
         "STOP" => Some(Command::Stop),
+        "PAUSE" => Some(Command::Pause),
+        "RESUME" => Some(Command::Resume),
         "NEXT" => Some(Command::Next),
         "PREV" | "PREVIOUS" => Some(Command::Prev),
+        "NEXTDIR" => Some(Command::NextDir),
+        "PREVDIR" => Some(Command::PrevDir),
         "CLEARQUEUE" => Some(Command::ClearQueue),
+        "RESCAN" => Some(Command::Rescan),
+        "SHUFFLE_QUEUE" | "TOGGLE_SHUFFLE" => Some(Command::ShuffleQueue),
+        "JUMP" => argument
+            .and_then(|index| index.parse().ok())
+            .map(|index| Command::JumpTo { index }),
+        "REMOVE_FROM_QUEUE" => argument
+            .and_then(|index| index.parse().ok())
+            .map(|index| Command::RemoveFromQueue { index }),
+        "MOVE" => argument.and_then(parse_move),
         "SHUTDOWN" => Some(Command::Shutdown),
+        "RESET_LIMIT" => Some(Command::ResetLimit),
+        "LIST_TAGS" => Some(Command::ListTags),
+        "CLEAR_TAGS" => Some(Command::ClearTags),
+        "REPEAT" => argument
+            .and_then(|mode| mode.parse().ok())
+            .map(|mode| Command::Repeat { mode }),
+        "SEEK" => argument
+            .and_then(|target| target.parse().ok())
+            .map(|target| Command::Seek { target }),
+        "INTERJECT" => argument.map(|path| Command::Interject {
+            path: PathBuf::from(path),
+        }),
         "VOLUMEUP" => Some(Command::VolumeUp),
         "VOLUMEDOWN" => Some(Command::VolumeDown),
+        "MUTE" => Some(Command::Mute),
+        "VOLUME" => argument
+            .and_then(|level| parse_volume(level).ok())
+            .map(|level| Command::SetVolume { level }),
+        "SPEED" => argument
+            .and_then(|factor| factor.parse::<f32>().ok())
+            .filter(|factor| factor.is_finite() && *factor > 0.0)
+            .map(|factor| Command::Speed { factor }),
+        "SLEEP" => argument
+            .and_then(|minutes| parse_sleep(minutes).ok())
+            .map(|duration| Command::SleepTimer { duration }),
+        _ => None,
+    }
+}
+
+/// Parses `;`-separated commands; every part must be valid and a single part is returned as-is.
+fn parse_sequence(input: &str) -> Option<Command> {
+    let mut commands = input
+        .split(';')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(parse_command)
+        .collect::<Option<Vec<_>>>()?;
+
+    match commands.len() {
+        0 => None,
+        1 => commands.pop(),
+        _ => Some(Command::Sequence(commands)),
+    }
+}
+
+/// Parses a volume given as a fraction (`0.5`) or a percentage (`50%`).
+pub fn parse_volume(input: &str) -> Result<f32, String> {
+    let input = input.trim();
+    let level = match input.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().map(|percent| percent / 100.0),
+        None => input.parse::<f32>(),
+    }
+    .map_err(|err| format!("Invalid volume '{input}': {err}"))?;
+
+    if (0.0..=1.0).contains(&level) {
+        Ok(level)
+    } else {
+        Err(format!(
+            "Volume '{input}' must be between 0 and 1 (or 0% and 100%)"
+        ))
+    }
+}
+
+/// Parses a sleep timer given in whole minutes, or `OFF` (a zero duration) to cancel it.
+pub fn parse_sleep(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("off") {
+        return Ok(Duration::ZERO);
+    }
+    input
+        .parse::<u64>()
+        .map(|minutes| Duration::from_secs(minutes.saturating_mul(60)))
+        .map_err(|err| format!("Invalid sleep timer '{input}': {err}"))
+}
+
+fn parse_move(argument: &str) -> Option<Command> {
+    let mut indices = argument.split_whitespace().map(str::parse);
+    match (indices.next(), indices.next(), indices.next()) {
+        (Some(Ok(from)), Some(Ok(to)), None) => Some(Command::MoveInQueue { from, to }),
         _ => None,
     }
 }
@@ -89,25 +394,93 @@ impl Command {
     pub fn has_filter(&self) -> bool {
         matches!(
             self,
-            Command::Play { .. } | Command::PlayPause { .. } | Command::Shuffle { .. }
+            Command::Play { .. }
+                | Command::PlayPause { .. }
+                | Command::Shuffle { .. }
+                | Command::Enqueue { .. }
+        )
+    }
+
+    /// The filters this command selects tracks with, including those inside a sequence.
+    pub fn filters(&self) -> Vec<&Filter> {
+        match self {
+            Command::Play { filter }
+            | Command::PlayPause { filter }
+            | Command::Shuffle { filter }
+            | Command::Enqueue { filter } => filter.iter().collect(),
+            Command::Sequence(commands) => commands.iter().flat_map(Command::filters).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Commands that start or resume playback and are blocked once the daily limit is reached.
+    pub fn starts_playback(&self) -> bool {
+        matches!(
+            self,
+            Command::Play { .. }
+                | Command::PlayPause { .. }
+                | Command::Shuffle { .. }
+                | Command::PlayFile { .. }
+                | Command::PlayPlaylist { .. }
+                | Command::Resume
+                | Command::JumpTo { .. }
+                | Command::Next
+                | Command::Prev
+                | Command::NextDir
+                | Command::PrevDir
+                | Command::TrackDone
+                | Command::Interject { .. }
         )
     }
 
+    /// Whether running this command powers the box off, also from inside a sequence.
+    pub fn shuts_down(&self) -> bool {
+        match self {
+            Command::Shutdown => true,
+            Command::Sequence(commands) => commands.iter().any(Command::shuts_down),
+            _ => false,
+        }
+    }
+
     pub fn name(&self) -> &'static str {
         match self {
             Command::Play { .. } => "PLAY",
             Command::PlayPause { .. } => "PLAYPAUSE",
             Command::Shuffle { .. } => "SHUFFLE",
+            Command::Enqueue { .. } => "ENQUEUE",
+            Command::PlayFile { .. } => "PLAYFILE",
+            Command::PlayPlaylist { .. } => "PLAYLIST",
             Command::Stop => "STOP",
+            Command::Pause => "PAUSE",
+            Command::Resume => "RESUME",
             Command::Next => "NEXT",
             Command::Prev => "PREV",
+            Command::NextDir => "NEXTDIR",
+            Command::PrevDir => "PREVDIR",
             Command::TrackDone => "TRACKDONE",
             Command::VolumeUp => "VOLUMEUP",
             Command::VolumeDown => "VOLUMEDOWN",
+            Command::SetVolume { .. } => "VOLUME",
+            Command::Mute => "MUTE",
             Command::ClearQueue => "CLEARQUEUE",
+            Command::Rescan => "RESCAN",
+            Command::ShuffleQueue => "SHUFFLE_QUEUE",
+            Command::Sequence(_) => "SEQUENCE",
+            Command::JumpTo { .. } => "JUMP",
+            Command::RemoveFromQueue { .. } => "REMOVE_FROM_QUEUE",
+            Command::MoveInQueue { .. } => "MOVE",
             Command::Shutdown => "SHUTDOWN",
+            Command::ResetLimit => "RESET_LIMIT",
+            Command::Seek { .. } => "SEEK",
+            Command::Repeat { .. } => "REPEAT",
+            Command::Interject { .. } => "INTERJECT",
             Command::AssignTag { .. } => "ASSIGN_TAG",
+            Command::AdjustTrackVolume { .. } => "TRACK_VOLUME",
             Command::Tag { .. } => "TAG",
+            Command::Speed { .. } => "SPEED",
+            Command::SleepTimer { .. } => "SLEEP",
+            Command::ListTags => "LIST_TAGS",
+            Command::ClearTags => "CLEAR_TAGS",
         }
     }
 }
@@ -115,7 +488,7 @@ impl Command {
 fn write_name_with_filter(
     f: &mut fmt::Formatter<'_>,
     name: &str,
-    filter: Option<&str>,
+    filter: Option<&Filter>,
 ) -> fmt::Result {
     if let Some(filter) = filter {
         write!(f, "{name} {filter}")
@@ -132,8 +505,278 @@ mod tests {
     fn parses_basic_commands() {
         assert_eq!(parse_command("PLAY"), Some(Command::Play { filter: None }));
         assert_eq!(parse_command("Stop"), Some(Command::Stop));
+        assert_eq!(parse_command("pause"), Some(Command::Pause));
+        assert_eq!(parse_command("RESUME"), Some(Command::Resume));
         assert_eq!(parse_command("previous"), Some(Command::Prev));
+        assert_eq!(parse_command("nextdir"), Some(Command::NextDir));
+        assert_eq!(parse_command("PREVDIR"), Some(Command::PrevDir));
         assert_eq!(parse_command("clearqueue"), Some(Command::ClearQueue));
+        assert_eq!(parse_command("reset_limit"), Some(Command::ResetLimit));
+        assert_eq!(parse_command("mute"), Some(Command::Mute));
+        assert_eq!(parse_command("rescan"), Some(Command::Rescan));
+        assert_eq!(parse_command("list_tags"), Some(Command::ListTags));
+        assert_eq!(parse_command("CLEAR_TAGS"), Some(Command::ClearTags));
+    }
+
+    #[test]
+    fn parses_speed() {
+        assert_eq!(
+            parse_command("speed 0.8"),
+            Some(Command::Speed { factor: 0.8 })
+        );
+        assert_eq!(
+            parse_command("SPEED 1.25").map(|command| command.to_string()),
+            Some("SPEED 1.25".to_string())
+        );
+        assert_eq!(parse_command("SPEED"), None);
+        assert_eq!(parse_command("SPEED fast"), None);
+        assert_eq!(parse_command("SPEED 0"), None);
+        assert_eq!(parse_command("SPEED -1"), None);
+        assert_eq!(parse_command("SPEED NaN"), None);
+    }
+
+    #[test]
+    fn parses_sleep_timer() {
+        assert_eq!(
+            parse_command("sleep 30"),
+            Some(Command::SleepTimer {
+                duration: Duration::from_mins(30)
+            })
+        );
+        assert_eq!(
+            parse_command("SLEEP off"),
+            Some(Command::SleepTimer {
+                duration: Duration::ZERO
+            })
+        );
+        assert_eq!(
+            parse_command("SLEEP 30").map(|command| command.to_string()),
+            Some("SLEEP 30".to_string())
+        );
+        assert_eq!(
+            parse_command("SLEEP OFF").map(|command| command.to_string()),
+            Some("SLEEP OFF".to_string())
+        );
+        assert_eq!(parse_command("SLEEP"), None);
+        assert_eq!(parse_command("SLEEP soon"), None);
+    }
+
+    #[test]
+    fn parses_repeat_modes() {
+        assert_eq!(
+            parse_command("repeat one"),
+            Some(Command::Repeat {
+                mode: RepeatMode::One
+            })
+        );
+        assert_eq!(
+            parse_command("REPEAT off").map(|command| command.to_string()),
+            Some("REPEAT OFF".to_string())
+        );
+        assert_eq!(parse_command("REPEAT"), None);
+        assert_eq!(parse_command("REPEAT twice"), None);
+    }
+
+    #[test]
+    fn parses_enqueue_with_filter() {
+        let command = parse_command("enqueue **/kids/**");
+        assert_eq!(
+            command,
+            Some(Command::Enqueue {
+                filter: Some(Filter::parse("**/kids/**"))
+            })
+        );
+        assert_eq!(command.unwrap().to_string(), "ENQUEUE **/kids/**");
+    }
+
+    #[test]
+    fn parses_playlist_path() {
+        let command = parse_command("PLAYLIST lists/Car Trip.m3u");
+        assert_eq!(
+            command,
+            Some(Command::PlayPlaylist {
+                path: PathBuf::from("lists/Car Trip.m3u")
+            })
+        );
+        assert_eq!(command.unwrap().to_string(), "PLAYLIST lists/Car Trip.m3u");
+        assert_eq!(parse_command("PLAYLIST"), None);
+    }
+
+    #[test]
+    fn parses_play_file_path() {
+        let command = parse_command("playfile /music/My Band/song.mp3");
+        assert_eq!(
+            command,
+            Some(Command::PlayFile {
+                path: PathBuf::from("/music/My Band/song.mp3")
+            })
+        );
+        assert_eq!(
+            command.unwrap().to_string(),
+            "PLAYFILE /music/My Band/song.mp3"
+        );
+        assert_eq!(parse_command("PLAYFILE"), None);
+    }
+
+    #[test]
+    fn parses_command_sequences() {
+        let command = parse_command("volume 0.3;  shuffle kids/* ;").expect("sequence");
+        assert_eq!(
+            command,
+            Command::Sequence(vec![
+                Command::SetVolume { level: 0.3 },
+                Command::Shuffle {
+                    filter: Some(Filter::parse("kids/*"))
+                },
+            ])
+        );
+        assert_eq!(command.to_string(), "VOLUME 0.3; SHUFFLE kids/*");
+        assert_eq!(parse_command(&command.to_string()), Some(command));
+
+        assert_eq!(parse_command("STOP;"), Some(Command::Stop));
+        assert_eq!(parse_command("STOP; DANCE"), None);
+        assert_eq!(parse_command(" ; "), None);
+    }
+
+    #[test]
+    fn interjections_are_blocked_by_the_daily_limit() {
+        assert!(
+            parse_command("INTERJECT clips/dinner.mp3")
+                .unwrap()
+                .starts_playback()
+        );
+        assert!(!parse_command("VOLUME 0.2").unwrap().starts_playback());
+    }
+
+    #[test]
+    fn sequences_containing_shutdown_shut_down() {
+        for command in ["SHUTDOWN", "VOLUME 0.2; SHUTDOWN"] {
+            assert!(parse_command(command).unwrap().shuts_down(), "{command}");
+        }
+        assert!(!parse_command("VOLUME 0.2; STOP").unwrap().shuts_down());
+    }
+
+    #[test]
+    fn parses_shuffle_queue() {
+        assert_eq!(parse_command("shuffle_queue"), Some(Command::ShuffleQueue));
+        assert_eq!(parse_command("TOGGLE_SHUFFLE"), Some(Command::ShuffleQueue));
+        assert_eq!(Command::ShuffleQueue.to_string(), "SHUFFLE_QUEUE");
+    }
+
+    #[test]
+    fn parses_jump() {
+        assert_eq!(parse_command("jump 2"), Some(Command::JumpTo { index: 2 }));
+        assert_eq!(Command::JumpTo { index: 2 }.to_string(), "JUMP 2");
+        assert_eq!(parse_command("JUMP"), None);
+        assert_eq!(parse_command("JUMP next"), None);
+    }
+
+    #[test]
+    fn parses_remove_from_queue() {
+        assert_eq!(
+            parse_command("remove_from_queue 3"),
+            Some(Command::RemoveFromQueue { index: 3 })
+        );
+        assert_eq!(
+            Command::RemoveFromQueue { index: 3 }.to_string(),
+            "REMOVE_FROM_QUEUE 3"
+        );
+        assert_eq!(parse_command("REMOVE_FROM_QUEUE"), None);
+        assert_eq!(parse_command("REMOVE_FROM_QUEUE -1"), None);
+    }
+
+    #[test]
+    fn parses_move_in_queue() {
+        assert_eq!(
+            parse_command("move 4 0"),
+            Some(Command::MoveInQueue { from: 4, to: 0 })
+        );
+        assert_eq!(
+            Command::MoveInQueue { from: 4, to: 0 }.to_string(),
+            "MOVE 4 0"
+        );
+        assert_eq!(parse_command("MOVE 4"), None);
+        assert_eq!(parse_command("MOVE 4 0 1"), None);
+        assert_eq!(parse_command("MOVE up 1"), None);
+    }
+
+    #[test]
+    fn parses_absolute_volume() {
+        assert_eq!(
+            parse_command("volume 0.5"),
+            Some(Command::SetVolume { level: 0.5 })
+        );
+        assert_eq!(
+            parse_command("VOLUME 25%"),
+            Some(Command::SetVolume { level: 0.25 })
+        );
+        assert_eq!(
+            parse_command("VOLUME 100 %"),
+            Some(Command::SetVolume { level: 1.0 })
+        );
+        assert_eq!(Command::SetVolume { level: 0.5 }.to_string(), "VOLUME 0.5");
+    }
+
+    #[test]
+    fn rejects_invalid_volume() {
+        assert_eq!(parse_command("VOLUME"), None);
+        assert_eq!(parse_command("VOLUME loud"), None);
+        assert_eq!(parse_command("VOLUME 1.5"), None);
+        assert_eq!(parse_command("VOLUME 150%"), None);
+        assert_eq!(parse_command("VOLUME -0.1"), None);
+        assert_eq!(parse_command("VOLUME NaN"), None);
+    }
+
+    #[test]
+    fn parses_seek_targets() {
+        assert_eq!(
+            parse_command("SEEK 90"),
+            Some(Command::Seek {
+                target: SeekTarget::Absolute(Duration::from_secs(90))
+            })
+        );
+        assert_eq!(
+            parse_command("seek +30"),
+            Some(Command::Seek {
+                target: SeekTarget::Relative(30)
+            })
+        );
+        assert_eq!(
+            parse_command("SEEK -10").map(|command| command.to_string()),
+            Some("SEEK -10".to_string())
+        );
+        assert_eq!(parse_command("SEEK"), None);
+        assert_eq!(parse_command("SEEK soon"), None);
+    }
+
+    #[test]
+    fn seek_target_resolves_against_current_position() {
+        let current = Duration::from_secs(20);
+        assert_eq!(
+            SeekTarget::Absolute(Duration::from_secs(5)).resolve(current),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            SeekTarget::Relative(30).resolve(current),
+            Duration::from_secs(50)
+        );
+        assert_eq!(SeekTarget::Relative(-30).resolve(current), Duration::ZERO);
+    }
+
+    #[test]
+    fn parses_interject_path() {
+        let command = parse_command("interject /sounds/dinner time.mp3");
+        assert_eq!(
+            command,
+            Some(Command::Interject {
+                path: PathBuf::from("/sounds/dinner time.mp3")
+            })
+        );
+        assert_eq!(
+            command.unwrap().to_string(),
+            "INTERJECT /sounds/dinner time.mp3"
+        );
+        assert_eq!(parse_command("INTERJECT"), None);
     }
 
     #[test]
@@ -141,14 +784,14 @@ mod tests {
         assert_eq!(
             parse_command("play chill/*"),
             Some(Command::Play {
-                filter: Some("chill/*".to_string())
+                filter: Some(Filter::parse("chill/*"))
             })
         );
 
         assert_eq!(
             parse_command(" shuffle   synthwave "),
             Some(Command::Shuffle {
-                filter: Some("synthwave".to_string())
+                filter: Some(Filter::parse("synthwave"))
             })
         );
     }
@@ -165,8 +808,23 @@ mod tests {
         assert_eq!(
             cmd,
             Command::Play {
-                filter: Some("mix/*".to_string())
+                filter: Some(Filter::parse("mix/*"))
             }
         );
     }
+
+    #[test]
+    fn parses_include_and_exclude_filter() {
+        let cmd = parse_command("PLAY rock/* !*/live/*").expect("should parse");
+        let Command::Play {
+            filter: Some(filter),
+        } = &cmd
+        else {
+            panic!("expected filtered play, got {cmd:?}");
+        };
+
+        assert_eq!(filter.includes().collect::<Vec<_>>(), vec!["rock/*"]);
+        assert_eq!(filter.excludes().collect::<Vec<_>>(), vec!["*/live/*"]);
+        assert_eq!(cmd.to_string(), "PLAY rock/* !*/live/*");
+    }
 }
@@ -2,12 +2,21 @@ use std::{
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use crate::{commands::Command, tag::TagId};
+use crate::{
+    commands::Command,
+    crabbox::ShuffleStrategy,
+    library::{is_music_extension, source::is_http_url},
+    logging,
+    mixer::{VolumeBackend, default_alsa_card, default_alsa_control},
+    player::VolumeCurve,
+    tag::TagId,
+};
 
 use serde::Deserialize;
-use tracing::warn;
+use tracing::{Level, warn};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -16,12 +25,47 @@ pub struct Config {
     pub server: ServerConfig,
     #[serde(default = "default_volume")]
     pub default_volume: f32,
+    /// How long the scanned track list is reused before the music directories are walked again.
+    #[serde(default = "default_library_cache_ttl_secs")]
+    pub library_cache_ttl_secs: u64,
+    /// When set, tracks up to this size are read into memory before decoding.
+    #[serde(default)]
+    pub buffered_read_max_bytes: Option<u64>,
+    /// Stop playback after this many minutes per day until midnight or `RESET_LIMIT`.
+    #[serde(default)]
+    pub daily_limit_minutes: Option<u64>,
+    /// Stop playback that has been paused or idle this long, releasing the audio device.
+    #[serde(default)]
+    pub idle_stop_secs: Option<u64>,
+    /// Fixed shuffle seed, used to replay a queue ordering from a bug report.
+    #[serde(default)]
+    pub shuffle_seed: Option<u64>,
+    /// `random` (default), `avoid_recent` to push recently played tracks to the end, or
+    /// `interleave` to keep tracks by the same artist apart.
+    #[serde(default)]
+    pub shuffle_strategy: ShuffleStrategy,
+    /// Keep the last shuffle seed and ordering available at `/api/debug/shuffle`.
     #[serde(default)]
-    pub tags: HashMap<TagId, Command>,
+    pub debug_shuffle: bool,
+    #[serde(default)]
+    pub tags: HashMap<TagId, TagMapping>,
+    /// Bookmarked tracks, starred on the library page.
+    #[serde(default)]
+    pub favorites: Vec<PathBuf>,
+    #[serde(default)]
+    pub tags_file: Option<PathBuf>,
+    /// Scans older than this are dropped from the recent tag list on the index page.
+    #[serde(default = "default_tag_history_max_age_secs")]
+    pub tag_history_max_age_secs: u64,
     #[serde(default)]
     pub state_file: Option<PathBuf>,
+    #[serde(default)]
+    pub track_volume_file: Option<PathBuf>,
     #[serde(default, rename = "config_backup_dir")]
     pub backup_dir: Option<PathBuf>,
+    /// How many backups of each file to keep in `config_backup_dir`; older ones are deleted.
+    #[serde(default = "default_config_backup_keep", rename = "config_backup_keep")]
+    pub backup_keep: usize,
     #[serde(skip)]
     pub path: PathBuf,
     #[cfg(feature = "rpi")]
@@ -30,19 +74,152 @@ pub struct Config {
     pub rfid: Option<RfidConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+/// What a tag does when scanned: a command, optionally played at a fixed volume.
+///
+/// Written either as a bare command string, `id = "PLAY x/*"`, or as a table,
+/// `id = { command = "PLAY x/*", volume = 0.4 }`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawTagMapping")]
+pub struct TagMapping {
+    pub command: Command,
+    pub volume: Option<f32>,
+}
+
+impl TagMapping {
+    pub fn new(command: Command) -> Self {
+        Self {
+            command,
+            volume: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTagMapping {
+    Command(Command),
+    Table {
+        command: Command,
+        #[serde(default)]
+        volume: Option<f32>,
+    },
+}
+
+impl TryFrom<RawTagMapping> for TagMapping {
+    type Error = String;
+
+    fn try_from(raw: RawTagMapping) -> Result<Self, Self::Error> {
+        match raw {
+            RawTagMapping::Command(command) => Ok(Self::new(command)),
+            RawTagMapping::Table { command, volume } => {
+                if let Some(volume) = volume
+                    && !(0.0..=1.0).contains(&volume)
+                {
+                    return Err(format!("tag volume {volume} must be between 0 and 1"));
+                }
+                Ok(Self { command, volume })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct MusicDirectory {
     pub dir: PathBuf,
+    /// Scan subdirectories too; set to `false` to only pick up files directly inside `dir`.
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+    /// File extensions to treat as music, replacing the built-in list.
+    #[serde(default)]
+    pub extensions: Option<Vec<String>>,
+}
+
+impl MusicDirectory {
+    #[cfg(test)]
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            recursive: true,
+            extensions: None,
+        }
+    }
+
+    /// The `http(s)://` index URL when the directory is remote rather than a local path.
+    pub fn url(&self) -> Option<&str> {
+        self.dir.to_str().filter(|dir| is_http_url(dir))
+    }
+
+    /// Whether a file with extension `ext` belongs in this directory's library.
+    pub fn accepts_extension(&self, ext: &str) -> bool {
+        match &self.extensions {
+            Some(extensions) => extensions
+                .iter()
+                .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext)),
+            None => is_music_extension(ext),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
     pub pipe: Option<PathBuf>,
+    /// FIFO that gets an `OK` or `ERR <reason>` line for each command read from `pipe`.
+    #[serde(default)]
+    pub pipe_response: Option<PathBuf>,
     pub web: String,
     #[serde(default)]
     pub startup_sound: Option<PathBuf>,
     #[serde(default)]
     pub shutdown_sound: Option<PathBuf>,
+    #[serde(default)]
+    pub limit_sound: Option<PathBuf>,
+    /// Short sound played before each queue track starts, e.g. a chime for young listeners.
+    #[serde(default)]
+    pub track_intro_sound: Option<PathBuf>,
+    /// `RUST_LOG`-style directives, e.g. `crabbox=info,crabbox::rfid=warn`; `RUST_LOG`
+    /// overrides them.
+    #[serde(default)]
+    pub log: Option<String>,
+    /// Audio output to play through, by name; the system default when unset or not found.
+    #[serde(default)]
+    pub output_device: Option<String>,
+    /// How the volume setting maps to loudness: `linear` (default) or `logarithmic`.
+    #[serde(default)]
+    pub volume_curve: VolumeCurve,
+    /// Where volume changes go: `software` (default) scales the samples, `alsa` sets the
+    /// `alsa_control` mixer control on `alsa_card`.
+    #[serde(default)]
+    pub volume_backend: VolumeBackend,
+    #[serde(default = "default_alsa_card")]
+    pub alsa_card: String,
+    #[serde(default = "default_alsa_control")]
+    pub alsa_control: String,
+    /// Milliseconds to fade out over on `STOP` and `SHUTDOWN`; 0 cuts off immediately.
+    #[serde(default)]
+    pub fade_out_ms: u64,
+    /// Milliseconds to hold back status pushes after a change, so a burst of commands
+    /// reaches subscribers as one settled update; 0 pushes every change at once.
+    #[serde(default)]
+    pub status_debounce_ms: u64,
+    /// Largest total size of the files in one web upload; bigger uploads get 413.
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: u64,
+    /// Require HTTP Basic auth for the web interface; open to the LAN when unset.
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// Key for signing `/share` links to the stream and cover; sharing is off when unset.
+    #[serde(default)]
+    pub share_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthConfig {
+    pub username: String,
+    /// Hex SHA-256 of the password, e.g. from `printf %s 'secret' | sha256sum`.
+    pub password_sha256: String,
+    /// Ask browsers for the password again after this many minutes without a request.
+    #[serde(default)]
+    pub session_timeout_mins: Option<u64>,
 }
 
 #[cfg(feature = "rpi")]
@@ -52,6 +229,9 @@ pub struct GpioConfig {
     pub play: Option<u8>,
     #[serde(default = "default_gpio_debounce_ms")]
     pub debounce_ms: u64,
+    /// Also ignore presses within `debounce_ms` of the last accepted press in software.
+    #[serde(default)]
+    pub software_debounce: bool,
     #[serde(default)]
     pub next: Option<u8>,
     #[serde(default)]
@@ -62,22 +242,93 @@ pub struct GpioConfig {
     pub volume_down: Option<u8>,
     #[serde(default)]
     pub shutdown: Option<u8>,
+    /// Optional command overrides for each button; defaults match the button names.
+    #[serde(default)]
+    pub play_command: Option<Command>,
+    #[serde(default)]
+    pub next_command: Option<Command>,
+    #[serde(default)]
+    pub prev_command: Option<Command>,
+    #[serde(default)]
+    pub volume_up_command: Option<Command>,
+    #[serde(default)]
+    pub volume_down_command: Option<Command>,
+    #[serde(default)]
+    pub shutdown_command: Option<Command>,
+    /// How long the shutdown button must be held before `shutdown_command` runs.
+    #[serde(default = "default_shutdown_hold_secs")]
+    pub shutdown_hold_secs: u64,
+    /// Optional command for a press of the shutdown button released before the hold.
+    #[serde(default)]
+    pub shutdown_short_command: Option<Command>,
+    #[serde(default)]
+    pub panic: Option<PanicButtonConfig>,
+    /// Output pin for an LED that is lit while a track plays.
+    #[serde(default)]
+    pub led: Option<u8>,
+    /// Drive the LED pin low to light it, for LEDs wired to 3.3 V.
+    #[serde(default)]
+    pub led_active_low: bool,
+    /// Clock (A) and data (B) pins of a rotary encoder that turns the volume up and down.
+    #[serde(default)]
+    pub volume_encoder_clk: Option<u8>,
+    #[serde(default)]
+    pub volume_encoder_dt: Option<u8>,
+}
+
+/// A single button that sends several commands in order, e.g. stop and turn the volume down.
+#[cfg(feature = "rpi")]
+#[derive(Debug, Deserialize)]
+pub struct PanicButtonConfig {
+    pub pin: u8,
+    pub commands: Vec<Command>,
 }
 
 #[cfg(feature = "rpi")]
 #[derive(Debug, Deserialize)]
 pub struct RfidConfig {
     pub bus: u8,
+    /// Chip-enable line the reader is wired to: 0 for CE0, 1 for CE1, 2 for CE2.
+    #[serde(default)]
+    pub slave_select: u8,
+    /// SPI clock; some RC522 clones need less than the default 1 MHz.
+    #[serde(default = "default_rfid_spi_speed_hz")]
+    pub spi_speed_hz: u32,
     pub irq: u8,
     #[serde(default)]
     pub reset: Option<u8>,
+    /// How often the reader is polled when no interrupt arrives.
+    #[serde(default = "default_rfid_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Reads of the same tag within this window of the last one are ignored, so a tag held
+    /// at the reader fires once.
+    #[serde(default = "default_rfid_tag_debounce_ms")]
+    pub tag_debounce_ms: u64,
+    /// Runs when the tag at the reader is taken away, e.g. `STOP` for "play while present".
+    #[serde(default)]
+    pub on_remove: Option<Command>,
+    /// Polls in a row without a tag before it counts as removed.
+    #[serde(default = "default_rfid_remove_after_misses")]
+    pub remove_after_misses: u32,
 }
 
 impl Config {
     pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let raw = fs::read_to_string(path)?;
-        let mut config: Config = toml::from_str(&raw)?;
+        let config: Config = toml::from_str(&raw)?;
+        config.checked(path)
+    }
+
+    /// The idle timeout, or `None` when unset or zero.
+    pub fn idle_stop(&self) -> Option<Duration> {
+        self.idle_stop_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
 
+    /// Checks a parsed config loaded from `path` and merges in its tags file.
+    pub fn checked(self, path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = self;
         config.path = path.to_path_buf();
 
         if config.music.is_empty() {
@@ -88,6 +339,22 @@ impl Config {
             .into());
         }
 
+        if let Some(dir) = config.music.iter().find_map(|music| {
+            music
+                .dir
+                .to_str()
+                .filter(|dir| dir.contains("://") && !is_http_url(dir))
+        }) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "music dir {dir} is not supported; use an http(s):// index or mount the share \
+                     locally (e.g. smb:// with mount.cifs)"
+                ),
+            )
+            .into());
+        }
+
         if config.server.web.trim().is_empty() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -116,6 +383,52 @@ impl Config {
             .into());
         }
 
+        if let Some(sound) = &config.server.limit_sound
+            && !sound.is_file()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "limit_sound must point to an existing file",
+            )
+            .into());
+        }
+
+        if let Some(sound) = &config.server.track_intro_sound
+            && !sound.is_file()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "track_intro_sound must point to an existing file",
+            )
+            .into());
+        }
+
+        if let Some(log) = &config.server.log
+            && let Err(err) = logging::parse_filter(log, Level::INFO)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("[server].log is invalid: {err}"),
+            )
+            .into());
+        }
+
+        if let Some(tags_file) = config.tags_file.as_ref().filter(|path| path.exists()) {
+            let tags = load_tags_file(tags_file)?;
+            config.tags.extend(tags);
+        }
+
+        for (id, mapping) in &config.tags {
+            for warning in mapping
+                .command
+                .filters()
+                .iter()
+                .filter_map(|f| f.split_path_warning())
+            {
+                warn!("Tag {id}: {warning}");
+            }
+        }
+
         #[cfg(feature = "rpi")]
         let _ = (&config.gpio, &config.rfid);
 
@@ -127,11 +440,363 @@ impl Config {
     }
 }
 
+/// Returns true when a tags file should be read and written as JSON rather than TOML.
+pub fn is_json_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+fn load_tags_file(
+    path: &Path,
+) -> Result<HashMap<TagId, TagMapping>, Box<dyn std::error::Error + Send + Sync>> {
+    let raw = fs::read_to_string(path)?;
+    let tags = if is_json_file(path) {
+        serde_json::from_str(&raw)?
+    } else {
+        toml::from_str(&raw)?
+    };
+    Ok(tags)
+}
+
+const fn default_recursive() -> bool {
+    true
+}
+
+const fn default_library_cache_ttl_secs() -> u64 {
+    300
+}
+
+const fn default_max_upload_bytes() -> u64 {
+    2 * 1024 * 1024 * 1024
+}
+
+const fn default_config_backup_keep() -> usize {
+    20
+}
+
 const fn default_volume() -> f32 {
     1.0
 }
 
+const fn default_tag_history_max_age_secs() -> u64 {
+    24 * 60 * 60
+}
+
 #[cfg(feature = "rpi")]
 const fn default_gpio_debounce_ms() -> u64 {
     200
 }
+
+#[cfg(feature = "rpi")]
+const fn default_shutdown_hold_secs() -> u64 {
+    5
+}
+
+#[cfg(feature = "rpi")]
+const fn default_rfid_spi_speed_hz() -> u32 {
+    1_000_000
+}
+
+#[cfg(feature = "rpi")]
+const fn default_rfid_poll_interval_ms() -> u64 {
+    500
+}
+
+#[cfg(feature = "rpi")]
+const fn default_rfid_tag_debounce_ms() -> u64 {
+    1_000
+}
+
+#[cfg(feature = "rpi")]
+const fn default_rfid_remove_after_misses() -> u32 {
+    3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[cfg(feature = "rpi")]
+    #[test]
+    fn rfid_spi_speed_defaults_to_one_megahertz() {
+        let rfid = |extra: &str| {
+            let raw = format!(
+                "[[music]]\ndir = \"/music\"\n\n[server]\nweb = \"0.0.0.0:8080\"\n\n[rfid]\nbus = 0\nirq = 5\n{extra}"
+            );
+            toml::from_str::<Config>(&raw)
+                .expect("parse config")
+                .rfid
+                .expect("rfid section")
+        };
+
+        assert_eq!(rfid("").spi_speed_hz, 1_000_000);
+        assert_eq!(rfid("spi_speed_hz = 500000\n").spi_speed_hz, 500_000);
+    }
+
+    #[test]
+    fn tags_file_entries_merge_over_inline_tags() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let tags_path = tmp.path().join("tags.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"tags_file = "{}"
+
+[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[tags]
+0A1B2C3D = "PLAY"
+DEADBEEF = "STOP"
+"#,
+                tags_path.display()
+            ),
+        )
+        .expect("write config");
+        fs::write(
+            &tags_path,
+            "DEADBEEF = \"NEXT\"\nABCD1234 = \"SHUFFLE kids/*\"\n",
+        )
+        .expect("write tags");
+
+        let config = Config::load(&config_path).expect("load config");
+        let command = |id: &str| {
+            config
+                .tags
+                .get(&TagId::from_hex_str(id).unwrap())
+                .map(|mapping| mapping.command.clone())
+        };
+
+        assert_eq!(command("0A1B2C3D"), Some(Command::Play { filter: None }));
+        assert_eq!(command("DEADBEEF"), Some(Command::Next));
+        assert_eq!(
+            command("ABCD1234").map(|command| command.to_string()),
+            Some("SHUFFLE kids/*".to_string())
+        );
+    }
+
+    #[test]
+    fn tag_mappings_accept_strings_and_tables_with_volume() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let tags_path = tmp.path().join("tags.json");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"tags_file = "{}"
+
+[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[tags]
+0A1B2C3D = "PLAY"
+DEADBEEF = {{ command = "PLAY books/*", volume = 0.3 }}
+"#,
+                tags_path.display()
+            ),
+        )
+        .expect("write config");
+        fs::write(
+            &tags_path,
+            r#"{"ABCD1234": {"command": "SHUFFLE kids/*", "volume": 0.8}}"#,
+        )
+        .expect("write tags");
+
+        let config = Config::load(&config_path).expect("load config");
+        let mapping = |id: &str| config.tags[&TagId::from_hex_str(id).unwrap()].clone();
+
+        assert_eq!(
+            mapping("0A1B2C3D"),
+            TagMapping::new(Command::Play { filter: None })
+        );
+        assert_eq!(mapping("DEADBEEF").volume, Some(0.3));
+        assert_eq!(mapping("DEADBEEF").command.to_string(), "PLAY books/*");
+        assert_eq!(mapping("ABCD1234").volume, Some(0.8));
+
+        let loud = toml::from_str::<HashMap<TagId, TagMapping>>(
+            r#"0A1B2C3D = { command = "PLAY", volume = 1.5 }"#,
+        );
+        assert!(loud.is_err());
+    }
+
+    #[test]
+    fn music_dirs_accept_http_urls_but_not_other_schemes() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let config_with = |dir: &str| {
+            fs::write(
+                &config_path,
+                format!("[[music]]\ndir = \"{dir}\"\n\n[server]\nweb = \"0.0.0.0:8080\"\n"),
+            )
+            .expect("write config");
+            Config::load(&config_path)
+        };
+
+        let config = config_with("http://nas:8000/music/").expect("http dir");
+        assert_eq!(config.music[0].url(), Some("http://nas:8000/music/"));
+        assert_eq!(
+            config_with("/music").expect("local dir").music[0].url(),
+            None
+        );
+
+        let err = config_with("smb://nas/music").expect_err("smb dir");
+        assert!(err.to_string().contains("mount"));
+    }
+
+    #[test]
+    fn configured_sounds_must_exist() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let sound = tmp.path().join("ding.wav");
+        fs::write(&sound, "wav").expect("write sound");
+        let config_with = |key: &str, path: &Path| {
+            fs::write(
+                &config_path,
+                format!(
+                    "[[music]]\ndir = \"/music\"\n\n[server]\nweb = \"0.0.0.0:8080\"\n\
+                     {key} = {path:?}\n"
+                ),
+            )
+            .expect("write config");
+            Config::load(&config_path)
+        };
+
+        for key in [
+            "startup_sound",
+            "shutdown_sound",
+            "limit_sound",
+            "track_intro_sound",
+        ] {
+            assert!(config_with(key, &sound).is_ok(), "{key}");
+            let err = config_with(key, &tmp.path().join("missing.wav")).expect_err(key);
+            assert_eq!(
+                err.to_string(),
+                format!("{key} must point to an existing file")
+            );
+        }
+    }
+
+    #[test]
+    fn missing_tags_file_is_not_an_error() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+
+        fs::write(
+            &config_path,
+            r#"tags_file = "/nonexistent/tags.json"
+
+[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+"#,
+        )
+        .expect("write config");
+
+        let config = Config::load(&config_path).expect("load config");
+        assert!(config.tags.is_empty());
+    }
+
+    #[test]
+    fn music_directory_overrides_default_to_recursive_builtin_extensions() {
+        let config: Config = toml::from_str(
+            r#"[[music]]
+dir = "/music"
+
+[[music]]
+dir = "/samples"
+recursive = false
+extensions = ["wma"]
+
+[server]
+web = "0.0.0.0:8080"
+"#,
+        )
+        .expect("parse config");
+
+        let [music, samples] = config.music.as_slice() else {
+            panic!("expected two music directories");
+        };
+        assert!(music.recursive);
+        assert!(music.accepts_extension("MP3"));
+        assert!(!music.accepts_extension("wma"));
+        assert!(!samples.recursive);
+        assert!(samples.accepts_extension("WMA"));
+        assert!(!samples.accepts_extension("mp3"));
+    }
+
+    #[cfg(feature = "rpi")]
+    #[test]
+    fn gpio_button_commands_are_parsed_at_load() {
+        let config: Config = toml::from_str(
+            r#"[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[gpio]
+play = 17
+play_command = "SHUFFLE kids/*"
+"#,
+        )
+        .expect("parse config");
+        let gpio = config.gpio.expect("gpio section");
+
+        assert_eq!(
+            gpio.play_command.map(|command| command.to_string()),
+            Some("SHUFFLE kids/*".to_string())
+        );
+        assert_eq!(gpio.next_command, None);
+
+        let invalid = toml::from_str::<Config>(
+            r#"[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[gpio]
+play = 17
+play_command = "DANCE"
+"#,
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[cfg(feature = "rpi")]
+    #[test]
+    fn gpio_panic_button_parses_command_list() {
+        let config: Config = toml::from_str(
+            r#"[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[gpio.panic]
+pin = 4
+commands = ["STOP", "VOLUMEDOWN", "VOLUMEDOWN"]
+"#,
+        )
+        .expect("parse config");
+        let panic = config.gpio.and_then(|gpio| gpio.panic).expect("panic");
+
+        assert_eq!(panic.pin, 4);
+        assert_eq!(
+            panic.commands,
+            vec![Command::Stop, Command::VolumeDown, Command::VolumeDown]
+        );
+    }
+}
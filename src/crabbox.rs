@@ -1,74 +1,606 @@
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, VecDeque},
     fs,
-    path::PathBuf,
+    io::Write,
+    mem,
+    path::{Component, Path, PathBuf},
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     thread,
+    time::{Duration, Instant},
 };
 
-use chrono::Utc;
-use rand::{rng, seq::SliceRandom};
-use tokio::{runtime::Builder, sync::mpsc};
-use tracing::{debug, info, warn};
-use walkdir::WalkDir;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use rand::{Rng, SeedableRng, rng, rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    runtime::Builder,
+    sync::{broadcast, mpsc},
+    task::{self, AbortHandle},
+    time::{self, timeout},
+};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    commands::Command,
-    config::{Config, MusicDirectory},
-    glob::Glob,
-    player::{Player, ToggleResult, play_blocking, play_track, toggle_play_pause},
+    commands::{Command, RepeatMode, SeekTarget},
+    config::{Config, MusicDirectory, TagMapping, is_json_file},
+    cue,
+    filter::Filter,
+    library::source::{self, LocalSource},
+    metadata::{MetadataCache, TrackMetadata},
+    mixer::MixerConfig,
+    player::{
+        PlayError, Player, ToggleResult, VolumeCurve, play_blocking, play_track, toggle_play_pause,
+    },
+    playlist,
+    playtime::Playtime,
     state::State,
     tag::TagId,
+    track_volume::TrackVolumes,
 };
-use toml_edit::{DocumentMut, Value, table, value};
+use toml_edit::{Array, DocumentMut, InlineTable, Table, Value, table, value};
+
+const TAG_HISTORY_LEN: usize = 10;
+const PLAY_HISTORY_LEN: usize = 50;
+const STATUS_EVENT_CAPACITY: usize = 32;
+const PLAYTIME_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// How long the playback thread may go without a heartbeat before it's reported dead.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(2 * PLAYTIME_CHECK_INTERVAL.as_secs());
+/// How far to rewind when resuming after an interjection, so the listener regains context.
+const RESUME_REWIND: Duration = Duration::from_secs(2);
+/// Unplayable queue tracks skipped in a row before giving up, so a bad queue can't spin.
+const MAX_UNPLAYABLE_SKIPS: usize = 20;
+pub const MAX_PAGE_LIMIT: usize = 1_000;
+
+/// Where to pick playback back up after an interjection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ResumePoint {
+    track: PathBuf,
+    position: Duration,
+    paused: bool,
+}
+
+impl ResumePoint {
+    fn seek_target(&self) -> Duration {
+        self.position.saturating_sub(RESUME_REWIND)
+    }
+}
+
+/// An interjection playing on the player, started at sink `generation`, with the track to
+/// pick back up once it ends.
+#[derive(Debug)]
+struct Interjection {
+    generation: u64,
+    resume: Option<ResumePoint>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ScanRequest {
+    filter: Option<Filter>,
+}
+
+/// Library scan done before locking the crabbox for the command that needs it.
+struct PrescannedTracks {
+    filter: Option<Filter>,
+    tracks: Vec<PathBuf>,
+}
 
 #[derive(Default)]
 struct PlaybackStatus {
     current: Option<PathBuf>,
+    repeat: RepeatMode,
     last_tag: Option<TagId>,
+    volume: f32,
+    muted: bool,
+    speed: f32,
+    /// The current track is paused rather than playing.
+    paused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagScan {
+    pub id: TagId,
+    pub at: DateTime<Utc>,
+    pub mapped: bool,
+}
+
+/// A track that started playing, for the recently played list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayedTrack {
+    pub path: PathBuf,
+    pub at: DateTime<Utc>,
+}
+
+/// Timestamp suffix of the copies `backup_file` writes, e.g. `config.toml.20250101T120000`.
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// A saved copy of the config or tags file in `config_backup_dir`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigBackup {
+    /// File name inside the backup directory.
+    pub name: String,
+    /// The file this backup restores.
+    pub file: PathBuf,
+    pub created: NaiveDateTime,
+}
+
+/// Playback changes pushed to live subscribers such as the `/api/events` stream.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StatusEvent {
+    TrackStarted { path: String },
+    Stopped,
+    PauseChanged { paused: bool },
+    VolumeChanged { volume: f32 },
+    MuteChanged { muted: bool },
+    TagScanned { id: String, mapped: bool },
+}
+
+impl StatusEvent {
+    /// Whether audio is playing after this event, for events that change it.
+    pub fn playing(&self) -> Option<bool> {
+        match self {
+            StatusEvent::TrackStarted { .. } => Some(true),
+            StatusEvent::Stopped => Some(false),
+            StatusEvent::PauseChanged { paused } => Some(!paused),
+            StatusEvent::VolumeChanged { .. }
+            | StatusEvent::MuteChanged { .. }
+            | StatusEvent::TagScanned { .. } => None,
+        }
+    }
+}
+
+/// The parts of playback state that produce status events when they change.
+#[derive(Debug, Clone, PartialEq)]
+struct PlaybackView {
+    current: Option<PathBuf>,
+    volume: f32,
+    muted: bool,
+    paused: bool,
+}
+
+/// Holds back status events until playback settles, so a burst of commands (mashing Next)
+/// reaches subscribers as the changes between the first and last state of the burst.
+#[derive(Debug, Default)]
+struct StatusDebounce {
+    window: Duration,
+    /// The view before the first held-back change, and when the changes go out.
+    pending: Option<(PlaybackView, Instant)>,
+}
+
+impl StatusDebounce {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: None,
+        }
+    }
+
+    /// Takes the view from before and after a loop iteration; returns the view to publish
+    /// changes from once the window after the first unpublished change has passed.
+    fn settle(
+        &mut self,
+        before: PlaybackView,
+        after: &PlaybackView,
+        now: Instant,
+    ) -> Option<PlaybackView> {
+        if self.window.is_zero() {
+            return Some(before);
+        }
+        let (from, due) = match self.pending.take() {
+            Some(pending) => pending,
+            None if before != *after => (before, now + self.window),
+            None => return None,
+        };
+        if now >= due {
+            Some(from)
+        } else {
+            self.pending = Some((from, due));
+            None
+        }
+    }
+
+    /// When held-back changes are due to go out.
+    fn due(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|(_, due)| *due)
+    }
+}
+
+/// How `SHUFFLE` orders a new queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShuffleStrategy {
+    /// Every ordering is equally likely.
+    #[default]
+    Random,
+    /// Recently played tracks go to the end of the shuffled queue, the most recent last.
+    AvoidRecent,
+    /// Spreads tracks out so consecutive tracks come from different artists when possible.
+    Interleave,
+}
+
+/// Seed and resulting ordering of the most recent shuffle, kept when `debug_shuffle` is on.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShuffleRecord {
+    pub seed: u64,
+    pub filter: Option<String>,
+    pub queue: Vec<PathBuf>,
+}
+
+/// Result of walking the music directories, listing files left out of the library.
+#[derive(Debug, Default, Serialize)]
+pub struct LibraryDiagnostics {
+    pub included: Vec<PathBuf>,
+    pub skipped: Vec<SkippedFile>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    UnknownExtension,
+    Unreadable,
 }
 
 #[derive(Clone, Default)]
 pub struct CrabboxSnapshot {
     pub current: Option<PathBuf>,
+    /// The current track is paused; `false` while playing or stopped.
+    pub paused: bool,
     pub queue: Vec<PathBuf>,
     pub queue_position: Option<usize>,
     pub tags: Vec<(TagId, Command)>,
     pub last_tag: Option<TagId>,
     pub last_tag_command: Option<Command>,
+    pub volume: f32,
+    pub muted: bool,
+    pub speed: f32,
+    pub current_metadata: Option<TrackMetadata>,
+    pub current_volume_offset: i32,
+    pub repeat: RepeatMode,
+    pub tag_history: Vec<TagScan>,
+    /// Recently played tracks, newest first.
+    pub history: Vec<PlayedTrack>,
+    pub playtime_used: Duration,
+    pub playtime_limit: Option<Duration>,
+    pub config_warning: Option<String>,
+    pub sleep_remaining: Option<Duration>,
+    /// Time until playback that is paused or idle gets stopped, if `idle_stop_secs` is set.
+    pub idle_remaining: Option<Duration>,
 }
 
+/// Clones share the metadata and track caches, so web handlers and the playback thread
+/// reuse each other's scans.
 #[derive(Clone, Default)]
 pub struct Library {
-    directories: Vec<PathBuf>,
+    directories: Vec<MusicDirectory>,
+    metadata: Arc<MetadataCache>,
+    index: Arc<Mutex<Option<TrackIndex>>>,
+    /// Zero disables caching.
+    cache_ttl: Duration,
+    scanned: Arc<AtomicBool>,
+}
+
+struct TrackIndex {
+    scanned_at: Instant,
+    tracks: Vec<PathBuf>,
 }
 
 impl Library {
-    fn new(directories: &[MusicDirectory]) -> Self {
+    fn new(directories: &[MusicDirectory], cache_ttl: Duration) -> Self {
         Self {
-            directories: directories.iter().map(|d| d.dir.clone()).collect(),
+            directories: directories.to_vec(),
+            metadata: Arc::default(),
+            index: Arc::default(),
+            cache_ttl,
+            scanned: Arc::default(),
+        }
+    }
+
+    /// The library of the music directories in `config`, without starting a server.
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            &config.music,
+            Duration::from_secs(config.library_cache_ttl_secs),
+        )
+    }
+
+    pub fn directories(&self) -> &[MusicDirectory] {
+        &self.directories
+    }
+
+    /// Whether the music directories have been enumerated at least once.
+    pub fn is_scanned(&self) -> bool {
+        self.scanned.load(Ordering::Relaxed)
+    }
+
+    /// Drops the cached track list so the next listing walks the music directories again.
+    pub fn refresh(&self) {
+        if let Ok(mut index) = self.index.lock() {
+            *index = None;
+        }
+        info!("Library cache cleared; rescanning on next use");
+    }
+
+    /// Moves the track `from` to `to`, which is relative to the track's music directory or an
+    /// absolute path inside it, creating parent directories as needed. Returns the new path.
+    pub fn rename_track(&self, from: &Path, to: &Path) -> Result<PathBuf, String> {
+        let roots: Vec<PathBuf> = self.directories.iter().map(|dir| dir.dir.clone()).collect();
+        if !is_library_track(from, &roots) {
+            return Err("Track must be a file inside a music directory".to_string());
+        }
+        let Some(root) = self
+            .directories
+            .iter()
+            .filter(|dir| from.starts_with(&dir.dir))
+            .max_by_key(|dir| dir.dir.components().count())
+        else {
+            return Err("Track must be a file inside a music directory".to_string());
+        };
+
+        let target = root.dir.join(to);
+        if target
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return Err("New path must not contain '..'".to_string());
+        }
+        if !target.starts_with(&root.dir) || target == root.dir {
+            return Err(format!(
+                "New path must stay inside {}; tracks can't move between music directories",
+                root.dir.display()
+            ));
+        }
+        let accepted = target
+            .extension()
+            .and_then(|os| os.to_str())
+            .is_some_and(|ext| root.accepts_extension(ext));
+        if !accepted {
+            return Err("New path must keep a music file extension".to_string());
+        }
+        if target.exists() {
+            return Err(format!("{} already exists", target.display()));
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        fs::rename(from, &target).map_err(|err| err.to_string())?;
+        info!(?from, to = ?target, "Renamed track");
+        self.refresh();
+        Ok(target)
+    }
+
+    /// Walks the music directories without the cache, reporting why files were skipped.
+    pub fn diagnostics(&self) -> LibraryDiagnostics {
+        let mut diagnostics = LibraryDiagnostics::default();
+
+        for dir in &self.directories {
+            if dir.url().is_some() {
+                diagnostics
+                    .included
+                    .extend(source::for_directory(dir).list_tracks());
+                continue;
+            }
+            for entry in LocalSource::new(dir).walker() {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        diagnostics.skipped.push(SkippedFile {
+                            path: err.path().unwrap_or(&dir.dir).to_path_buf(),
+                            reason: SkipReason::Unreadable,
+                            detail: Some(err.to_string()),
+                        });
+                        continue;
+                    }
+                };
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+
+                let accepted = entry
+                    .path()
+                    .extension()
+                    .and_then(|os| os.to_str())
+                    .is_some_and(|ext| dir.accepts_extension(ext));
+                let skipped = if !accepted {
+                    Some((SkipReason::UnknownExtension, None))
+                } else if let Err(err) = fs::File::open(entry.path()) {
+                    Some((SkipReason::Unreadable, Some(err.to_string())))
+                } else {
+                    None
+                };
+
+                match skipped {
+                    Some((reason, detail)) => diagnostics.skipped.push(SkippedFile {
+                        path: entry.into_path(),
+                        reason,
+                        detail,
+                    }),
+                    None => diagnostics.included.push(entry.into_path()),
+                }
+            }
+        }
+
+        diagnostics.included.sort();
+        diagnostics.skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        diagnostics
+    }
+
+    /// All tracks, sorted by path, from the cache while it is fresh.
+    fn all_tracks(&self) -> Vec<PathBuf> {
+        let Ok(mut index) = self.index.lock() else {
+            return collect_music_files(&self.directories);
+        };
+        if let Some(cached) = index.as_ref()
+            && cached.scanned_at.elapsed() < self.cache_ttl
+        {
+            return cached.tracks.clone();
+        }
+
+        let tracks = collect_music_files(&self.directories);
+        *index = Some(TrackIndex {
+            scanned_at: Instant::now(),
+            tracks: tracks.clone(),
+        });
+        self.scanned.store(true, Ordering::Relaxed);
+        tracks
+    }
+
+    /// Tags for `path`, read once and cached until the file changes.
+    pub fn metadata(&self, path: &Path) -> Option<TrackMetadata> {
+        self.metadata.get(path)
+    }
+
+    /// What `Interleave` shuffles spread apart: the artist tag, else the album tag, else the
+    /// track's directory.
+    fn artist_key(&self, path: &Path) -> String {
+        self.metadata(path)
+            .and_then(|metadata| {
+                metadata
+                    .artist
+                    .map(|artist| format!("artist:{artist}"))
+                    .or(metadata.album.map(|album| format!("album:{album}")))
+            })
+            .unwrap_or_else(|| format!("dir:{}", path.parent().unwrap_or(path).display()))
+    }
+
+    pub fn list_tracks_page(&self, filter: Option<&Filter>, page: PageRequest) -> TrackPage {
+        let mut tracks = self.list_tracks(filter);
+        match page.sort {
+            // Already sorted by path.
+            TrackSort::Path => {}
+            TrackSort::Name => tracks.sort_by_cached_key(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase())
+            }),
+            TrackSort::Mtime => tracks.sort_by_cached_key(|path| {
+                fs::metadata(path).and_then(|meta| meta.modified()).ok()
+            }),
+        }
+        if page.order == SortOrder::Desc {
+            tracks.reverse();
+        }
+
+        let total = tracks.len();
+        let offset = page.offset.min(total);
+        let limit = page
+            .limit
+            .map_or(total, |limit| limit.clamp(1, MAX_PAGE_LIMIT));
+        let tracks = tracks.into_iter().skip(offset).take(limit).collect();
+
+        TrackPage {
+            total,
+            offset,
+            tracks,
+        }
+    }
+
+    /// The music directory `path` belongs to, preferring the deepest when roots are nested.
+    fn root_of(&self, path: &Path) -> &Path {
+        self.directories
+            .iter()
+            .map(|dir| dir.dir.as_path())
+            .filter(|dir| path.starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+            .unwrap_or(Path::new(""))
+    }
+
+    /// Formats `path` relative to its music directory for display.
+    ///
+    /// With several music directories the directory name is kept as a prefix so
+    /// tracks from different roots stay distinguishable.
+    pub fn display_path(&self, path: &Path) -> String {
+        let root = self.root_of(path);
+        if root.as_os_str().is_empty() {
+            return path.display().to_string();
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        match root.file_name() {
+            Some(label) if self.directories.len() > 1 => {
+                Path::new(label).join(relative).display().to_string()
+            }
+            _ => relative.display().to_string(),
         }
     }
 
-    pub fn list_tracks(&self, filter: Option<String>) -> Vec<PathBuf> {
-        let mut tracks = collect_music_files(&self.directories);
+    pub fn list_tracks(&self, filter: Option<&Filter>) -> Vec<PathBuf> {
+        let mut tracks = self.all_tracks();
 
         let Some(filter) = filter else {
             return tracks;
         };
 
-        match Glob::new(&filter) {
-            Ok(glob) => {
-                tracks.retain(|path| glob.is_match_path(path));
+        match filter.matcher() {
+            Ok(matcher) => {
+                tracks.retain(|path| matcher.is_match_under(path, self.root_of(path)));
                 tracks
             }
             Err(err) => {
-                warn!(?filter, "Invalid glob: {err}");
+                warn!(%filter, "Invalid glob: {err}");
                 Vec::new()
             }
         }
     }
+
+    /// Fails when a filter in `command` matches no tracks, e.g. a typo in a tag's folder.
+    /// Commands without a filter, like `STOP` or `NEXT`, always pass.
+    pub fn check_filters(&self, command: &Command) -> Result<(), String> {
+        match command
+            .filters()
+            .into_iter()
+            .find(|filter| self.list_tracks(Some(filter)).is_empty())
+        {
+            Some(filter) => Err(format!("Filter {filter} matches 0 tracks")),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackSort {
+    #[default]
+    Path,
+    /// File name, ignoring case and folders.
+    Name,
+    /// Modification time, oldest first.
+    Mtime,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Which slice of the (sorted) library to return.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageRequest {
+    pub sort: TrackSort,
+    pub order: SortOrder,
+    pub offset: usize,
+    /// Clamped to `1..=MAX_PAGE_LIMIT`; `None` returns everything after `offset`.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TrackPage {
+    pub total: usize,
+    pub offset: usize,
+    pub tracks: Vec<PathBuf>,
 }
 
 pub struct Queue {
@@ -89,13 +621,58 @@ impl Queue {
         }
     }
 
-    fn from_tracks_shuffled(mut tracks: Vec<PathBuf>) -> Self {
-        tracks.shuffle(&mut rng());
+    fn from_tracks_shuffled(mut tracks: Vec<PathBuf>, seed: u64) -> Self {
+        tracks.shuffle(&mut StdRng::seed_from_u64(seed));
         let current = if tracks.is_empty() { None } else { Some(0) };
 
         Self { tracks, current }
     }
 
+    /// Shuffles each group of tracks (e.g. per artist), then repeatedly takes a track from
+    /// the largest group other than the previous pick's. Always draining the largest group
+    /// first means two neighbours only share a group when nothing else is left.
+    fn from_tracks_interleaved(
+        tracks: Vec<PathBuf>,
+        seed: u64,
+        group_of: impl Fn(&Path) -> String,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let total = tracks.len();
+        let mut grouped: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for track in tracks {
+            grouped.entry(group_of(&track)).or_default().push(track);
+        }
+        let mut groups: Vec<Vec<PathBuf>> = grouped.into_values().collect();
+        for group in &mut groups {
+            group.shuffle(&mut rng);
+        }
+
+        let mut ordered = Vec::with_capacity(total);
+        let mut last: Option<usize> = None;
+        while ordered.len() < total {
+            let largest = groups
+                .iter()
+                .enumerate()
+                .filter(|&(idx, group)| Some(idx) != last && !group.is_empty())
+                .map(|(_, group)| group.len())
+                .max();
+            let pick = match largest {
+                Some(len) => {
+                    let candidates: Vec<usize> = (0..groups.len())
+                        .filter(|&idx| Some(idx) != last && groups[idx].len() == len)
+                        .collect();
+                    candidates[rng.random_range(0..candidates.len())]
+                }
+                // Only the previous pick's group has tracks left.
+                None => last.expect("tracks remain in some group"),
+            };
+            ordered.extend(groups[pick].pop());
+            last = Some(pick);
+        }
+
+        Self::from_tracks_ordered(ordered)
+    }
+
     fn from_state(state: State) -> Self {
         let mut queue = Self {
             tracks: state.queue,
@@ -138,6 +715,116 @@ impl Queue {
         self.track_at(next_idx)
     }
 
+    /// Picks the track to play after the current one finishes, or `None` to stop.
+    fn track_after_done(&mut self, repeat: RepeatMode) -> Option<PathBuf> {
+        let idx = self.index_after_done(repeat)?;
+        self.current = Some(idx);
+        self.track_at(idx)
+    }
+
+    /// Returns the track `track_after_done` would pick without advancing the queue.
+    fn peek_after_done(&self, repeat: RepeatMode) -> Option<PathBuf> {
+        self.index_after_done(repeat)
+            .and_then(|idx| self.track_at(idx))
+    }
+
+    fn index_after_done(&self, repeat: RepeatMode) -> Option<usize> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+        match (repeat, self.current) {
+            (RepeatMode::One, Some(idx)) => Some(idx),
+            (RepeatMode::Off, Some(idx)) if idx + 1 >= self.tracks.len() => None,
+            (_, Some(idx)) => Some((idx + 1) % self.tracks.len()),
+            (_, None) => Some(0),
+        }
+    }
+
+    /// Moves tracks in `recent` (newest first) behind all others, keeping the order of the
+    /// rest, so a reshuffle doesn't start with what just played.
+    fn move_recent_to_end(&mut self, recent: &[&Path]) {
+        self.tracks.sort_by_key(|track| {
+            recent
+                .iter()
+                .position(|played| played == track)
+                .map(Reverse)
+        });
+    }
+
+    /// Shuffles the queue in place, moving the current track to the front.
+    fn shuffle_in_place(&mut self, rng: &mut impl Rng) {
+        let rest = match self.current {
+            Some(current) if current < self.tracks.len() => {
+                self.tracks.swap(0, current);
+                self.current = Some(0);
+                &mut self.tracks[1..]
+            }
+            _ => &mut self.tracks[..],
+        };
+        rest.shuffle(rng);
+    }
+
+    /// Makes the track at `index` current and returns it, or `None` if out of range.
+    fn jump_to(&mut self, index: usize) -> Option<PathBuf> {
+        let track = self.track_at(index)?;
+        self.current = Some(index);
+        Some(track)
+    }
+
+    /// Adds tracks to the end; an empty queue starts at the first new track.
+    fn append(&mut self, tracks: Vec<PathBuf>) {
+        if self.tracks.is_empty() && !tracks.is_empty() {
+            self.current = Some(0);
+        }
+        self.tracks.extend(tracks);
+    }
+
+    /// Removes the track at `index`, keeping `current` on the same track when possible.
+    /// Removing the current track makes the track that slides into its place current.
+    fn remove(&mut self, index: usize) -> Option<PathBuf> {
+        if index >= self.tracks.len() {
+            return None;
+        }
+        let removed = self.tracks.remove(index);
+
+        self.current = match self.current {
+            Some(current) if index < current => Some(current - 1),
+            Some(current) if index == current && current >= self.tracks.len() => None,
+            current => current,
+        };
+
+        Some(removed)
+    }
+
+    /// Points every queue entry for `from` at `to` after the file was renamed.
+    fn rename_track(&mut self, from: &Path, to: &Path) -> bool {
+        let mut renamed = false;
+        for track in self.tracks.iter_mut().filter(|track| *track == from) {
+            *track = to.to_path_buf();
+            renamed = true;
+        }
+        renamed
+    }
+
+    /// Moves the track at `from` to position `to`, keeping the current track current.
+    /// Returns `false` without changing anything when either index is out of range.
+    fn move_track(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.tracks.len() || to >= self.tracks.len() {
+            return false;
+        }
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+
+        self.current = self.current.map(|current| match current {
+            current if current == from => to,
+            current if from < current && to >= current => current - 1,
+            current if from > current && to <= current => current + 1,
+            current => current,
+        });
+
+        true
+    }
+
     fn prev_track(&mut self) -> Option<PathBuf> {
         if self.tracks.is_empty() {
             return None;
@@ -152,6 +839,51 @@ impl Queue {
         self.track_at(prev_idx)
     }
 
+    /// Index of the first track of the run of tracks sharing `index`'s directory.
+    fn dir_start(&self, index: usize) -> usize {
+        let dir = self.tracks[index].parent();
+        (0..index)
+            .rev()
+            .take_while(|&idx| self.tracks[idx].parent() == dir)
+            .last()
+            .unwrap_or(index)
+    }
+
+    /// Moves to the first track after the current directory, wrapping to the start.
+    fn next_dir(&mut self) -> Option<PathBuf> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+
+        let next_idx = self.current.map_or(0, |current| {
+            let dir = self.tracks[current].parent();
+            (current + 1..self.tracks.len())
+                .find(|&idx| self.tracks[idx].parent() != dir)
+                .unwrap_or(0)
+        });
+
+        self.current = Some(next_idx);
+        self.track_at(next_idx)
+    }
+
+    /// Moves to the first track of the directory before the current one, wrapping to the
+    /// last directory.
+    fn prev_dir(&mut self) -> Option<PathBuf> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+
+        let prev_idx = self
+            .current
+            .map_or(0, |current| match self.dir_start(current) {
+                0 => self.dir_start(self.tracks.len() - 1),
+                start => self.dir_start(start - 1),
+            });
+
+        self.current = Some(prev_idx);
+        self.track_at(prev_idx)
+    }
+
     fn log(&self) {
         info!("new queue: {} tracks", self.tracks.len());
         for track in &self.tracks {
@@ -164,17 +896,131 @@ impl Queue {
     }
 }
 
+/// Lets the web server tell whether the playback thread is still looping.
+#[derive(Clone)]
+pub struct Heartbeat {
+    started: Instant,
+    /// Milliseconds after `started` of the last beat.
+    last_beat_ms: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_beat_ms: Arc::default(),
+        }
+    }
+
+    fn beat(&self) {
+        let elapsed = u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        self.last_beat_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Time since the crabbox was created.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn is_alive(&self) -> bool {
+        let last_beat = Duration::from_millis(self.last_beat_ms.load(Ordering::Relaxed));
+        self.uptime().saturating_sub(last_beat) <= HEARTBEAT_TIMEOUT
+    }
+}
+
+type SoundPlayer = fn(&Path, f32, VolumeCurve, Option<&str>) -> Result<(), String>;
+
+/// Sends `STOP` once its duration passes; re-arming or cancelling aborts the pending countdown.
+#[derive(Default)]
+struct StopTimer {
+    countdown: Option<AbortHandle>,
+    deadline: Option<time::Instant>,
+}
+
+impl StopTimer {
+    /// Starts counting down `duration` on the playback runtime; `name` says which timer
+    /// stopped playback in the log.
+    fn arm(&mut self, name: &'static str, duration: Duration, sender: mpsc::Sender<Command>) {
+        self.cancel();
+        self.deadline = Some(time::Instant::now() + duration);
+        let countdown = task::spawn(async move {
+            time::sleep(duration).await;
+            info!("{name} expired; stopping playback");
+            let _ = sender.send(Command::Stop).await;
+        });
+        self.countdown = Some(countdown.abort_handle());
+    }
+
+    fn cancel(&mut self) {
+        if let Some(countdown) = self.countdown.take() {
+            countdown.abort();
+        }
+        self.deadline = None;
+    }
+
+    /// Time left until the timer stops playback, if one is pending.
+    fn remaining(&self, now: time::Instant) -> Option<Duration> {
+        self.deadline
+            .filter(|deadline| *deadline > now)
+            .map(|deadline| deadline - now)
+    }
+}
+
+/// The timers that stop playback, named as in `/timers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimerKind {
+    /// Set with `SLEEP`.
+    Sleep,
+    /// Counts down while paused or idle, when `idle_stop_secs` is set.
+    Idle,
+}
+
 pub struct Crabbox {
     pub library: Library,
     pub queue: Queue,
-    tags: HashMap<TagId, Command>,
+    tags: HashMap<TagId, TagMapping>,
+    tag_history: VecDeque<TagScan>,
+    tag_history_max_age: TimeDelta,
+    history: VecDeque<PlayedTrack>,
+    favorites: Vec<PathBuf>,
+    track_volumes: TrackVolumes,
     command_tx: mpsc::Sender<Command>,
+    events: broadcast::Sender<StatusEvent>,
     status: PlaybackStatus,
+    startup_sound: Option<PathBuf>,
+    /// Whether the startup sound may still be on the player, to be cut off by the first
+    /// command.
+    startup_sound_playing: bool,
     shutdown_sound: Option<PathBuf>,
+    limit_sound: Option<PathBuf>,
+    track_intro_sound: Option<PathBuf>,
+    /// Plays a sound to completion; `play_blocking` outside of tests.
+    sound_player: SoundPlayer,
+    interjection: Option<Interjection>,
     default_volume: f32,
+    playtime: Playtime,
+    buffered_read_max_bytes: Option<u64>,
+    volume_curve: VolumeCurve,
+    output_device: Option<String>,
+    mixer: MixerConfig,
+    fade_out: Duration,
+    status_debounce: Duration,
+    shuffle_seed: Option<u64>,
+    shuffle_strategy: ShuffleStrategy,
+    debug_shuffle: bool,
+    last_shuffle: Option<ShuffleRecord>,
+    prescanned: Option<PrescannedTracks>,
     state_file: Option<PathBuf>,
     config_path: PathBuf,
     config_backup_dir: Option<PathBuf>,
+    config_backup_keep: usize,
+    tags_file: Option<PathBuf>,
+    config_warning: Option<String>,
+    heartbeat: Heartbeat,
+    sleep_timer: StopTimer,
+    idle_stop: Option<Duration>,
+    idle_timer: StopTimer,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -185,44 +1031,8 @@ enum QueueOrder {
 
 impl Crabbox {
     pub fn new(config: &Config) -> Arc<Mutex<Self>> {
-        let library = Library::new(&config.music);
-        let state_file = config.state_file.clone();
-        let queue = match state_file.as_ref().filter(|path| path.exists()) {
-            Some(path) => match State::load(path) {
-                Ok(state) => {
-                    let queue = Queue::from_state(state);
-                    info!(?path, "Restored playback state from file");
-                    queue.log();
-                    queue
-                }
-                Err(err) => {
-                    warn!(?path, "Failed to load playback state: {err}");
-                    Queue::empty()
-                }
-            },
-            None => Queue::empty(),
-        };
-        let tags = config.tags.clone();
-        let (tx, rx) = mpsc::channel(16);
-        let status = PlaybackStatus {
-            current: queue.current_track(),
-            ..PlaybackStatus::default()
-        };
-        let shutdown_sound = config.server.shutdown_sound.clone();
-        let default_volume = config.default_volume;
-
-        let crabbox = Arc::new(Mutex::new(Self {
-            library,
-            queue,
-            tags,
-            command_tx: tx,
-            status,
-            shutdown_sound,
-            default_volume,
-            state_file,
-            config_path: config.path.clone(),
-            config_backup_dir: config.backup_dir.clone(),
-        }));
+        let (crabbox, rx) = Self::build(config);
+        let crabbox = Arc::new(Mutex::new(crabbox));
 
         thread::spawn({
             let playback_crabbox = Arc::clone(&crabbox);
@@ -238,44 +1048,390 @@ impl Crabbox {
             }
         });
 
-        crabbox
-    }
+        // Enumerate the library up front so it is ready before the first request needs it.
+        thread::spawn({
+            let library = crabbox
+                .lock()
+                .expect("failed to lock crabbox")
+                .library
+                .clone();
+            move || {
+                let tracks = library.list_tracks(None);
+                info!(tracks = tracks.len(), "Music library scanned");
+            }
+        });
 
-    pub fn sender(&self) -> mpsc::Sender<Command> {
-        self.command_tx.clone()
+        crabbox
+    }
+
+    /// The crabbox described by `config` and the receiving end of its command channel, with
+    /// nothing playing commands yet.
+    pub fn build(config: &Config) -> (Self, mpsc::Receiver<Command>) {
+        let library = Library::from_config(config);
+        let state_file = config.state_file.clone();
+        let saved_state = state_file.as_deref().and_then(load_state);
+        let playtime = restore_playtime(config.daily_limit_minutes, saved_state.as_ref(), today());
+        let mut status = restore_status(saved_state.as_ref(), config.default_volume);
+        let queue = saved_state.map_or_else(Queue::empty, |state| {
+            let queue = Queue::from_state(state);
+            queue.log();
+            queue
+        });
+        status.current = queue.current_track();
+        let tags = config.tags.clone();
+        let (tx, rx) = mpsc::channel(16);
+        let tag_store = config.tags_file.as_ref().unwrap_or(&config.path);
+        let config_warning = if is_writable(tag_store) {
+            None
+        } else {
+            warn!(path = ?tag_store, "Tag mappings are not writable; tag changes won't be saved");
+            Some(format!(
+                "{} is not writable; tag changes won't be saved",
+                tag_store.display()
+            ))
+        };
+
+        let crabbox = Self {
+            library,
+            queue,
+            tags,
+            tag_history: VecDeque::new(),
+            tag_history_max_age: TimeDelta::seconds(
+                i64::try_from(config.tag_history_max_age_secs).unwrap_or(i64::MAX),
+            ),
+            history: VecDeque::new(),
+            favorites: config.favorites.clone(),
+            track_volumes: TrackVolumes::load(config.track_volume_file.clone()),
+            command_tx: tx,
+            events: broadcast::channel(STATUS_EVENT_CAPACITY).0,
+            status,
+            startup_sound: config.server.startup_sound.clone(),
+            startup_sound_playing: false,
+            shutdown_sound: config.server.shutdown_sound.clone(),
+            limit_sound: config.server.limit_sound.clone(),
+            track_intro_sound: config.server.track_intro_sound.clone(),
+            sound_player: play_blocking,
+            interjection: None,
+            default_volume: config.default_volume,
+            playtime,
+            buffered_read_max_bytes: config.buffered_read_max_bytes,
+            volume_curve: config.server.volume_curve,
+            output_device: config.server.output_device.clone(),
+            mixer: MixerConfig::from_server(&config.server),
+            fade_out: Duration::from_millis(config.server.fade_out_ms),
+            status_debounce: Duration::from_millis(config.server.status_debounce_ms),
+            shuffle_seed: config.shuffle_seed,
+            shuffle_strategy: config.shuffle_strategy,
+            debug_shuffle: config.debug_shuffle,
+            last_shuffle: None,
+            prescanned: None,
+            state_file,
+            config_path: config.path.clone(),
+            config_backup_dir: config.backup_dir.clone(),
+            config_backup_keep: config.backup_keep,
+            tags_file: config.tags_file.clone(),
+            config_warning,
+            heartbeat: Heartbeat::new(),
+            sleep_timer: StopTimer::default(),
+            idle_stop: config.idle_stop(),
+            idle_timer: StopTimer::default(),
+        };
+        (crabbox, rx)
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    pub fn sender(&self) -> mpsc::Sender<Command> {
+        self.command_tx.clone()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StatusEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: StatusEvent) {
+        // Sending only fails when nobody is listening.
+        let _ = self.events.send(event);
+    }
+
+    fn playback_view(&self, player: &Player) -> PlaybackView {
+        PlaybackView {
+            current: self.status.current.clone(),
+            volume: self.status.volume,
+            muted: self.status.muted,
+            paused: player.is_paused(),
+        }
+    }
+
+    fn publish_changes(&self, before: &PlaybackView, after: &PlaybackView) {
+        if before.current != after.current {
+            self.publish(match &after.current {
+                Some(track) => StatusEvent::TrackStarted {
+                    path: track.display().to_string(),
+                },
+                None => StatusEvent::Stopped,
+            });
+        } else if before.paused != after.paused {
+            self.publish(StatusEvent::PauseChanged {
+                paused: after.paused,
+            });
+        }
+
+        if (before.volume - after.volume).abs() > f32::EPSILON {
+            self.publish(StatusEvent::VolumeChanged {
+                volume: after.volume,
+            });
+        }
+        if before.muted != after.muted {
+            self.publish(StatusEvent::MuteChanged { muted: after.muted });
+        }
     }
 
     pub fn snapshot(&self) -> CrabboxSnapshot {
         let last_tag_command = self
             .status
             .last_tag
-            .and_then(|tag| self.tags.get(&tag).cloned());
+            .and_then(|tag| self.tags.get(&tag))
+            .map(|mapping| mapping.command.clone());
         let mut tags: Vec<_> = self
             .tags
             .iter()
-            .map(|(id, command)| (*id, command.clone()))
+            .map(|(id, mapping)| (*id, mapping.command.clone()))
             .collect();
-        tags.sort_by(|(left, _), (right, _)| left.to_string().cmp(&right.to_string()));
+        tags.sort_by_key(|(id, _)| id.to_string());
 
         CrabboxSnapshot {
             current: self.status.current.clone(),
+            paused: self.status.paused,
             queue: self.queue.tracks.clone(),
             queue_position: self.queue.current,
             tags,
             last_tag: self.status.last_tag,
             last_tag_command,
+            volume: self.status.volume,
+            muted: self.status.muted,
+            speed: self.status.speed,
+            current_metadata: self
+                .status
+                .current
+                .as_deref()
+                .and_then(|track| self.library.metadata(track)),
+            current_volume_offset: self
+                .status
+                .current
+                .as_deref()
+                .map_or(0, |track| self.track_volumes.offset(track)),
+            repeat: self.status.repeat,
+            tag_history: self.recent_tag_scans(Utc::now()),
+            history: self.history(),
+            playtime_used: self.playtime.used(Instant::now()),
+            playtime_limit: self.playtime.limit(),
+            config_warning: self.config_warning.clone(),
+            sleep_remaining: self.sleep_timer.remaining(time::Instant::now()),
+            idle_remaining: self.idle_timer.remaining(time::Instant::now()),
+        }
+    }
+
+    fn recent_tag_scans(&self, now: DateTime<Utc>) -> Vec<TagScan> {
+        self.tag_history
+            .iter()
+            .filter(|scan| now - scan.at <= self.tag_history_max_age)
+            .cloned()
+            .collect()
+    }
+
+    /// Pending timers with the time left until each stops playback.
+    pub fn timers(&self) -> Vec<(TimerKind, Duration)> {
+        let now = time::Instant::now();
+        [
+            (TimerKind::Sleep, &self.sleep_timer),
+            (TimerKind::Idle, &self.idle_timer),
+        ]
+        .into_iter()
+        .filter_map(|(kind, timer)| Some((kind, timer.remaining(now)?)))
+        .collect()
+    }
+
+    /// Cancels the `kind` timer, or every pending one, and returns what was cancelled. The
+    /// idle timer is armed again by the next command while playback stays paused.
+    pub fn cancel_timers(&mut self, kind: Option<TimerKind>) -> Vec<TimerKind> {
+        let cancelled: Vec<TimerKind> = self
+            .timers()
+            .into_iter()
+            .map(|(pending, _)| pending)
+            .filter(|pending| kind.is_none_or(|kind| kind == *pending))
+            .collect();
+        for timer in &cancelled {
+            match timer {
+                TimerKind::Sleep => self.sleep_timer.cancel(),
+                TimerKind::Idle => self.idle_timer.cancel(),
+            }
+            info!(?timer, "Timer cancelled");
+        }
+        cancelled
+    }
+
+    /// Recently played tracks, newest first.
+    pub fn history(&self) -> Vec<PlayedTrack> {
+        self.history.iter().cloned().collect()
+    }
+
+    /// The current track followed by the play history, newest first.
+    fn recently_played(&self) -> Vec<&Path> {
+        let current = self.status.current.as_deref();
+        current
+            .into_iter()
+            .chain(
+                self.history
+                    .iter()
+                    .map(|played| played.path.as_path())
+                    .filter(|path| Some(*path) != current),
+            )
+            .collect()
+    }
+
+    /// Notes that `track` started playing; replaying the latest entry isn't recorded twice.
+    fn record_played(&mut self, track: &Path, now: DateTime<Utc>) {
+        if self.history.front().is_some_and(|last| last.path == track) {
+            return;
+        }
+        self.history.push_front(PlayedTrack {
+            path: track.to_path_buf(),
+            at: now,
+        });
+        self.history.truncate(PLAY_HISTORY_LEN);
+    }
+
+    fn record_tag_scan(&mut self, id: TagId, now: DateTime<Utc>) {
+        let max_age = self.tag_history_max_age;
+        self.tag_history.retain(|scan| now - scan.at <= max_age);
+        let mapped = self.tags.contains_key(&id);
+        self.tag_history.push_front(TagScan {
+            id,
+            at: now,
+            mapped,
+        });
+        self.publish(StatusEvent::TagScanned {
+            id: id.to_string(),
+            mapped,
+        });
+        self.tag_history.truncate(TAG_HISTORY_LEN);
+    }
+
+    /// Renames a library track and updates the queue and current track to match.
+    pub fn rename_track(&mut self, from: &Path, to: &Path) -> Result<PathBuf, String> {
+        let target = self.library.rename_track(from, to)?;
+        let queued = self.queue.rename_track(from, &target);
+        if self.status.current.as_deref() == Some(from) {
+            self.status.current = Some(target.clone());
+        }
+        if queued {
+            self.save_state();
         }
+        Ok(target)
+    }
+
+    /// The track playing (or paused) right now.
+    pub fn current(&self) -> Option<&Path> {
+        self.status.current.as_deref()
     }
 
     pub fn music_directories(&self) -> Vec<PathBuf> {
-        self.library.directories.clone()
+        self.library
+            .directories
+            .iter()
+            .map(|dir| dir.dir.clone())
+            .collect()
+    }
+
+    /// Bookmarked tracks, in the order they were added.
+    pub fn favorites(&self) -> &[PathBuf] {
+        &self.favorites
+    }
+
+    /// Bookmarks a library track and saves the list to the config file.
+    pub fn add_favorite(&mut self, path: PathBuf) -> Result<(), String> {
+        if !is_library_track(&path, &self.music_directories()) {
+            return Err("Track must be a file inside a music directory".to_string());
+        }
+        if self.favorites.contains(&path) {
+            return Ok(());
+        }
+        let mut favorites = self.favorites.clone();
+        favorites.push(path);
+        self.save_favorites(favorites)
+    }
+
+    pub fn remove_favorite(&mut self, path: &Path) -> Result<(), String> {
+        if !self.favorites.iter().any(|favorite| favorite == path) {
+            return Ok(());
+        }
+        let favorites = self
+            .favorites
+            .iter()
+            .filter(|favorite| *favorite != path)
+            .cloned()
+            .collect();
+        self.save_favorites(favorites)
+    }
+
+    /// Writes `favorites` to the config, keeping them in memory only once saved.
+    fn save_favorites(&mut self, favorites: Vec<PathBuf>) -> Result<(), String> {
+        let array: Array = favorites
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        self.update_toml_file(&self.config_path, |document| {
+            set_toml_value(document.as_table_mut(), "favorites", Some(array.into()));
+            Ok(())
+        })?;
+        info!(count = favorites.len(), "Favorites saved");
+        self.favorites = favorites;
+        Ok(())
+    }
+
+    pub fn configured_sounds(&self) -> Vec<PathBuf> {
+        self.startup_sound
+            .iter()
+            .chain(self.shutdown_sound.iter())
+            .chain(self.limit_sound.iter())
+            .chain(self.track_intro_sound.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the last shuffle record, or `None` unless `debug_shuffle` is enabled.
+    pub fn last_shuffle(&self) -> Option<ShuffleRecord> {
+        self.last_shuffle.clone()
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.status.volume
+    }
+
+    pub fn volume_curve(&self) -> VolumeCurve {
+        self.volume_curve
     }
 
+    pub fn output_device(&self) -> Option<&str> {
+        self.output_device.as_deref()
+    }
+
+    #[allow(clippy::too_many_lines)]
     fn process_command(&mut self, cmd: Command, player: &mut Player) {
         debug!(?cmd, "Processing command");
+        if mem::take(&mut self.startup_sound_playing) {
+            player.stop();
+        }
+        if cmd.starts_playback() && self.playtime.is_exhausted(today(), Instant::now()) {
+            info!(%cmd, "Daily playtime limit reached; ignoring command");
+            return;
+        }
         match cmd {
             Command::Play { filter } => {
-                let filter = filter.as_deref();
+                let filter = filter.as_ref();
 
                 player.stop();
 
@@ -292,7 +1448,7 @@ impl Crabbox {
                 self.on_play_pause(player, filter.as_ref());
             }
             Command::Shuffle { filter } => {
-                let filter = filter.as_deref();
+                let filter = filter.as_ref();
                 self.rebuild_queue(filter, QueueOrder::Shuffled);
                 player.stop();
 
@@ -300,8 +1456,17 @@ impl Crabbox {
 
                 self.play_queue_track(track, player);
             }
+            Command::Pause => player.pause(),
+            Command::Resume => {
+                if player.has_sink() {
+                    player.resume();
+                } else {
+                    let track = self.queue.current_track();
+                    self.play_queue_track(track, player);
+                }
+            }
             Command::Stop => {
-                player.stop();
+                player.stop_with_fade(self.fade_out);
                 self.status.current = None;
                 self.save_state();
             }
@@ -311,26 +1476,89 @@ impl Crabbox {
                 self.status.current = None;
                 self.save_state();
             }
-            Command::Next | Command::TrackDone => {
+            Command::Rescan => self.library.refresh(),
+            Command::Enqueue { filter } => self.enqueue(filter.as_ref()),
+            Command::PlayFile { path } => self.play_file(path, player),
+            Command::PlayPlaylist { path } => self.play_playlist(&path, player),
+            Command::JumpTo { index } => self.jump_to(index, player),
+            Command::RemoveFromQueue { index } => self.remove_from_queue(index, player),
+            Command::Sequence(commands) => {
+                for command in commands {
+                    if matches!(command, Command::Tag { .. } | Command::Sequence(_)) {
+                        warn!(%command, "Ignoring nested command in sequence");
+                        continue;
+                    }
+                    self.process_command(command, player);
+                }
+            }
+            Command::ShuffleQueue => {
+                let seed = self.shuffle_seed.unwrap_or_else(|| rng().random());
+                self.queue
+                    .shuffle_in_place(&mut StdRng::seed_from_u64(seed));
+                info!(seed, "Shuffled queue in place");
+                self.queue.log();
+                self.save_state();
+            }
+            Command::MoveInQueue { from, to } => {
+                if self.queue.move_track(from, to) {
+                    self.save_state();
+                } else {
+                    warn!(from, to, "Queue index out of range; nothing moved");
+                }
+            }
+            Command::Next => {
                 let track = self.queue.next_track();
                 self.play_queue_track(track, player);
             }
+            Command::TrackDone => self.on_track_done(player),
+            Command::Repeat { mode } => {
+                info!(%mode, "Repeat mode set");
+                self.status.repeat = mode;
+                self.save_state();
+            }
             Command::Prev => {
                 let track = self.queue.prev_track();
                 self.play_queue_track(track, player);
             }
+            Command::NextDir => {
+                let track = self.queue.next_dir();
+                self.play_queue_track(track, player);
+            }
+            Command::PrevDir => {
+                let track = self.queue.prev_dir();
+                self.play_queue_track(track, player);
+            }
             Command::VolumeUp => {
                 player.volume_up();
+                self.status.volume = player.volume();
             }
             Command::VolumeDown => {
                 player.volume_down();
+                self.status.volume = player.volume();
+            }
+            Command::SetVolume { level } => {
+                player.set_volume(level);
+                self.status.volume = player.volume();
+            }
+            Command::Mute => {
+                player.toggle_mute();
+                self.status.muted = player.is_muted();
+            }
+            Command::Speed { factor } => {
+                player.set_speed(factor);
+                self.status.speed = player.speed();
+                self.save_state();
             }
             Command::Shutdown => {
+                // Nothing plays after shutdown, so waiting out the fade here is fine; the
+                // final stop frees the audio device for the shutdown sound.
+                player.stop_with_fade(self.fade_out);
+                thread::sleep(self.fade_out);
                 player.stop();
                 self.status.current = None;
                 self.save_state();
                 if let Some(sound) = self.shutdown_sound.as_ref()
-                    && let Err(err) = play_blocking(sound, self.default_volume)
+                    && let Err(err) = self.play_sound(sound, self.default_volume)
                 {
                     warn!("Failed to play shutdown sound {}: {err}", sound.display());
                 }
@@ -338,24 +1566,213 @@ impl Crabbox {
                     warn!("Failed to trigger shutdown: {err}");
                 }
             }
+            Command::Seek { target } => seek(target, player),
+            Command::Interject { path } => self.interject(&path, player),
+            Command::ResetLimit => {
+                self.playtime.reset(Instant::now());
+                info!("Daily playtime limit reset");
+                self.save_state();
+            }
+            Command::SleepTimer { duration } if duration.is_zero() => {
+                self.sleep_timer.cancel();
+                info!("Sleep timer cancelled");
+            }
+            Command::SleepTimer { duration } => {
+                self.sleep_timer
+                    .arm("Sleep timer", duration, self.command_tx.clone());
+                info!(minutes = duration.as_secs() / 60, "Sleep timer set");
+            }
             Command::AssignTag { id, command } => {
                 self.assign_tag(id, command.as_deref());
                 debug!(?id, "Command received: AssignTag");
             }
+            Command::ListTags => {
+                info!(count = self.tags.len(), "Tag mappings");
+                for (id, mapping) in self.tags_json() {
+                    info!(id, %mapping, "Tag mapping");
+                }
+            }
+            Command::ClearTags => {
+                if let Err(err) = self.clear_tags() {
+                    warn!("Failed to save cleared tag mappings: {err}");
+                }
+            }
+            Command::AdjustTrackVolume { path, delta } => {
+                let offset = self.track_volumes.adjust(&path, delta);
+                if self.status.current.as_ref() == Some(&path) {
+                    player.set_track_offset(offset);
+                }
+            }
             Command::Tag { id } => {
                 self.status.last_tag = Some(id);
+                self.record_tag_scan(id, Utc::now());
+                self.save_state();
                 match self.tags.get(&id).cloned() {
-                    Some(Command::Tag { .. }) => {
+                    Some(TagMapping {
+                        command: Command::Tag { .. },
+                        ..
+                    }) => {
                         warn!(?id, "Tag is mapped to another tag command; ignoring");
                     }
-                    Some(mapped) => self.process_command(mapped, player),
+                    Some(TagMapping { command, volume }) => {
+                        if let Some(level) = volume {
+                            debug!(?id, level, "Applying tag volume");
+                            player.set_volume(level);
+                            self.status.volume = player.volume();
+                        }
+                        self.process_command(command, player);
+                    }
                     None => debug!(?id, "No command mapped for tag"),
                 }
             }
         }
+        self.status.paused = player.is_paused();
+        self.reset_idle_timer(player);
+    }
+
+    /// Restarts the idle countdown after each command while nothing is audible, and cancels
+    /// it once playback is running or stopped.
+    fn reset_idle_timer(&mut self, player: &Player) {
+        let Some(idle_stop) = self.idle_stop else {
+            return;
+        };
+        if player.is_idle() {
+            self.idle_timer
+                .arm("Idle timeout", idle_stop, self.command_tx.clone());
+        } else {
+            self.idle_timer.cancel();
+        }
+    }
+
+    fn on_track_done(&mut self, player: &mut Player) {
+        if let Some(interjection) = self.interjection.take()
+            && interjection.generation == player.generation()
+        {
+            self.end_interjection(interjection.resume, player);
+            return;
+        }
+        let preloaded = player.take_preloaded();
+        if let Some(track) = self.queue.track_after_done(self.status.repeat) {
+            if preloaded.as_ref() == Some(&track) {
+                // Already playing gaplessly; just catch up with the player.
+                self.apply_track_offset(Some(&track), player);
+                self.record_played(&track, Utc::now());
+                self.status.current = Some(track);
+                self.preload_next(player);
+                self.save_state();
+            } else {
+                self.play_queue_track(Some(track), player);
+            }
+        } else {
+            info!("Reached the end of the queue");
+            player.stop();
+            self.status.current = None;
+            self.save_state();
+        }
+    }
+
+    fn jump_to(&mut self, index: usize, player: &mut Player) {
+        let Some(track) = self.queue.jump_to(index) else {
+            warn!(
+                index,
+                len = self.queue.tracks.len(),
+                "Queue index out of range; not jumping"
+            );
+            return;
+        };
+        info!(?track, index, "Jumping to queue track");
+        player.stop();
+        self.play_queue_track(Some(track), player);
+    }
+
+    fn remove_from_queue(&mut self, index: usize, player: &mut Player) {
+        let was_current = self.queue.current == Some(index);
+        let Some(removed) = self.queue.remove(index) else {
+            warn!(index, "Queue index out of range; nothing removed");
+            return;
+        };
+        info!(?removed, index, "Removed track from queue");
+
+        if was_current && player.has_sink() {
+            let paused = player.is_paused();
+            if let Some(track) = self.queue.current_track() {
+                self.play_queue_track(Some(track), player);
+                if paused {
+                    player.pause();
+                }
+            } else {
+                player.stop();
+                self.status.current = None;
+            }
+        } else if was_current {
+            self.status.current = None;
+        }
+        self.save_state();
+    }
+
+    /// Stops the current track and plays the library file `path` on the player. The track
+    /// picks up where it left off once the clip ends, in `on_track_done`.
+    fn interject(&mut self, path: &Path, player: &mut Player) {
+        let Some(clip) = find_library_file(path, &self.music_directories()) else {
+            warn!(
+                ?path,
+                "INTERJECT path is not a file inside a music directory"
+            );
+            return;
+        };
+        let resume = self.resume_point(player);
+
+        info!(?clip, "Playing interjection");
+        match player.play(&clip, true) {
+            Ok(()) => {
+                self.interjection = Some(Interjection {
+                    generation: player.generation(),
+                    resume,
+                });
+            }
+            Err(err) => {
+                warn!("Failed to play interjection {}: {err}", clip.display());
+                if !player.has_sink() {
+                    self.end_interjection(resume, player);
+                }
+            }
+        }
+    }
+
+    /// Goes back to `resume` after an interjection, or stops if nothing was playing.
+    fn end_interjection(&mut self, resume: Option<ResumePoint>, player: &mut Player) {
+        let Some(resume) = resume else {
+            player.stop();
+            return;
+        };
+        self.apply_track_offset(Some(&resume.track), player);
+        let Some(track) = play_track(Some(resume.track.clone()), player, true) else {
+            self.status.current = None;
+            return;
+        };
+        if let Err(err) = player.seek(resume.seek_target()) {
+            warn!(?track, "{err}");
+        }
+        if resume.paused {
+            player.pause();
+        }
+        self.status.current = Some(track);
+        self.preload_next(player);
     }
 
-    fn on_play_pause(&mut self, player: &mut Player, filter: Option<&String>) {
+    fn resume_point(&self, player: &Player) -> Option<ResumePoint> {
+        if !player.has_sink() {
+            return None;
+        }
+        let track = self.status.current.clone()?;
+        Some(ResumePoint {
+            track,
+            position: player.position(),
+            paused: player.is_paused(),
+        })
+    }
+
+    fn on_play_pause(&mut self, player: &mut Player, filter: Option<&Filter>) {
         let queue_rebuilt = if let Some(filter) = filter {
             self.rebuild_queue(Some(filter), QueueOrder::Ordered);
             true
@@ -368,6 +1785,7 @@ impl Crabbox {
         }
 
         let track = self.queue.current_track();
+        self.apply_track_offset(track.as_deref(), player);
 
         let toggle_result = if queue_rebuilt {
             match play_track(track, player, true) {
@@ -380,7 +1798,9 @@ impl Crabbox {
 
         match toggle_result {
             ToggleResult::Started(track) => {
+                self.record_played(&track, Utc::now());
                 self.status.current = Some(track.clone());
+                self.preload_next(player);
             }
             ToggleResult::Stopped => self.status.current = None,
             ToggleResult::Toggled => {}
@@ -388,114 +1808,563 @@ impl Crabbox {
         self.save_state();
     }
 
+    /// Plays `track`, moving on through the queue past tracks that can't be opened.
     fn play_queue_track(&mut self, track: Option<PathBuf>, player: &mut Player) {
-        match play_track(track, player, true) {
+        let max_skips = MAX_UNPLAYABLE_SKIPS.min(self.queue.tracks.len().saturating_sub(1));
+        let mut track = track;
+        let mut skipped = 0;
+        let started = loop {
+            let Some(candidate) = track else {
+                error!("No tracks available to play");
+                break None;
+            };
+            self.apply_track_offset(Some(&candidate), player);
+            let outcome = match self.track_intro_sound.as_deref() {
+                Some(intro) => player.play_with_intro(intro, &candidate, true),
+                None => player.play(&candidate, true),
+            };
+            match outcome {
+                Ok(()) => break Some(candidate),
+                Err(PlayError::Track(err)) if skipped < max_skips => {
+                    warn!(?candidate, "Skipping unplayable track: {err}");
+                    skipped += 1;
+                    track = self.queue.next_track();
+                }
+                Err(err) => {
+                    error!("{err}");
+                    break None;
+                }
+            }
+        };
+
+        match started {
             Some(track) => {
-                self.status.current = Some(track.clone());
+                self.record_played(&track, Utc::now());
+                self.status.current = Some(track);
+                self.preload_next(player);
             }
             None => self.status.current = None,
         }
         self.save_state();
     }
 
-    fn rebuild_queue(&mut self, filter: Option<&str>, order: QueueOrder) {
-        let tracks = self.library.list_tracks(filter.map(str::to_string));
+    /// Starts `startup_sound` on the playback thread's player without waiting for it, so the
+    /// first command stops it instead of playing over it.
+    fn play_startup_sound(&mut self, player: &mut Player) {
+        let Some(sound) = self.startup_sound.as_ref() else {
+            return;
+        };
+        info!("Playing startup sound from {}", sound.display());
+        match player.play(sound, false) {
+            Ok(()) => self.startup_sound_playing = true,
+            Err(err) => error!("Failed to play startup sound {}: {err}", sound.display()),
+        }
+    }
 
-        if tracks.is_empty() {
-            if let Some(filter) = filter {
-                warn!(filter, "Filter matched no tracks");
-            } else {
-                warn!("Library is empty");
+    fn play_sound(&self, path: &Path, volume: f32) -> Result<(), String> {
+        (self.sound_player)(path, volume, self.volume_curve, self.output_device())
+    }
+
+    /// Plays `path` on a background thread, so the crabbox stays unlocked while it plays.
+    fn spawn_sound(&self, name: &'static str, path: &Path, volume: f32) {
+        let sound_player = self.sound_player;
+        let (path, curve) = (path.to_path_buf(), self.volume_curve);
+        let output_device = self.output_device().map(str::to_string);
+        thread::spawn(move || {
+            if let Err(err) = sound_player(&path, volume, curve, output_device.as_deref()) {
+                warn!("Failed to play {name} {}: {err}", path.display());
             }
-        }
+        });
+    }
 
-        self.queue = match order {
-            QueueOrder::Ordered => Queue::from_tracks_ordered(tracks),
-            QueueOrder::Shuffled => Queue::from_tracks_shuffled(tracks),
+    /// Appends the track that follows the current one so it starts without a gap. Skipped
+    /// with a track intro, which has to play between the tracks.
+    fn preload_next(&self, player: &mut Player) {
+        if self.track_intro_sound.is_some() {
+            return;
+        }
+        let Some(next) = self.queue.peek_after_done(self.status.repeat) else {
+            return;
         };
-        self.queue.log();
-        self.status.current = None;
-        self.save_state();
+        if let Err(err) = player.preload(&next) {
+            warn!(?next, "Failed to preload next track: {err}");
+        }
     }
 
-    fn assign_tag(&mut self, id: TagId, command: Option<&str>) {
-        let parsed_command = command
-            .map(str::trim)
-            .filter(|value| !value.is_empty())
-            .map(Command::from_str)
-            .transpose();
+    fn apply_track_offset(&self, track: Option<&Path>, player: &mut Player) {
+        player.set_track_offset(track.map_or(0, |track| self.track_volumes.offset(track)));
+    }
 
-        match parsed_command {
-            Ok(Some(parsed_command)) => {
-                self.tags.insert(id, parsed_command.clone());
-                if let Err(err) = self.persist_tag_mapping(id, Some(&parsed_command)) {
-                    warn!(?id, ?err, "Failed to save tag mapping to config");
-                }
+    /// Returns the scan `cmd` will rebuild the queue from, or `None` if it won't scan.
+    fn pending_scan(&self, cmd: &Command) -> Option<ScanRequest> {
+        let filter = match cmd {
+            Command::Play { filter } if filter.is_some() || self.queue.is_empty() => filter,
+            Command::PlayPause { filter } if filter.is_some() => filter,
+            Command::Shuffle { filter } | Command::Enqueue { filter } => filter,
+            Command::Tag { id } => {
+                return match self.tags.get(id).map(|mapping| &mapping.command) {
+                    Some(Command::Tag { .. }) | None => None,
+                    Some(mapped) => self.pending_scan(mapped),
+                };
             }
-            Ok(None) => {
-                self.tags.remove(&id);
-                if let Err(err) = self.persist_tag_mapping(id, None) {
-                    warn!(?id, ?err, "Failed to remove tag mapping from config");
-                }
+            Command::Sequence(commands) => {
+                return commands
+                    .iter()
+                    .filter(|command| !matches!(command, Command::Tag { .. }))
+                    .find_map(|command| self.pending_scan(command));
             }
-            Err(err) => warn!(?id, command, "Invalid command for tag: {err}"),
-        }
+            _ => return None,
+        };
+        Some(ScanRequest {
+            filter: filter.clone(),
+        })
     }
 
-    fn persist_tag_mapping(&self, id: TagId, command: Option<&Command>) -> Result<(), String> {
-        let config_raw = fs::read_to_string(&self.config_path).map_err(|err| err.to_string())?;
-        let mut document: DocumentMut = config_raw
-            .parse::<DocumentMut>()
-            .map_err(|err| err.to_string())?;
-
-        self.backup_config_file().map_err(|err| err.to_string())?;
-
-        let tags = document.as_table_mut().entry("tags").or_insert_with(table);
-
-        let Some(tags) = tags.as_table_mut() else {
-            return Err("[tags] is not a table".to_string());
+    /// Tracks matching `filter`, from the prescan when it was for the same filter.
+    fn scan(&mut self, filter: Option<&Filter>) -> Vec<PathBuf> {
+        let tracks = match self.prescanned.take() {
+            Some(prescanned) if prescanned.filter.as_ref() == filter => prescanned.tracks,
+            _ => self.library.list_tracks(filter),
         };
 
-        let tag_key = id.to_string();
-        match command {
-            Some(command) => {
-                if let Some(existing) = tags.get_mut(&tag_key) {
-                    if let Some(value_mut) = existing.as_value_mut() {
-                        *value_mut = Value::from(command.to_string());
-                    } else {
-                        *existing = value(command.to_string());
-                    }
-                } else {
-                    tags.insert(&tag_key, value(command.to_string()));
-                }
+        if tracks.is_empty() {
+            if let Some(filter) = filter {
+                warn!(%filter, "Filter matched no tracks");
+            } else {
+                warn!("Library is empty");
             }
-            None => {
-                tags.remove(&tag_key);
+        }
+        tracks
+    }
+
+    /// Replaces the queue with the single track at `path` and plays it.
+    fn play_file(&mut self, path: PathBuf, player: &mut Player) {
+        if !is_library_track(&path, &self.music_directories()) {
+            warn!(
+                ?path,
+                "PLAYFILE path is not a file inside a music directory"
+            );
+            return;
+        }
+
+        player.stop();
+        self.queue = Queue::from_tracks_ordered(vec![path]);
+        self.queue.log();
+        let track = self.queue.current_track();
+        self.play_queue_track(track, player);
+    }
+
+    /// Replaces the queue with a playlist's tracks in order and plays the first. A relative
+    /// `path` is looked up in the music directories; entries that aren't files inside a
+    /// music directory are skipped.
+    fn play_playlist(&mut self, path: &Path, player: &mut Player) {
+        let directories = self.music_directories();
+        let Some(path) = find_library_file(path, &directories) else {
+            warn!(
+                ?path,
+                "PLAYLIST path is not a file inside a music directory"
+            );
+            return;
+        };
+        let entries = match playlist::load(&path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("{err}");
+                return;
+            }
+        };
+
+        let (tracks, skipped): (Vec<PathBuf>, Vec<PathBuf>) = entries
+            .into_iter()
+            .partition(|track| is_library_track(track, &directories));
+        for track in &skipped {
+            warn!(
+                ?track,
+                "Skipping playlist entry outside the music directories"
+            );
+        }
+        if tracks.is_empty() {
+            warn!(?path, "Playlist has no playable tracks");
+            return;
+        }
+
+        info!(?path, count = tracks.len(), "Playing playlist");
+        player.stop();
+        self.queue = Queue::from_tracks_ordered(tracks);
+        self.queue.log();
+        let track = self.queue.current_track();
+        self.play_queue_track(track, player);
+    }
+
+    /// Adds matching tracks to the end of the queue without interrupting playback.
+    fn enqueue(&mut self, filter: Option<&Filter>) {
+        let tracks = self.scan(filter);
+        info!(count = tracks.len(), "Appending tracks to queue");
+        self.queue.append(tracks);
+        self.save_state();
+    }
+
+    fn rebuild_queue(&mut self, filter: Option<&Filter>, order: QueueOrder) {
+        let tracks = self.scan(filter);
+
+        self.queue = match order {
+            QueueOrder::Ordered => Queue::from_tracks_ordered(tracks),
+            QueueOrder::Shuffled => {
+                let seed = self.shuffle_seed.unwrap_or_else(|| rng().random());
+                info!(seed, "Shuffling queue");
+                let mut queue = match self.shuffle_strategy {
+                    ShuffleStrategy::Interleave => {
+                        Queue::from_tracks_interleaved(tracks, seed, |track| {
+                            self.library.artist_key(track)
+                        })
+                    }
+                    _ => Queue::from_tracks_shuffled(tracks, seed),
+                };
+                if self.shuffle_strategy == ShuffleStrategy::AvoidRecent {
+                    queue.move_recent_to_end(&self.recently_played());
+                }
+                if self.debug_shuffle {
+                    self.last_shuffle = Some(ShuffleRecord {
+                        seed,
+                        filter: filter.map(ToString::to_string),
+                        queue: queue.tracks.clone(),
+                    });
+                }
+                queue
+            }
+        };
+        self.queue.log();
+        self.status.current = None;
+        self.save_state();
+    }
+
+    fn assign_tag(&mut self, id: TagId, command: Option<&str>) {
+        let parsed_command = command
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(Command::from_str)
+            .transpose();
+
+        match parsed_command {
+            Ok(Some(parsed_command)) => {
+                // Reassigning the command keeps a volume set in the config.
+                let mapping = TagMapping {
+                    command: parsed_command,
+                    volume: self.tags.get(&id).and_then(|mapping| mapping.volume),
+                };
+                let result = self.persist_tag_mapping(id, Some(&mapping));
+                self.tags.insert(id, mapping);
+                if let Err(err) = &result {
+                    warn!(?id, ?err, "Failed to save tag mapping to config");
+                }
+                self.record_persist_result(result);
+            }
+            Ok(None) => {
+                self.tags.remove(&id);
+                let result = self.persist_tag_mapping(id, None);
+                if let Err(err) = &result {
+                    warn!(?id, ?err, "Failed to remove tag mapping from config");
+                }
+                self.record_persist_result(result);
+            }
+            Err(err) => warn!(?id, command, "Invalid command for tag: {err}"),
+        }
+    }
+
+    /// The command tag `id` runs when scanned.
+    pub fn tag_command(&self, id: TagId) -> Option<&Command> {
+        self.tags.get(&id).map(|mapping| &mapping.command)
+    }
+
+    /// Tag mappings keyed by tag id, in the same shape as a JSON `tags_file`.
+    pub fn tags_json(&self) -> BTreeMap<String, serde_json::Value> {
+        self.tags
+            .iter()
+            .map(|(id, mapping)| (id.to_string(), tag_mapping_json(mapping)))
+            .collect()
+    }
+
+    /// Removes every tag mapping, from `tags_file` and the config's `[tags]` table alike,
+    /// so none come back on restart. Other config sections are left alone.
+    pub fn clear_tags(&mut self) -> Result<(), String> {
+        let count = self.tags.len();
+        self.tags.clear();
+
+        let result = self.persist_cleared_tags();
+        if result.is_ok() {
+            info!(count, "Tag mappings cleared");
+        }
+        self.record_persist_result(result.clone());
+        result
+    }
+
+    fn persist_cleared_tags(&self) -> Result<(), String> {
+        match self.tags_file.as_deref() {
+            Some(path) if is_json_file(path) => {
+                self.backup_file(path).map_err(|err| err.to_string())?;
+                write_atomically(path, b"{}").map_err(|err| err.to_string())?;
+            }
+            Some(path) => self.update_toml_file(path, |document| {
+                document.as_table_mut().clear();
+                Ok(())
+            })?,
+            None => {}
+        }
+
+        let has_inline_tags = fs::read_to_string(&self.config_path)
+            .ok()
+            .and_then(|raw| raw.parse::<DocumentMut>().ok())
+            .is_some_and(|document| document.contains_key("tags"));
+        if self.tags_file.is_some() && !has_inline_tags {
+            return Ok(());
+        }
+        self.update_toml_file(&self.config_path, |document| {
+            document["tags"] = table();
+            Ok(())
+        })
+    }
+
+    fn record_persist_result(&mut self, result: Result<(), String>) {
+        self.config_warning = result
+            .err()
+            .map(|err| format!("Tag change was applied but could not be saved: {err}"));
+    }
+
+    fn persist_tag_mapping(&self, id: TagId, mapping: Option<&TagMapping>) -> Result<(), String> {
+        match self.tags_file.as_deref() {
+            Some(path) if is_json_file(path) => self.persist_tag_mapping_json(path, id, mapping)?,
+            Some(path) => self.persist_tag_mapping_toml(path, None, id, mapping)?,
+            None => {
+                return self.persist_tag_mapping_toml(&self.config_path, Some("tags"), id, mapping);
+            }
+        }
+        self.remove_inline_tag(id)
+    }
+
+    /// Drops `id` from the config's `[tags]` table, which `tags_file` is merged over, so an
+    /// old inline entry doesn't come back on restart.
+    fn remove_inline_tag(&self, id: TagId) -> Result<(), String> {
+        let inline_keys = |document: &DocumentMut| -> Vec<String> {
+            document
+                .get("tags")
+                .and_then(|tags| tags.as_table_like())
+                .map(|tags| {
+                    tags.iter()
+                        .filter(|(key, _)| TagId::from_str(key).ok() == Some(id))
+                        .map(|(key, _)| key.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let has_inline_tag = fs::read_to_string(&self.config_path)
+            .ok()
+            .and_then(|raw| raw.parse::<DocumentMut>().ok())
+            .is_some_and(|document| !inline_keys(&document).is_empty());
+        if !has_inline_tag {
+            return Ok(());
+        }
+        self.update_toml_file(&self.config_path, |document| {
+            let keys = inline_keys(document);
+            if let Some(tags) = document
+                .get_mut("tags")
+                .and_then(|tags| tags.as_table_like_mut())
+            {
+                for key in keys {
+                    tags.remove(&key);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Updates a tag entry in a TOML document, either inside `table_key` or at the root.
+    fn persist_tag_mapping_toml(
+        &self,
+        path: &Path,
+        table_key: Option<&str>,
+        id: TagId,
+        mapping: Option<&TagMapping>,
+    ) -> Result<(), String> {
+        self.update_toml_file(path, |document| {
+            let tags = match table_key {
+                Some(key) => {
+                    let Some(tags) = document
+                        .as_table_mut()
+                        .entry(key)
+                        .or_insert_with(table)
+                        .as_table_mut()
+                    else {
+                        return Err(format!("[{key}] is not a table"));
+                    };
+                    tags
+                }
+                None => document.as_table_mut(),
+            };
+
+            set_toml_value(tags, &id.to_string(), mapping.map(tag_mapping_toml));
+            Ok(())
+        })
+    }
+
+    /// Backs up the TOML document at `path`, applies `edit`, and writes it back with
+    /// comments and formatting intact.
+    fn update_toml_file(
+        &self,
+        path: &Path,
+        edit: impl FnOnce(&mut DocumentMut) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let raw = if path.exists() {
+            fs::read_to_string(path).map_err(|err| err.to_string())?
+        } else {
+            String::new()
+        };
+        let mut document: DocumentMut =
+            raw.parse::<DocumentMut>().map_err(|err| err.to_string())?;
+
+        self.backup_file(path).map_err(|err| err.to_string())?;
+        edit(&mut document)?;
+
+        write_atomically(path, document.to_string().as_bytes()).map_err(|err| err.to_string())
+    }
+
+    fn persist_tag_mapping_json(
+        &self,
+        path: &Path,
+        id: TagId,
+        mapping: Option<&TagMapping>,
+    ) -> Result<(), String> {
+        let mut tags: BTreeMap<String, serde_json::Value> = if path.exists() {
+            let raw = fs::read_to_string(path).map_err(|err| err.to_string())?;
+            serde_json::from_str(&raw).map_err(|err| err.to_string())?
+        } else {
+            BTreeMap::new()
+        };
+
+        self.backup_file(path).map_err(|err| err.to_string())?;
+
+        match mapping {
+            Some(mapping) => {
+                tags.insert(id.to_string(), tag_mapping_json(mapping));
+            }
+            None => {
+                tags.remove(&id.to_string());
             }
         }
 
-        fs::write(&self.config_path, document.to_string()).map_err(|err| err.to_string())
+        let data = serde_json::to_string_pretty(&tags).map_err(|err| err.to_string())?;
+        write_atomically(path, data.as_bytes()).map_err(|err| err.to_string())
     }
 
-    fn backup_config_file(&self) -> Result<(), std::io::Error> {
+    fn backup_file(&self, path: &Path) -> Result<(), std::io::Error> {
         let Some(backup_dir) = self.config_backup_dir.as_ref() else {
             return Ok(());
         };
 
+        if !path.exists() {
+            return Ok(());
+        }
+
         fs::create_dir_all(backup_dir)?;
-        let filename = self.config_path.file_name().map_or_else(
+        let filename = path.file_name().map_or_else(
             || String::from("config.toml"),
             |name| name.to_string_lossy().into_owned(),
         );
-        let timestamp = Utc::now().format("%Y%m%dT%H%M%S");
+        let timestamp = Utc::now().format(BACKUP_TIMESTAMP_FORMAT);
         let backup_name = format!("{filename}.{timestamp}");
         let backup_path = backup_dir.join(backup_name);
 
-        fs::copy(&self.config_path, backup_path)?;
-        Ok(())
+        fs::copy(path, backup_path)?;
+        prune_backups(backup_dir, path, self.config_backup_keep)
+    }
+
+    /// Backups of the config and tags file, newest first.
+    pub fn config_backups(&self) -> Vec<ConfigBackup> {
+        let Some(backup_dir) = self.config_backup_dir.as_ref() else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir(backup_dir) else {
+            return Vec::new();
+        };
+        let files: Vec<&Path> = std::iter::once(self.config_path.as_path())
+            .chain(self.tags_file.as_deref())
+            .collect();
+
+        let mut backups: Vec<ConfigBackup> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter_map(|name| {
+                files.iter().find_map(|file| {
+                    let created = backup_timestamp(&name, file)?;
+                    Some(ConfigBackup {
+                        name: name.clone(),
+                        file: file.to_path_buf(),
+                        created,
+                    })
+                })
+            })
+            .collect();
+        backups.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| a.name.cmp(&b.name)));
+        backups
+    }
+
+    /// Copies the backup `name` over the file it was taken from and reloads tags and
+    /// favorites. The current file is backed up first, so a restore can itself be undone;
+    /// a backup that doesn't load as a valid config is rolled back.
+    pub fn restore_config_backup(&mut self, name: &str) -> Result<(), String> {
+        let backup = self
+            .config_backups()
+            .into_iter()
+            .find(|backup| backup.name == name)
+            .ok_or_else(|| format!("No config backup named {name}"))?;
+        let backup_dir = self.config_backup_dir.as_ref().expect("listed backups");
+        let restored =
+            fs::read_to_string(backup_dir.join(&backup.name)).map_err(|err| err.to_string())?;
+        let previous = fs::read_to_string(&backup.file).ok();
+
+        self.backup_file(&backup.file)
+            .map_err(|err| err.to_string())?;
+        write_atomically(&backup.file, restored.as_bytes()).map_err(|err| err.to_string())?;
+
+        match Config::load(&self.config_path) {
+            Ok(config) => {
+                info!(backup = name, file = ?backup.file, "Config backup restored");
+                self.tags = config.tags;
+                self.favorites = config.favorites;
+                self.config_warning = None;
+                Ok(())
+            }
+            Err(err) => {
+                if let Some(previous) = previous {
+                    let _ = write_atomically(&backup.file, previous.as_bytes());
+                }
+                Err(format!("Backup {name} does not load: {err}"))
+            }
+        }
+    }
+
+    /// Updates the playtime clock and stops playback once the daily limit is used up.
+    fn enforce_playtime(&mut self, player: &mut Player) {
+        let (today, now) = (today(), Instant::now());
+        self.playtime
+            .set_playing(player.has_sink() && !player.is_paused(), today, now);
+
+        if !player.has_sink() || !self.playtime.is_exhausted(today, now) {
+            return;
+        }
+
+        info!("Daily playtime limit reached; stopping playback");
+        player.stop();
+        self.status.paused = false;
+        self.playtime.set_playing(false, today, now);
+        self.status.current = None;
+        self.save_state();
+        if let Some(sound) = self.limit_sound.as_ref() {
+            self.spawn_sound("limit sound", sound, self.status.volume);
+        }
     }
 
-    fn save_state(&self) {
+    /// Writes the queue, position and playtime to the state file, if one is configured.
+    pub fn save_state(&self) {
         let Some(path) = self.state_file.as_ref() else {
             return;
         };
@@ -503,6 +2372,11 @@ impl Crabbox {
         let state = State {
             queue: self.queue.tracks.clone(),
             position: self.queue.current,
+            playtime_date: Some(self.playtime.date().to_string()),
+            playtime_secs: self.playtime.used(Instant::now()).as_secs(),
+            speed: Some(self.status.speed),
+            last_tag: self.status.last_tag,
+            repeat_mode: Some(self.status.repeat),
         };
 
         if let Err(err) = state.save(path) {
@@ -516,45 +2390,299 @@ async fn process_commands(
     crabbox: Arc<Mutex<Crabbox>>,
     default_volume: f32,
 ) {
-    let sender = {
+    let (
+        sender,
+        buffered_read_max_bytes,
+        volume_curve,
+        output_device,
+        mixer,
+        speed,
+        heartbeat,
+        mut debounce,
+    ) = {
         let crabbox = crabbox.lock().expect("failed to lock crabbox");
-        crabbox.command_tx.clone()
+        (
+            crabbox.command_tx.clone(),
+            crabbox.buffered_read_max_bytes,
+            crabbox.volume_curve,
+            crabbox.output_device.clone(),
+            crabbox.mixer.clone(),
+            crabbox.status.speed,
+            crabbox.heartbeat(),
+            StatusDebounce::new(crabbox.status_debounce),
+        )
     };
     let mut player = Player::new(default_volume, sender);
+    player.set_buffered_read(buffered_read_max_bytes);
+    player.set_volume_curve(volume_curve);
+    player.set_output_device(output_device);
+    match mixer.open() {
+        Ok(control) => player.set_volume_control(control),
+        Err(err) => error!("{err}; falling back to software volume"),
+    }
+    player.set_speed(speed);
+    if let Ok(mut crabbox) = crabbox.lock() {
+        crabbox.play_startup_sound(&mut player);
+    }
 
-    while let Some(cmd) = rx.recv().await {
+    loop {
+        heartbeat.beat();
+        // Wake up periodically even without commands so the playtime limit is enforced
+        // mid-track, and when held-back status changes are due.
+        let wait = debounce.due().map_or(PLAYTIME_CHECK_INTERVAL, |due| {
+            due.saturating_duration_since(Instant::now())
+                .min(PLAYTIME_CHECK_INTERVAL)
+        });
+        let cmd = match timeout(wait, rx.recv()).await {
+            Ok(Some(cmd)) => Some(cmd),
+            Ok(None) => break,
+            Err(_) => None,
+        };
+        let prescanned = cmd
+            .as_ref()
+            .and_then(|cmd| prescan(&crabbox, cmd, Library::list_tracks));
         if let Ok(mut crabbox) = crabbox.lock() {
-            crabbox.process_command(cmd, &mut player);
+            let before = crabbox.playback_view(&player);
+            if let Some(cmd) = cmd {
+                crabbox.prescanned = prescanned;
+                crabbox.process_command(cmd, &mut player);
+                crabbox.prescanned = None;
+            }
+            crabbox.enforce_playtime(&mut player);
+            let after = crabbox.playback_view(&player);
+            if let Some(from) = debounce.settle(before, &after, Instant::now()) {
+                crabbox.publish_changes(&from, &after);
+            }
         }
     }
 }
 
-fn collect_music_files(directories: &[PathBuf]) -> Vec<PathBuf> {
-    let mut files = Vec::new();
+/// Scans the library for `cmd` without holding the crabbox lock, so web requests
+/// aren't blocked while a large library is walked.
+fn prescan(
+    crabbox: &Mutex<Crabbox>,
+    cmd: &Command,
+    scan: impl FnOnce(&Library, Option<&Filter>) -> Vec<PathBuf>,
+) -> Option<PrescannedTracks> {
+    let (library, ScanRequest { filter }) = {
+        let crabbox = crabbox.lock().ok()?;
+        let request = crabbox.pending_scan(cmd)?;
+        (crabbox.library.clone(), request)
+    };
 
-    for dir in directories {
-        for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
-            if !entry.file_type().is_file() {
-                continue;
-            }
+    let tracks = scan(&library, filter.as_ref());
+    Some(PrescannedTracks { filter, tracks })
+}
 
-            if let Some(ext) = entry.path().extension().and_then(|os| os.to_str())
-                && is_music_extension(ext)
-            {
-                files.push(entry.into_path());
+fn seek(target: SeekTarget, player: &Player) {
+    let position = target.resolve(player.position());
+    match player.seek(position) {
+        Ok(()) => info!(?position, "Seeked within current track"),
+        Err(err) => warn!("{err}"),
+    }
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// The speed, last tag and repeat mode saved in `state`, at `volume`.
+fn restore_status(state: Option<&State>, volume: f32) -> PlaybackStatus {
+    PlaybackStatus {
+        volume,
+        speed: state.and_then(|state| state.speed).unwrap_or(1.0),
+        last_tag: state.and_then(|state| state.last_tag),
+        repeat: state
+            .and_then(|state| state.repeat_mode)
+            .unwrap_or_default(),
+        ..PlaybackStatus::default()
+    }
+}
+
+fn restore_playtime(
+    limit_minutes: Option<u64>,
+    state: Option<&State>,
+    today: NaiveDate,
+) -> Playtime {
+    let used = state
+        .filter(|state| {
+            state
+                .playtime_date
+                .as_deref()
+                .and_then(|date| date.parse::<NaiveDate>().ok())
+                == Some(today)
+        })
+        .map_or(Duration::ZERO, |state| {
+            Duration::from_secs(state.playtime_secs)
+        });
+    let limit = limit_minutes.map(Duration::from_mins);
+    Playtime::new(limit, today, used)
+}
+
+/// Sets `key` to `new_value`, keeping the comments around an existing entry, or removes it
+/// when `new_value` is `None`.
+fn set_toml_value(table: &mut Table, key: &str, new_value: Option<Value>) {
+    let Some(new_value) = new_value else {
+        table.remove(key);
+        return;
+    };
+    match table.get_mut(key) {
+        Some(existing) => match existing.as_value_mut() {
+            Some(value_mut) => *value_mut = new_value,
+            None => *existing = value(new_value),
+        },
+        None => {
+            table.insert(key, value(new_value));
+        }
+    }
+}
+
+/// A tag entry for a TOML tags table: a plain command string unless a volume is set.
+fn tag_mapping_toml(mapping: &TagMapping) -> Value {
+    let command = mapping.command.to_string();
+    match mapping.volume {
+        Some(volume) => {
+            let mut entry = InlineTable::new();
+            entry.insert("command", Value::from(command));
+            entry.insert("volume", Value::from(f32_to_f64(volume)));
+            Value::InlineTable(entry)
+        }
+        None => Value::from(command),
+    }
+}
+
+fn tag_mapping_json(mapping: &TagMapping) -> serde_json::Value {
+    let command = mapping.command.to_string();
+    match mapping.volume {
+        Some(volume) => serde_json::json!({ "command": command, "volume": volume }),
+        None => serde_json::Value::String(command),
+    }
+}
+
+/// Widens through the shortest decimal form so `0.3` is written as `0.3`, not `0.30000001`.
+fn f32_to_f64(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(f64::from(value))
+}
+
+/// Whether `path` is an existing file, or a track of an existing cue sheet, inside one of
+/// the music directories.
+pub fn is_library_track(path: &Path, directories: &[PathBuf]) -> bool {
+    let exists = match cue::split_track_path(path) {
+        Some((sheet, _)) => sheet.is_file(),
+        None => path.is_file(),
+    };
+    exists
+        && !path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        && directories.iter().any(|dir| path.starts_with(dir))
+}
+
+/// Resolves a file named in a command: absolute paths must lie in a music directory,
+/// relative ones are tried against each music directory in turn.
+fn find_library_file(path: &Path, directories: &[PathBuf]) -> Option<PathBuf> {
+    if path.is_absolute() {
+        return is_library_track(path, directories).then(|| path.to_path_buf());
+    }
+    directories
+        .iter()
+        .map(|dir| dir.join(path))
+        .find(|candidate| is_library_track(candidate, directories))
+}
+
+/// When `name` is a backup of `file`, the time it was taken.
+fn backup_timestamp(name: &str, file: &Path) -> Option<NaiveDateTime> {
+    let filename = file.file_name()?.to_str()?;
+    let timestamp = name.strip_prefix(filename)?.strip_prefix('.')?;
+    NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).ok()
+}
+
+/// Writes `contents` to a temporary sibling of `path` and renames it into place, so a crash
+/// mid-write leaves either the old file or the new one, never a truncated mix.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{name}.tmp"));
+
+    let result = fs::File::create(&temp)
+        .and_then(|mut file| {
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
             }
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Deletes all but the newest `keep` backups of `file`, leaving other files in the
+/// directory alone.
+fn prune_backups(backup_dir: &Path, file: &Path, keep: usize) -> Result<(), std::io::Error> {
+    let mut backups: Vec<(NaiveDateTime, PathBuf)> = fs::read_dir(backup_dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let created = backup_timestamp(entry.file_name().to_str()?, file)?;
+            Some((created, entry.path()))
+        })
+        .collect();
+    backups.sort_by(|a, b| b.cmp(a));
+
+    for (_, old) in backups.into_iter().skip(keep) {
+        debug!(path = ?old, "Pruning old config backup");
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+fn load_state(path: &Path) -> Option<State> {
+    if !path.exists() {
+        return None;
+    }
+    match State::load(path) {
+        Ok(state) => {
+            info!(?path, "Restored playback state from file");
+            Some(state)
+        }
+        Err(err) => {
+            warn!(?path, "Failed to load playback state: {err}");
+            None
         }
     }
+}
+
+fn collect_music_files(directories: &[MusicDirectory]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = directories
+        .iter()
+        .flat_map(|dir| source::for_directory(dir).list_tracks())
+        .collect();
 
     files.sort();
     files
 }
 
-fn is_music_extension(ext: &str) -> bool {
-    matches!(
-        ext.to_ascii_lowercase().as_str(),
-        "mp3" | "flac" | "wav" | "ogg" | "m4a" | "aac" | "opus" | "alac"
-    )
+/// Whether `path` can be saved: config writes replace the file with a sibling, so both
+/// the file (if it exists) and its directory must be writable.
+fn is_writable(path: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file = path.exists().then_some(path);
+
+    std::iter::once(parent).chain(file).all(|target| {
+        let Ok(c_path) = CString::new(target.as_os_str().as_bytes()) else {
+            return false;
+        };
+        unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+    })
 }
 
 fn shutdown_now() -> std::io::Result<()> {
@@ -578,88 +2706,663 @@ mod tests {
         Crabbox {
             library: Library {
                 directories: vec![],
+                ..Library::default()
             },
             queue: Queue::empty(),
             tags: HashMap::new(),
+            tag_history: VecDeque::new(),
+            tag_history_max_age: TimeDelta::hours(1),
+            history: VecDeque::new(),
+            favorites: Vec::new(),
+            track_volumes: TrackVolumes::load(None),
             command_tx: tx,
+            events: broadcast::channel(STATUS_EVENT_CAPACITY).0,
             status: PlaybackStatus::default(),
+            startup_sound: None,
+            startup_sound_playing: false,
             shutdown_sound: None,
+            limit_sound: None,
+            track_intro_sound: None,
+            sound_player: play_blocking,
+            interjection: None,
             default_volume: 1.0,
+            playtime: Playtime::new(None, today(), Duration::ZERO),
+            buffered_read_max_bytes: None,
+            volume_curve: VolumeCurve::Linear,
+            output_device: None,
+            mixer: MixerConfig::default(),
+            fade_out: Duration::ZERO,
+            status_debounce: Duration::ZERO,
+            shuffle_seed: None,
+            shuffle_strategy: ShuffleStrategy::Random,
+            debug_shuffle: false,
+            last_shuffle: None,
+            prescanned: None,
             state_file: None,
             config_path,
             config_backup_dir: backup_dir,
+            config_backup_keep: 20,
+            tags_file: None,
+            config_warning: None,
+            heartbeat: Heartbeat::new(),
+            sleep_timer: StopTimer::default(),
+            idle_stop: None,
+            idle_timer: StopTimer::default(),
         }
     }
 
-    #[test]
-    fn list_tracks_returns_sorted_paths() {
-        let tmp = tempdir().expect("tempdir");
-        let dir_a = tmp.path().join("a_dir");
-        let dir_b = tmp.path().join("b_dir");
-        fs::create_dir_all(&dir_a).expect("create dir a");
-        fs::create_dir_all(&dir_b).expect("create dir b");
+    #[tokio::test(start_paused = true)]
+    async fn sleep_timer_sends_stop_when_it_expires() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut timer = StopTimer::default();
 
-        let path_a = dir_a.join("track.mp3");
-        let path_b = dir_b.join("track.mp3");
-        fs::write(&path_b, "audio").expect("write track_b");
-        fs::write(&path_a, "audio").expect("write track_a");
+        timer.arm("Sleep timer", Duration::from_mins(20), tx);
+        assert!(timer.remaining(time::Instant::now()).is_some());
+        time::sleep(Duration::from_mins(19)).await;
+        assert!(rx.try_recv().is_err());
+        time::sleep(Duration::from_mins(2)).await;
 
-        let library = Library {
-            directories: vec![dir_b, dir_a],
-        };
+        assert_eq!(rx.try_recv().ok(), Some(Command::Stop));
+        assert_eq!(timer.remaining(time::Instant::now()), None);
+    }
 
-        let tracks = library.list_tracks(None);
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_or_rearmed_sleep_timer_does_not_fire() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut timer = StopTimer::default();
 
-        let mut expected = vec![path_a, path_b];
-        expected.sort();
+        timer.arm("Sleep timer", Duration::from_mins(1), tx.clone());
+        timer.cancel();
+        assert_eq!(timer.remaining(time::Instant::now()), None);
 
-        assert_eq!(tracks, expected);
+        timer.arm("Sleep timer", Duration::from_mins(1), tx.clone());
+        timer.arm("Sleep timer", Duration::from_mins(10), tx);
+        time::sleep(Duration::from_mins(5)).await;
+
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            timer.remaining(time::Instant::now()),
+            Some(Duration::from_mins(5))
+        );
+        timer.cancel();
+        time::sleep(Duration::from_mins(10)).await;
+        assert!(rx.try_recv().is_err());
     }
 
-    #[test]
-    fn persist_tag_mapping_creates_backup_before_saving() {
-        let tmp = tempdir().expect("tempdir");
-        let config_path = tmp.path().join("config.toml");
-        let backup_dir = tmp.path().join("backups");
-        let initial_config = r#"[[music]]
-dir = "/music"
+    #[tokio::test(start_paused = true)]
+    async fn resetting_sleep_replaces_the_earlier_countdown() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let mut crabbox = crabbox_with_config(PathBuf::from("/tmp/config.toml"), None);
+        crabbox.command_tx = tx;
+        let mut player = Player::new(1.0, crabbox.sender());
+        let sleep = |minutes| Command::SleepTimer {
+            duration: Duration::from_mins(minutes),
+        };
 
-[server]
-web = "0.0.0.0:8080"
+        crabbox.process_command(sleep(10), &mut player);
+        crabbox.process_command(sleep(30), &mut player);
+        time::sleep(Duration::from_mins(20)).await;
+        assert!(
+            rx.try_recv().is_err(),
+            "the 10 minute countdown was replaced"
+        );
 
-[tags]
-0A1B2C3D = "PLAY"
-"#;
+        time::sleep(Duration::from_mins(11)).await;
+        assert_eq!(rx.try_recv().ok(), Some(Command::Stop));
+        assert!(rx.try_recv().is_err());
 
-        fs::write(&config_path, initial_config).expect("write config");
+        crabbox.process_command(sleep(5), &mut player);
+        crabbox.process_command(sleep(0), &mut player);
+        time::sleep(Duration::from_mins(10)).await;
+        assert!(rx.try_recv().is_err(), "SLEEP 0 cancels the countdown");
+    }
 
-        let crabbox = crabbox_with_config(config_path.clone(), Some(backup_dir.clone()));
+    #[tokio::test(start_paused = true)]
+    async fn idle_timer_restarts_on_each_command_while_paused() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let mut crabbox = crabbox_with_config(PathBuf::from("/tmp/config.toml"), None);
+        crabbox.command_tx = tx;
+        crabbox.idle_stop = Some(Duration::from_mins(30));
+        crabbox.status.current = Some(PathBuf::from("/music/a.mp3"));
+        let mut player = Player::new(1.0, crabbox.sender());
+        player.attach_test_sink();
 
-        crabbox
-            .persist_tag_mapping(
-                TagId::from_hex_str("0A1B2C3D").unwrap(),
-                Some(&Command::Stop),
-            )
-            .expect("persist tag");
+        crabbox.process_command(Command::Pause, &mut player);
+        assert!(crabbox.snapshot().idle_remaining.is_some());
+        time::sleep(Duration::from_mins(20)).await;
+        crabbox.process_command(Command::VolumeDown, &mut player);
+        time::sleep(Duration::from_mins(20)).await;
+        assert!(
+            rx.try_recv().is_err(),
+            "activity should restart the countdown"
+        );
 
-        let backups: Vec<_> = fs::read_dir(&backup_dir)
-            .expect("read backup dir")
-            .map(|entry| entry.expect("dir entry").path())
-            .collect();
+        time::sleep(Duration::from_mins(11)).await;
+        assert_eq!(rx.try_recv().ok(), Some(Command::Stop));
 
-        assert_eq!(backups.len(), 1, "expected exactly one backup file");
+        crabbox.process_command(Command::Stop, &mut player);
+        assert_eq!(crabbox.snapshot().idle_remaining, None);
+    }
 
-        let backup_contents = fs::read_to_string(&backups[0]).expect("backup contents");
-        assert_eq!(backup_contents, initial_config);
+    fn view(current: &str, paused: bool) -> PlaybackView {
+        PlaybackView {
+            current: Some(PathBuf::from(current)),
+            volume: 0.5,
+            muted: false,
+            paused,
+        }
+    }
 
-        let updated_config = fs::read_to_string(config_path).expect("updated config");
-        assert!(updated_config.contains("0A1B2C3D = \"STOP\""));
+    #[test]
+    fn debounce_publishes_the_settled_state_of_a_burst() {
+        let start = Instant::now();
+        let window = Duration::from_millis(300);
+        let mut debounce = StatusDebounce::new(window);
+
+        assert_eq!(
+            debounce.settle(view("/a", false), &view("/a", false), start),
+            None
+        );
+        assert_eq!(debounce.due(), None);
+
+        assert_eq!(
+            debounce.settle(view("/a", false), &view("/b", false), start),
+            None
+        );
+        assert_eq!(debounce.due(), Some(start + window));
+        let later = start + Duration::from_millis(100);
+        assert_eq!(
+            debounce.settle(view("/b", false), &view("/c", false), later),
+            None
+        );
+        assert_eq!(debounce.due(), Some(start + window), "not pushed back");
+
+        let due = start + window;
+        assert_eq!(
+            debounce.settle(view("/c", false), &view("/c", false), due),
+            Some(view("/a", false))
+        );
+        assert_eq!(debounce.due(), None);
     }
 
     #[test]
-    fn persist_tag_mapping_preserves_existing_tags_table() {
+    fn debounce_without_a_window_publishes_each_change() {
+        let now = Instant::now();
+        let mut debounce = StatusDebounce::default();
+
+        assert_eq!(
+            debounce.settle(view("/a", false), &view("/a", true), now),
+            Some(view("/a", false))
+        );
+        assert_eq!(debounce.due(), None);
+    }
+
+    #[test]
+    fn debounced_burst_reaches_subscribers_as_one_update() {
+        let crabbox = crabbox_with_config(PathBuf::from("/tmp/config.toml"), None);
+        let mut events = crabbox.subscribe();
+        let start = Instant::now();
+        let mut debounce = StatusDebounce::new(Duration::from_millis(300));
+
+        for (step, (before, after)) in [("/a", "/b"), ("/b", "/c"), ("/c", "/d")]
+            .into_iter()
+            .enumerate()
+        {
+            let now = start + Duration::from_millis(100) * u32::try_from(step).unwrap();
+            if let Some(from) = debounce.settle(view(before, false), &view(after, false), now) {
+                crabbox.publish_changes(&from, &view(after, false));
+            }
+        }
+        assert!(events.try_recv().is_err(), "changes are held back");
+
+        let due = debounce.due().unwrap();
+        let from = debounce.settle(view("/d", false), &view("/d", true), due);
+        crabbox.publish_changes(&from.unwrap(), &view("/d", true));
+        assert_eq!(
+            events.try_recv().ok(),
+            Some(StatusEvent::TrackStarted {
+                path: "/d".to_string()
+            })
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lists_and_cancels_pending_timers() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let mut crabbox = crabbox_with_config(PathBuf::from("/tmp/config.toml"), None);
+        crabbox.command_tx = tx;
+        crabbox.idle_stop = Some(Duration::from_mins(1));
+        crabbox.status.current = Some(PathBuf::from("/music/a.mp3"));
+        let mut player = Player::new(1.0, crabbox.sender());
+        player.attach_test_sink();
+        assert!(crabbox.timers().is_empty());
+
+        crabbox.process_command(Command::Pause, &mut player);
+        crabbox.process_command(
+            Command::SleepTimer {
+                duration: Duration::from_mins(10),
+            },
+            &mut player,
+        );
+        let kinds: Vec<TimerKind> = crabbox.timers().into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![TimerKind::Sleep, TimerKind::Idle]);
+        assert!(crabbox.timers()[0].1 > Duration::from_mins(9));
+
+        assert_eq!(
+            crabbox.cancel_timers(Some(TimerKind::Idle)),
+            vec![TimerKind::Idle]
+        );
+        assert_eq!(crabbox.snapshot().idle_remaining, None);
+        assert!(crabbox.snapshot().sleep_remaining.is_some());
+        time::sleep(Duration::from_mins(2)).await;
+        assert!(
+            rx.try_recv().is_err(),
+            "a cancelled idle timer must not fire"
+        );
+
+        assert_eq!(crabbox.cancel_timers(None), vec![TimerKind::Sleep]);
+        assert!(crabbox.timers().is_empty());
+        assert!(crabbox.cancel_timers(None).is_empty());
+    }
+
+    #[test]
+    fn heartbeat_goes_stale_without_beats() {
+        let heartbeat = Heartbeat::new();
+        heartbeat.beat();
+        assert!(heartbeat.is_alive());
+
+        let stalled = Heartbeat {
+            started: Instant::now()
+                .checked_sub(HEARTBEAT_TIMEOUT * 2)
+                .expect("uptime exceeds heartbeat timeout"),
+            last_beat_ms: Arc::default(),
+        };
+        assert!(!stalled.is_alive());
+        stalled.beat();
+        assert!(stalled.is_alive());
+    }
+
+    #[test]
+    fn library_is_scanned_after_first_listing() {
+        let tmp = tempdir().expect("tempdir");
+        let library = Library::new(
+            &[MusicDirectory::new(tmp.path().to_path_buf())],
+            Duration::ZERO,
+        );
+
+        assert!(!library.is_scanned());
+        library.list_tracks(None);
+        assert!(library.is_scanned());
+        assert!(library.clone().is_scanned());
+    }
+
+    #[test]
+    fn list_tracks_returns_sorted_paths() {
+        let tmp = tempdir().expect("tempdir");
+        let dir_a = tmp.path().join("a_dir");
+        let dir_b = tmp.path().join("b_dir");
+        fs::create_dir_all(&dir_a).expect("create dir a");
+        fs::create_dir_all(&dir_b).expect("create dir b");
+
+        let path_a = dir_a.join("track.mp3");
+        let path_b = dir_b.join("track.mp3");
+        fs::write(&path_b, "audio").expect("write track_b");
+        fs::write(&path_a, "audio").expect("write track_a");
+
+        let library = Library {
+            directories: vec![MusicDirectory::new(dir_b), MusicDirectory::new(dir_a)],
+            ..Library::default()
+        };
+
+        let tracks = library.list_tracks(None);
+
+        let mut expected = vec![path_a, path_b];
+        expected.sort();
+
+        assert_eq!(tracks, expected);
+    }
+
+    #[test]
+    fn list_tracks_respects_non_recursive_directories() {
+        let tmp = tempdir().expect("tempdir");
+        let top = tmp.path().join("kick.wav");
+        let nested = tmp.path().join("drums/snare.wav");
+        fs::create_dir_all(nested.parent().unwrap()).expect("create dir");
+        fs::write(&top, "audio").expect("write top");
+        fs::write(&nested, "audio").expect("write nested");
+
+        let library = Library {
+            directories: vec![MusicDirectory {
+                recursive: false,
+                ..MusicDirectory::new(tmp.path().to_path_buf())
+            }],
+            ..Library::default()
+        };
+
+        assert_eq!(library.list_tracks(None), vec![top]);
+    }
+
+    #[test]
+    fn list_tracks_uses_extension_overrides() {
+        let tmp = tempdir().expect("tempdir");
+        let wma = tmp.path().join("old.WMA");
+        let mp3 = tmp.path().join("new.mp3");
+        fs::write(&wma, "audio").expect("write wma");
+        fs::write(&mp3, "audio").expect("write mp3");
+
+        let library = Library {
+            directories: vec![MusicDirectory {
+                extensions: Some(vec![".wma".to_string()]),
+                ..MusicDirectory::new(tmp.path().to_path_buf())
+            }],
+            ..Library::default()
+        };
+
+        assert_eq!(library.list_tracks(None), vec![wma]);
+    }
+
+    #[test]
+    fn cached_library_picks_up_new_files_after_rescan() {
+        let tmp = tempdir().expect("tempdir");
+        let first = tmp.path().join("first.mp3");
+        let second = tmp.path().join("second.mp3");
+        fs::write(&first, "audio").expect("write first");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library = Library::new(
+            &[MusicDirectory::new(tmp.path().to_path_buf())],
+            Duration::from_hours(1),
+        );
+        let web_view = crabbox.library.clone();
+        assert_eq!(web_view.list_tracks(None), vec![first.clone()]);
+
+        fs::write(&second, "audio").expect("write second");
+        assert_eq!(web_view.list_tracks(None), vec![first.clone()]);
+
+        let mut player = Player::new(1.0, crabbox.sender());
+        crabbox.process_command(Command::Rescan, &mut player);
+
+        assert_eq!(web_view.list_tracks(None), vec![first, second]);
+    }
+
+    #[test]
+    fn check_filters_rejects_filters_without_matches() {
+        let tmp = tempdir().expect("tempdir");
+        fs::create_dir_all(tmp.path().join("kids")).expect("kids dir");
+        fs::write(tmp.path().join("kids/song.mp3"), "audio").expect("write track");
+        let library = Library::new(
+            &[MusicDirectory::new(tmp.path().to_path_buf())],
+            Duration::ZERO,
+        );
+        let check = |command: &str| library.check_filters(&Command::from_str(command).unwrap());
+
+        assert_eq!(check("SHUFFLE **/kids/**"), Ok(()));
+        assert_eq!(
+            check("SHUFFLE nonexistent/*"),
+            Err("Filter nonexistent/* matches 0 tracks".to_string())
+        );
+        assert!(check("VOLUME 0.3; PLAY **/nope/**").is_err());
+        assert_eq!(check("STOP"), Ok(()));
+        assert_eq!(check("NEXT"), Ok(()));
+        assert_eq!(check("PLAY"), Ok(()));
+    }
+
+    #[test]
+    fn library_from_config_lists_tracks_like_the_server() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        for name in ["rock/a.mp3", "rock/b.flac", "jazz/c.mp3", "rock/notes.txt"] {
+            let path = music.join(name);
+            fs::create_dir_all(path.parent().unwrap()).expect("create dir");
+            fs::write(path, "audio").expect("write track");
+        }
+        let config_path = tmp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!("[[music]]\ndir = {music:?}\n\n[server]\nweb = \"127.0.0.1:8080\"\n"),
+        )
+        .expect("write config");
+        let library = Library::from_config(&Config::load(&config_path).expect("load config"));
+
+        assert_eq!(
+            library.list_tracks(Some(&Filter::parse("**/rock/*"))),
+            vec![music.join("rock/a.mp3"), music.join("rock/b.flac")]
+        );
+        assert_eq!(library.list_tracks(None).len(), 3);
+    }
+
+    #[test]
+    fn filters_match_paths_relative_to_each_music_directory() {
+        let tmp = tempdir().expect("tempdir");
+        let (first, second) = (tmp.path().join("first"), tmp.path().join("second"));
+        for path in [
+            first.join("rock/a.mp3"),
+            second.join("rock/b.mp3"),
+            second.join("jazz/rock/c.mp3"),
+        ] {
+            fs::create_dir_all(path.parent().unwrap()).expect("create dir");
+            fs::write(path, "audio").expect("write track");
+        }
+        let library = Library::new(
+            &[
+                MusicDirectory::new(first.clone()),
+                MusicDirectory::new(second.clone()),
+            ],
+            Duration::ZERO,
+        );
+        let list = |filter: &str| library.list_tracks(Some(&Filter::parse(filter)));
+
+        assert_eq!(
+            list("rock/*"),
+            vec![first.join("rock/a.mp3"), second.join("rock/b.mp3")]
+        );
+        assert_eq!(list("**/rock/*").len(), 3);
+        assert_eq!(list("* !rock/*"), Vec::<PathBuf>::new());
+        // Absolute patterns still pick out one root.
+        assert_eq!(
+            list(&format!("{}/**", second.display())),
+            vec![second.join("jazz/rock/c.mp3"), second.join("rock/b.mp3")]
+        );
+    }
+
+    #[test]
+    fn exclude_patterns_win_over_includes_in_the_library() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        for name in ["kids/a.mp3", "xmas/b.mp3", "xmas/2024/c.mp3", "d.mp3"] {
+            let path = music.join(name);
+            fs::create_dir_all(path.parent().unwrap()).expect("create dir");
+            fs::write(path, "audio").expect("write track");
+        }
+        let library = Library::new(&[MusicDirectory::new(music.clone())], Duration::ZERO);
+        let list = |filter: &str| library.list_tracks(Some(&Filter::parse(filter)));
+
+        let all_but_xmas = vec![music.join("d.mp3"), music.join("kids/a.mp3")];
+        assert_eq!(list("** !xmas/**"), all_but_xmas);
+        assert_eq!(list("!xmas/**"), all_but_xmas);
+        // An excluded track stays out even when another include names it directly.
+        assert_eq!(
+            list("xmas/b.mp3 kids/* !xmas/**"),
+            vec![music.join("kids/a.mp3")]
+        );
+        // `*` stays within one folder, so only the top-level track is left.
+        assert_eq!(list("* !xmas/**"), vec![music.join("d.mp3")]);
+    }
+
+    #[test]
+    fn library_reads_track_tags() {
+        let tmp = tempdir().expect("tempdir");
+        let tagged = tmp.path().join("a.wav");
+        let plain = tmp.path().join("b.wav");
+        crate::metadata::tests::write_wav(&tagged, &[(b"INAM", "Anthem")], 8);
+        crate::metadata::tests::write_wav(&plain, &[], 8);
+
+        let library = Library::new(
+            &[MusicDirectory::new(tmp.path().to_path_buf())],
+            Duration::ZERO,
+        );
+
+        assert_eq!(
+            library.metadata(&tagged).and_then(|m| m.title),
+            Some("Anthem".to_string())
+        );
+        assert_eq!(library.metadata(&plain).and_then(|m| m.title), None);
+    }
+
+    fn paged_library(tmp: &Path) -> (Library, Vec<PathBuf>) {
+        let names = ["b/Zeta.mp3", "a/alpha.mp3", "c/Mid.mp3"];
+        let paths: Vec<_> = names.iter().map(|name| tmp.join(name)).collect();
+        for (age, path) in paths.iter().enumerate() {
+            fs::create_dir_all(path.parent().unwrap()).expect("create dir");
+            fs::write(path, "audio").expect("write track");
+            let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(age as u64);
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(modified))
+                .expect("set mtime");
+        }
+        (
+            Library::new(&[MusicDirectory::new(tmp.to_path_buf())], Duration::ZERO),
+            paths,
+        )
+    }
+
+    #[test]
+    fn list_tracks_page_sorts_by_path_name_and_mtime() {
+        let tmp = tempdir().expect("tempdir");
+        let (library, paths) = paged_library(tmp.path());
+        let [zeta, alpha, mid] = [&paths[0], &paths[1], &paths[2]].map(Clone::clone);
+        let sorted = |sort, order| {
+            library
+                .list_tracks_page(
+                    None,
+                    PageRequest {
+                        sort,
+                        order,
+                        ..PageRequest::default()
+                    },
+                )
+                .tracks
+        };
+
+        assert_eq!(
+            sorted(TrackSort::Path, SortOrder::Asc),
+            vec![alpha.clone(), zeta.clone(), mid.clone()]
+        );
+        assert_eq!(
+            sorted(TrackSort::Name, SortOrder::Asc),
+            vec![alpha.clone(), mid.clone(), zeta.clone()]
+        );
+        assert_eq!(
+            sorted(TrackSort::Mtime, SortOrder::Desc),
+            vec![mid, alpha, zeta]
+        );
+    }
+
+    #[test]
+    fn list_tracks_page_clamps_offset_and_limit() {
+        let tmp = tempdir().expect("tempdir");
+        let (library, _) = paged_library(tmp.path());
+        let page = |offset, limit| {
+            library.list_tracks_page(
+                None,
+                PageRequest {
+                    offset,
+                    limit,
+                    ..PageRequest::default()
+                },
+            )
+        };
+
+        let first = page(0, Some(2));
+        assert_eq!((first.total, first.offset, first.tracks.len()), (3, 0, 2));
+        let rest = page(2, Some(2));
+        assert_eq!((rest.total, rest.offset, rest.tracks.len()), (3, 2, 1));
+        let past_end = page(10, None);
+        assert_eq!((past_end.offset, past_end.tracks.len()), (3, 0));
+        let zero_limit = page(0, Some(0));
+        assert_eq!(zero_limit.tracks.len(), 1);
+    }
+
+    #[test]
+    fn display_path_strips_music_roots() {
+        let single = Library {
+            directories: vec![MusicDirectory::new(PathBuf::from("/home/pi/music"))],
+            ..Library::default()
+        };
+        assert_eq!(
+            single.display_path(Path::new("/home/pi/music/kids/song.mp3")),
+            "kids/song.mp3"
+        );
+        assert_eq!(
+            single.display_path(Path::new("/elsewhere/song.mp3")),
+            "/elsewhere/song.mp3"
+        );
+
+        let multiple = Library {
+            directories: vec![
+                MusicDirectory::new(PathBuf::from("/home/pi/music")),
+                MusicDirectory::new(PathBuf::from("/mnt/nas/audiobooks")),
+            ],
+            ..Library::default()
+        };
+        assert_eq!(
+            multiple.display_path(Path::new("/home/pi/music/kids/song.mp3")),
+            "music/kids/song.mp3"
+        );
+        assert_eq!(
+            multiple.display_path(Path::new("/mnt/nas/audiobooks/gruffalo.mp3")),
+            "audiobooks/gruffalo.mp3"
+        );
+
+        let nested = Library {
+            directories: vec![
+                MusicDirectory::new(PathBuf::from("/music")),
+                MusicDirectory::new(PathBuf::from("/music/audiobooks")),
+            ],
+            ..Library::default()
+        };
+        assert_eq!(
+            nested.display_path(Path::new("/music/audiobooks/gruffalo.mp3")),
+            "audiobooks/gruffalo.mp3"
+        );
+        assert_eq!(
+            nested.display_path(Path::new("/music/kids/song.mp3")),
+            "music/kids/song.mp3"
+        );
+    }
+
+    #[test]
+    fn list_tracks_applies_include_and_exclude_patterns() {
+        let tmp = tempdir().expect("tempdir");
+        let studio = tmp.path().join("rock/studio/anthem.mp3");
+        let live = tmp.path().join("rock/live/anthem.mp3");
+        let jazz = tmp.path().join("jazz/standard.mp3");
+        for path in [&studio, &live, &jazz] {
+            fs::create_dir_all(path.parent().unwrap()).expect("create dir");
+            fs::write(path, "audio").expect("write track");
+        }
+
+        let library = Library {
+            directories: vec![MusicDirectory::new(tmp.path().to_path_buf())],
+            ..Library::default()
+        };
+
+        let filter = Filter::parse("**/rock/** !**/live/**");
+        assert_eq!(library.list_tracks(Some(&filter)), vec![studio.clone()]);
+
+        let filter = Filter::parse("!**/live/**");
+        let mut expected = vec![studio, jazz];
+        expected.sort();
+        assert_eq!(library.list_tracks(Some(&filter)), expected);
+    }
+
+    #[test]
+    fn persist_tag_mapping_creates_backup_before_saving() {
         let tmp = tempdir().expect("tempdir");
         let config_path = tmp.path().join("config.toml");
+        let backup_dir = tmp.path().join("backups");
         let initial_config = r#"[[music]]
 dir = "/music"
 
@@ -667,60 +3370,1723 @@ dir = "/music"
 web = "0.0.0.0:8080"
 
 [tags]
-# keep this comment
-ABCD1234 = "PLAY"
-# untouched entry
-DEADBEEF = "SHUFFLE 80s/*"
+0A1B2C3D = "PLAY"
 "#;
 
         fs::write(&config_path, initial_config).expect("write config");
 
-        let crabbox = crabbox_with_config(config_path.clone(), None);
+        let crabbox = crabbox_with_config(config_path.clone(), Some(backup_dir.clone()));
 
         crabbox
             .persist_tag_mapping(
-                TagId::from_hex_str("ABCD1234").unwrap(),
-                Some(&Command::Stop),
+                TagId::from_hex_str("0A1B2C3D").unwrap(),
+                Some(&TagMapping::new(Command::Stop)),
             )
             .expect("persist tag");
 
+        let backups: Vec<_> = fs::read_dir(&backup_dir)
+            .expect("read backup dir")
+            .map(|entry| entry.expect("dir entry").path())
+            .collect();
+
+        assert_eq!(backups.len(), 1, "expected exactly one backup file");
+
+        let backup_contents = fs::read_to_string(&backups[0]).expect("backup contents");
+        assert_eq!(backup_contents, initial_config);
+
         let updated_config = fs::read_to_string(config_path).expect("updated config");
+        assert!(updated_config.contains("0A1B2C3D = \"STOP\""));
+    }
 
-        assert!(updated_config.contains("# keep this comment"));
-        assert!(updated_config.contains("# untouched entry"));
-        assert!(updated_config.contains("DEADBEEF = \"SHUFFLE 80s/*\""));
-        assert!(updated_config.contains("ABCD1234 = \"STOP\""));
+    #[test]
+    fn lists_config_backups_newest_first() {
+        let tmp = tempdir().expect("tempdir");
+        let backup_dir = tmp.path().join("backups");
+        fs::create_dir_all(&backup_dir).expect("backup dir");
+        for name in [
+            "config.toml.20250102T080000",
+            "config.toml.20250301T120000",
+            "config.toml.20241231T235959",
+            "config.toml.notatime",
+            "other.toml.20250401T000000",
+            "README",
+        ] {
+            fs::write(backup_dir.join(name), "").expect("write backup");
+        }
+        let crabbox = crabbox_with_config(tmp.path().join("config.toml"), Some(backup_dir));
+
+        let names: Vec<_> = crabbox
+            .config_backups()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect();
+
+        assert_eq!(
+            names,
+            [
+                "config.toml.20250301T120000",
+                "config.toml.20250102T080000",
+                "config.toml.20241231T235959",
+            ]
+        );
     }
 
     #[test]
-    fn persist_tag_mapping_removes_entry_without_touching_other_tags() {
+    fn backups_are_pruned_to_the_newest() {
         let tmp = tempdir().expect("tempdir");
         let config_path = tmp.path().join("config.toml");
-        let initial_config = r#"[[music]]
-dir = "/music"
+        let backup_dir = tmp.path().join("backups");
+        fs::create_dir_all(&backup_dir).expect("backup dir");
+        fs::write(&config_path, "").expect("write config");
+        for day in 1..=5 {
+            fs::write(
+                backup_dir.join(format!("config.toml.2025010{day}T000000")),
+                "",
+            )
+            .expect("write backup");
+        }
+        fs::write(backup_dir.join("config.toml.notes"), "").expect("write unrelated");
+        fs::write(backup_dir.join("tags.json.20240101T000000"), "").expect("write unrelated");
+        let mut crabbox = crabbox_with_config(config_path.clone(), Some(backup_dir.clone()));
+        crabbox.config_backup_keep = 3;
 
-[server]
-web = "0.0.0.0:8080"
+        crabbox.backup_file(&config_path).expect("backup");
 
-[tags]
-# primary tag comment
-ABCD1234 = "PLAY"
-# preserve this entry
-DEADBEEF = "SHUFFLE 80s/*"
-"#;
+        let remaining: Vec<_> = fs::read_dir(&backup_dir)
+            .expect("backups")
+            .map(|entry| entry.expect("entry").file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(remaining.len(), 5, "{remaining:?}");
+        for kept in [
+            "config.toml.20250104T000000",
+            "config.toml.20250105T000000",
+            "config.toml.notes",
+            "tags.json.20240101T000000",
+        ] {
+            assert!(remaining.iter().any(|name| name == kept), "{kept} pruned");
+        }
+        assert_eq!(crabbox.config_backups().len(), 3);
+    }
 
-        fs::write(&config_path, initial_config).expect("write config");
+    #[test]
+    fn restoring_a_backup_reloads_tags() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let backup_dir = tmp.path().join("backups");
+        fs::create_dir_all(&backup_dir).expect("backup dir");
+        let config = |command: &str| {
+            format!(
+                "favorites = [\"/music/a.mp3\"]\n\n[[music]]\ndir = \"/music\"\n\n[server]\nweb = \"0.0.0.0:8080\"\n\n[tags]\n0A1B2C3D = \"{command}\"\n"
+            )
+        };
+        fs::write(&config_path, config("STOP")).expect("write config");
+        fs::write(
+            backup_dir.join("config.toml.20250101T000000"),
+            config("PLAY"),
+        )
+        .expect("write backup");
+        fs::write(backup_dir.join("config.toml.20250102T000000"), "[broken")
+            .expect("write broken backup");
+        let mut crabbox = crabbox_with_config(config_path.clone(), Some(backup_dir.clone()));
+        let id = TagId::from_hex_str("0A1B2C3D").unwrap();
+
+        let err = crabbox
+            .restore_config_backup("config.toml.20250102T000000")
+            .expect_err("broken backup");
+        assert!(err.contains("does not load"), "{err}");
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), config("STOP"));
+        assert!(crabbox.restore_config_backup("../config.toml").is_err());
 
+        crabbox
+            .restore_config_backup("config.toml.20250101T000000")
+            .expect("restore");
+
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), config("PLAY"));
+        assert_eq!(
+            crabbox.tags.get(&id).map(|mapping| &mapping.command),
+            Some(&Command::Play { filter: None })
+        );
+        assert_eq!(crabbox.favorites(), [PathBuf::from("/music/a.mp3")]);
+        // The replaced config was backed up, so the restore can be undone.
+        assert!(crabbox.config_backups().iter().any(|backup| {
+            fs::read_to_string(backup_dir.join(&backup.name)).unwrap() == config("STOP")
+        }));
+    }
+
+    #[test]
+    fn config_writes_replace_the_file_without_leaving_temp_files() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let initial_config = "# keep me\n[[music]]\ndir = \"/music\"\n";
+        fs::write(&config_path, initial_config).expect("write config");
         let crabbox = crabbox_with_config(config_path.clone(), None);
 
         crabbox
-            .persist_tag_mapping(TagId::from_hex_str("ABCD1234").unwrap(), None)
+            .persist_tag_mapping(
+                TagId::from_hex_str("0A1B2C3D").unwrap(),
+                Some(&TagMapping::new(Command::Stop)),
+            )
             .expect("persist tag");
 
-        let updated_config = fs::read_to_string(config_path).expect("updated config");
-
+        let updated = fs::read_to_string(&config_path).expect("config");
+        assert_eq!(
+            updated,
+            format!("{initial_config}\n[tags]\n0A1B2C3D = \"STOP\"\n")
+        );
+        let files: Vec<_> = fs::read_dir(tmp.path())
+            .expect("dir")
+            .map(|entry| entry.expect("entry").file_name())
+            .collect();
+        assert_eq!(files, ["config.toml"]);
+    }
+
+    #[test]
+    fn clearing_tags_keeps_other_config_sections() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let backup_dir = tmp.path().join("backups");
+        fs::write(
+            &config_path,
+            r#"favorites = ["/music/a.mp3"]
+
+[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[tags]
+# Kids' tags
+0A1B2C3D = "PLAY"
+11223344 = { command = "STOP", volume = 0.5 }
+"#,
+        )
+        .expect("write config");
+        let mut crabbox = crabbox_with_config(config_path.clone(), Some(backup_dir.clone()));
+        crabbox.tags.insert(
+            TagId::from_hex_str("0A1B2C3D").unwrap(),
+            TagMapping::new(Command::Play { filter: None }),
+        );
+        assert_eq!(crabbox.tags_json().len(), 1);
+
+        crabbox.clear_tags().expect("clear tags");
+
+        assert!(crabbox.tags_json().is_empty());
+        let config = Config::load(&config_path).expect("config loads");
+        assert!(config.tags.is_empty());
+        assert_eq!(config.favorites, [PathBuf::from("/music/a.mp3")]);
+        assert_eq!(config.server.web, "0.0.0.0:8080");
+        assert!(fs::read_to_string(&config_path).unwrap().contains("[tags]"));
+        assert_eq!(crabbox.config_backups().len(), 1);
+    }
+
+    #[test]
+    fn persist_tag_mapping_preserves_existing_tags_table() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let initial_config = r#"[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[tags]
+# keep this comment
+ABCD1234 = "PLAY"
+# untouched entry
+DEADBEEF = "SHUFFLE 80s/*"
+"#;
+
+        fs::write(&config_path, initial_config).expect("write config");
+
+        let crabbox = crabbox_with_config(config_path.clone(), None);
+
+        crabbox
+            .persist_tag_mapping(
+                TagId::from_hex_str("ABCD1234").unwrap(),
+                Some(&TagMapping::new(Command::Stop)),
+            )
+            .expect("persist tag");
+
+        let updated_config = fs::read_to_string(config_path).expect("updated config");
+
+        assert!(updated_config.contains("# keep this comment"));
+        assert!(updated_config.contains("# untouched entry"));
+        assert!(updated_config.contains("DEADBEEF = \"SHUFFLE 80s/*\""));
+        assert!(updated_config.contains("ABCD1234 = \"STOP\""));
+    }
+
+    #[test]
+    fn persist_tag_mapping_removes_entry_without_touching_other_tags() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let initial_config = r#"[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[tags]
+# primary tag comment
+ABCD1234 = "PLAY"
+# preserve this entry
+DEADBEEF = "SHUFFLE 80s/*"
+"#;
+
+        fs::write(&config_path, initial_config).expect("write config");
+
+        let crabbox = crabbox_with_config(config_path.clone(), None);
+
+        crabbox
+            .persist_tag_mapping(TagId::from_hex_str("ABCD1234").unwrap(), None)
+            .expect("persist tag");
+
+        let updated_config = fs::read_to_string(config_path).expect("updated config");
+
         assert!(updated_config.contains("# preserve this entry"));
         assert!(updated_config.contains("DEADBEEF = \"SHUFFLE 80s/*\""));
         assert!(!updated_config.contains("ABCD1234"));
     }
+
+    #[test]
+    fn persist_tag_mapping_writes_separate_toml_tags_file() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let tags_path = tmp.path().join("tags.toml");
+        let initial_config = r#"[[music]]
+dir = "/music"
+
+[server]
+web = "0.0.0.0:8080"
+
+[tags]
+0A1B2C3D = "PLAY"
+"#;
+
+        fs::write(&config_path, initial_config).expect("write config");
+        fs::write(
+            &tags_path,
+            "# managed by crabbox\n0A0A0A0A = \"PLAY\"\nDEADBEEF = \"STOP\"\n",
+        )
+        .expect("write tags");
+
+        let mut crabbox = crabbox_with_config(config_path.clone(), None);
+        crabbox.tags_file = Some(tags_path.clone());
+
+        crabbox
+            .persist_tag_mapping(
+                TagId::from_hex_str("ABCD1234").unwrap(),
+                Some(&TagMapping::new(Command::Next)),
+            )
+            .expect("persist tag");
+        crabbox
+            .persist_tag_mapping(TagId::from_hex_str("DEADBEEF").unwrap(), None)
+            .expect("remove tag");
+
+        let config_after = fs::read_to_string(config_path).expect("config");
+        assert_eq!(config_after, initial_config);
+
+        let tags_after = fs::read_to_string(tags_path).expect("tags");
+        assert!(tags_after.contains("# managed by crabbox"));
+        assert!(tags_after.contains("0A0A0A0A = \"PLAY\""));
+        assert!(tags_after.contains("ABCD1234 = \"NEXT\""));
+        assert!(!tags_after.contains("DEADBEEF"));
+    }
+
+    #[test]
+    fn tags_file_changes_drop_the_inline_entry() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let tags_path = tmp.path().join("tags.json");
+        let header = format!(
+            "# my crabbox\ntags_file = {:?}\n\n[[music]]\ndir = {:?}\n\n[server]\n\
+             web = \"127.0.0.1:8080\"\n\n[tags]\n",
+            tags_path,
+            tmp.path()
+        );
+        fs::write(
+            &config_path,
+            format!("{header}0A1B2C3D = \"PLAY\"\ndeadbeef = \"STOP\"\n"),
+        )
+        .expect("write config");
+        fs::write(&tags_path, r#"{"0A1B2C3D": "PLAY"}"#).expect("write tags");
+
+        let mut crabbox = crabbox_with_config(config_path.clone(), None);
+        crabbox.tags_file = Some(tags_path);
+
+        crabbox
+            .persist_tag_mapping(TagId::from_hex_str("0A1B2C3D").unwrap(), None)
+            .expect("remove tag");
+        crabbox
+            .persist_tag_mapping(
+                TagId::from_hex_str("DEADBEEF").unwrap(),
+                Some(&TagMapping::new(Command::Next)),
+            )
+            .expect("reassign tag");
+
+        assert_eq!(fs::read_to_string(&config_path).expect("config"), header);
+        let tags = Config::load(&config_path).expect("reload config").tags;
+        assert_eq!(tags.len(), 1);
+        assert_eq!(
+            tags.get(&TagId::from_hex_str("DEADBEEF").unwrap())
+                .map(|mapping| &mapping.command),
+            Some(&Command::Next)
+        );
+    }
+
+    #[test]
+    fn persist_tag_mapping_creates_json_tags_file() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        let tags_path = tmp.path().join("tags.json");
+        fs::write(&config_path, "").expect("write config");
+
+        let mut crabbox = crabbox_with_config(config_path, None);
+        crabbox.tags_file = Some(tags_path.clone());
+
+        crabbox
+            .persist_tag_mapping(
+                TagId::from_hex_str("ABCD1234").unwrap(),
+                Some(&TagMapping::new(Command::Shuffle {
+                    filter: Some(Filter::parse("kids/*")),
+                })),
+            )
+            .expect("persist tag");
+
+        let tags: HashMap<TagId, TagMapping> =
+            serde_json::from_str(&fs::read_to_string(tags_path).expect("tags")).expect("json");
+        assert_eq!(
+            tags.get(&TagId::from_hex_str("ABCD1234").unwrap())
+                .map(|mapping| mapping.command.to_string()),
+            Some("SHUFFLE kids/*".to_string())
+        );
+    }
+
+    #[test]
+    fn adding_a_favorite_writes_it_to_config() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let track = music.join("song.mp3");
+        fs::write(&track, "audio").expect("write track");
+        let config_path = tmp.path().join("config.toml");
+        let backup_dir = tmp.path().join("backups");
+        let initial_config = r#"# my crabbox
+[[music]]
+dir = "/music"
+
+[tags]
+# keep this comment
+ABCD1234 = "PLAY"
+"#;
+        fs::write(&config_path, initial_config).expect("write config");
+
+        let mut crabbox = crabbox_with_config(config_path.clone(), Some(backup_dir.clone()));
+        crabbox.library.directories = vec![MusicDirectory::new(music.clone())];
+
+        crabbox.add_favorite(track.clone()).expect("add favorite");
+        crabbox.add_favorite(track.clone()).expect("add again");
+
+        let updated = fs::read_to_string(&config_path).expect("config");
+        assert!(updated.contains("# my crabbox"));
+        assert!(updated.contains("# keep this comment"));
+        assert!(updated.contains("ABCD1234 = \"PLAY\""));
+        let config: toml::Value = toml::from_str(&updated).expect("valid toml");
+        assert_eq!(
+            config["favorites"],
+            toml::Value::Array(vec![track.display().to_string().into()])
+        );
+        assert_eq!(crabbox.favorites(), std::slice::from_ref(&track));
+        assert_eq!(fs::read_dir(&backup_dir).expect("backups").count(), 1);
+
+        crabbox.remove_favorite(&track).expect("remove favorite");
+        let updated = fs::read_to_string(&config_path).expect("config");
+        assert!(updated.contains("favorites = []"));
+        assert!(crabbox.favorites().is_empty());
+    }
+
+    #[test]
+    fn favorites_must_be_library_tracks() {
+        let tmp = tempdir().expect("tempdir");
+        let outside = tmp.path().join("outside.mp3");
+        fs::write(&outside, "audio").expect("write track");
+        let config_path = tmp.path().join("config.toml");
+        fs::write(&config_path, "").expect("write config");
+
+        let mut crabbox = crabbox_with_config(config_path.clone(), None);
+        crabbox.library.directories = vec![MusicDirectory::new(tmp.path().join("music"))];
+
+        assert!(crabbox.add_favorite(outside).is_err());
+        assert!(crabbox.favorites().is_empty());
+        assert_eq!(fs::read_to_string(config_path).expect("config"), "");
+    }
+
+    #[test]
+    fn renaming_a_track_updates_the_queue() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let track = music.join("track01.mp3");
+        let other = music.join("other.mp3");
+        fs::write(&track, "audio").expect("write track");
+        fs::write(&other, "audio").expect("write other");
+
+        let mut crabbox = crabbox_with_config(PathBuf::from("/nonexistent/config.toml"), None);
+        crabbox.library.directories = vec![MusicDirectory::new(music.clone())];
+        crabbox.queue = Queue::from_tracks_ordered(vec![track.clone(), other.clone()]);
+        crabbox.status.current = Some(track.clone());
+
+        let renamed = crabbox
+            .rename_track(&track, Path::new("Kids/Song.mp3"))
+            .expect("rename");
+
+        assert_eq!(renamed, music.join("Kids").join("Song.mp3"));
+        assert!(renamed.is_file());
+        assert!(!track.exists());
+        assert_eq!(crabbox.queue.tracks, vec![renamed.clone(), other]);
+        assert_eq!(crabbox.current(), Some(renamed.as_path()));
+    }
+
+    #[test]
+    fn renaming_rejects_moves_out_of_the_music_directory() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        let stories = tmp.path().join("stories");
+        fs::create_dir_all(&music).expect("music dir");
+        fs::create_dir_all(&stories).expect("stories dir");
+        let track = music.join("track.mp3");
+        fs::write(&track, "audio").expect("write track");
+        let library = Library {
+            directories: vec![
+                MusicDirectory::new(music.clone()),
+                MusicDirectory::new(stories.clone()),
+            ],
+            ..Library::default()
+        };
+
+        for to in [
+            stories.join("track.mp3"),
+            PathBuf::from("../stories/track.mp3"),
+            PathBuf::from("track.txt"),
+        ] {
+            assert!(library.rename_track(&track, &to).is_err(), "{to:?}");
+        }
+        assert!(
+            library
+                .rename_track(&stories.join("missing.mp3"), Path::new("a.mp3"))
+                .is_err()
+        );
+        assert!(track.is_file());
+        assert_eq!(fs::read_dir(&stories).expect("stories").count(), 0);
+    }
+
+    #[test]
+    fn failed_tag_persistence_is_reported_in_snapshot() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("missing").join("config.toml");
+        let id = TagId::from_hex_str("ABCD1234").unwrap();
+
+        let mut crabbox = crabbox_with_config(config_path, None);
+        crabbox.tags_file = Some(tmp.path().join("missing").join("tags.toml"));
+        crabbox.assign_tag(id, Some("STOP"));
+
+        let snapshot = crabbox.snapshot();
+        assert_eq!(snapshot.tags, vec![(id, Command::Stop)]);
+        assert!(
+            snapshot
+                .config_warning
+                .is_some_and(|warning| warning.contains("could not be saved"))
+        );
+
+        let config_path = tmp.path().join("config.toml");
+        fs::write(&config_path, "").expect("write config");
+        let mut crabbox = crabbox_with_config(config_path, None);
+        crabbox.config_warning = Some("stale".to_string());
+        crabbox.assign_tag(id, Some("STOP"));
+
+        assert_eq!(crabbox.snapshot().config_warning, None);
+    }
+
+    #[test]
+    fn is_writable_checks_parent_of_missing_files() {
+        let tmp = tempdir().expect("tempdir");
+        assert!(is_writable(&tmp.path().join("config.toml")));
+        assert!(!is_writable(
+            &tmp.path().join("missing").join("config.toml")
+        ));
+    }
+
+    #[test]
+    fn play_history_keeps_newest_first_without_immediate_repeats() {
+        let mut crabbox = crabbox_with_config(PathBuf::from("/nonexistent/config.toml"), None);
+        let start = Utc::now();
+        for (idx, name) in ["a", "b", "b", "c", "a"].iter().enumerate() {
+            let at = start + TimeDelta::seconds(i64::try_from(idx).unwrap());
+            crabbox.record_played(Path::new(&format!("/music/{name}.mp3")), at);
+        }
+
+        let history: Vec<_> = crabbox
+            .history()
+            .into_iter()
+            .map(|played| played.path)
+            .collect();
+        assert_eq!(
+            history,
+            ["a", "c", "b", "a"]
+                .map(|name| PathBuf::from(format!("/music/{name}.mp3")))
+                .to_vec()
+        );
+        assert_eq!(crabbox.history()[0].at, start + TimeDelta::seconds(4));
+
+        for idx in 0..PLAY_HISTORY_LEN + 5 {
+            crabbox.record_played(Path::new(&format!("/music/{idx}.mp3")), start);
+        }
+        assert_eq!(crabbox.history().len(), PLAY_HISTORY_LEN);
+    }
+
+    #[test]
+    fn tag_history_is_bounded_and_expires_old_scans() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        let mapped = TagId::from_hex_str("ABCD1234").unwrap();
+        let unknown = TagId::from_hex_str("0A1B2C3D").unwrap();
+        crabbox.tags.insert(mapped, TagMapping::new(Command::Stop));
+        let start = Utc::now();
+
+        crabbox.record_tag_scan(unknown, start);
+        crabbox.record_tag_scan(mapped, start + TimeDelta::minutes(1));
+
+        let history = crabbox.recent_tag_scans(start + TimeDelta::minutes(2));
+        assert_eq!(
+            history
+                .iter()
+                .map(|scan| (scan.id, scan.mapped))
+                .collect::<Vec<_>>(),
+            vec![(mapped, true), (unknown, false)]
+        );
+
+        let later = start + TimeDelta::minutes(61);
+        assert_eq!(crabbox.recent_tag_scans(later).len(), 1);
+
+        for minute in 0..20 {
+            crabbox.record_tag_scan(unknown, later + TimeDelta::seconds(minute));
+        }
+        assert_eq!(crabbox.tag_history.len(), TAG_HISTORY_LEN);
+    }
+
+    #[test]
+    fn play_commands_are_ignored_until_limit_is_reset() {
+        let tmp = tempdir().expect("tempdir");
+        let track = tmp.path().join("music/track.mp3");
+        fs::create_dir_all(track.parent().unwrap()).expect("create music dir");
+        fs::write(&track, "audio").expect("write track");
+
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library.directories = vec![MusicDirectory::new(tmp.path().join("music"))];
+        crabbox.playtime = Playtime::new(
+            Some(Duration::from_mins(1)),
+            today(),
+            Duration::from_mins(1),
+        );
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.process_command(Command::Play { filter: None }, &mut player);
+        assert!(crabbox.queue.is_empty());
+
+        crabbox.process_command(Command::ResetLimit, &mut player);
+        crabbox.process_command(Command::Play { filter: None }, &mut player);
+        assert_eq!(crabbox.queue.tracks, vec![track]);
+    }
+
+    #[test]
+    fn restores_last_tag_from_the_state_file() {
+        let tmp = tempdir().expect("tempdir");
+        let state_file = tmp.path().join("state.json");
+        fs::write(
+            &state_file,
+            r#"{"queue": [], "position": null, "last_tag": "0A1B2C3D"}"#,
+        )
+        .expect("write state");
+        let config_path = tmp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "state_file = {state_file:?}\n\n[[music]]\ndir = {:?}\n\n\
+                 [server]\nweb = \"127.0.0.1:8080\"\n\n[tags]\n0A1B2C3D = \"STOP\"\n",
+                tmp.path()
+            ),
+        )
+        .expect("write config");
+        let config = Config::load(&config_path).expect("load config");
+
+        let crabbox = Crabbox::new(&config);
+        let snapshot = crabbox.lock().expect("lock crabbox").snapshot();
+
+        assert_eq!(snapshot.last_tag, TagId::from_hex_str("0A1B2C3D").ok());
+        assert_eq!(snapshot.last_tag_command, Some(Command::Stop));
+    }
+
+    #[test]
+    fn repeat_mode_survives_a_restart() {
+        let tmp = tempdir().expect("tempdir");
+        let state_file = tmp.path().join("state.json");
+        let config_path = tmp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "state_file = {state_file:?}\n\n[[music]]\ndir = {:?}\n\n\
+                 [server]\nweb = \"127.0.0.1:8080\"\n",
+                tmp.path()
+            ),
+        )
+        .expect("write config");
+        let config = Config::load(&config_path).expect("load config");
+
+        let crabbox = Crabbox::new(&config);
+        assert_eq!(
+            crabbox.lock().expect("lock crabbox").snapshot().repeat,
+            RepeatMode::All
+        );
+        {
+            let mut crabbox = crabbox.lock().expect("lock crabbox");
+            let mut player = Player::new(1.0, crabbox.sender());
+            crabbox.process_command(
+                Command::Repeat {
+                    mode: RepeatMode::One,
+                },
+                &mut player,
+            );
+        }
+
+        let restarted = Crabbox::new(&config);
+        assert_eq!(
+            restarted.lock().expect("lock crabbox").snapshot().repeat,
+            RepeatMode::One
+        );
+    }
+
+    #[test]
+    fn restores_playtime_only_for_the_same_day() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 2).unwrap();
+        let state = State {
+            playtime_date: Some("2024-05-02".to_string()),
+            playtime_secs: 600,
+            ..State::default()
+        };
+        let playtime = restore_playtime(Some(30), Some(&state), today);
+        assert_eq!(playtime.used(Instant::now()), Duration::from_mins(10));
+        assert_eq!(playtime.limit(), Some(Duration::from_mins(30)));
+
+        let yesterday = State {
+            playtime_date: Some("2024-05-01".to_string()),
+            ..state
+        };
+        let playtime = restore_playtime(Some(30), Some(&yesterday), today);
+        assert_eq!(playtime.used(Instant::now()), Duration::ZERO);
+    }
+
+    #[test]
+    fn same_shuffle_seed_reproduces_queue_order() {
+        let tracks: Vec<PathBuf> = (0..20)
+            .map(|idx| PathBuf::from(format!("/music/{idx:02}.mp3")))
+            .collect();
+
+        let first = Queue::from_tracks_shuffled(tracks.clone(), 42);
+        let second = Queue::from_tracks_shuffled(tracks.clone(), 42);
+        let other = Queue::from_tracks_shuffled(tracks.clone(), 43);
+
+        assert_eq!(first.tracks, second.tracks);
+        assert_ne!(first.tracks, other.tracks);
+        assert_ne!(first.tracks, tracks);
+    }
+
+    #[test]
+    fn debug_shuffle_records_seed_and_order() {
+        let tmp = tempdir().expect("tempdir");
+        for idx in 0..5 {
+            fs::write(tmp.path().join(format!("{idx}.mp3")), "audio").expect("write track");
+        }
+
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library.directories = vec![MusicDirectory::new(tmp.path().to_path_buf())];
+        crabbox.shuffle_seed = Some(7);
+        crabbox.rebuild_queue(None, QueueOrder::Shuffled);
+        assert!(crabbox.last_shuffle().is_none());
+
+        crabbox.debug_shuffle = true;
+        crabbox.rebuild_queue(None, QueueOrder::Shuffled);
+        let record = crabbox.last_shuffle().expect("shuffle record");
+        assert_eq!(record.seed, 7);
+        assert_eq!(record.queue, crabbox.queue.tracks);
+    }
+
+    #[test]
+    fn resume_point_rewinds_slightly() {
+        let point = ResumePoint {
+            track: PathBuf::from("/books/chapter1.mp3"),
+            position: Duration::from_secs(125),
+            paused: false,
+        };
+        assert_eq!(point.seek_target(), Duration::from_secs(123));
+
+        let near_start = ResumePoint {
+            position: Duration::from_secs(1),
+            ..point
+        };
+        assert_eq!(near_start.seek_target(), Duration::ZERO);
+    }
+
+    #[test]
+    fn interject_without_playback_leaves_queue_untouched() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(vec![PathBuf::from("/books/chapter1.mp3")]);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        assert_eq!(crabbox.resume_point(&player), None);
+        crabbox.process_command(
+            Command::Interject {
+                path: tmp.path().join("missing.mp3"),
+            },
+            &mut player,
+        );
+
+        assert_eq!(
+            crabbox.queue.current_track(),
+            Some(PathBuf::from("/books/chapter1.mp3"))
+        );
+        assert_eq!(crabbox.status.current, None);
+    }
+
+    #[test]
+    fn prescan_does_not_hold_the_crabbox_lock() {
+        let tmp = tempdir().expect("tempdir");
+        let crabbox = Mutex::new(crabbox_with_config(tmp.path().join("config.toml"), None));
+        let filter = Filter::parse("*/rock/*");
+
+        let prescanned = prescan(
+            &crabbox,
+            &Command::Shuffle {
+                filter: Some(filter.clone()),
+            },
+            |_, scanned_filter| {
+                assert!(crabbox.try_lock().is_ok(), "lock held during scan");
+                assert_eq!(scanned_filter, Some(&filter));
+                vec![PathBuf::from("/music/rock/anthem.mp3")]
+            },
+        )
+        .expect("shuffle scans the library");
+        assert_eq!(
+            prescanned.tracks,
+            vec![PathBuf::from("/music/rock/anthem.mp3")]
+        );
+
+        let mut crabbox = crabbox.into_inner().unwrap();
+        crabbox.prescanned = Some(prescanned);
+        crabbox.rebuild_queue(Some(&filter), QueueOrder::Ordered);
+        assert_eq!(
+            crabbox.queue.tracks,
+            vec![PathBuf::from("/music/rock/anthem.mp3")]
+        );
+    }
+
+    #[test]
+    fn tag_volume_is_applied_before_mapped_command() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        let quiet = TagId::from_hex_str("ABCD1234").unwrap();
+        let plain = TagId::from_hex_str("0A1B2C3D").unwrap();
+        crabbox.tags.insert(
+            quiet,
+            TagMapping {
+                command: Command::Stop,
+                volume: Some(0.25),
+            },
+        );
+        crabbox.tags.insert(plain, TagMapping::new(Command::Stop));
+        let mut player = Player::new(0.8, crabbox.sender());
+
+        crabbox.process_command(Command::Tag { id: plain }, &mut player);
+        assert!((player.volume() - 0.8).abs() < f32::EPSILON);
+
+        crabbox.process_command(Command::Tag { id: quiet }, &mut player);
+        assert!((player.volume() - 0.25).abs() < f32::EPSILON);
+        assert!((crabbox.status.volume - 0.25).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn reassigning_tag_keeps_its_volume() {
+        let tmp = tempdir().expect("tempdir");
+        let config_path = tmp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            "[tags]\nABCD1234 = { command = \"PLAY books/*\", volume = 0.3 }\n",
+        )
+        .expect("write config");
+        let mut crabbox = crabbox_with_config(config_path.clone(), None);
+        let id = TagId::from_hex_str("ABCD1234").unwrap();
+        crabbox.tags.insert(
+            id,
+            TagMapping {
+                command: Command::from_str("PLAY books/*").unwrap(),
+                volume: Some(0.3),
+            },
+        );
+
+        crabbox.assign_tag(id, Some("SHUFFLE books/*"));
+
+        assert_eq!(crabbox.tags[&id].volume, Some(0.3));
+        let config = fs::read_to_string(config_path).expect("config");
+        assert!(
+            config.contains(r#"ABCD1234 = { command = "SHUFFLE books/*", volume = 0.3 }"#),
+            "{config}"
+        );
+    }
+
+    #[test]
+    fn tag_sequence_runs_commands_in_order_and_skips_tags() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        let id = TagId::from_hex_str("ABCD1234").unwrap();
+        crabbox.tags.insert(
+            id,
+            TagMapping::new(Command::Sequence(vec![
+                Command::SetVolume { level: 0.3 },
+                Command::Tag { id },
+                Command::Mute,
+            ])),
+        );
+        let mut player = Player::new(0.8, crabbox.sender());
+
+        crabbox.process_command(Command::Tag { id }, &mut player);
+
+        assert!((crabbox.status.volume - 0.3).abs() < f32::EPSILON);
+        assert!(crabbox.status.muted);
+        assert_eq!(crabbox.tag_history.len(), 1);
+    }
+
+    #[test]
+    fn pending_scan_follows_tag_mappings() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        let id = TagId::from_hex_str("ABCD1234").unwrap();
+        crabbox.tags.insert(
+            id,
+            TagMapping::new(Command::from_str("SHUFFLE kids/*").unwrap()),
+        );
+
+        assert_eq!(
+            crabbox.pending_scan(&Command::Tag { id }),
+            Some(ScanRequest {
+                filter: Some(Filter::parse("kids/*"))
+            })
+        );
+        assert_eq!(
+            crabbox.pending_scan(&Command::Play { filter: None }),
+            Some(ScanRequest { filter: None })
+        );
+        assert_eq!(crabbox.pending_scan(&Command::Next), None);
+        assert_eq!(
+            crabbox.pending_scan(&Command::PlayPause { filter: None }),
+            None
+        );
+        assert_eq!(
+            crabbox.pending_scan(&Command::from_str("VOLUME 0.5; ENQUEUE rock/*").unwrap()),
+            Some(ScanRequest {
+                filter: Some(Filter::parse("rock/*"))
+            })
+        );
+    }
+
+    #[test]
+    fn publishes_events_for_playback_changes() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        let mut events = crabbox.subscribe();
+        let idle = PlaybackView {
+            current: None,
+            volume: 0.5,
+            muted: false,
+            paused: false,
+        };
+        let playing = PlaybackView {
+            current: Some(PathBuf::from("/music/a.mp3")),
+            volume: 0.55,
+            ..idle.clone()
+        };
+        let paused = PlaybackView {
+            paused: true,
+            ..playing.clone()
+        };
+        let muted = PlaybackView {
+            muted: true,
+            ..idle.clone()
+        };
+
+        crabbox.publish_changes(&idle, &idle);
+        crabbox.publish_changes(&idle, &playing);
+        crabbox.publish_changes(&playing, &paused);
+        crabbox.publish_changes(&paused, &idle);
+        crabbox.publish_changes(&idle, &muted);
+        crabbox.record_tag_scan(TagId::from_hex_str("ABCD1234").unwrap(), Utc::now());
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(
+            received,
+            vec![
+                StatusEvent::TrackStarted {
+                    path: "/music/a.mp3".to_string()
+                },
+                StatusEvent::VolumeChanged { volume: 0.55 },
+                StatusEvent::PauseChanged { paused: true },
+                StatusEvent::Stopped,
+                StatusEvent::VolumeChanged { volume: 0.5 },
+                StatusEvent::MuteChanged { muted: true },
+                StatusEvent::TagScanned {
+                    id: "ABCD1234".to_string(),
+                    mapped: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn status_events_report_playing_state() {
+        let playing: Vec<_> = [
+            StatusEvent::TrackStarted {
+                path: "/music/a.mp3".to_string(),
+            },
+            StatusEvent::PauseChanged { paused: true },
+            StatusEvent::PauseChanged { paused: false },
+            StatusEvent::VolumeChanged { volume: 0.5 },
+            StatusEvent::Stopped,
+        ]
+        .iter()
+        .map(StatusEvent::playing)
+        .collect();
+
+        assert_eq!(
+            playing,
+            [Some(true), Some(false), Some(true), None, Some(false)]
+        );
+    }
+
+    fn queue_at(current: usize) -> Queue {
+        let mut queue = Queue::from_tracks_ordered(
+            ["a", "b", "c", "d"]
+                .iter()
+                .map(|name| PathBuf::from(format!("/music/{name}.mp3")))
+                .collect(),
+        );
+        queue.current = Some(current);
+        queue
+    }
+
+    fn album_queue(current: usize) -> Queue {
+        let mut queue = Queue::from_tracks_ordered(
+            ["a/1", "a/2", "b/1", "c/1", "c/2", "c/3"]
+                .iter()
+                .map(|name| PathBuf::from(format!("/music/{name}.mp3")))
+                .collect(),
+        );
+        queue.current = Some(current);
+        queue
+    }
+
+    #[test]
+    fn next_dir_jumps_to_the_next_directory_and_wraps() {
+        let next = |current| {
+            let mut queue = album_queue(current);
+            let track = queue.next_dir();
+            (queue.current, track)
+        };
+
+        assert_eq!(next(0), (Some(2), Some(PathBuf::from("/music/b/1.mp3"))));
+        assert_eq!(next(1).0, Some(2));
+        assert_eq!(next(2).0, Some(3));
+        // From the last directory back to the first.
+        assert_eq!(next(4), (Some(0), Some(PathBuf::from("/music/a/1.mp3"))));
+        assert_eq!(Queue::empty().next_dir(), None);
+    }
+
+    #[test]
+    fn prev_dir_jumps_to_the_previous_directory_and_wraps() {
+        let prev = |current| {
+            let mut queue = album_queue(current);
+            queue.prev_dir();
+            queue.current
+        };
+
+        assert_eq!(prev(5), Some(2));
+        assert_eq!(prev(3), Some(2));
+        assert_eq!(prev(2), Some(0));
+        // From the first directory to the start of the last one.
+        assert_eq!(prev(1), Some(3));
+        assert_eq!(prev(0), Some(3));
+
+        let mut single = Queue::from_tracks_ordered(vec![PathBuf::from("/music/a/1.mp3")]);
+        assert_eq!(single.prev_dir(), Some(PathBuf::from("/music/a/1.mp3")));
+    }
+
+    #[test]
+    fn diagnostics_report_skipped_files_with_reason() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(music.join("album")).expect("create album");
+        fs::write(music.join("album/song.mp3"), "audio").expect("write song");
+        fs::write(music.join("album/cover.jpg"), "image").expect("write cover");
+        fs::write(music.join("README"), "notes").expect("write readme");
+        let missing = tmp.path().join("missing");
+        let library = Library::new(
+            &[
+                MusicDirectory::new(music.clone()),
+                MusicDirectory::new(missing.clone()),
+            ],
+            Duration::ZERO,
+        );
+
+        let diagnostics = library.diagnostics();
+
+        assert_eq!(diagnostics.included, vec![music.join("album/song.mp3")]);
+        let skipped: Vec<_> = diagnostics
+            .skipped
+            .iter()
+            .map(|file| (file.path.clone(), file.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (missing, SkipReason::Unreadable),
+                (music.join("README"), SkipReason::UnknownExtension),
+                (music.join("album/cover.jpg"), SkipReason::UnknownExtension),
+            ]
+        );
+        assert!(diagnostics.skipped[0].detail.is_some());
+    }
+
+    #[test]
+    fn accepts_only_existing_tracks_inside_music_directories() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music dir");
+        let track = music.join("loud.mp3");
+        let outside = tmp.path().join("outside.mp3");
+        fs::write(&track, "audio").expect("write track");
+        fs::write(&outside, "audio").expect("write outside");
+        let directories = vec![music.clone()];
+
+        assert!(is_library_track(&track, &directories));
+        assert!(!is_library_track(&outside, &directories));
+        assert!(!is_library_track(
+            &music.join("../outside.mp3"),
+            &directories
+        ));
+        assert!(!is_library_track(&music.join("missing.mp3"), &directories));
+    }
+
+    #[test]
+    fn play_file_rejects_paths_outside_music_directories() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music dir");
+        let outside = tmp.path().join("outside.mp3");
+        fs::write(&outside, "audio").expect("write outside");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library = Library::new(&[MusicDirectory::new(music)], Duration::ZERO);
+        crabbox.queue = Queue::from_tracks_ordered(vec![PathBuf::from("/music/a.mp3")]);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.process_command(Command::PlayFile { path: outside }, &mut player);
+
+        assert_eq!(crabbox.queue.tracks, vec![PathBuf::from("/music/a.mp3")]);
+    }
+
+    #[test]
+    fn play_playlist_queues_entries_inside_music_directories_in_order() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(music.join("album")).expect("create album");
+        for name in ["album/b.mp3", "a.mp3"] {
+            fs::write(music.join(name), "audio").expect("write track");
+        }
+        fs::write(tmp.path().join("outside.mp3"), "audio").expect("write outside");
+        fs::write(
+            music.join("mix.m3u"),
+            "#EXTM3U
+album/b.mp3
+../outside.mp3
+missing.mp3
+a.mp3
+",
+        )
+        .expect("write playlist");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library = Library::new(&[MusicDirectory::new(music.clone())], Duration::ZERO);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.process_command(
+            Command::PlayPlaylist {
+                path: PathBuf::from("mix.m3u"),
+            },
+            &mut player,
+        );
+
+        assert_eq!(
+            crabbox.queue.tracks,
+            vec![music.join("album/b.mp3"), music.join("a.mp3")]
+        );
+    }
+
+    #[test]
+    fn avoid_recent_shuffle_never_starts_with_the_last_played_track() {
+        let tracks: Vec<PathBuf> = (0..5)
+            .map(|i| PathBuf::from(format!("/music/{i}.mp3")))
+            .collect();
+        let mut crabbox = crabbox_with_config(PathBuf::from("/tmp/config.toml"), None);
+        crabbox.shuffle_strategy = ShuffleStrategy::AvoidRecent;
+        crabbox.record_played(&tracks[1], Utc::now());
+        crabbox.record_played(&tracks[3], Utc::now());
+
+        for seed in 0..50 {
+            crabbox.shuffle_seed = Some(seed);
+            crabbox.prescanned = Some(PrescannedTracks {
+                filter: None,
+                tracks: tracks.clone(),
+            });
+            crabbox.rebuild_queue(None, QueueOrder::Shuffled);
+
+            let queue = &crabbox.queue.tracks;
+            assert_eq!(queue.len(), tracks.len());
+            assert_eq!(queue[4], tracks[3], "seed {seed}: {queue:?}");
+            assert_eq!(queue[3], tracks[1], "seed {seed}: {queue:?}");
+        }
+    }
+
+    #[test]
+    fn interleaved_shuffle_separates_artists_when_possible() {
+        let artist_of = |track: &Path| {
+            track
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.split('-').next())
+                .unwrap_or_default()
+                .to_string()
+        };
+        // Five tracks by `a`, three by `b`, two by `c`: enough others to separate every `a`.
+        let tracks: Vec<PathBuf> = ["a"; 5]
+            .iter()
+            .chain(&["b"; 3])
+            .chain(&["c"; 2])
+            .enumerate()
+            .map(|(i, artist)| PathBuf::from(format!("/music/{artist}-{i}.mp3")))
+            .collect();
+
+        for seed in 0..50 {
+            let queue = Queue::from_tracks_interleaved(tracks.clone(), seed, artist_of);
+
+            let mut sorted = queue.tracks.clone();
+            sorted.sort();
+            assert_eq!(sorted, tracks);
+            assert_eq!(queue.current, Some(0));
+            for pair in queue.tracks.windows(2) {
+                assert_ne!(artist_of(&pair[0]), artist_of(&pair[1]), "seed {seed}");
+            }
+        }
+
+        // With a single artist there is nothing to separate.
+        let solo = vec![
+            PathBuf::from("/music/a-1.mp3"),
+            PathBuf::from("/music/a-2.mp3"),
+        ];
+        assert_eq!(
+            Queue::from_tracks_interleaved(solo, 1, artist_of)
+                .tracks
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn moving_recent_tracks_keeps_the_shuffled_order_of_the_rest() {
+        let mut queue = Queue::from_tracks_ordered(
+            ["c", "a", "d", "b"]
+                .iter()
+                .map(|name| PathBuf::from(format!("/music/{name}.mp3")))
+                .collect(),
+        );
+
+        queue.move_recent_to_end(&[Path::new("/music/c.mp3"), Path::new("/music/b.mp3")]);
+
+        let names: Vec<_> = queue
+            .tracks
+            .iter()
+            .map(|track| track.file_stem().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a", "d", "b", "c"]);
+    }
+
+    #[test]
+    fn shuffle_in_place_keeps_tracks_and_current() {
+        let tracks: Vec<PathBuf> = (0..10)
+            .map(|i| PathBuf::from(format!("/music/{i}.mp3")))
+            .collect();
+        let mut queue = Queue::from_tracks_ordered(tracks.clone());
+        queue.current = Some(4);
+
+        queue.shuffle_in_place(&mut StdRng::seed_from_u64(1));
+
+        assert_eq!(queue.current, Some(0));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/4.mp3")));
+        let mut shuffled = queue.tracks.clone();
+        shuffled.sort();
+        let mut expected = tracks;
+        expected.sort();
+        assert_eq!(shuffled, expected);
+    }
+
+    #[test]
+    fn shuffle_in_place_without_current_shuffles_everything() {
+        let tracks: Vec<PathBuf> = (0..10)
+            .map(|i| PathBuf::from(format!("/music/{i}.mp3")))
+            .collect();
+        let mut queue = Queue::from_tracks_ordered(tracks.clone());
+        queue.current = None;
+
+        queue.shuffle_in_place(&mut StdRng::seed_from_u64(1));
+
+        assert_eq!(queue.current, None);
+        assert_ne!(queue.tracks, tracks);
+        assert_eq!(queue.tracks.len(), tracks.len());
+    }
+
+    #[test]
+    fn unplayable_queue_track_is_skipped() {
+        let tmp = tempdir().expect("tempdir");
+        let bogus = tmp.path().join("bogus.mp3");
+        let valid = tmp.path().join("valid.wav");
+        fs::write(&bogus, "not audio").expect("write bogus");
+        crate::metadata::tests::write_wav(&valid, &[], 8);
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(vec![bogus, valid.clone()]);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        let track = crabbox.queue.current_track();
+        crabbox.play_queue_track(track, &mut player);
+
+        // Without an audio device the valid track still fails, but only after the skip.
+        assert_eq!(crabbox.queue.current_track(), Some(valid));
+    }
+
+    #[test]
+    fn queue_of_unplayable_tracks_gives_up_after_one_pass() {
+        let tmp = tempdir().expect("tempdir");
+        let tracks: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = tmp.path().join(format!("bogus{i}.mp3"));
+                fs::write(&path, "not audio").expect("write bogus");
+                path
+            })
+            .collect();
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(tracks);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        let track = crabbox.queue.current_track();
+        crabbox.play_queue_track(track, &mut player);
+
+        assert_eq!(crabbox.queue.current, Some(2));
+        assert_eq!(crabbox.status.current, None);
+    }
+
+    #[test]
+    fn pause_when_stopped_does_nothing_and_resume_starts_the_queue() {
+        let tmp = tempdir().expect("tempdir");
+        let tracks: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = tmp.path().join(format!("bogus{i}.mp3"));
+                fs::write(&path, "not audio").expect("write bogus");
+                path
+            })
+            .collect();
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(tracks);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.process_command(Command::Pause, &mut player);
+        assert_eq!(crabbox.queue.current, Some(0));
+        assert!(!player.has_sink());
+
+        // Starting from the current track walks the queue past the unplayable files.
+        crabbox.process_command(Command::Resume, &mut player);
+        assert_eq!(crabbox.queue.current, Some(2));
+        assert_eq!(crabbox.status.current, None);
+    }
+
+    /// Sounds played through `record_sound`, from any thread.
+    static SOUNDS_PLAYED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn record_sound(
+        path: &Path,
+        _volume: f32,
+        _curve: VolumeCurve,
+        _output_device: Option<&str>,
+    ) -> Result<(), String> {
+        thread::sleep(Duration::from_millis(200));
+        SOUNDS_PLAYED.lock().unwrap().push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn sound_played(path: &Path) -> bool {
+        SOUNDS_PLAYED
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|played| played == path)
+    }
+
+    #[test]
+    fn track_intro_is_queued_with_the_track_instead_of_played_first() {
+        let tmp = tempdir().expect("tempdir");
+        let tracks: Vec<PathBuf> = (0..2)
+            .map(|i| {
+                let path = tmp.path().join(format!("bogus{i}.mp3"));
+                fs::write(&path, "not audio").expect("write bogus");
+                path
+            })
+            .collect();
+        let intro = tmp.path().join("chime.wav");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.sound_player = record_sound;
+        crabbox.track_intro_sound = Some(intro.clone());
+        crabbox.queue = Queue::from_tracks_ordered(tracks);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        let started = Instant::now();
+        crabbox.process_command(Command::Resume, &mut player);
+
+        assert_eq!(crabbox.queue.current, Some(1), "both tracks were tried");
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert!(!sound_played(&intro), "the intro goes on the player's sink");
+        assert_eq!(crabbox.status.current, None);
+    }
+
+    #[test]
+    fn limit_sound_plays_without_holding_the_crabbox() {
+        let tmp = tempdir().expect("tempdir");
+        let sound = tmp.path().join("limit.wav");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.sound_player = record_sound;
+        crabbox.limit_sound = Some(sound.clone());
+        crabbox.playtime = Playtime::new(
+            Some(Duration::from_mins(1)),
+            today(),
+            Duration::from_mins(1),
+        );
+        crabbox.status.current = Some(tmp.path().join("a.mp3"));
+        let mut player = Player::new(1.0, crabbox.sender());
+        player.attach_test_sink();
+
+        let started = Instant::now();
+        crabbox.enforce_playtime(&mut player);
+        assert!(started.elapsed() < Duration::from_millis(200));
+        assert!(!player.has_sink());
+        assert_eq!(crabbox.status.current, None);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !sound_played(&sound) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(sound_played(&sound));
+    }
+
+    #[test]
+    fn interjections_must_be_library_files() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music");
+        let outside = tmp.path().join("secret.wav");
+        fs::write(&outside, "audio").expect("write outside");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library = Library::new(&[MusicDirectory::new(music.clone())], Duration::ZERO);
+        crabbox.status.current = Some(music.join("a.mp3"));
+        let mut player = Player::new(1.0, crabbox.sender());
+        player.attach_test_sink();
+
+        for path in [outside, music.join("../secret.wav")] {
+            crabbox.process_command(Command::Interject { path }, &mut player);
+            assert!(player.has_sink(), "the current track keeps playing");
+            assert!(crabbox.interjection.is_none());
+        }
+    }
+
+    #[test]
+    fn track_done_after_an_interjection_resumes_instead_of_advancing() {
+        let tmp = tempdir().expect("tempdir");
+        let tracks: Vec<PathBuf> = (0..3)
+            .map(|i| tmp.path().join(format!("missing{i}.mp3")))
+            .collect();
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(tracks.clone());
+        crabbox.status.current = Some(tracks[0].clone());
+        let mut player = Player::new(1.0, crabbox.sender());
+        player.attach_test_sink();
+
+        crabbox.interjection = Some(Interjection {
+            generation: player.generation(),
+            resume: None,
+        });
+        crabbox.process_command(Command::TrackDone, &mut player);
+        assert!(crabbox.interjection.is_none());
+        assert!(!player.has_sink(), "nothing to resume, so playback stops");
+        assert_eq!(
+            crabbox.status.current,
+            Some(tracks[0].clone()),
+            "the queue was left alone"
+        );
+
+        player.attach_test_sink();
+        crabbox.interjection = Some(Interjection {
+            generation: player.generation() + 1,
+            resume: None,
+        });
+        crabbox.process_command(Command::TrackDone, &mut player);
+        assert!(
+            crabbox.interjection.is_none(),
+            "stale interjections are dropped"
+        );
+        assert_eq!(
+            crabbox.status.current, None,
+            "an ordinary track end moves through the (unplayable) queue"
+        );
+    }
+
+    #[test]
+    fn first_command_stops_the_startup_sound() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.startup_sound = Some(tmp.path().join("missing.wav"));
+        crabbox.play_startup_sound(&mut player);
+        assert!(!crabbox.startup_sound_playing, "nothing to stop");
+
+        player.attach_test_sink();
+        crabbox.startup_sound_playing = true;
+        crabbox.process_command(Command::VolumeUp, &mut player);
+        assert!(!player.has_sink(), "startup sound stopped");
+
+        player.attach_test_sink();
+        crabbox.process_command(Command::VolumeUp, &mut player);
+        assert!(player.has_sink(), "later playback is left alone");
+    }
+
+    #[test]
+    fn snapshot_reports_pause_transitions() {
+        let mut crabbox = crabbox_with_config(PathBuf::from("/tmp/config.toml"), None);
+        crabbox.status.current = Some(PathBuf::from("/music/a.mp3"));
+        let mut player = Player::new(1.0, crabbox.sender());
+        player.attach_test_sink();
+        let mut paused_after = |command| {
+            crabbox.process_command(command, &mut player);
+            crabbox.snapshot().paused
+        };
+
+        assert!(paused_after(Command::PlayPause { filter: None }));
+        assert!(!paused_after(Command::PlayPause { filter: None }));
+        assert!(paused_after(Command::Pause));
+        assert!(paused_after(Command::Pause));
+        assert!(!paused_after(Command::Resume));
+        assert!(paused_after(Command::Pause));
+        assert!(!paused_after(Command::Stop));
+    }
+
+    #[test]
+    fn jump_to_moves_current_to_index() {
+        let mut queue = Queue::from_tracks_ordered(vec![
+            PathBuf::from("/music/a.mp3"),
+            PathBuf::from("/music/b.mp3"),
+            PathBuf::from("/music/c.mp3"),
+        ]);
+
+        assert_eq!(queue.jump_to(2), Some(PathBuf::from("/music/c.mp3")));
+        assert_eq!(queue.current, Some(2));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/c.mp3")));
+
+        assert_eq!(queue.jump_to(3), None);
+        assert_eq!(queue.current, Some(2));
+    }
+
+    #[test]
+    fn jump_to_out_of_range_keeps_queue_position() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(vec![
+            PathBuf::from("/music/a.mp3"),
+            PathBuf::from("/music/b.mp3"),
+        ]);
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.process_command(Command::JumpTo { index: 5 }, &mut player);
+
+        assert_eq!(crabbox.queue.current, Some(0));
+    }
+
+    #[test]
+    fn append_to_empty_queue_starts_at_first_track() {
+        let mut queue = Queue::empty();
+        queue.append(vec![PathBuf::from("/music/a.mp3")]);
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/a.mp3")));
+
+        let mut queue = Queue::empty();
+        queue.append(Vec::new());
+        assert_eq!(queue.current, None);
+    }
+
+    #[test]
+    fn enqueue_keeps_playing_and_track_done_reaches_new_tracks() {
+        let tmp = tempdir().expect("tempdir");
+        let extra = tmp.path().join("extra/song.mp3");
+        fs::create_dir_all(extra.parent().unwrap()).expect("create dir");
+        fs::write(&extra, "audio").expect("write track");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.library = Library::new(
+            &[MusicDirectory::new(tmp.path().to_path_buf())],
+            Duration::ZERO,
+        );
+        crabbox.queue = Queue::from_tracks_ordered(vec![PathBuf::from("/music/a.mp3")]);
+        crabbox.status.current = Some(PathBuf::from("/music/a.mp3"));
+        crabbox.status.repeat = RepeatMode::Off;
+        let mut player = Player::new(1.0, crabbox.sender());
+
+        crabbox.process_command(
+            Command::from_str("ENQUEUE **/extra/**").unwrap(),
+            &mut player,
+        );
+
+        assert_eq!(crabbox.queue.current, Some(0));
+        assert_eq!(crabbox.status.current, Some(PathBuf::from("/music/a.mp3")));
+        assert_eq!(crabbox.queue.tracks.len(), 2);
+        assert_eq!(crabbox.queue.peek_after_done(RepeatMode::Off), Some(extra));
+    }
+
+    #[test]
+    fn remove_before_current_shifts_current_back() {
+        let mut queue = queue_at(2);
+        assert_eq!(queue.remove(0), Some(PathBuf::from("/music/a.mp3")));
+        assert_eq!(queue.current, Some(1));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/c.mp3")));
+    }
+
+    #[test]
+    fn remove_current_advances_to_following_track() {
+        let mut queue = queue_at(1);
+        assert_eq!(queue.remove(1), Some(PathBuf::from("/music/b.mp3")));
+        assert_eq!(queue.current, Some(1));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/c.mp3")));
+
+        let mut queue = queue_at(3);
+        queue.remove(3);
+        assert_eq!(queue.current, None);
+    }
+
+    #[test]
+    fn remove_after_current_or_out_of_range_keeps_current() {
+        let mut queue = queue_at(1);
+        assert_eq!(queue.remove(3), Some(PathBuf::from("/music/d.mp3")));
+        assert_eq!(queue.current, Some(1));
+        assert_eq!(queue.remove(10), None);
+        assert_eq!(queue.tracks.len(), 3);
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/b.mp3")));
+    }
+
+    #[test]
+    fn moving_the_current_track_keeps_it_current() {
+        let mut queue = queue_at(1);
+        assert!(queue.move_track(1, 3));
+        assert_eq!(queue.current, Some(3));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/b.mp3")));
+    }
+
+    #[test]
+    fn moving_around_the_current_track_shifts_it() {
+        let mut queue = queue_at(2);
+        assert!(queue.move_track(0, 3));
+        assert_eq!(queue.current, Some(1));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/c.mp3")));
+
+        assert!(queue.move_track(3, 0));
+        assert_eq!(queue.current, Some(2));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/c.mp3")));
+
+        assert!(queue.move_track(3, 2));
+        assert_eq!(queue.current, Some(3));
+        assert_eq!(queue.current_track(), Some(PathBuf::from("/music/c.mp3")));
+    }
+
+    #[test]
+    fn moving_with_invalid_indices_is_a_no_op() {
+        let mut queue = queue_at(2);
+        assert!(!queue.move_track(4, 0));
+        assert!(!queue.move_track(0, 4));
+        assert_eq!(queue.current, Some(2));
+        assert_eq!(queue.track_at(0), Some(PathBuf::from("/music/a.mp3")));
+    }
+
+    #[test]
+    fn track_done_honors_repeat_mode() {
+        let tracks = vec![PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b.mp3")];
+
+        let mut queue = Queue::from_tracks_ordered(tracks.clone());
+        assert_eq!(
+            queue.track_after_done(RepeatMode::Off),
+            Some(tracks[1].clone())
+        );
+        assert_eq!(queue.peek_after_done(RepeatMode::Off), None);
+        assert_eq!(queue.track_after_done(RepeatMode::Off), None);
+        assert_eq!(queue.current, Some(1));
+        assert_eq!(
+            queue.peek_after_done(RepeatMode::All),
+            Some(tracks[0].clone())
+        );
+        assert_eq!(queue.current, Some(1));
+
+        assert_eq!(
+            queue.track_after_done(RepeatMode::One),
+            Some(tracks[1].clone())
+        );
+        assert_eq!(
+            queue.track_after_done(RepeatMode::All),
+            Some(tracks[0].clone())
+        );
+        assert_eq!(
+            queue.track_after_done(RepeatMode::One),
+            Some(tracks[0].clone())
+        );
+    }
+
+    #[test]
+    fn repeat_off_stops_at_end_of_queue() {
+        let tmp = tempdir().expect("tempdir");
+        let mut crabbox = crabbox_with_config(tmp.path().join("config.toml"), None);
+        crabbox.queue = Queue::from_tracks_ordered(vec![PathBuf::from("/music/a.mp3")]);
+        crabbox.status.current = Some(PathBuf::from("/music/a.mp3"));
+        let mut player = Player::new(1.0, crabbox.sender());
+        assert_eq!(crabbox.snapshot().repeat, RepeatMode::All);
+
+        crabbox.process_command(
+            Command::Repeat {
+                mode: RepeatMode::Off,
+            },
+            &mut player,
+        );
+        crabbox.process_command(Command::TrackDone, &mut player);
+
+        assert_eq!(crabbox.snapshot().repeat, RepeatMode::Off);
+        assert_eq!(crabbox.status.current, None);
+        assert_eq!(crabbox.queue.current, Some(0));
+    }
 }
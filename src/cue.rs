@@ -0,0 +1,357 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tracing::warn;
+
+/// Cue sheet timestamps count frames of 1/75 s, the CD sector rate.
+const FRAMES_PER_SECOND: u64 = 75;
+
+/// One logical track of a cue sheet: a slice of `file` from `start` to `end`, or to the end
+/// of the file for the last track.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    /// Track performer, or the album performer when the track has none.
+    pub performer: Option<String>,
+    pub album: Option<String>,
+    pub file: PathBuf,
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+/// Reads the cue sheet at `path`, resolving `FILE` entries against its directory.
+pub fn load(path: &Path) -> Result<Vec<CueTrack>, String> {
+    let contents = fs::read(path)
+        .map_err(|err| format!("Failed to read cue sheet {}: {err}", path.display()))?;
+    // Older rippers write Latin-1; keep whatever decodes instead of rejecting the sheet.
+    let contents = String::from_utf8_lossy(&contents);
+    parse(&contents, path.parent().unwrap_or(Path::new("")))
+        .map_err(|err| format!("Invalid cue sheet {}: {err}", path.display()))
+}
+
+/// Parses the `FILE`, `TRACK`, `INDEX 01`, `TITLE` and `PERFORMER` commands of a cue sheet.
+/// Each track ends where the next track in the same file starts.
+pub fn parse(contents: &str, base: &Path) -> Result<Vec<CueTrack>, String> {
+    let mut album = None;
+    let mut album_performer = None;
+    let mut file: Option<PathBuf> = None;
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    // Whether the last track in `tracks` has seen its INDEX 01.
+    let mut indexed = true;
+
+    for (line, text) in contents.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line + 1;
+        let (command, rest) = split_word(text);
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => file = Some(base.join(unquote(strip_file_type(rest)))),
+            "TRACK" => {
+                let file = file
+                    .clone()
+                    .ok_or_else(|| format!("line {line}: TRACK before any FILE"))?;
+                let number = split_word(rest)
+                    .0
+                    .parse()
+                    .map_err(|_| format!("line {line}: bad track number"))?;
+                check_indexed(&tracks, indexed)?;
+                tracks.push(CueTrack {
+                    number,
+                    title: None,
+                    performer: None,
+                    album: None,
+                    file,
+                    start: Duration::ZERO,
+                    end: None,
+                });
+                indexed = false;
+            }
+            "INDEX" => {
+                let (index, timestamp) = split_word(rest);
+                let track = tracks
+                    .last_mut()
+                    .ok_or_else(|| format!("line {line}: INDEX outside a TRACK"))?;
+                if index.parse::<u32>() == Ok(1) {
+                    track.start = parse_timestamp(timestamp)
+                        .ok_or_else(|| format!("line {line}: bad INDEX time"))?;
+                    indexed = true;
+                }
+            }
+            "TITLE" | "PERFORMER" => {
+                let value = Some(unquote(rest).to_string()).filter(|value| !value.is_empty());
+                let field = match (tracks.last_mut(), command.eq_ignore_ascii_case("TITLE")) {
+                    (Some(track), true) => &mut track.title,
+                    (Some(track), false) => &mut track.performer,
+                    (None, true) => &mut album,
+                    (None, false) => &mut album_performer,
+                };
+                *field = value;
+            }
+            _ => {}
+        }
+    }
+
+    if tracks.is_empty() {
+        return Err("no tracks".to_string());
+    }
+    check_indexed(&tracks, indexed)?;
+
+    let next_starts: Vec<Option<(PathBuf, Duration)>> = tracks
+        .iter()
+        .skip(1)
+        .map(|next| Some((next.file.clone(), next.start)))
+        .chain([None])
+        .collect();
+    for (track, next) in tracks.iter_mut().zip(next_starts) {
+        track.end = next
+            .filter(|(file, _)| *file == track.file)
+            .map(|(_, start)| start);
+        track.album.clone_from(&album);
+        if track.performer.is_none() {
+            track.performer.clone_from(&album_performer);
+        }
+    }
+    Ok(tracks)
+}
+
+/// Library path for track `number` of the cue sheet at `sheet`, e.g. `album.cue#03`.
+pub fn track_path(sheet: &Path, number: u32) -> PathBuf {
+    let mut path = sheet.as_os_str().to_owned();
+    path.push(format!("#{number:02}"));
+    PathBuf::from(path)
+}
+
+/// Splits a path made by `track_path` into the cue sheet and track number.
+pub fn split_track_path(path: &Path) -> Option<(PathBuf, u32)> {
+    let (sheet, number) = path.to_str()?.rsplit_once('#')?;
+    let is_cue = Path::new(sheet)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"));
+    let number = number.parse().ok().filter(|_| is_cue)?;
+    Some((PathBuf::from(sheet), number))
+}
+
+/// The cue track a `track_path` path points at, or `None` for ordinary paths.
+pub fn lookup(path: &Path) -> Option<Result<CueTrack, String>> {
+    let (sheet, number) = split_track_path(path)?;
+    Some(load(&sheet).and_then(|tracks| {
+        tracks
+            .into_iter()
+            .find(|track| track.number == number)
+            .ok_or_else(|| format!("{} has no track {number}", sheet.display()))
+    }))
+}
+
+/// Replaces each audio file in `tracks` that a cue sheet in `sheets` splits up with that
+/// sheet's track paths. Sheets that fail to parse are skipped, leaving their file whole.
+pub fn split_tracks(tracks: Vec<PathBuf>, sheets: &[PathBuf]) -> Vec<PathBuf> {
+    let listed: HashSet<&PathBuf> = tracks.iter().collect();
+    let mut split = HashSet::new();
+    let mut cue_tracks = Vec::new();
+    for sheet in sheets {
+        let sheet_tracks = match load(sheet) {
+            Ok(sheet_tracks) => sheet_tracks,
+            Err(err) => {
+                warn!("{err}");
+                continue;
+            }
+        };
+        for track in sheet_tracks {
+            if listed.contains(&track.file) {
+                cue_tracks.push(track_path(sheet, track.number));
+                split.insert(track.file);
+            }
+        }
+    }
+
+    drop(listed);
+    tracks
+        .into_iter()
+        .filter(|track| !split.contains(track))
+        .chain(cue_tracks)
+        .collect()
+}
+
+fn check_indexed(tracks: &[CueTrack], indexed: bool) -> Result<(), String> {
+    match tracks.last() {
+        Some(track) if !indexed => Err(format!("track {} has no INDEX 01", track.number)),
+        _ => Ok(()),
+    }
+}
+
+/// `mm:ss:ff` with minutes allowed past 59.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let mut parts = timestamp.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+    Some(
+        Duration::from_secs(minutes * 60 + seconds)
+            + Duration::from_millis(frames * 1000 / FRAMES_PER_SECOND),
+    )
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    text.split_once(char::is_whitespace)
+        .map_or((text, ""), |(word, rest)| (word, rest.trim()))
+}
+
+/// Drops the trailing file type from `"name.flac" WAVE` (or an unquoted name).
+fn strip_file_type(rest: &str) -> &str {
+    if rest.starts_with('"') {
+        return rest.rfind('"').map_or(rest, |end| &rest[..=end]);
+    }
+    rest.rsplit_once(char::is_whitespace)
+        .map_or(rest, |(name, _)| name)
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    use tempfile::tempdir;
+
+    use super::{lookup, parse, split_track_path, split_tracks, track_path};
+
+    const SHEET: &str = "\u{feff}REM GENRE Classical\r\n\
+        PERFORMER \"Berliner Philharmoniker\"\r\n\
+        TITLE \"Symphonies\"\r\n\
+        FILE \"Symphonies.flac\" WAVE\r\n\
+        \x20 TRACK 01 AUDIO\r\n\
+        \x20   TITLE \"I. Allegro con brio\"\r\n\
+        \x20   INDEX 01 00:00:00\r\n\
+        \x20 TRACK 02 AUDIO\r\n\
+        \x20   TITLE \"II. Andante con moto\"\r\n\
+        \x20   PERFORMER \"Karajan\"\r\n\
+        \x20   INDEX 00 07:25:50\r\n\
+        \x20   INDEX 01 07:27:15\r\n\
+        \x20 TRACK 03 AUDIO\r\n\
+        \x20   TITLE \"III. Allegro\"\r\n\
+        \x20   INDEX 01 117:03:74\r\n";
+
+    #[test]
+    fn parses_tracks_with_index_offsets() {
+        let tracks = parse(SHEET, Path::new("/music/Beethoven")).expect("valid sheet");
+
+        assert_eq!(tracks.len(), 3);
+        let file = PathBuf::from("/music/Beethoven/Symphonies.flac");
+        assert!(tracks.iter().all(|track| track.file == file));
+        assert_eq!(
+            tracks.iter().map(|track| track.number).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        let second_start = Duration::from_secs(7 * 60 + 27) + Duration::from_millis(200);
+        let third_start = Duration::from_secs(117 * 60 + 3) + Duration::from_millis(986);
+        assert_eq!(tracks[0].start, Duration::ZERO);
+        assert_eq!(tracks[0].end, Some(second_start));
+        assert_eq!(tracks[1].start, second_start);
+        assert_eq!(tracks[1].end, Some(third_start));
+        assert_eq!(tracks[2].start, third_start);
+        assert_eq!(tracks[2].end, None);
+
+        assert_eq!(tracks[1].title.as_deref(), Some("II. Andante con moto"));
+        assert_eq!(tracks[1].performer.as_deref(), Some("Karajan"));
+        assert_eq!(
+            tracks[0].performer.as_deref(),
+            Some("Berliner Philharmoniker")
+        );
+        assert_eq!(tracks[2].album.as_deref(), Some("Symphonies"));
+    }
+
+    #[test]
+    fn tracks_end_at_the_file_boundary() {
+        let sheet = "FILE \"a.wav\" WAVE\n\
+            TRACK 01 AUDIO\nINDEX 01 00:00:00\n\
+            TRACK 02 AUDIO\nINDEX 01 03:00:00\n\
+            FILE \"b.wav\" WAVE\n\
+            TRACK 03 AUDIO\nINDEX 01 00:00:00\n";
+
+        let tracks = parse(sheet, Path::new("/cd")).expect("valid sheet");
+
+        assert_eq!(tracks[0].end, Some(Duration::from_mins(3)));
+        assert_eq!(tracks[1].end, None);
+        assert_eq!(tracks[2].file, PathBuf::from("/cd/b.wav"));
+    }
+
+    #[test]
+    fn rejects_sheets_without_usable_tracks() {
+        assert!(parse("TITLE \"Empty\"\n", Path::new("/")).is_err());
+        assert!(parse("TRACK 01 AUDIO\nINDEX 01 00:00:00\n", Path::new("/")).is_err());
+        assert!(
+            parse(
+                "FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:61:00\n",
+                Path::new("/")
+            )
+            .is_err()
+        );
+        assert!(
+            parse(
+                "FILE a.wav WAVE\nTRACK 01 AUDIO\nTRACK 02 AUDIO\nINDEX 01 01:00:00\n",
+                Path::new("/")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn track_paths_round_trip() {
+        let sheet = Path::new("/music/Bach/Goldberg.cue");
+        let path = track_path(sheet, 7);
+
+        assert_eq!(path, PathBuf::from("/music/Bach/Goldberg.cue#07"));
+        assert_eq!(split_track_path(&path), Some((sheet.to_path_buf(), 7)));
+        assert_eq!(split_track_path(Path::new("/music/a.mp3")), None);
+        assert_eq!(split_track_path(Path::new("/music/#1 hit.mp3#2")), None);
+    }
+
+    #[test]
+    fn splits_library_files_covered_by_a_sheet() {
+        let tmp = tempdir().expect("tempdir");
+        let album = tmp.path().join("album.flac");
+        let single = tmp.path().join("single.mp3");
+        let sheet = tmp.path().join("album.cue");
+        fs::write(
+            &sheet,
+            "FILE \"album.flac\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\nTITLE \"Two\"\nINDEX 01 04:00:00\n",
+        )
+        .expect("write sheet");
+        let broken = tmp.path().join("broken.cue");
+        fs::write(&broken, "nothing useful").expect("write broken sheet");
+
+        let tracks = split_tracks(
+            vec![album.clone(), single.clone()],
+            &[sheet.clone(), broken],
+        );
+
+        assert_eq!(
+            tracks,
+            vec![single, track_path(&sheet, 1), track_path(&sheet, 2)]
+        );
+        let second = lookup(&tracks[2])
+            .expect("cue track path")
+            .expect("track in sheet");
+        assert_eq!(second.title.as_deref(), Some("Two"));
+        assert_eq!(second.file, album);
+        assert!(lookup(&track_path(&sheet, 9)).expect("cue path").is_err());
+    }
+}
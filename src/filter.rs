@@ -0,0 +1,297 @@
+use std::{fmt, path::Path};
+
+use crate::glob::Glob;
+
+/// A track filter made of whitespace-separated glob patterns.
+///
+/// Patterns prefixed with `!` exclude matching tracks; all other patterns
+/// include them. A track matches when it matches any include pattern (or
+/// there are no include patterns) and matches none of the exclude patterns.
+/// Whitespace inside a pattern can be escaped with a backslash or the pattern put in double
+/// quotes, e.g. `"kids/Peter and the Wolf/*"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    patterns: Vec<FilterPattern>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FilterPattern {
+    glob: String,
+    exclude: bool,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Self {
+        let patterns = split_patterns(input)
+            .into_iter()
+            .filter_map(|token| match token.strip_prefix('!') {
+                Some("") => None,
+                Some(glob) => Some(FilterPattern {
+                    glob: glob.to_string(),
+                    exclude: true,
+                }),
+                None => Some(FilterPattern {
+                    glob: token,
+                    exclude: false,
+                }),
+            })
+            .collect();
+
+        Self { patterns }
+    }
+
+    pub fn includes(&self) -> impl Iterator<Item = &str> {
+        self.patterns
+            .iter()
+            .filter(|pattern| !pattern.exclude)
+            .map(|pattern| pattern.glob.as_str())
+    }
+
+    pub fn excludes(&self) -> impl Iterator<Item = &str> {
+        self.patterns
+            .iter()
+            .filter(|pattern| pattern.exclude)
+            .map(|pattern| pattern.glob.as_str())
+    }
+
+    /// Include patterns without a wildcard or file extension. They can't match any track, and
+    /// are usually the pieces of an unquoted path with spaces, e.g. `kids/Peter and the Wolf/*`.
+    pub fn unmatchable_includes(&self) -> Vec<&str> {
+        if self.patterns.len() < 2 {
+            return Vec::new();
+        }
+        self.includes()
+            .filter(|glob| {
+                !has_wildcard(glob) && !glob.rsplit('/').next().unwrap_or("").contains('.')
+            })
+            .collect()
+    }
+
+    /// A warning for filters that look like an unquoted path with spaces.
+    pub fn split_path_warning(&self) -> Option<String> {
+        let unmatchable = self.unmatchable_includes();
+        if unmatchable.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "filter `{self}` has patterns that can't match any track ({}); put paths with \
+             spaces in double quotes",
+            unmatchable.join(", ")
+        ))
+    }
+
+    pub fn matcher(&self) -> Result<FilterMatcher, regex::Error> {
+        Ok(FilterMatcher {
+            include: self.includes().map(Glob::new).collect::<Result<_, _>>()?,
+            exclude: self.excludes().map(Glob::new).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, pattern) in self.patterns.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            if pattern.exclude {
+                f.write_str("!")?;
+            }
+            f.write_str(&pattern.glob)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct FilterMatcher {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl FilterMatcher {
+    /// Matches a track inside the music directory `root`: patterns are matched against the
+    /// path below `root`, except absolute ones (`/home/pi/music/rock/*`), which see the
+    /// full path.
+    pub fn is_match_under(&self, path: &Path, root: &Path) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let is_match =
+            |glob: &Glob| glob.is_match_path(if glob.is_absolute() { path } else { relative });
+        let included = self.include.is_empty() || self.include.iter().any(is_match);
+
+        included && !self.exclude.iter().any(is_match)
+    }
+}
+
+fn has_wildcard(glob: &str) -> bool {
+    let mut chars = glob.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' | '{' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Splits on unescaped whitespace outside double quotes, keeping escapes intact for the glob
+/// parser. Whitespace inside quotes is escaped so the patterns still print back unquoted.
+fn split_patterns(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = input.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                current.push(ch);
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            '"' => quoted = !quoted,
+            ch if ch.is_whitespace() && quoted => {
+                current.push('\\');
+                current.push(ch);
+            }
+            ch if ch.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(ch),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use std::path::Path;
+
+    fn matches(filter: &str, path: &str) -> bool {
+        Filter::parse(filter)
+            .matcher()
+            .expect("valid filter")
+            .is_match_under(Path::new(path), Path::new(""))
+    }
+
+    #[test]
+    fn single_include_matches_like_glob() {
+        assert!(matches("rock/*", "rock/anthem.mp3"));
+        assert!(!matches("rock/*", "jazz/anthem.mp3"));
+    }
+
+    #[test]
+    fn exclude_removes_from_include() {
+        let filter = "rock/** !**/live/**";
+        assert!(matches(filter, "rock/studio/anthem.mp3"));
+        assert!(!matches(filter, "rock/live/anthem.mp3"));
+        assert!(!matches(filter, "jazz/studio/anthem.mp3"));
+    }
+
+    #[test]
+    fn multiple_includes_are_alternatives() {
+        let filter = "rock/* jazz/* !**.wav";
+        assert!(matches(filter, "rock/anthem.mp3"));
+        assert!(matches(filter, "jazz/standard.flac"));
+        assert!(!matches(filter, "jazz/standard.wav"));
+        assert!(!matches(filter, "pop/hit.mp3"));
+    }
+
+    #[test]
+    fn exclude_only_matches_everything_else() {
+        assert!(matches("!xmas/*", "rock/anthem.mp3"));
+        assert!(matches("!xmas/*", "xmas/2023/jingle.mp3"));
+        assert!(!matches("!xmas/**", "xmas/2023/jingle.mp3"));
+        assert!(!matches("!xmas/*", "xmas/jingle.mp3"));
+    }
+
+    #[test]
+    fn escaped_whitespace_stays_in_pattern() {
+        let filter = Filter::parse(r"my\ band/* !*/live\ at/*");
+        assert_eq!(filter.includes().collect::<Vec<_>>(), vec![r"my\ band/*"]);
+        assert_eq!(filter.excludes().collect::<Vec<_>>(), vec![r"*/live\ at/*"]);
+        assert!(matches(r"my\ band/*", "my band/song.mp3"));
+    }
+
+    #[test]
+    fn quoted_paths_keep_their_spaces() {
+        let filter = Filter::parse(r#""kids/Peter and the Wolf/*" !"*/live at/*""#);
+
+        assert_eq!(
+            filter.includes().collect::<Vec<_>>(),
+            vec![r"kids/Peter\ and\ the\ Wolf/*"]
+        );
+        assert_eq!(filter.excludes().collect::<Vec<_>>(), vec![r"*/live\ at/*"]);
+        assert_eq!(Filter::parse(&filter.to_string()), filter);
+        assert!(filter.unmatchable_includes().is_empty());
+
+        let matcher = filter.matcher().expect("valid filter");
+        assert!(matcher.is_match_under(Path::new("kids/Peter and the Wolf/01.mp3"), Path::new("")));
+        assert!(!matcher.is_match_under(Path::new("kids/Peter/01.mp3"), Path::new("")));
+    }
+
+    #[test]
+    fn flags_the_pieces_of_an_unquoted_path_with_spaces() {
+        let filter = Filter::parse("kids/Peter and the Wolf/*");
+
+        assert_eq!(filter.includes().count(), 4);
+        assert_eq!(
+            filter.unmatchable_includes(),
+            vec!["kids/Peter", "and", "the"]
+        );
+        assert!(
+            filter
+                .split_path_warning()
+                .is_some_and(|warning| warning.contains("kids/Peter, and, the"))
+        );
+
+        for fine in [
+            "rock/* jazz/*",
+            "a.mp3 b.mp3",
+            "synthwave",
+            r"my\ band/* !live",
+            "track[0-9].mp3 {rock,jazz}",
+            "blues/[!x]*",
+        ] {
+            assert_eq!(Filter::parse(fine).split_path_warning(), None, "{fine}");
+        }
+    }
+
+    #[test]
+    fn relative_patterns_match_below_the_root() {
+        let matcher = Filter::parse("rock/* !**/live.mp3").matcher().unwrap();
+        let root = Path::new("/home/pi/music");
+
+        assert!(matcher.is_match_under(Path::new("/home/pi/music/rock/song.mp3"), root));
+        assert!(!matcher.is_match_under(Path::new("/home/pi/music/rock/live.mp3"), root));
+        assert!(!matcher.is_match_under(Path::new("/home/pi/music/rock/song.mp3"), Path::new("")));
+
+        let absolute = Filter::parse("/home/pi/music/rock/*").matcher().unwrap();
+        assert!(absolute.is_match_under(Path::new("/home/pi/music/rock/song.mp3"), root));
+        assert!(!absolute.is_match_under(Path::new("/srv/music/rock/song.mp3"), root));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let filter = Filter::parse("  rock/*   !*/live/*  jazz/* ");
+        assert_eq!(filter.to_string(), "rock/* !*/live/* jazz/*");
+        assert_eq!(Filter::parse(&filter.to_string()), filter);
+    }
+
+    #[test]
+    fn bare_bang_is_ignored() {
+        let filter = Filter::parse("rock/* !");
+        assert_eq!(filter.to_string(), "rock/*");
+    }
+}
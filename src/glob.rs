@@ -2,54 +2,168 @@ use std::path::Path;
 
 use regex::{Regex, RegexBuilder};
 
+use crate::library::source::is_http_url;
+
+/// Translates a glob into an anchored, case-insensitive regex.
+///
+/// `*` and `?` stay within one path segment, `**` matches across directories, and
+/// `**/` also matches no directory at all. `[abc]`, `[a-z]` and `[!abc]` (or `[^abc]`) match
+/// one character of a segment, and `{rock,jazz}` matches any of its comma-separated
+/// alternatives. A `[` or `{` without its closing bracket, or braces without a comma, match
+/// literally.
 pub fn glob_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
-    let mut regex_str = String::from("^");
-    let mut literal = String::new();
-    let mut chars = pattern.chars().peekable();
-
-    let flush_literal = |buf: &mut String, out: &mut String| {
-        if !buf.is_empty() {
-            out.push_str(&regex::escape(buf));
-            buf.clear();
+    let chars: Vec<char> = pattern.chars().collect();
+    RegexBuilder::new(&format!("^{}$", translate(&chars)))
+        .case_insensitive(true)
+        .build()
+}
+
+/// Regex syntax for `pattern`, without anchors.
+fn translate(pattern: &[char]) -> String {
+    let mut regex = String::new();
+    let mut idx = 0;
+    while idx < pattern.len() {
+        let rest = &pattern[idx..];
+        let (translated, len) = match rest {
+            ['*', '*', '/', ..] => ("(?:.*/)?".to_string(), 3),
+            ['*', '*', ..] => (".*".to_string(), 2),
+            ['*', ..] => ("[^/]*".to_string(), 1),
+            ['?', ..] => ("[^/]".to_string(), 1),
+            ['\\', next, ..] => (literal(*next), 2),
+            ['[', ..] => class(rest).unwrap_or_else(|| (literal('['), 1)),
+            ['{', ..] => alternatives(rest).unwrap_or_else(|| (literal('{'), 1)),
+            [ch, ..] => (literal(*ch), 1),
+            [] => break,
+        };
+        regex.push_str(&translated);
+        idx += len;
+    }
+    regex
+}
+
+fn literal(ch: char) -> String {
+    regex::escape(ch.encode_utf8(&mut [0; 4]))
+}
+
+/// Translates the character class at the start of `pattern` and returns it with the number
+/// of glob characters it spans, or `None` when it is never closed.
+fn class(pattern: &[char]) -> Option<(String, usize)> {
+    let mut idx = 1;
+    let negated = matches!(pattern.get(idx), Some('!' | '^'));
+    if negated {
+        idx += 1;
+    }
+    let mut items = String::new();
+    let mut first = true;
+    loop {
+        let (lo, len) = class_char(pattern, idx, first)?;
+        idx += len;
+        first = false;
+        items.push_str(&class_literal(lo));
+        if pattern.get(idx) == Some(&'-') && !matches!(pattern.get(idx + 1), Some(']') | None) {
+            let (hi, len) = class_char(pattern, idx + 1, false)?;
+            idx += 1 + len;
+            items.push('-');
+            items.push_str(&class_literal(hi));
         }
+        if pattern.get(idx) == Some(&']') {
+            break;
+        }
+    }
+    // Like `?`, a class never matches the path separator.
+    let regex = if negated {
+        format!("[^/{items}]")
+    } else {
+        format!("[{items}&&[^/]]")
     };
+    Some((regex, idx + 1))
+}
+
+/// The class member at `idx`, taking a backslash escape into account. A `]` right after the
+/// opening bracket is a member rather than the end of the class.
+fn class_char(pattern: &[char], idx: usize, first: bool) -> Option<(char, usize)> {
+    match pattern.get(idx)? {
+        '\\' => pattern.get(idx + 1).map(|ch| (*ch, 2)),
+        ']' if !first => None,
+        ch => Some((*ch, 1)),
+    }
+}
+
+fn class_literal(ch: char) -> String {
+    if matches!(ch, '\\' | '[' | ']' | '^' | '-' | '&' | '~') {
+        format!("\\{ch}")
+    } else {
+        ch.to_string()
+    }
+}
 
-    while let Some(ch) = chars.next() {
-        match ch {
-            '*' => {
-                flush_literal(&mut literal, &mut regex_str);
-                regex_str.push_str(".*");
+/// Translates the `{a,b}` group at the start of `pattern` and returns it with the number of
+/// glob characters it spans, or `None` when it is never closed or has no comma.
+fn alternatives(pattern: &[char]) -> Option<(String, usize)> {
+    let mut depth = 0;
+    let mut start = 1;
+    let mut parts = Vec::new();
+    let mut idx = 0;
+    while idx < pattern.len() {
+        match pattern[idx] {
+            '\\' => idx += 1,
+            '[' => {
+                if let Some((_, len)) = class(&pattern[idx..]) {
+                    idx += len - 1;
+                }
             }
-            '?' => {
-                flush_literal(&mut literal, &mut regex_str);
-                regex_str.push('.');
+            '{' => depth += 1,
+            ',' if depth == 1 => {
+                parts.push(translate(&pattern[start..idx]));
+                start = idx + 1;
             }
-            '\\' => {
-                flush_literal(&mut literal, &mut regex_str);
-                if let Some(next) = chars.next() {
-                    regex_str.push_str(&regex::escape(&next.to_string()));
-                } else {
-                    regex_str.push_str(r"\\");
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    if parts.is_empty() {
+                        return None;
+                    }
+                    parts.push(translate(&pattern[start..idx]));
+                    return Some((format!("(?:{})", parts.join("|")), idx + 1));
                 }
             }
-            _ => literal.push(ch),
+            _ => {}
         }
+        idx += 1;
     }
+    None
+}
 
-    flush_literal(&mut literal, &mut regex_str);
-    regex_str.push('$');
-
-    RegexBuilder::new(&regex_str).case_insensitive(true).build()
+/// Escapes glob metacharacters and whitespace so `value` matches literally inside a filter.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '*' | '?' | '\\' | '[' | ']' | '{' | '}' | '"') || ch.is_whitespace() {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
 }
 
 pub struct Glob {
     regex: Regex,
+    absolute: bool,
 }
 
 impl Glob {
     pub fn new(pattern: &str) -> Result<Self, regex::Error> {
         let regex = glob_to_regex(pattern)?;
-        Ok(Self { regex })
+        Ok(Self {
+            regex,
+            absolute: pattern.starts_with('/') || is_http_url(pattern),
+        })
+    }
+
+    /// Whether the pattern spells out a full path or URL rather than one relative to a
+    /// music directory.
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
     }
 
     pub fn is_match(&self, value: &str) -> bool {
@@ -64,7 +178,8 @@ impl Glob {
 
 #[cfg(test)]
 mod tests {
-    use super::{Glob, glob_to_regex};
+    use super::{Glob, escape, glob_to_regex};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
     use std::path::Path;
 
     fn glob_matches(pattern: &str, path: &Path) -> Result<bool, regex::Error> {
@@ -111,6 +226,40 @@ mod tests {
         assert!(!glob.is_match("music/rock/song12.mp3"));
     }
 
+    #[test]
+    fn star_stays_within_a_segment() {
+        let glob = Glob::new("music/*/song.mp3").unwrap();
+        assert!(glob.is_match("music/rock/song.mp3"));
+        assert!(!glob.is_match("music/rock/live/song.mp3"));
+        assert!(
+            !Glob::new("music/*")
+                .unwrap()
+                .is_match("music/rock/song.mp3")
+        );
+        assert!(
+            !Glob::new("music/rock?song.mp3")
+                .unwrap()
+                .is_match("music/rock/song.mp3")
+        );
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        let glob = Glob::new("music/**/song.mp3").unwrap();
+        assert!(glob.is_match("music/rock/song.mp3"));
+        assert!(glob.is_match("music/rock/live/song.mp3"));
+        assert!(glob.is_match("music/song.mp3"));
+        assert!(!glob.is_match("other/rock/song.mp3"));
+
+        let glob = Glob::new("music/**").unwrap();
+        assert!(glob.is_match("music/rock/live/song.mp3"));
+        assert!(
+            Glob::new("**.mp3")
+                .unwrap()
+                .is_match("/music/rock/song.mp3")
+        );
+    }
+
     #[test]
     fn escape_star_and_question() {
         let glob = Glob::new(r"file\*name\?").unwrap();
@@ -136,8 +285,8 @@ mod tests {
     #[test]
     fn glob_matches_path_helper() {
         let path = Path::new("/music/rock/anthem.mp3");
-        assert!(glob_matches("*/rock/*.mp3", path).unwrap());
-        assert!(!glob_matches("*/jazz/*.mp3", path).unwrap());
+        assert!(glob_matches("**/rock/*.mp3", path).unwrap());
+        assert!(!glob_matches("**/jazz/*.mp3", path).unwrap());
     }
 
     #[test]
@@ -162,4 +311,196 @@ mod tests {
         assert!(glob.is_match("ROCK/ANTHEM.MP3"));
         assert!(!glob.is_match("jazz/anthem.mp3"));
     }
+
+    #[test]
+    fn escaped_values_match_literally() {
+        let escaped = escape(r"/music/My Band?*\live");
+        assert_eq!(escaped, r"/music/My\ Band\?\*\\live");
+
+        let glob = Glob::new(&format!("{escaped}/**")).unwrap();
+        assert!(glob.is_match(r"/music/My Band?*\live/song.mp3"));
+        assert!(!glob.is_match(r"/music/My BandX*\live/song.mp3"));
+    }
+
+    #[test]
+    fn translates_to_the_expected_regexes() {
+        let cases = [
+            ("", "^$"),
+            ("*.mp3", r"^[^/]*\.mp3$"),
+            ("a?c", "^a[^/]c$"),
+            ("**", "^.*$"),
+            ("rock/**/live.mp3", r"^rock/(?:.*/)?live\.mp3$"),
+            ("**.mp3", r"^.*\.mp3$"),
+            ("[abc]", "^[abc&&[^/]]$"),
+            ("[a-c]", "^[a-c&&[^/]]$"),
+            ("[!a-c]", "^[^/a-c]$"),
+            ("[^x]", "^[^/x]$"),
+            ("[]]", r"^[\]&&[^/]]$"),
+            (r"[\]\-]", r"^[\]\-&&[^/]]$"),
+            ("[a-]", r"^[a\-&&[^/]]$"),
+            ("[*?]", "^[*?&&[^/]]$"),
+            ("{mp3,ogg}", "^(?:mp3|ogg)$"),
+            ("{a,{b,c}}", "^(?:a|(?:b|c))$"),
+            ("{*.mp3,**/x}", r"^(?:[^/]*\.mp3|(?:.*/)?x)$"),
+            ("{[,a],b}", "^(?:[,a&&[^/]]|b)$"),
+            ("{abc}", r"^\{abc\}$"),
+            ("[abc", r"^\[abc$"),
+            ("{a,b", r"^\{a,b$"),
+            (r"\[x\]\{y\}", r"^\[x\]\{y\}$"),
+            ("song.(v1)", r"^song\.\(v1\)$"),
+        ];
+
+        for (glob, expected) in cases {
+            assert_eq!(glob_to_regex(glob).unwrap().as_str(), expected, "{glob}");
+        }
+    }
+
+    #[test]
+    fn star_stays_in_a_segment_and_double_star_crosses_them() {
+        let one_level = Glob::new("rock/*/*.mp3").unwrap();
+        assert!(one_level.is_match("rock/live/a.mp3"));
+        assert!(!one_level.is_match("rock/a.mp3"));
+        assert!(!one_level.is_match("rock/live/1999/a.mp3"));
+
+        let any_level = Glob::new("rock/**/*.mp3").unwrap();
+        assert!(any_level.is_match("rock/a.mp3"));
+        assert!(any_level.is_match("rock/live/1999/a.mp3"));
+        assert!(!any_level.is_match("rocks/a.mp3"));
+
+        let trailing = Glob::new("rock/**").unwrap();
+        assert!(trailing.is_match("rock/"));
+        assert!(trailing.is_match("rock/live/a.mp3"));
+        assert!(!trailing.is_match("rock"));
+
+        assert!(Glob::new("*").unwrap().is_match("a.mp3"));
+        assert!(!Glob::new("*").unwrap().is_match("/a.mp3"));
+        assert!(Glob::new("**").unwrap().is_match("/music/rock/a.mp3"));
+    }
+
+    #[test]
+    fn classes_match_one_character_of_a_segment() {
+        let digits = Glob::new("track[0-9][0-9].mp3").unwrap();
+        assert!(digits.is_match("track07.mp3"));
+        assert!(!digits.is_match("track7.mp3"));
+        assert!(!digits.is_match("trackA7.mp3"));
+
+        let set = Glob::new("[abc].mp3").unwrap();
+        assert!(set.is_match("b.mp3"));
+        assert!(set.is_match("B.mp3"), "case-insensitive like the rest");
+        assert!(!set.is_match("d.mp3"));
+
+        let ranges = Glob::new("[a-cx-z]").unwrap();
+        assert!(ranges.is_match("y"));
+        assert!(!ranges.is_match("m"));
+
+        assert!(!Glob::new("a[/]b").unwrap().is_match("a/b"));
+        assert!(!Glob::new("a[!x]b").unwrap().is_match("a/b"));
+        assert!(!Glob::new("a[+-0]b").unwrap().is_match("a/b"));
+    }
+
+    #[test]
+    fn negated_classes_match_everything_else() {
+        for glob in ["[!0-9]*.mp3", "[^0-9]*.mp3"] {
+            let glob = Glob::new(glob).unwrap();
+            assert!(glob.is_match("intro.mp3"));
+            assert!(!glob.is_match("01 intro.mp3"));
+        }
+
+        let not_live = Glob::new("[!l]ive.mp3").unwrap();
+        assert!(not_live.is_match("hive.mp3"));
+        assert!(!not_live.is_match("live.mp3"));
+        assert!(!not_live.is_match("LIVE.mp3"));
+    }
+
+    #[test]
+    fn escaped_metacharacters_are_members_of_classes() {
+        let brackets = Glob::new(r"[\[\]]").unwrap();
+        assert!(brackets.is_match("["));
+        assert!(brackets.is_match("]"));
+        assert!(!brackets.is_match("a"));
+
+        let dash = Glob::new(r"a[\-x]b").unwrap();
+        assert!(dash.is_match("a-b"));
+        assert!(dash.is_match("axb"));
+        assert!(!dash.is_match("awb"), "escaped dash is not a range");
+
+        let leading_bracket = Glob::new("[]a]").unwrap();
+        assert!(leading_bracket.is_match("]"));
+        assert!(leading_bracket.is_match("a"));
+
+        let wildcards = Glob::new("[*?]").unwrap();
+        assert!(wildcards.is_match("*"));
+        assert!(wildcards.is_match("?"));
+        assert!(!wildcards.is_match("a"));
+
+        let backslash = Glob::new(r"[\\]").unwrap();
+        assert!(backslash.is_match("\\"));
+        assert!(!backslash.is_match("a"));
+
+        let set_operators = Glob::new("[&~^]").unwrap();
+        assert!(set_operators.is_match("&"));
+        assert!(set_operators.is_match("~"));
+        assert!(set_operators.is_match("^"));
+    }
+
+    #[test]
+    fn braces_match_any_alternative() {
+        let formats = Glob::new("**/*.{mp3,ogg,flac}").unwrap();
+        assert!(formats.is_match("rock/a.mp3"));
+        assert!(formats.is_match("rock/a.FLAC"));
+        assert!(!formats.is_match("rock/a.wav"));
+
+        let folders = Glob::new("{rock,jazz/**}/*.mp3").unwrap();
+        assert!(folders.is_match("rock/a.mp3"));
+        assert!(folders.is_match("jazz/trio/live/a.mp3"));
+        assert!(!folders.is_match("rock/live/a.mp3"));
+
+        let nested = Glob::new("{a,b{1,2}}.mp3").unwrap();
+        for name in ["a.mp3", "b1.mp3", "b2.mp3"] {
+            assert!(nested.is_match(name), "{name}");
+        }
+        assert!(!nested.is_match("b.mp3"));
+
+        let optional = Glob::new("song{,-live}.mp3").unwrap();
+        assert!(optional.is_match("song.mp3"));
+        assert!(optional.is_match("song-live.mp3"));
+
+        let escaped_comma = Glob::new(r"{a\,b,c}").unwrap();
+        assert!(escaped_comma.is_match("a,b"));
+        assert!(escaped_comma.is_match("c"));
+        assert!(!escaped_comma.is_match("a"));
+    }
+
+    #[test]
+    fn unfinished_or_empty_syntax_matches_literally() {
+        for literal in [
+            "[", "]", "{", "}", "[]", "{}", "[abc", "{a,b", "{abc}", "a[!]",
+        ] {
+            let glob = Glob::new(literal).unwrap();
+            assert!(glob.is_match(literal), "{literal}");
+        }
+        assert!(Glob::new("{,}").unwrap().is_match(""));
+        assert!(Glob::new("[z-a]").is_err(), "reversed range");
+    }
+
+    #[test]
+    fn escaped_strings_always_match_only_themselves() {
+        const ALPHABET: &[char] = &[
+            'a', 'B', '/', '.', '-', '*', '?', '[', ']', '{', '}', ',', '!', '^', '\\', ' ', '"',
+            '&', '~', '#', '(', ')', '|', '$', 'é',
+        ];
+        let mut rng = StdRng::seed_from_u64(212);
+
+        for _ in 0..500 {
+            let len = rng.random_range(0..12);
+            let value: String = (0..len)
+                .map(|_| ALPHABET[rng.random_range(0..ALPHABET.len())])
+                .collect();
+            let glob = Glob::new(&escape(&value)).unwrap();
+
+            assert!(glob.is_match(&value), "{value:?}");
+            assert!(!glob.is_match(&format!("{value}x")), "{value:?}");
+            assert!(!glob.is_match(&format!("x{value}")), "{value:?}");
+        }
+    }
 }
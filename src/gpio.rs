@@ -1,36 +1,76 @@
 use std::{
     sync::{Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use rppal::gpio::{Gpio, InputPin, Trigger};
+use rppal::gpio::{Gpio, InputPin, OutputPin, Trigger};
 use tracing::{debug, error, info};
 
-use tokio::sync::mpsc;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
 
-use crate::{commands::Command, config::GpioConfig};
+use crate::{commands::Command, config::GpioConfig, crabbox::StatusEvent};
 
 pub struct Button {
     _pin: InputPin,
 }
 
 impl Button {
+    /// With `software_debounce`, presses within `debounce` of the last accepted one are also
+    /// dropped in software, for buttons that still double trigger.
     pub fn new(
         gpio: &Gpio,
         pin_number: u8,
         debounce: Duration,
+        software_debounce: bool,
         on_press: impl Fn() + Send + Sync + 'static,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let mut pin = gpio.get(pin_number)?.into_input_pullup();
+        let filter = software_debounce.then(|| EdgeFilter::new(debounce));
         pin.set_async_interrupt(Trigger::FallingEdge, Some(debounce), move |_level| {
-            on_press();
+            if filter
+                .as_ref()
+                .is_none_or(|filter| filter.accept(Instant::now()))
+            {
+                on_press();
+            }
         })?;
 
         Ok(Self { _pin: pin })
     }
 }
 
+/// Drops edges that arrive within `window` of the previously accepted edge.
+pub struct EdgeFilter {
+    window: Duration,
+    last_accepted: Mutex<Option<Instant>>,
+}
+
+impl EdgeFilter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_accepted: Mutex::new(None),
+        }
+    }
+
+    pub fn accept(&self, now: Instant) -> bool {
+        let Ok(mut last_accepted) = self.last_accepted.lock() else {
+            return true;
+        };
+        if last_accepted.is_some_and(|last| now.saturating_duration_since(last) < self.window) {
+            return false;
+        }
+        *last_accepted = Some(now);
+        true
+    }
+}
+
+/// A button that fires `on_hold` once held for the hold duration and, when given, `on_short`
+/// if released before that.
 pub struct LongPressButton {
     _pin: InputPin,
     _timer: Arc<Mutex<Timer>>,
@@ -43,6 +83,7 @@ impl LongPressButton {
         debounce: Duration,
         hold_duration: Duration,
         on_hold: Arc<dyn Fn() + Send + Sync + 'static>,
+        on_short: Option<Arc<dyn Fn() + Send + Sync + 'static>>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let mut pin = gpio.get(pin_number)?.into_input_pullup();
         let timer = Arc::new(Mutex::new(Timer::new(hold_duration, on_hold)));
@@ -50,7 +91,13 @@ impl LongPressButton {
         pin.set_async_interrupt(Trigger::Both, Some(debounce), move |event| {
             match event.trigger {
                 Trigger::FallingEdge => Timer::arm(&timer_for_interrupt),
-                Trigger::RisingEdge => Timer::reset(&timer_for_interrupt),
+                Trigger::RisingEdge => {
+                    if Timer::reset(&timer_for_interrupt)
+                        && let Some(on_short) = &on_short
+                    {
+                        on_short();
+                    }
+                }
                 _ => {}
             }
         })?;
@@ -62,6 +109,164 @@ impl LongPressButton {
     }
 }
 
+/// Which way a rotary encoder was turned by one detent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Decodes the two-bit Gray code of a quadrature encoder. Contacts bounce and edges can be
+/// missed, so a step is only reported once a full cycle of four transitions ends back at
+/// the resting position, which is one detent on common encoders.
+#[derive(Debug)]
+pub struct QuadratureDecoder {
+    state: u8,
+    transitions: i8,
+}
+
+impl QuadratureDecoder {
+    /// Both pins are pulled up, so a resting encoder reads high on both.
+    const REST: u8 = 0b11;
+
+    pub fn new(clk: bool, dt: bool) -> Self {
+        Self {
+            state: Self::encode(clk, dt),
+            transitions: 0,
+        }
+    }
+
+    fn encode(clk: bool, dt: bool) -> u8 {
+        (u8::from(clk) << 1) | u8::from(dt)
+    }
+
+    /// Feeds the pin levels after an edge and returns the detent completed by it, if any.
+    pub fn update(&mut self, clk: bool, dt: bool) -> Option<Rotation> {
+        let next = Self::encode(clk, dt);
+        // Clockwise the clock pin leads: 11 -> 01 -> 00 -> 10 -> 11.
+        let delta = match (self.state, next) {
+            (0b11, 0b01) | (0b01, 0b00) | (0b00, 0b10) | (0b10, 0b11) => 1,
+            (0b11, 0b10) | (0b10, 0b00) | (0b00, 0b01) | (0b01, 0b11) => -1,
+            // No change, or both pins changed at once so the direction is unknown.
+            _ => 0,
+        };
+        self.state = next;
+        self.transitions = self.transitions.saturating_add(delta);
+
+        if next != Self::REST {
+            return None;
+        }
+        let rotation = match self.transitions {
+            4.. => Some(Rotation::Clockwise),
+            ..=-4 => Some(Rotation::CounterClockwise),
+            _ => None,
+        };
+        self.transitions = 0;
+        rotation
+    }
+}
+
+/// Pin levels and decoder shared by the interrupt handlers of both encoder pins.
+struct EncoderState {
+    clk: bool,
+    dt: bool,
+    decoder: QuadratureDecoder,
+}
+
+/// A quadrature rotary encoder that sends a command per detent in each direction.
+pub struct RotaryEncoder {
+    _clk: InputPin,
+    _dt: InputPin,
+}
+
+impl RotaryEncoder {
+    pub fn new(
+        gpio: &Gpio,
+        clk_pin: u8,
+        dt_pin: u8,
+        on_turn: impl Fn(Rotation) + Send + Sync + 'static,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut clk = gpio.get(clk_pin)?.into_input_pullup();
+        let mut dt = gpio.get(dt_pin)?.into_input_pullup();
+        let (clk_level, dt_level) = (clk.is_high(), dt.is_high());
+        let state = Arc::new(Mutex::new(EncoderState {
+            clk: clk_level,
+            dt: dt_level,
+            decoder: QuadratureDecoder::new(clk_level, dt_level),
+        }));
+        let on_turn = Arc::new(on_turn);
+
+        // No interrupt debounce: it would drop edges the decoder needs.
+        let clk_state = Arc::clone(&state);
+        let clk_turn = Arc::clone(&on_turn);
+        clk.set_async_interrupt(Trigger::Both, None, move |event| {
+            let rotation = clk_state.lock().ok().and_then(|mut state| {
+                state.clk = event.trigger == Trigger::RisingEdge;
+                let (clk, dt) = (state.clk, state.dt);
+                state.decoder.update(clk, dt)
+            });
+            if let Some(rotation) = rotation {
+                clk_turn(rotation);
+            }
+        })?;
+        dt.set_async_interrupt(Trigger::Both, None, move |event| {
+            let rotation = state.lock().ok().and_then(|mut state| {
+                state.dt = event.trigger == Trigger::RisingEdge;
+                let (clk, dt) = (state.clk, state.dt);
+                state.decoder.update(clk, dt)
+            });
+            if let Some(rotation) = rotation {
+                on_turn(rotation);
+            }
+        })?;
+
+        Ok(Self { _clk: clk, _dt: dt })
+    }
+}
+
+/// An LED that is lit while a track plays, following the player's status events.
+pub struct PlaybackLed {
+    _worker: thread::JoinHandle<()>,
+}
+
+impl PlaybackLed {
+    pub fn new(
+        gpio: &Gpio,
+        pin_number: u8,
+        active_low: bool,
+        mut events: broadcast::Receiver<StatusEvent>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut pin = gpio.get(pin_number)?.into_output();
+        set_led(&mut pin, false, active_low);
+
+        let worker = thread::spawn(move || {
+            loop {
+                match events.blocking_recv() {
+                    Ok(event) => {
+                        if let Some(playing) = event.playing() {
+                            set_led(&mut pin, playing, active_low);
+                        }
+                    }
+                    // Missed events only delay the LED until the next playback change.
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+            set_led(&mut pin, false, active_low);
+        });
+
+        Ok(Self { _worker: worker })
+    }
+}
+
+fn set_led(pin: &mut OutputPin, lit: bool, active_low: bool) {
+    if lit == active_low {
+        pin.set_low();
+    } else {
+        pin.set_high();
+    }
+}
+
 pub struct GpioController {
     _play: Option<Button>,
     _next: Option<Button>,
@@ -69,6 +274,9 @@ pub struct GpioController {
     _volume_up: Option<Button>,
     _volume_down: Option<Button>,
     _shutdown: Option<LongPressButton>,
+    _panic: Option<Button>,
+    _led: Option<PlaybackLed>,
+    _volume_encoder: Option<RotaryEncoder>,
 }
 
 impl GpioController {
@@ -76,13 +284,18 @@ impl GpioController {
     pub fn new(
         config: &GpioConfig,
         command_tx: &mpsc::Sender<Command>,
+        events: broadcast::Receiver<StatusEvent>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let pins_configured = config.play.is_some()
             || config.next.is_some()
             || config.prev.is_some()
             || config.volume_up.is_some()
             || config.volume_down.is_some()
-            || config.shutdown.is_some();
+            || config.shutdown.is_some()
+            || config.panic.is_some()
+            || config.led.is_some()
+            || config.volume_encoder_clk.is_some()
+            || config.volume_encoder_dt.is_some();
 
         if !pins_configured {
             info!("GPIO control disabled: no pins configured");
@@ -93,12 +306,28 @@ impl GpioController {
                 _volume_up: None,
                 _volume_down: None,
                 _shutdown: None,
+                _panic: None,
+                _led: None,
+                _volume_encoder: None,
             });
         }
 
         let gpio = Gpio::new()?;
         let debounce_duration = Duration::from_millis(config.debounce_ms);
 
+        let play_command = button_command(
+            config.play_command.as_ref(),
+            Command::PlayPause { filter: None },
+        );
+        let next_command = button_command(config.next_command.as_ref(), Command::Next);
+        let prev_command = button_command(config.prev_command.as_ref(), Command::Prev);
+        let volume_up_command =
+            button_command(config.volume_up_command.as_ref(), Command::VolumeUp);
+        let volume_down_command =
+            button_command(config.volume_down_command.as_ref(), Command::VolumeDown);
+        let shutdown_command = button_command(config.shutdown_command.as_ref(), Command::Shutdown);
+        let shutdown_hold = Duration::from_secs(config.shutdown_hold_secs);
+
         let play = config
             .play
             .map(|pin| {
@@ -106,7 +335,8 @@ impl GpioController {
                     &gpio,
                     pin,
                     debounce_duration,
-                    make_sender(command_tx, Command::PlayPause { filter: None }, "PlayPause"),
+                    config.software_debounce,
+                    make_sender(command_tx, play_command.clone()),
                 )
             })
             .transpose()?;
@@ -118,7 +348,8 @@ impl GpioController {
                     &gpio,
                     pin,
                     debounce_duration,
-                    make_sender(command_tx, Command::Next, "Next"),
+                    config.software_debounce,
+                    make_sender(command_tx, next_command.clone()),
                 )
             })
             .transpose()?;
@@ -130,7 +361,8 @@ impl GpioController {
                     &gpio,
                     pin,
                     debounce_duration,
-                    make_sender(command_tx, Command::Prev, "Prev"),
+                    config.software_debounce,
+                    make_sender(command_tx, prev_command.clone()),
                 )
             })
             .transpose()?;
@@ -142,7 +374,8 @@ impl GpioController {
                     &gpio,
                     pin,
                     debounce_duration,
-                    make_sender(command_tx, Command::VolumeUp, "VolumeUp"),
+                    config.software_debounce,
+                    make_sender(command_tx, volume_up_command.clone()),
                 )
             })
             .transpose()?;
@@ -154,7 +387,8 @@ impl GpioController {
                     &gpio,
                     pin,
                     debounce_duration,
-                    make_sender(command_tx, Command::VolumeDown, "VolumeDown"),
+                    config.software_debounce,
+                    make_sender(command_tx, volume_down_command.clone()),
                 )
             })
             .transpose()?;
@@ -166,33 +400,111 @@ impl GpioController {
                     &gpio,
                     pin,
                     debounce_duration,
-                    Duration::from_secs(5),
-                    Arc::new(make_sender(
-                        command_tx,
-                        Command::Shutdown,
-                        "Shutdown (long press)",
-                    )),
+                    shutdown_hold,
+                    Arc::new(make_sender(command_tx, shutdown_command.clone())),
+                    config.shutdown_short_command.clone().map(|command| {
+                        Arc::new(make_sender(command_tx, command))
+                            as Arc<dyn Fn() + Send + Sync + 'static>
+                    }),
+                )
+            })
+            .transpose()?;
+
+        let panic = config
+            .panic
+            .as_ref()
+            .map(|panic| {
+                Button::new(
+                    &gpio,
+                    panic.pin,
+                    debounce_duration,
+                    config.software_debounce,
+                    make_sequence_sender(command_tx, panic.commands.clone()),
                 )
             })
             .transpose()?;
 
+        let volume_encoder = match (config.volume_encoder_clk, config.volume_encoder_dt) {
+            (Some(clk), Some(dt)) => {
+                let volume_up = make_sender(command_tx, Command::VolumeUp);
+                let volume_down = make_sender(command_tx, Command::VolumeDown);
+                Some(RotaryEncoder::new(
+                    &gpio,
+                    clk,
+                    dt,
+                    move |rotation| match rotation {
+                        Rotation::Clockwise => volume_up(),
+                        Rotation::CounterClockwise => volume_down(),
+                    },
+                )?)
+            }
+            (None, None) => None,
+            _ => {
+                return Err("volume_encoder_clk and volume_encoder_dt must be set together".into());
+            }
+        };
+
+        let led = config
+            .led
+            .map(|pin| PlaybackLed::new(&gpio, pin, config.led_active_low, events))
+            .transpose()?;
+
         if let Some(pin) = config.play {
-            info!("GPIO control enabled (play/pause pin {})", pin);
+            info!(
+                "GPIO control enabled (play/pause pin {}, command {})",
+                pin, play_command
+            );
         }
         if let Some(pin) = config.next {
-            info!("GPIO control enabled (next pin {})", pin);
+            info!(
+                "GPIO control enabled (next pin {}, command {})",
+                pin, next_command
+            );
         }
         if let Some(pin) = config.prev {
-            info!("GPIO control enabled (prev pin {})", pin);
+            info!(
+                "GPIO control enabled (prev pin {}, command {})",
+                pin, prev_command
+            );
         }
         if let Some(pin) = config.volume_up {
-            info!("GPIO control enabled (volume up pin {})", pin);
+            info!(
+                "GPIO control enabled (volume up pin {}, command {})",
+                pin, volume_up_command
+            );
         }
         if let Some(pin) = config.volume_down {
-            info!("GPIO control enabled (volume down pin {})", pin);
+            info!(
+                "GPIO control enabled (volume down pin {}, command {})",
+                pin, volume_down_command
+            );
         }
         if let Some(pin) = config.shutdown {
-            info!("GPIO control enabled (shutdown pin {}, hold 5s)", pin);
+            info!(
+                "GPIO control enabled (shutdown pin {}, hold {}s, command {})",
+                pin, config.shutdown_hold_secs, shutdown_command
+            );
+            if let Some(command) = &config.shutdown_short_command {
+                info!("GPIO control enabled (shutdown pin {pin} short press, command {command})");
+            }
+        }
+        if let Some(panic) = config.panic.as_ref() {
+            info!(
+                "GPIO control enabled (panic pin {}, {} commands)",
+                panic.pin,
+                panic.commands.len()
+            );
+        }
+
+        if let (Some(clk), Some(dt)) = (config.volume_encoder_clk, config.volume_encoder_dt) {
+            info!("GPIO control enabled (volume encoder clk pin {clk}, dt pin {dt})");
+        }
+        if let Some(pin) = config.led {
+            info!(
+                "GPIO playback LED enabled (pin {}, active {})",
+                pin,
+                if config.led_active_low { "low" } else { "high" }
+            );
         }
 
         Ok(Self {
@@ -202,6 +514,9 @@ impl GpioController {
             _volume_up: volume_up,
             _volume_down: volume_down,
             _shutdown: shutdown,
+            _panic: panic,
+            _led: led,
+            _volume_encoder: volume_encoder,
         })
     }
 }
@@ -211,6 +526,8 @@ pub struct Timer {
     duration: Duration,
     on_fire: Arc<dyn Fn() + Send + Sync + 'static>,
     generation: u64,
+    /// Armed and not yet fired.
+    pending: bool,
 }
 
 impl Timer {
@@ -219,6 +536,7 @@ impl Timer {
             duration,
             on_fire,
             generation: 0,
+            pending: false,
         }
     }
 
@@ -226,6 +544,7 @@ impl Timer {
         let (generation, duration, on_fire, timer_ref) = {
             let mut guard = timer.lock().expect("timer lock poisoned");
             guard.generation = guard.generation.wrapping_add(1);
+            guard.pending = true;
             (
                 guard.generation,
                 guard.duration,
@@ -238,7 +557,13 @@ impl Timer {
             thread::sleep(duration);
             let should_fire = timer_ref
                 .lock()
-                .map(|t| t.generation == generation)
+                .map(|mut t| {
+                    let current = t.generation == generation;
+                    if current {
+                        t.pending = false;
+                    }
+                    current
+                })
                 .unwrap_or(false);
             if should_fire {
                 on_fire();
@@ -246,23 +571,41 @@ impl Timer {
         });
     }
 
-    pub fn reset(timer: &Arc<Mutex<Self>>) {
-        if let Ok(mut guard) = timer.lock() {
-            guard.generation = guard.generation.wrapping_add(1);
-        }
+    /// Cancels the timer, returning whether it was armed and had not fired yet, i.e. whether
+    /// a press ended before the hold duration.
+    pub fn reset(timer: &Arc<Mutex<Self>>) -> bool {
+        let Ok(mut guard) = timer.lock() else {
+            return false;
+        };
+        guard.generation = guard.generation.wrapping_add(1);
+        std::mem::replace(&mut guard.pending, false)
     }
 }
 
+fn button_command(configured: Option<&Command>, default: Command) -> Command {
+    configured.cloned().unwrap_or(default)
+}
+
 fn make_sender(
     command_tx: &mpsc::Sender<Command>,
     cmd: Command,
-    label: &'static str,
+) -> impl Fn() + Send + Sync + 'static {
+    make_sequence_sender(command_tx, vec![cmd])
+}
+
+/// Sends every command in order on each press, stopping at the first failed send.
+fn make_sequence_sender(
+    command_tx: &mpsc::Sender<Command>,
+    commands: Vec<Command>,
 ) -> impl Fn() + Send + Sync + 'static {
     let sender = command_tx.clone();
     move || {
-        debug!("{label}");
-        if let Err(err) = sender.blocking_send(cmd.clone()) {
-            error!("Failed to send {label} command from GPIO interrupt: {err}");
+        for cmd in &commands {
+            debug!("GPIO {cmd}");
+            if let Err(err) = sender.blocking_send(cmd.clone()) {
+                error!("Failed to send {cmd} command from GPIO interrupt: {err}");
+                return;
+            }
         }
     }
 }
@@ -272,3 +615,116 @@ impl Drop for GpioController {
         info!("GPIO control stopped");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Feeds `(clk, dt)` levels to a decoder resting with both pins high.
+    fn decode(levels: &[(u8, u8)]) -> Vec<Rotation> {
+        let mut decoder = QuadratureDecoder::new(true, true);
+        levels
+            .iter()
+            .filter_map(|&(clk, dt)| decoder.update(clk == 1, dt == 1))
+            .collect()
+    }
+
+    #[test]
+    fn decoder_reports_one_step_per_detent() {
+        let clockwise = [(0, 1), (0, 0), (1, 0), (1, 1)];
+        let counter_clockwise = [(1, 0), (0, 0), (0, 1), (1, 1)];
+
+        assert_eq!(decode(&clockwise), [Rotation::Clockwise]);
+        assert_eq!(decode(&counter_clockwise), [Rotation::CounterClockwise]);
+        assert_eq!(
+            decode(&[clockwise, clockwise, counter_clockwise].concat()),
+            [
+                Rotation::Clockwise,
+                Rotation::Clockwise,
+                Rotation::CounterClockwise
+            ]
+        );
+    }
+
+    #[test]
+    fn decoder_ignores_bounce_and_half_turns() {
+        // The clock contact bounces before the turn goes through.
+        let bouncy = [(0, 1), (1, 1), (0, 1), (0, 0), (1, 0), (1, 1)];
+        assert_eq!(decode(&bouncy), [Rotation::Clockwise]);
+
+        // Turned halfway and let go back to the detent.
+        let half = [(0, 1), (0, 0), (0, 1), (1, 1)];
+        assert_eq!(decode(&half), []);
+    }
+
+    fn counting_timer(hold: Duration) -> (Arc<Mutex<Timer>>, Arc<AtomicUsize>) {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&fired);
+        let timer = Timer::new(
+            hold,
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+        );
+        (Arc::new(Mutex::new(timer)), fired)
+    }
+
+    #[test]
+    fn edge_filter_drops_edges_within_the_window() {
+        let filter = EdgeFilter::new(Duration::from_millis(200));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let accepted: Vec<u64> = [0, 5, 150, 199, 200, 260, 450]
+            .into_iter()
+            .filter(|&millis| filter.accept(at(millis)))
+            .collect();
+        // Rejected edges don't extend the window.
+        assert_eq!(accepted, [0, 200, 450]);
+    }
+
+    #[test]
+    fn releasing_before_the_hold_is_a_short_press() {
+        let (timer, fired) = counting_timer(Duration::from_millis(200));
+
+        Timer::arm(&timer);
+        assert!(Timer::reset(&timer));
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+
+        // A release without a press (e.g. contact bounce) is neither.
+        assert!(!Timer::reset(&timer));
+    }
+
+    #[test]
+    fn holding_fires_once_without_a_short_press() {
+        let (timer, fired) = counting_timer(Duration::from_millis(20));
+
+        Timer::arm(&timer);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+        assert!(!Timer::reset(&timer));
+    }
+
+    #[test]
+    fn sequence_sender_emits_all_commands_in_order() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let on_press = make_sequence_sender(
+            &tx,
+            vec![Command::Stop, Command::VolumeDown, Command::VolumeDown],
+        );
+
+        on_press();
+
+        let mut received = Vec::new();
+        while let Ok(cmd) = rx.try_recv() {
+            received.push(cmd);
+        }
+        assert_eq!(
+            received,
+            vec![Command::Stop, Command::VolumeDown, Command::VolumeDown]
+        );
+    }
+}
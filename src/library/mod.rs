@@ -0,0 +1,10 @@
+pub mod source;
+
+/// Extensions treated as music when a directory doesn't list its own.
+const MUSIC_EXTENSIONS: &[&str] = &["mp3", "flac", "wav", "ogg", "m4a", "aac", "opus", "alac"];
+
+pub fn is_music_extension(ext: &str) -> bool {
+    MUSIC_EXTENSIONS
+        .iter()
+        .any(|music| music.eq_ignore_ascii_case(ext))
+}
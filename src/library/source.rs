@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use regex::Regex;
+use tracing::warn;
+use ureq::Agent;
+use walkdir::WalkDir;
+
+use crate::{config::MusicDirectory, cue};
+
+/// Largest remote track downloaded for playback.
+const MAX_REMOTE_TRACK_BYTES: u64 = 512 * 1024 * 1024;
+/// How many directory levels below the root an HTTP index is followed.
+const MAX_INDEX_DEPTH: usize = 16;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+static AGENT: LazyLock<Agent> = LazyLock::new(|| {
+    Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .build()
+        .new_agent()
+});
+
+static HREF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)href\s*=\s*["']([^"']+)["']"#).expect("valid regex"));
+
+/// Somewhere library tracks are listed from.
+pub trait MusicSource {
+    /// Every track the source offers, in no particular order.
+    fn list_tracks(&self) -> Vec<PathBuf>;
+}
+
+/// Picks the source for a configured music directory: an HTTP index for `http(s)://` URLs,
+/// otherwise the local filesystem.
+pub fn for_directory(dir: &MusicDirectory) -> Box<dyn MusicSource + '_> {
+    match dir.url() {
+        Some(url) => Box::new(HttpIndexSource::new(dir, url)),
+        None => Box::new(LocalSource::new(dir)),
+    }
+}
+
+pub struct LocalSource<'a> {
+    dir: &'a MusicDirectory,
+}
+
+impl<'a> LocalSource<'a> {
+    pub fn new(dir: &'a MusicDirectory) -> Self {
+        Self { dir }
+    }
+
+    /// Walks the directory, descending into subdirectories only when `recursive` is set.
+    pub fn walker(&self) -> walkdir::IntoIter {
+        let walker = WalkDir::new(&self.dir.dir);
+        if self.dir.recursive {
+            walker.into_iter()
+        } else {
+            walker.max_depth(1).into_iter()
+        }
+    }
+}
+
+impl MusicSource for LocalSource<'_> {
+    /// Music files, with files that a `.cue` sheet splits up listed as its tracks instead.
+    fn list_tracks(&self) -> Vec<PathBuf> {
+        let mut tracks = Vec::new();
+        let mut sheets = Vec::new();
+        for entry in self.walker().filter_map(Result::ok) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            match entry.path().extension().and_then(|os| os.to_str()) {
+                Some(ext) if ext.eq_ignore_ascii_case("cue") => sheets.push(entry.into_path()),
+                Some(ext) if self.dir.accepts_extension(ext) => tracks.push(entry.into_path()),
+                _ => {}
+            }
+        }
+        cue::split_tracks(tracks, &sheets)
+    }
+}
+
+/// Lists tracks from the HTML directory index a web server (nginx, Apache, `python -m
+/// http.server`) generates, following links to subdirectories under the root URL.
+pub struct HttpIndexSource<'a> {
+    dir: &'a MusicDirectory,
+    root: String,
+}
+
+impl<'a> HttpIndexSource<'a> {
+    pub fn new(dir: &'a MusicDirectory, url: &str) -> Self {
+        let root = if url.ends_with('/') {
+            url.to_string()
+        } else {
+            format!("{url}/")
+        };
+        Self { dir, root }
+    }
+
+    fn accepts(&self, url: &str) -> bool {
+        let name = url.rsplit('/').next().unwrap_or_default();
+        Path::new(name)
+            .extension()
+            .and_then(|os| os.to_str())
+            .is_some_and(|ext| self.dir.accepts_extension(ext))
+    }
+}
+
+impl MusicSource for HttpIndexSource<'_> {
+    fn list_tracks(&self) -> Vec<PathBuf> {
+        let max_depth = if self.dir.recursive {
+            MAX_INDEX_DEPTH
+        } else {
+            0
+        };
+        let mut tracks = Vec::new();
+        let mut visited = HashSet::from([self.root.clone()]);
+        let mut pending = VecDeque::from([(self.root.clone(), 0)]);
+
+        while let Some((page, depth)) = pending.pop_front() {
+            let html = match fetch_text(&page) {
+                Ok(html) => html,
+                Err(err) => {
+                    warn!(url = page, "Failed to read music index: {err}");
+                    continue;
+                }
+            };
+
+            for link in index_links(&page, &html) {
+                if !link.starts_with(&self.root) {
+                    continue;
+                }
+                if link.ends_with('/') {
+                    if depth < max_depth && visited.insert(link.clone()) {
+                        pending.push_back((link, depth + 1));
+                    }
+                } else if self.accepts(&link) {
+                    tracks.push(PathBuf::from(link));
+                }
+            }
+        }
+
+        tracks
+    }
+}
+
+/// Whether `path` is a track URL rather than a local file.
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str().is_some_and(is_http_url)
+}
+
+pub fn is_http_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+/// Downloads a remote track so it can be decoded from memory.
+pub fn fetch_track(path: &Path) -> Result<Vec<u8>, String> {
+    let url = path.to_string_lossy();
+    AGENT
+        .get(url.as_ref())
+        .call()
+        .map_err(|err| format!("Failed to fetch {url}: {err}"))?
+        .into_body()
+        .with_config()
+        .limit(MAX_REMOTE_TRACK_BYTES)
+        .read_to_vec()
+        .map_err(|err| format!("Failed to download {url}: {err}"))
+}
+
+fn fetch_text(url: &str) -> Result<String, ureq::Error> {
+    AGENT.get(url).call()?.into_body().read_to_string()
+}
+
+/// Absolute URLs of the links on an index page, without queries or fragments.
+fn index_links(page: &str, html: &str) -> Vec<String> {
+    HREF.captures_iter(html)
+        .filter_map(|captures| {
+            let href = captures[1].replace("&amp;", "&");
+            let href = href.split(['?', '#']).next().unwrap_or_default();
+            resolve_href(page, href)
+        })
+        .collect()
+}
+
+/// Resolves `href` against the index page URL `page`, which ends with `/`.
+fn resolve_href(page: &str, href: &str) -> Option<String> {
+    if href.is_empty() || href.starts_with("../") || href == ".." {
+        return None;
+    }
+    if is_http_url(href) {
+        return Some(href.to_string());
+    }
+    if href.contains(':') && !href.starts_with('/') && !href.starts_with("./") {
+        // mailto:, javascript:, and other schemes.
+        return None;
+    }
+    if let Some(path) = href.strip_prefix('/') {
+        let scheme_end = page.find("://")? + 3;
+        let host_end = page[scheme_end..]
+            .find('/')
+            .map_or(page.len(), |idx| scheme_end + idx);
+        return Some(format!("{}/{path}", &page[..host_end]));
+    }
+    Some(format!("{page}{}", href.trim_start_matches("./")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_links, resolve_href};
+
+    #[test]
+    fn resolves_index_links_against_the_page() {
+        let page = "http://nas:8000/music/";
+        assert_eq!(
+            resolve_href(page, "song.mp3").as_deref(),
+            Some("http://nas:8000/music/song.mp3")
+        );
+        assert_eq!(
+            resolve_href(page, "./album/").as_deref(),
+            Some("http://nas:8000/music/album/")
+        );
+        assert_eq!(
+            resolve_href(page, "/music/other.flac").as_deref(),
+            Some("http://nas:8000/music/other.flac")
+        );
+        assert_eq!(resolve_href(page, "../"), None);
+        assert_eq!(resolve_href(page, "mailto:admin@nas"), None);
+    }
+
+    #[test]
+    fn extracts_links_from_an_autoindex_page() {
+        let html = r#"<html><body><h1>Index of /music/</h1><hr><pre>
+<a href="../">../</a>
+<a href="Kids%20Songs/">Kids Songs/</a>
+<a href="intro.mp3">intro.mp3</a>
+<a href="?C=M;O=A">Last modified</a>
+<a HREF='cover.jpg'>cover.jpg</a>
+</pre></body></html>"#;
+
+        assert_eq!(
+            index_links("http://nas/music/", html),
+            vec![
+                "http://nas/music/Kids%20Songs/",
+                "http://nas/music/intro.mp3",
+                "http://nas/music/cover.jpg",
+            ]
+        );
+    }
+}
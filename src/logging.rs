@@ -0,0 +1,210 @@
+use std::{env, io};
+
+use clap::ValueEnum;
+use tracing::{Level, Subscriber, info, level_filters::LevelFilter, warn};
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, layer::SubscriberExt, reload};
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines (default).
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+/// The installed global subscriber, whose filter can still be swapped for the one in the
+/// config file once that is loaded.
+pub struct Logging {
+    filter: reload::Handle<EnvFilter, Registry>,
+    default_level: Level,
+}
+
+impl Logging {
+    /// Installs the global subscriber, logging to stdout. Filters by `RUST_LOG` when set,
+    /// otherwise logs everything up to `default_level`.
+    pub fn init(format: LogFormat, default_level: Level) -> Self {
+        let rust_log = env::var(EnvFilter::DEFAULT_ENV)
+            .ok()
+            .map(|directives| parse_filter(&directives, default_level));
+        let (filter, rust_log_error) = match rust_log {
+            Some(Ok(filter)) => (filter, None),
+            Some(Err(err)) => (level_filter(default_level), Some(err)),
+            None => (level_filter(default_level), None),
+        };
+        let (subscriber, handle) = subscriber(format, filter, io::stdout);
+        tracing::subscriber::set_global_default(subscriber)
+            .expect("failed to set global tracing subscriber");
+        if let Some(err) = rust_log_error {
+            warn!("Ignoring invalid RUST_LOG: {err}");
+        }
+        Self {
+            filter: handle,
+            default_level,
+        }
+    }
+
+    /// Switches to the `[server].log` directives, unless `RUST_LOG` is set.
+    pub fn apply_config(&self, directives: Option<&str>) {
+        let Some(directives) = directives else {
+            return;
+        };
+        if env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+            info!("RUST_LOG is set; ignoring [server].log");
+            return;
+        }
+        match parse_filter(directives, self.default_level) {
+            Ok(filter) => {
+                if let Err(err) = self.filter.reload(filter) {
+                    warn!("Failed to apply [server].log: {err}");
+                }
+            }
+            Err(err) => warn!("Ignoring invalid [server].log: {err}"),
+        }
+    }
+}
+
+/// Parses `RUST_LOG`-style directives such as `crabbox=info,crabbox::rfid=warn`. Targets
+/// without a directive log up to `default_level`.
+pub fn parse_filter(directives: &str, default_level: Level) -> Result<EnvFilter, String> {
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(default_level).into())
+        .parse(directives)
+        .map_err(|err| err.to_string())
+}
+
+fn level_filter(level: Level) -> EnvFilter {
+    EnvFilter::default().add_directive(LevelFilter::from_level(level).into())
+}
+
+/// Builds a subscriber writing `format` lines that pass `filter` to `writer`, along with a
+/// handle for replacing the filter.
+pub fn subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+) -> (
+    Box<dyn Subscriber + Send + Sync>,
+    reload::Handle<EnvFilter, Registry>,
+)
+where
+    W: for<'writer> fmt::MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(filter);
+    let layer = fmt::layer()
+        .with_file(true)
+        .with_line_number(true)
+        .with_writer(writer);
+    let layer = match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    (
+        Box::new(Registry::default().with(filter).with(layer)),
+        handle,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use tempfile::tempdir;
+    use tracing::{Level, debug, info, warn};
+    use tracing_subscriber::EnvFilter;
+
+    use super::{LogFormat, level_filter, parse_filter, subscriber};
+    use crate::config::Config;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().expect("capture lock").extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture(format: LogFormat, filter: EnvFilter) -> Vec<String> {
+        let output = Capture::default();
+        let writer = output.clone();
+        let (subscriber, _) = subscriber(format, filter, move || writer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            info!(track = "a.mp3", "Playing");
+            debug!("Preloading");
+            debug!(target: "crabbox::rfid", "Polling");
+            warn!(target: "crabbox::rfid", "Reader lost");
+        });
+        let bytes = output.0.lock().expect("capture lock").clone();
+        String::from_utf8(bytes)
+            .expect("utf-8 logs")
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn json_format_writes_one_object_per_event() {
+        let lines = capture(LogFormat::Json, level_filter(Level::DEBUG));
+
+        assert_eq!(lines.len(), 4, "{lines:?}");
+        let event: serde_json::Value = serde_json::from_str(&lines[0]).expect("json line");
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["fields"]["message"], "Playing");
+        assert_eq!(event["fields"]["track"], "a.mp3");
+        assert!(event["line_number"].is_number());
+    }
+
+    #[test]
+    fn text_format_respects_the_max_level() {
+        let lines = capture(LogFormat::Text, level_filter(Level::INFO));
+
+        assert_eq!(lines.len(), 2, "{lines:?}");
+        assert!(lines[0].contains("INFO"), "{lines:?}");
+        assert!(lines[0].contains("Playing"), "{lines:?}");
+        assert!(serde_json::from_str::<serde_json::Value>(&lines[0]).is_err());
+    }
+
+    #[test]
+    fn config_log_directives_quiet_individual_modules() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "[[music]]\ndir = {:?}\n\n[server]\nweb = \"127.0.0.1:8080\"\n\
+                 log = \"debug,crabbox::rfid=warn\"\n",
+                tmp.path()
+            ),
+        )
+        .expect("write config");
+        let config = Config::load(&path).expect("load config");
+        let directives = config.server.log.as_deref().expect("log directives");
+
+        let filter = parse_filter(directives, Level::INFO).expect("valid directives");
+        let lines = capture(LogFormat::Json, filter);
+
+        let messages: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                let event: serde_json::Value = serde_json::from_str(line).expect("json line");
+                event["fields"]["message"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(messages, vec!["Playing", "Preloading", "Reader lost"]);
+        assert!(parse_filter("crabbox=loud", Level::INFO).is_err());
+    }
+}
@@ -1,26 +1,30 @@
 #![warn(clippy::pedantic)]
 
-use std::{
-    net::SocketAddr,
-    path::{Path, PathBuf},
-    process::ExitCode,
-    sync::Arc,
-    thread,
-};
+use std::{net::SocketAddr, path::PathBuf, process::ExitCode, sync::Arc};
 
 use clap::{Args, Parser, Subcommand};
 use serde::Serialize;
-use tracing::{error, info};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{Level, error, info};
 
 mod commands;
 mod config;
 mod crabbox;
+mod cue;
+mod filter;
 mod glob;
+mod library;
+mod logging;
+mod metadata;
+mod mixer;
 mod pipe;
 mod player;
+mod playlist;
+mod playtime;
+mod shutdown;
 mod state;
 mod tag;
+mod track_volume;
+mod validate;
 mod web;
 
 #[cfg(feature = "rpi")]
@@ -29,11 +33,13 @@ mod gpio;
 mod rfid;
 
 use config::Config;
-use crabbox::Crabbox;
+use crabbox::{Crabbox, Library};
+use filter::Filter;
 #[cfg(feature = "rpi")]
 use gpio::GpioController;
+use logging::{LogFormat, Logging};
 use pipe::serve_control_pipe;
-use player::play_blocking;
+use player::output_device_names;
 #[cfg(feature = "rpi")]
 use rfid::Reader;
 use web::serve_web;
@@ -84,11 +90,23 @@ pub(crate) const BUILD_INFO: BuildInfo = BuildInfo {
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Log line format
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Most verbose level to log: error, warn, info, debug or trace
+    #[arg(long, global = true, default_value_t = Level::DEBUG)]
+    log_level: Level,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Server(ServerArgs),
+    /// Check a config file without starting the server
+    Validate(ValidateArgs),
+    /// Send one command to a running server through its control pipe
+    Ctl(CtlArgs),
+    /// Print the library tracks matching a filter, e.g. to check a glob for a tag
+    List(ListArgs),
 }
 
 #[derive(Args)]
@@ -97,15 +115,39 @@ struct ServerArgs {
     config: PathBuf,
 }
 
+#[derive(Args)]
+struct ValidateArgs {
+    /// Path to the TOML configuration file
+    config: PathBuf,
+}
+
+#[derive(Args)]
+struct CtlArgs {
+    /// Path to the TOML configuration file of the running server
+    config: PathBuf,
+    /// Command to send, e.g. "PLAY rock/*"
+    command: String,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Path to the TOML configuration file
+    config: PathBuf,
+    /// Filter with the same syntax as PLAY, e.g. "rock/*"; lists everything when omitted
+    filter: Option<String>,
+}
+
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> ExitCode {
-    init_tracing();
-    log_build_info();
-
     let cli = Cli::parse();
+    let logging = Logging::init(cli.log_format, cli.log_level);
+    log_build_info();
 
     let result = match cli.command {
-        Commands::Server(args) => run_server(&args).await,
+        Commands::Server(args) => run_server(&args, &logging).await,
+        Commands::Validate(args) => run_validate(&args),
+        Commands::Ctl(args) => run_ctl(&args),
+        Commands::List(args) => run_list(&args),
     };
 
     match result {
@@ -117,13 +159,11 @@ async fn main() -> ExitCode {
     }
 }
 
-async fn run_server(args: &ServerArgs) -> AnyResult<()> {
+async fn run_server(args: &ServerArgs, logging: &Logging) -> AnyResult<()> {
     let config = Config::load(&args.config)?;
+    logging.apply_config(config.server.log.as_deref());
 
-    if let Some(startup_sound) = config.server.startup_sound.as_ref() {
-        play_startup_sound(startup_sound.as_path(), config.default_volume);
-    }
-
+    info!("Audio outputs: {}", output_device_names().join(", "));
     for entry in &config.music {
         info!("Music directory: {}", entry.dir.display());
     }
@@ -139,9 +179,10 @@ async fn run_server(args: &ServerArgs) -> AnyResult<()> {
     {
         info!("Starting control pipe at {}", pipe_path.display());
         let path = pipe_path.to_owned();
+        let response_path = config.server.pipe_response.clone();
         let sender = command_sender.clone();
         tokio::spawn(async move {
-            if let Err(err) = serve_control_pipe(path, sender).await {
+            if let Err(err) = serve_control_pipe(path, response_path, sender).await {
                 error!("Control pipe failed: {err}");
             }
         });
@@ -149,7 +190,8 @@ async fn run_server(args: &ServerArgs) -> AnyResult<()> {
 
     #[cfg(feature = "rpi")]
     let _gpio_controller = if let Some(gpio_cfg) = config.gpio.as_ref() {
-        Some(GpioController::new(gpio_cfg, &command_sender)?)
+        let events = crabbox.lock().expect("crabbox lock poisoned").subscribe();
+        Some(GpioController::new(gpio_cfg, &command_sender, events)?)
     } else {
         None
     };
@@ -162,18 +204,62 @@ async fn run_server(args: &ServerArgs) -> AnyResult<()> {
 
     let web_addr: SocketAddr = config.server.web.parse()?;
     info!("Starting web control interface at http://{web_addr}");
-    serve_web(web_addr, Arc::clone(&crabbox)).await
+    let pipes = config
+        .server
+        .pipe
+        .iter()
+        .chain(&config.server.pipe_response)
+        .filter(|path| !path.as_os_str().is_empty())
+        .cloned()
+        .collect();
+    serve_web(
+        web_addr,
+        Arc::clone(&crabbox),
+        config.server.auth.clone(),
+        config.server.share_secret.clone(),
+        config.server.max_upload_bytes,
+        shutdown::on_shutdown(shutdown::termination_signal(), crabbox, pipes),
+    )
+    .await
+}
+
+fn run_validate(args: &ValidateArgs) -> AnyResult<()> {
+    let problems = validate::validate(&args.config);
+    if problems.is_empty() {
+        println!("{}: OK", args.config.display());
+        return Ok(());
+    }
+
+    println!("{}:", args.config.display());
+    for problem in &problems {
+        println!("  - {problem}");
+    }
+    Err(format!("{} problem(s) found", problems.len()).into())
+}
+
+fn run_ctl(args: &CtlArgs) -> AnyResult<()> {
+    let config = Config::load(&args.config)?;
+    let pipe_path = config
+        .server
+        .pipe
+        .filter(|path| !path.as_os_str().is_empty())
+        .ok_or("No control pipe configured; set [server].pipe")?;
+    pipe::send_command(&pipe_path, &args.command)
 }
 
-fn init_tracing() {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(tracing::Level::DEBUG)
-        .with_file(true)
-        .with_line_number(true)
-        .finish();
+fn run_list(args: &ListArgs) -> AnyResult<()> {
+    let config = Config::load(&args.config)?;
+    let filter = args.filter.as_deref().map(Filter::parse);
+    if let Some(filter) = &filter {
+        filter
+            .matcher()
+            .map_err(|err| format!("Invalid filter {filter}: {err}"))?;
+    }
 
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("failed to set global tracing subscriber");
+    for track in Library::from_config(&config).list_tracks(filter.as_ref()) {
+        println!("{}", track.display());
+    }
+    Ok(())
 }
 
 fn log_build_info() {
@@ -188,19 +274,3 @@ fn log_build_info() {
         "Crabbox build metadata",
     );
 }
-
-fn play_startup_sound(startup_sound: &Path, default_volume: f32) {
-    let startup_sound = startup_sound.to_path_buf();
-    let handle = thread::spawn(move || {
-        info!("Playing startup sound from {}", startup_sound.display());
-        match play_blocking(&startup_sound, default_volume) {
-            Ok(()) => {}
-            Err(err) => error!(
-                "Failed to play startup sound {}: {err}",
-                startup_sound.display()
-            ),
-        }
-    });
-
-    let _ = handle;
-}
@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSourceStream, MediaSourceStreamOptions},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+    units::TimeBase,
+};
+use tracing::debug;
+
+use crate::cue;
+
+/// Tags read from a track's ID3, Vorbis comment, MP4, or RIFF INFO metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl TrackMetadata {
+    /// "Artist – Title", or just the title when the artist is unknown.
+    pub fn display_name(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        Some(match self.artist.as_deref() {
+            Some(artist) => format!("{artist} – {title}"),
+            None => title.to_string(),
+        })
+    }
+
+    fn apply(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            let value = tag.value.to_string();
+            // RIFF INFO strings keep their NUL terminator.
+            let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if field.is_none() && !value.is_empty() {
+                *field = Some(value.to_string());
+            }
+        }
+    }
+}
+
+pub fn read_metadata(path: &Path) -> Result<TrackMetadata, String> {
+    if let Some(track) = cue::lookup(path) {
+        let track = track?;
+        let duration = match track.end {
+            Some(end) => Some(end.saturating_sub(track.start)),
+            None => read_metadata(&track.file)?
+                .duration
+                .map(|duration| duration.saturating_sub(track.start)),
+        };
+        return Ok(TrackMetadata {
+            title: track.title,
+            artist: track.performer,
+            album: track.album,
+            duration,
+        });
+    }
+
+    let file =
+        File::open(path).map_err(|err| format!("Failed to open {}: {err}", path.display()))?;
+    let source = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+
+    let mut metadata = TrackMetadata::default();
+    // Container metadata (e.g. Vorbis comments) wins over tags found while probing (e.g. ID3).
+    if let Some(revision) = probed.format.metadata().current() {
+        metadata.apply(revision);
+    }
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|log| log.current()) {
+        metadata.apply(revision);
+    }
+    metadata.duration = probed.format.default_track().and_then(|track| {
+        let params = &track.codec_params;
+        let frames = params.n_frames?;
+        let time_base = params
+            .time_base
+            .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))?;
+        let time = time_base.calc_time(frames);
+        Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+    });
+
+    Ok(metadata)
+}
+
+/// Remembers metadata per path, re-reading a file only when its modification time changes.
+#[derive(Default)]
+pub struct MetadataCache {
+    entries: Mutex<HashMap<PathBuf, CachedMetadata>>,
+}
+
+struct CachedMetadata {
+    modified: SystemTime,
+    metadata: Option<TrackMetadata>,
+}
+
+impl MetadataCache {
+    pub fn get(&self, path: &Path) -> Option<TrackMetadata> {
+        // Cue tracks change along with their sheet.
+        let file =
+            cue::split_track_path(path).map_or_else(|| path.to_path_buf(), |(sheet, _)| sheet);
+        let modified = fs::metadata(file).and_then(|meta| meta.modified()).ok()?;
+        if let Ok(entries) = self.entries.lock()
+            && let Some(cached) = entries.get(path)
+            && cached.modified == modified
+        {
+            return cached.metadata.clone();
+        }
+
+        let metadata = read_metadata(path).inspect_err(|err| debug!("{err}")).ok();
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                path.to_path_buf(),
+                CachedMetadata {
+                    modified,
+                    metadata: metadata.clone(),
+                },
+            );
+        }
+        metadata
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Writes a silent 8 kHz mono WAV file with RIFF INFO tags.
+    pub(crate) fn write_wav(path: &Path, tags: &[(&[u8; 4], &str)], frames: u32) {
+        let mut info = b"INFO".to_vec();
+        for (id, value) in tags {
+            let mut data = value.as_bytes().to_vec();
+            data.push(0);
+            info.extend_from_slice(*id);
+            info.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            info.extend_from_slice(&data);
+        }
+
+        let mut body = b"WAVE".to_vec();
+        body.extend_from_slice(b"fmt ");
+        body.extend_from_slice(&16u32.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        body.extend_from_slice(&1u16.to_le_bytes()); // mono
+        body.extend_from_slice(&8_000u32.to_le_bytes());
+        body.extend_from_slice(&16_000u32.to_le_bytes());
+        body.extend_from_slice(&2u16.to_le_bytes());
+        body.extend_from_slice(&16u16.to_le_bytes());
+        body.extend_from_slice(b"LIST");
+        body.extend_from_slice(&u32::try_from(info.len()).unwrap().to_le_bytes());
+        body.extend_from_slice(&info);
+        body.extend_from_slice(b"data");
+        body.extend_from_slice(&(frames * 2).to_le_bytes());
+        body.resize(body.len() + frames as usize * 2, 0);
+
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&u32::try_from(body.len()).unwrap().to_le_bytes());
+        file.extend_from_slice(&body);
+        fs::write(path, file).expect("write wav");
+    }
+
+    #[test]
+    fn reads_tags_and_duration() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("song.wav");
+        write_wav(
+            &path,
+            &[
+                (b"INAM", "Anthem"),
+                (b"IART", "The Crabs"),
+                (b"IPRD", "Shells"),
+            ],
+            16_000,
+        );
+
+        let metadata = read_metadata(&path).expect("metadata");
+
+        assert_eq!(
+            metadata,
+            TrackMetadata {
+                title: Some("Anthem".to_string()),
+                artist: Some("The Crabs".to_string()),
+                album: Some("Shells".to_string()),
+                duration: Some(Duration::from_secs(2)),
+            }
+        );
+        assert_eq!(
+            metadata.display_name(),
+            Some("The Crabs – Anthem".to_string())
+        );
+    }
+
+    #[test]
+    fn untagged_and_unreadable_files() {
+        let tmp = tempdir().expect("tempdir");
+        let untagged = tmp.path().join("untagged.wav");
+        let garbage = tmp.path().join("garbage.mp3");
+        write_wav(&untagged, &[], 8);
+        fs::write(&garbage, "not audio").expect("write garbage");
+
+        let metadata = read_metadata(&untagged).expect("metadata");
+        assert_eq!(metadata.display_name(), None);
+        assert!(read_metadata(&garbage).is_err());
+    }
+
+    #[test]
+    fn cache_rereads_when_the_file_changes() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("song.wav");
+        let cache = MetadataCache::default();
+
+        write_wav(&path, &[(b"INAM", "First")], 8);
+        assert_eq!(
+            cache.get(&path).and_then(|m| m.title),
+            Some("First".to_string())
+        );
+
+        write_wav(&path, &[(b"INAM", "Second")], 8);
+        let later = SystemTime::now() + Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(later))
+            .expect("touch");
+        assert_eq!(
+            cache.get(&path).and_then(|m| m.title),
+            Some("Second".to_string())
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use serde::Deserialize;
+
+use crate::config::ServerConfig;
+
+/// Where volume changes are applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeBackend {
+    /// Scale the samples before they reach the output (default).
+    #[default]
+    Software,
+    /// Set an ALSA mixer control and play the samples at full level.
+    Alsa,
+}
+
+/// Volume settings from `[server]`, cloned onto the playback thread that opens the mixer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MixerConfig {
+    pub backend: VolumeBackend,
+    pub card: String,
+    pub control: String,
+}
+
+impl MixerConfig {
+    pub fn from_server(server: &ServerConfig) -> Self {
+        Self {
+            backend: server.volume_backend,
+            card: server.alsa_card.clone(),
+            control: server.alsa_control.clone(),
+        }
+    }
+
+    /// Opens the configured backend.
+    pub fn open(&self) -> Result<Box<dyn VolumeControl>, String> {
+        match self.backend {
+            VolumeBackend::Software => Ok(Box::new(SoftwareVolume)),
+            VolumeBackend::Alsa => open_alsa(&self.card, &self.control),
+        }
+    }
+}
+
+impl Default for MixerConfig {
+    fn default() -> Self {
+        Self {
+            backend: VolumeBackend::Software,
+            card: default_alsa_card(),
+            control: default_alsa_control(),
+        }
+    }
+}
+
+pub fn default_alsa_card() -> String {
+    "default".to_string()
+}
+
+pub fn default_alsa_control() -> String {
+    "Master".to_string()
+}
+
+/// Applies the output gain somewhere along the audio path.
+pub trait VolumeControl {
+    /// Applies `gain` (0 is silent, 1 is full level) and returns the gain that is left for
+    /// the sink to apply to the samples.
+    fn set_volume(&mut self, gain: f32) -> Result<f32, String>;
+}
+
+/// Leaves all of the gain to the sink.
+pub struct SoftwareVolume;
+
+impl VolumeControl for SoftwareVolume {
+    fn set_volume(&mut self, gain: f32) -> Result<f32, String> {
+        Ok(gain.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_alsa(card: &str, control: &str) -> Result<Box<dyn VolumeControl>, String> {
+    Ok(Box::new(AlsaVolume::open(card, control)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_alsa(_card: &str, _control: &str) -> Result<Box<dyn VolumeControl>, String> {
+    Err("ALSA volume is only available on Linux".to_string())
+}
+
+/// Sets the playback volume of an ALSA simple mixer control, e.g. `Master` or `PCM`.
+#[cfg(target_os = "linux")]
+pub struct AlsaVolume {
+    mixer: alsa::Mixer,
+    control: alsa::mixer::SelemId,
+}
+
+#[cfg(target_os = "linux")]
+impl AlsaVolume {
+    pub fn open(card: &str, control: &str) -> Result<Self, String> {
+        let mixer = alsa::Mixer::new(card, false)
+            .map_err(|err| format!("Failed to open ALSA mixer {card}: {err}"))?;
+        let control = alsa::mixer::SelemId::new(control, 0);
+        let selem = mixer.find_selem(&control).ok_or_else(|| {
+            format!(
+                "ALSA mixer {card} has no control {}",
+                control.get_name().unwrap_or_default()
+            )
+        })?;
+        if !selem.has_playback_volume() {
+            return Err(format!(
+                "ALSA control {} has no playback volume",
+                control.get_name().unwrap_or_default()
+            ));
+        }
+        Ok(Self { mixer, control })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl VolumeControl for AlsaVolume {
+    fn set_volume(&mut self, gain: f32) -> Result<f32, String> {
+        // Pick up changes made by other mixers, e.g. alsamixer, before writing.
+        self.mixer
+            .handle_events()
+            .map_err(|err| format!("Failed to read ALSA mixer: {err}"))?;
+        let selem = self
+            .mixer
+            .find_selem(&self.control)
+            .ok_or("ALSA mixer control disappeared")?;
+        let (min, max) = selem.get_playback_volume_range();
+        selem
+            .set_playback_volume_all(scale_to_range(gain, min, max))
+            .map_err(|err| format!("Failed to set ALSA volume: {err}"))?;
+        Ok(1.0)
+    }
+}
+
+/// Maps `gain` in 0..=1 linearly onto the control's raw `min..=max` range.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn scale_to_range(gain: f32, min: i64, max: i64) -> i64 {
+    let span = (max - min) as f64;
+    min + (f64::from(gain.clamp(0.0, 1.0)) * span).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MixerConfig, SoftwareVolume, VolumeBackend, VolumeControl, scale_to_range};
+
+    #[test]
+    fn software_volume_leaves_the_gain_to_the_sink() {
+        let mut volume = SoftwareVolume;
+
+        assert_eq!(volume.set_volume(0.4), Ok(0.4));
+        assert_eq!(volume.set_volume(0.0), Ok(0.0));
+        assert_eq!(volume.set_volume(1.5), Ok(1.0));
+    }
+
+    #[test]
+    fn default_config_opens_software_volume() {
+        let config = MixerConfig::default();
+        assert_eq!(config.backend, VolumeBackend::Software);
+
+        let mut control = config.open().expect("software volume always opens");
+        assert_eq!(control.set_volume(0.25), Ok(0.25));
+    }
+
+    #[test]
+    fn scales_gain_onto_the_raw_control_range() {
+        assert_eq!(scale_to_range(0.0, 0, 255), 0);
+        assert_eq!(scale_to_range(1.0, 0, 255), 255);
+        assert_eq!(scale_to_range(0.5, -10_239, 400), -4_919);
+        assert_eq!(scale_to_range(2.0, 0, 100), 100);
+    }
+}
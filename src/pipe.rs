@@ -1,8 +1,13 @@
 use std::{
     ffi::CString,
-    fs,
-    os::unix::ffi::OsStrExt,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, OpenOptionsExt},
+    },
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use tokio::{
@@ -12,25 +17,25 @@ use tokio::{
 };
 
 use tokio::sync::mpsc;
+use tracing::warn;
 
-use crate::{
-    AnyResult,
-    commands::{Command, parse_command},
-};
+use crate::{AnyResult, commands::Command};
 
+/// Reads commands from the FIFO at `socket_path`. With `response_path`, each non-blank line
+/// gets `OK` or `ERR <reason>` written back to a second FIFO.
 pub async fn serve_control_pipe(
     socket_path: PathBuf,
+    response_path: Option<PathBuf>,
     sender: mpsc::Sender<Command>,
 ) -> AnyResult<()> {
-    if socket_path.exists() {
-        fs::remove_file(&socket_path)?;
-    }
-
-    task::spawn_blocking({
-        let path = socket_path.clone();
-        move || create_fifo(&path)
-    })
-    .await??;
+    recreate_fifo(&socket_path).await?;
+    let mut responder = match response_path {
+        Some(path) => {
+            recreate_fifo(&path).await?;
+            Some(Responder::open(&path)?)
+        }
+        None => None,
+    };
 
     let file = OpenOptions::new()
         .read(true)
@@ -47,15 +52,111 @@ pub async fn serve_control_pipe(
             continue;
         }
 
-        let Some(cmd) = parse_command(line.trim()) else {
+        let line = line.trim();
+        if line.is_empty() {
             continue;
+        }
+
+        let result = match Command::from_str(line) {
+            Ok(cmd) => sender
+                .send(cmd)
+                .await
+                .map_err(|_| "crabbox is not running".to_string()),
+            Err(err) => Err(err),
         };
 
-        let _ = sender.send(cmd).await;
+        if let Some(responder) = responder.as_mut() {
+            responder.respond(&result);
+        }
     }
 }
 
-fn create_fifo(path: &Path) -> std::io::Result<()> {
+/// Writes one command line to the control pipe of a running server, for `crabbox ctl`.
+pub fn send_command(pipe_path: &Path, command: &str) -> AnyResult<()> {
+    let command = Command::from_str(command.trim())?;
+    let is_fifo = fs::metadata(pipe_path).is_ok_and(|meta| meta.file_type().is_fifo());
+    if !is_fifo {
+        return Err(format!(
+            "{} is not a control pipe; is the server running?",
+            pipe_path.display()
+        )
+        .into());
+    }
+
+    // Without O_NONBLOCK opening would wait forever for a server to start reading.
+    let mut pipe = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(pipe_path)
+        .map_err(|err| match err.raw_os_error() {
+            Some(libc::ENXIO) => format!(
+                "No server is reading {}; is crabbox running?",
+                pipe_path.display()
+            ),
+            _ => format!("Failed to open {}: {err}", pipe_path.display()),
+        })?;
+    pipe.write_all(format!("{command}\n").as_bytes())?;
+    Ok(())
+}
+
+async fn recreate_fifo(path: &Path) -> AnyResult<()> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    task::spawn_blocking({
+        let path = path.to_path_buf();
+        move || create_fifo(&path)
+    })
+    .await??;
+    Ok(())
+}
+
+/// Writes one result line per command to the response FIFO.
+///
+/// The FIFO is kept open for reading as well, so replies wait in the pipe until a script
+/// reads them instead of blocking or failing when no reader is attached yet.
+struct Responder {
+    file: File,
+}
+
+impl Responder {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        Ok(Self { file })
+    }
+
+    fn respond(&mut self, result: &Result<(), String>) {
+        match self.file.write_all(response_line(result).as_bytes()) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                warn!("Control pipe responses are not being read; dropping response");
+            }
+            Err(err) => warn!("Failed to write control pipe response: {err}"),
+        }
+    }
+}
+
+fn response_line(result: &Result<(), String>) -> String {
+    match result {
+        Ok(()) => "OK\n".to_string(),
+        Err(err) => format!("ERR {}\n", err.replace('\n', " ")),
+    }
+}
+
+/// Removes the FIFO at `path` on shutdown; anything that isn't a FIFO is left alone.
+pub fn remove_fifo(path: &Path) {
+    let is_fifo = fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_fifo());
+    if is_fifo && let Err(err) = fs::remove_file(path) {
+        warn!("Failed to remove control pipe {}: {err}", path.display());
+    }
+}
+
+pub(crate) fn create_fifo(path: &Path) -> std::io::Result<()> {
     let c_path = CString::new(path.as_os_str().as_bytes())
         .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
     let mode = 0o666;
@@ -66,3 +167,75 @@ fn create_fifo(path: &Path) -> std::io::Result<()> {
         Err(std::io::Error::last_os_error())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use std::{fs::OpenOptions, io::Read, os::unix::fs::OpenOptionsExt};
+
+    use super::{Responder, create_fifo, response_line, send_command};
+    use tempfile::tempdir;
+
+    #[test]
+    fn formats_ok_and_single_line_errors() {
+        assert_eq!(response_line(&Ok(())), "OK\n");
+        assert_eq!(
+            response_line(&Err("Invalid command 'DANCE'\nnow".to_string())),
+            "ERR Invalid command 'DANCE' now\n"
+        );
+    }
+
+    #[test]
+    fn sends_a_command_line_to_the_fifo() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("crabbox.pipe");
+        create_fifo(&path).expect("create fifo");
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .expect("open reader");
+
+        send_command(&path, "  play rock/*  ").expect("send command");
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).expect("read fifo");
+        assert_eq!(received, "PLAY rock/*\n");
+    }
+
+    #[test]
+    fn sending_fails_clearly_without_a_server() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("crabbox.pipe");
+
+        let err = send_command(&path, "STOP").unwrap_err().to_string();
+        assert!(err.contains("is not a control pipe"), "{err}");
+
+        create_fifo(&path).expect("create fifo");
+        let err = send_command(&path, "STOP").unwrap_err().to_string();
+        assert!(err.starts_with("No server is reading"), "{err}");
+
+        let err = send_command(&path, "DANCE").unwrap_err().to_string();
+        assert!(err.contains("DANCE"), "{err}");
+    }
+
+    #[test]
+    fn responses_wait_in_the_fifo_until_read() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("crabbox.response");
+        create_fifo(&path).expect("create fifo");
+        let mut responder = Responder::open(&path).expect("open responder");
+
+        responder.respond(&Ok(()));
+        responder.respond(&Err("Invalid command 'DANCE'".to_string()));
+
+        let reader = std::fs::File::open(&path).expect("open reader");
+        let lines: Vec<String> = BufReader::new(reader)
+            .lines()
+            .take(2)
+            .map(|line| line.expect("line"))
+            .collect();
+        assert_eq!(lines, vec!["OK", "ERR Invalid command 'DANCE'"]);
+    }
+}
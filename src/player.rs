@@ -1,25 +1,62 @@
 use std::{
+    fmt,
     fs::File,
+    io::{Cursor, Read},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
 };
 
-use rodio::{OutputStream, OutputStreamBuilder, Sink};
-use tokio::task::JoinHandle;
-use tokio::{sync::mpsc, task};
-use tracing::{error, info};
-
-use crate::commands::Command;
+use rodio::{
+    Decoder, DeviceTrait, OutputStream, OutputStreamBuilder, Sink, Source,
+    cpal::{self, traits::HostTrait},
+    source::{EmptyCallback, SeekError},
+};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{
+    commands::Command,
+    cue,
+    library::source,
+    mixer::{SoftwareVolume, VolumeControl},
+};
 
 pub const VOLUME_STEP: f32 = 0.05;
 pub const MAX_VOLUME: f32 = 1.0;
 pub const MIN_VOLUME: f32 = 0.01;
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+/// How often the volume is lowered while fading out.
+const FADE_STEP: Duration = Duration::from_millis(20);
+
+type TrackSource = Box<dyn Source + Send>;
 
 pub struct Player {
-    sink: Option<Arc<Sink>>,
+    sink: Option<Sink>,
     stream: Option<OutputStream>,
     volume: f32,
-    track_end_task: Option<JoinHandle<()>>,
+    track_offset: i32,
+    /// Volume to restore on unmute; `Some` while muted.
+    muted: Option<f32>,
+    /// Playback rate; 1.0 is normal. Pitch shifts along with it.
+    speed: f32,
+    buffered_read_max_bytes: Option<u64>,
+    volume_curve: VolumeCurve,
+    /// Name of the audio output to open; the system default when `None`.
+    output_device: Option<String>,
+    /// Backend that gets the volume first; whatever gain it leaves is applied by the sink.
+    volume_control: Box<dyn VolumeControl>,
+    sink_gain: f32,
+    /// Track already appended to the sink to start right after the current one.
+    preloaded: Option<PathBuf>,
+    /// Bumped whenever the sink is replaced so callbacks from old sinks are ignored.
+    generation: Arc<AtomicU64>,
     command_sender: mpsc::Sender<Command>,
 }
 
@@ -27,70 +64,300 @@ impl Player {
     pub fn new(volume: f32, command_sender: mpsc::Sender<Command>) -> Self {
         Self {
             volume,
+            track_offset: 0,
+            muted: None,
+            speed: 1.0,
+            buffered_read_max_bytes: None,
+            volume_curve: VolumeCurve::default(),
+            output_device: None,
+            volume_control: Box::new(SoftwareVolume),
+            sink_gain: volume,
             sink: None,
             stream: None,
-            track_end_task: None,
+            preloaded: None,
+            generation: Arc::new(AtomicU64::new(0)),
             command_sender,
         }
     }
 
-    fn new_stream() -> Result<OutputStream, String> {
+    /// Reads tracks up to `max_bytes` fully into memory before decoding, which avoids
+    /// stutter on slow network mounts. `None` always streams from disk.
+    pub fn set_buffered_read(&mut self, max_bytes: Option<u64>) {
+        self.buffered_read_max_bytes = max_bytes;
+    }
+
+    pub fn set_volume_curve(&mut self, curve: VolumeCurve) {
+        self.volume_curve = curve;
+        self.apply_volume();
+    }
+
+    /// Routes volume changes through `control`, e.g. a hardware mixer.
+    pub fn set_volume_control(&mut self, control: Box<dyn VolumeControl>) {
+        self.volume_control = control;
+        self.apply_volume();
+    }
+
+    /// Hands the effective volume to the volume control and the rest of the gain to the
+    /// sink. If the control fails, the sink does all of it so volume keys keep working.
+    fn apply_volume(&mut self) {
+        let gain = self.effective_volume();
+        self.sink_gain = self.volume_control.set_volume(gain).unwrap_or_else(|err| {
+            warn!("{err}");
+            gain
+        });
+        if let Some(sink) = self.sink.as_ref() {
+            sink.set_volume(self.sink_gain);
+        }
+    }
+
+    /// Plays through the output device called `name` instead of the system default.
+    pub fn set_output_device(&mut self, name: Option<String>) {
+        self.output_device = name;
+    }
+
+    fn new_stream(&self) -> Result<OutputStream, String> {
+        if let Some(name) = self.output_device.as_deref() {
+            if let Some(device) = find_output_device(name) {
+                return OutputStreamBuilder::from_device(device)
+                    .and_then(|builder| builder.open_stream_or_fallback())
+                    .map_err(|err| format!("Failed to open audio output {name}: {err}"));
+            }
+            warn!(
+                available = ?output_device_names(),
+                "Audio output {name} not found, using the default output"
+            );
+        }
         OutputStreamBuilder::open_default_stream()
             .map_err(|err| format!("Failed to open default audio output: {err}"))
     }
 
-    pub fn play(&mut self, track: &Path, notify: bool) -> Result<(), String> {
-        let stream = Self::new_stream()?;
+    fn open_source(&self, track: &Path) -> Result<TrackSource, String> {
+        if let Some(cue_track) = cue::lookup(track) {
+            let cue_track = cue_track?;
+            let source = self.open_source(&cue_track.file)?;
+            return Ok(Box::new(
+                Segment::new(source, cue_track.start, cue_track.end)
+                    .map_err(|err| format!("Failed to start {}: {err}", track.display()))?,
+            ));
+        }
+        if source::is_remote(track) {
+            let data = source::fetch_track(track)?;
+            let decoder = Decoder::new(Cursor::new(data))
+                .map_err(|err| format!("Failed to start {}: {err}", track.display()))?;
+            return Ok(Box::new(decoder));
+        }
 
-        let file = File::open(track)
+        let mut file = File::open(track)
             .map_err(|err| format!("Failed to open file {}: {err}", track.display()))?;
+        let len = file.metadata().map(|metadata| metadata.len()).ok();
+
+        let decode_error = |err| format!("Failed to start file {}: {err}", track.display());
+        if should_buffer(len, self.buffered_read_max_bytes) {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)
+                .map_err(|err| format!("Failed to read file {}: {err}", track.display()))?;
+            Ok(Box::new(
+                Decoder::new(Cursor::new(data)).map_err(decode_error)?,
+            ))
+        } else {
+            Ok(Box::new(Decoder::new(file).map_err(decode_error)?))
+        }
+    }
 
-        let sink = rodio::play(stream.mixer(), file)
-            .map_err(|err| format!("Failed to start file {}: {err}", track.display()))?;
-        sink.set_volume(self.volume);
+    /// Starts `track` on a fresh sink, reusing the open output stream so consecutive
+    /// tracks don't pay for reopening the audio device.
+    pub fn play(&mut self, track: &Path, notify: bool) -> Result<(), PlayError> {
+        let source = self.open_source(track).map_err(PlayError::Track)?;
+        self.start(None, source, notify)
+    }
 
-        self.stream = Some(stream);
-        self.sink = Some(Arc::new(sink));
+    /// Starts `track` like `play`, with `intro` queued ahead of it on the same sink. An intro
+    /// that can't be opened is skipped.
+    pub fn play_with_intro(
+        &mut self,
+        intro: &Path,
+        track: &Path,
+        notify: bool,
+    ) -> Result<(), PlayError> {
+        let source = self.open_source(track).map_err(PlayError::Track)?;
+        let intro = self
+            .open_source(intro)
+            .inspect_err(|err| warn!("Skipping track intro: {err}"))
+            .ok();
+        self.start(intro, source, notify)
+    }
 
-        if notify {
-            self.watch_for_track_end();
+    fn start(
+        &mut self,
+        intro: Option<TrackSource>,
+        source: TrackSource,
+        notify: bool,
+    ) -> Result<(), PlayError> {
+        self.stop_sink();
+
+        let stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => self.new_stream().map_err(PlayError::Output)?,
+        };
+        let sink = Sink::connect_new(stream.mixer());
+        sink.set_volume(self.sink_gain);
+        sink.set_speed(self.speed);
+        let notifier = notify.then(|| self.track_end_notifier());
+        if let Some(intro) = intro {
+            sink.append(intro);
         }
+        append_track(&sink, source, notifier);
+
+        self.stream = Some(stream);
+        self.sink = Some(sink);
 
         Ok(())
     }
 
+    /// Appends `track` behind the current one so it starts without a gap.
+    pub fn preload(&mut self, track: &Path) -> Result<(), String> {
+        if self.sink.is_none() {
+            return Err("Nothing is playing".to_string());
+        }
+        let source = self.open_source(track)?;
+        let notifier = self.track_end_notifier();
+        if let Some(sink) = self.sink.as_ref() {
+            append_track(sink, source, Some(notifier));
+        }
+        self.preloaded = Some(track.to_path_buf());
+        Ok(())
+    }
+
+    /// Returns the preloaded track, which is now playing after the previous one ended.
+    pub fn take_preloaded(&mut self) -> Option<PathBuf> {
+        self.preloaded.take()
+    }
+
     pub fn stop(&mut self) {
-        self.cancel_track_end_task();
+        self.stop_sink();
+        if let Some(_stream) = self.stream.take() {
+            // todo stop playback so that it is not logged?
+        }
+    }
+
+    /// Ramps the volume down over `fade` before stopping, on a background thread so the
+    /// caller isn't blocked. The output stream stays open for the next track. A zero
+    /// `fade` (or a paused sink) stops immediately.
+    pub fn stop_with_fade(&mut self, fade: Duration) {
+        if fade.is_zero() || self.sink.as_ref().is_none_or(Sink::is_paused) {
+            self.stop();
+            return;
+        }
+
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.preloaded = None;
+        if let Some(sink) = self.sink.take() {
+            thread::spawn(move || fade_out(&sink, fade));
+        }
+    }
+
+    fn stop_sink(&mut self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.preloaded = None;
         if let Some(sink) = self.sink.take() {
             sink.stop();
         }
-        if let Some(_stream) = self.stream.take() {
-            // todo stop playback so that it is not logged?
+    }
+
+    /// Changes whenever the sink is stopped or replaced, so a `TrackDone` can be matched to
+    /// what was started.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn track_end_notifier(&self) -> TrackEndNotifier {
+        TrackEndNotifier {
+            generation: self.generation.load(Ordering::SeqCst),
+            current: Arc::clone(&self.generation),
+            sender: self.command_sender.clone(),
         }
     }
 
+    /// Gives the player a sink that isn't connected to an audio device.
+    #[cfg(test)]
+    pub fn attach_test_sink(&mut self) {
+        let (sink, _output) = Sink::new();
+        self.sink = Some(sink);
+    }
+
     pub fn has_sink(&self) -> bool {
         self.sink.is_some()
     }
 
     pub fn is_paused(&self) -> bool {
+        self.sink.as_ref().is_some_and(Sink::is_paused)
+    }
+
+    /// Whether the output is open but nothing is audible: paused, or out of queued audio.
+    pub fn is_idle(&self) -> bool {
         self.sink
             .as_ref()
-            .is_some_and(|s| Sink::is_paused(s.as_ref()))
+            .is_some_and(|sink| sink.is_paused() || sink.empty())
     }
 
     pub fn pause(&mut self) {
-        if let Some(sink) = self.sink.as_deref() {
+        if let Some(sink) = self.sink.as_ref() {
             sink.pause();
         }
     }
 
     pub fn resume(&mut self) {
-        if let Some(sink) = self.sink.as_deref() {
+        if let Some(sink) = self.sink.as_ref() {
             sink.play();
         }
     }
 
+    pub fn position(&self) -> Duration {
+        self.sink.as_ref().map_or(Duration::ZERO, Sink::get_pos)
+    }
+
+    pub fn seek(&self, position: Duration) -> Result<(), String> {
+        let Some(sink) = self.sink.as_ref() else {
+            return Err("Nothing is playing".to_string());
+        };
+        sink.try_seek(position)
+            .map_err(|err| format!("Failed to seek to {position:?}: {err}"))
+    }
+
+    /// The user-facing volume; while muted this is the volume that unmuting restores.
+    pub fn volume(&self) -> f32 {
+        self.muted.unwrap_or(self.volume)
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.is_some()
+    }
+
+    /// Silences the sink, or restores the volume from before muting.
+    pub fn toggle_mute(&mut self) {
+        match self.muted.take() {
+            Some(volume) => self.volume = volume,
+            None => self.muted = Some(self.volume),
+        }
+        self.apply_volume();
+        info!(muted = self.is_muted(), "Mute toggled");
+    }
+
+    /// Sets the per-track offset (in percentage points) applied on top of the global volume.
+    pub fn set_track_offset(&mut self, offset: i32) {
+        self.track_offset = offset;
+        self.apply_volume();
+    }
+
+    fn effective_volume(&self) -> f32 {
+        if self.is_muted() {
+            0.0
+        } else {
+            self.volume_curve
+                .gain(apply_track_offset(self.volume, self.track_offset))
+        }
+    }
+
     pub fn volume_up(&mut self) {
         self.adjust_volume(VOLUME_STEP);
     }
@@ -100,45 +367,200 @@ impl Player {
     }
 
     fn adjust_volume(&mut self, delta: f32) {
-        let new_volume = (self.volume + delta).clamp(MIN_VOLUME, MAX_VOLUME);
-        self.volume = new_volume;
-        if let Some(sink) = self.sink.as_deref() {
-            sink.set_volume(new_volume);
+        self.set_volume(self.volume() + delta);
+    }
+
+    /// Sets the volume; while muted only the volume restored on unmute changes.
+    pub fn set_volume(&mut self, volume: f32) {
+        let new_volume = volume.clamp(MIN_VOLUME, MAX_VOLUME);
+        match self.muted.as_mut() {
+            Some(stashed) => *stashed = new_volume,
+            None => self.volume = new_volume,
         }
+        self.apply_volume();
         info!("Volume set to {:.2}", new_volume);
     }
 
-    fn cancel_track_end_task(&mut self) {
-        if let Some(task) = self.track_end_task.take() {
-            task.abort();
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Changes the playback rate, clamped to `MIN_SPEED..=MAX_SPEED`.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        if let Some(sink) = self.sink.as_ref() {
+            sink.set_speed(self.speed);
         }
+        info!("Speed set to {:.2}", self.speed);
     }
 
-    pub fn watch_for_track_end(&mut self) {
-        self.cancel_track_end_task();
+    pub fn wait_until_end(&self) {
+        if let Some(sink) = self.sink.as_ref() {
+            sink.sleep_until_end();
+        }
+    }
+}
 
-        let Some(sink) = self.sink.clone() else {
-            return;
+/// Sends `TrackDone` when the track it follows finishes, unless the sink was replaced since.
+struct TrackEndNotifier {
+    generation: u64,
+    current: Arc<AtomicU64>,
+    sender: mpsc::Sender<Command>,
+}
+
+impl TrackEndNotifier {
+    /// Runs on the audio thread, so it waits for room in a full command channel rather than
+    /// dropping `TrackDone` and leaving the queue stuck after this track.
+    fn notify(&self) {
+        if self.current.load(Ordering::SeqCst) == self.generation
+            && self.sender.blocking_send(Command::TrackDone).is_err()
+        {
+            warn!("Command channel closed; track end not reported");
+        }
+    }
+}
+
+fn fade_out(sink: &Sink, fade: Duration) {
+    let steps = u16::try_from(fade.as_millis() / FADE_STEP.as_millis())
+        .unwrap_or(u16::MAX)
+        .max(1);
+    let start = sink.volume();
+    for step in 1..=steps {
+        sink.set_volume(start * (1.0 - f32::from(step) / f32::from(steps)));
+        thread::sleep(fade / u32::from(steps));
+    }
+    sink.stop();
+}
+
+fn append_track(sink: &Sink, source: TrackSource, notifier: Option<TrackEndNotifier>) {
+    sink.append(source);
+    if let Some(notifier) = notifier {
+        sink.append(EmptyCallback::new(Box::new(move || notifier.notify())));
+    }
+}
+
+/// Plays `start..end` of a source as a track of its own, for cue sheet tracks. Positions and
+/// seeks are relative to `start`.
+struct Segment {
+    inner: TrackSource,
+    start: Duration,
+    end: Option<Duration>,
+    /// Samples left before `end`; `None` plays to the end of the source.
+    remaining: Option<u64>,
+}
+
+impl Segment {
+    fn new(
+        mut inner: TrackSource,
+        start: Duration,
+        end: Option<Duration>,
+    ) -> Result<Self, SeekError> {
+        if !start.is_zero() {
+            inner.try_seek(start)?;
+        }
+        let mut segment = Self {
+            inner,
+            start,
+            end,
+            remaining: None,
         };
+        segment.reset_remaining(Duration::ZERO);
+        Ok(segment)
+    }
 
-        let sender = self.command_sender.clone();
+    fn reset_remaining(&mut self, position: Duration) {
+        let frames_per_second = f64::from(self.inner.sample_rate());
+        let channels = u64::from(self.inner.channels());
+        self.remaining = self.end.map(|end| {
+            let left = end.saturating_sub(self.start + position);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let frames = (left.as_secs_f64() * frames_per_second).round() as u64;
+            frames * channels
+        });
+    }
+}
 
-        let handle = task::spawn(async move {
-            let wait_result = task::spawn_blocking(move || sink.sleep_until_end()).await;
+impl Iterator for Segment {
+    type Item = rodio::Sample;
 
-            if wait_result.is_ok() {
-                let _ = sender.send(Command::TrackDone).await;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(remaining) = self.remaining.as_mut() {
+            if *remaining == 0 {
+                return None;
             }
-        });
+            *remaining -= 1;
+        }
+        self.inner.next()
+    }
+}
 
-        self.track_end_task = Some(handle);
+impl Source for Segment {
+    fn current_span_len(&self) -> Option<usize> {
+        let remaining = self
+            .remaining
+            .map(|remaining| usize::try_from(remaining).unwrap_or(usize::MAX));
+        match (self.inner.current_span_len(), remaining) {
+            (Some(span), Some(remaining)) => Some(span.min(remaining)),
+            (span, remaining) => span.or(remaining),
+        }
     }
 
-    pub fn wait_until_end(&self) {
-        if let Some(sink) = self.sink.as_ref() {
-            sink.sleep_until_end();
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self.end {
+            Some(end) => Some(end.saturating_sub(self.start)),
+            None => self
+                .inner
+                .total_duration()
+                .map(|total| total.saturating_sub(self.start)),
         }
     }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(self.start + position)?;
+        self.reset_remaining(position);
+        Ok(())
+    }
+}
+
+/// How the 0..1 volume setting maps to the amplitude passed to the sink.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeCurve {
+    #[default]
+    Linear,
+    /// Spreads the setting over a 60 dB range so each step sounds about equally loud.
+    Logarithmic,
+}
+
+impl VolumeCurve {
+    const LOG_RANGE_DB: f32 = 60.0;
+
+    fn gain(self, volume: f32) -> f32 {
+        match self {
+            VolumeCurve::Linear => volume,
+            VolumeCurve::Logarithmic if volume <= 0.0 => 0.0,
+            VolumeCurve::Logarithmic => {
+                10_f32.powf((volume.min(1.0) - 1.0) * Self::LOG_RANGE_DB / 20.0)
+            }
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn apply_track_offset(volume: f32, offset: i32) -> f32 {
+    (volume + offset as f32 / 100.0).clamp(MIN_VOLUME, MAX_VOLUME)
+}
+
+fn should_buffer(len: Option<u64>, max_bytes: Option<u64>) -> bool {
+    matches!((len, max_bytes), (Some(len), Some(max)) if len <= max)
 }
 
 pub fn play_track(track: Option<PathBuf>, player: &mut Player, notify: bool) -> Option<PathBuf> {
@@ -147,8 +569,6 @@ pub fn play_track(track: Option<PathBuf>, player: &mut Player, notify: bool) ->
         return None;
     };
 
-    player.stop();
-
     match player.play(track.as_path(), notify) {
         Ok(()) => Some(track),
         Err(err) => {
@@ -178,6 +598,22 @@ pub fn toggle_play_pause(
     }
 }
 
+#[derive(Debug)]
+pub enum PlayError {
+    /// The track couldn't be opened or decoded; other tracks may still play.
+    Track(String),
+    /// The audio output couldn't be opened, so nothing will play.
+    Output(String),
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::Track(err) | PlayError::Output(err) => f.write_str(err),
+        }
+    }
+}
+
 pub enum ToggleResult {
     Started(PathBuf),
     Toggled,
@@ -191,10 +627,295 @@ impl Drop for Player {
     }
 }
 
-pub fn play_blocking(track: &Path, volume: f32) -> Result<(), String> {
+/// Names of the audio outputs the default host offers, as accepted by `output_device`.
+pub fn output_device_names() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn find_output_device(name: &str) -> Option<cpal::Device> {
+    let devices: Vec<_> = cpal::default_host().output_devices().ok()?.collect();
+    let names: Vec<String> = devices
+        .iter()
+        .map(|device| device.name().unwrap_or_default())
+        .collect();
+    let index = pick_device(&names, name)?;
+    devices.into_iter().nth(index)
+}
+
+/// Index of the device called `wanted`, preferring an exact match over one that only
+/// differs in case.
+fn pick_device(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.trim();
+    names.iter().position(|name| name == wanted).or_else(|| {
+        names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(wanted))
+    })
+}
+
+pub fn play_blocking(
+    track: &Path,
+    volume: f32,
+    curve: VolumeCurve,
+    output_device: Option<&str>,
+) -> Result<(), String> {
     let (tx, _rx) = mpsc::channel(1);
     let mut player = Player::new(volume, tx);
-    player.play(track, false)?;
+    player.set_volume_curve(curve);
+    player.set_output_device(output_device.map(str::to_string));
+    player.play(track, false).map_err(|err| err.to_string())?;
     player.wait_until_end();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        sync::{
+            Arc,
+            atomic::{AtomicU64, Ordering},
+        },
+        thread,
+        time::Duration,
+    };
+
+    use rodio::{Sink, Source, buffer::SamplesBuffer};
+    use tokio::sync::mpsc;
+
+    use super::{
+        MAX_SPEED, MAX_VOLUME, MIN_SPEED, MIN_VOLUME, PlayError, Player, Segment, TrackEndNotifier,
+        TrackSource, VolumeCurve, append_track, apply_track_offset, fade_out, pick_device,
+        should_buffer,
+    };
+    use crate::commands::Command;
+
+    fn samples(len: usize) -> TrackSource {
+        Box::new(SamplesBuffer::new(1, 1_000, vec![0.5; len]))
+    }
+
+    #[test]
+    fn intro_waits_for_a_track_that_opens() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut player = Player::new(1.0, tx);
+        player.attach_test_sink();
+        let generation = player.generation();
+
+        let result = player.play_with_intro(
+            Path::new("/missing/chime.wav"),
+            Path::new("/missing/track.mp3"),
+            true,
+        );
+
+        assert!(matches!(result, Err(PlayError::Track(_))));
+        assert!(player.has_sink(), "the playing track is left alone");
+        assert_eq!(player.generation(), generation);
+    }
+
+    #[test]
+    fn buffers_only_files_within_the_cap() {
+        assert!(should_buffer(Some(1_000), Some(1_000)));
+        assert!(!should_buffer(Some(1_001), Some(1_000)));
+        assert!(!should_buffer(Some(10), None));
+        assert!(!should_buffer(None, Some(1_000)));
+    }
+
+    #[test]
+    fn segments_play_only_their_slice_of_the_source() {
+        let ramp: Vec<f32> = (0..1_000u16).map(|idx| f32::from(idx) / 1_000.0).collect();
+        let source = Box::new(SamplesBuffer::new(1, 1_000, ramp.clone()));
+
+        let mut segment = Segment::new(
+            source,
+            Duration::from_millis(200),
+            Some(Duration::from_millis(500)),
+        )
+        .expect("seekable source");
+        assert_eq!(segment.total_duration(), Some(Duration::from_millis(300)));
+        assert_eq!(segment.next(), Some(ramp[200]));
+
+        segment
+            .try_seek(Duration::from_millis(250))
+            .expect("seek within segment");
+        let rest: Vec<f32> = segment.collect();
+        assert_eq!(rest, ramp[450..500]);
+
+        let to_end = Segment::new(
+            Box::new(SamplesBuffer::new(1, 1_000, ramp.clone())),
+            Duration::from_millis(900),
+            None,
+        )
+        .expect("seekable source");
+        assert_eq!(to_end.count(), 100);
+    }
+
+    #[test]
+    fn picks_output_devices_by_name_preferring_exact_matches() {
+        let names = [
+            "default".to_string(),
+            "USB Audio".to_string(),
+            "usb audio".to_string(),
+            "sysdefault:CARD=Headphones".to_string(),
+        ];
+
+        assert_eq!(pick_device(&names, "usb audio"), Some(2));
+        assert_eq!(pick_device(&names, "USB AUDIO"), Some(1));
+        assert_eq!(pick_device(&names, " sysdefault:CARD=Headphones "), Some(3));
+        assert_eq!(pick_device(&names, "HDMI"), None);
+        assert_eq!(pick_device(&[], "default"), None);
+    }
+
+    #[test]
+    fn volume_curves_map_endpoints_and_midpoint() {
+        assert!(VolumeCurve::Linear.gain(0.0).abs() < f32::EPSILON);
+        assert!((VolumeCurve::Linear.gain(0.5) - 0.5).abs() < f32::EPSILON);
+        assert!((VolumeCurve::Linear.gain(1.0) - 1.0).abs() < f32::EPSILON);
+
+        assert!(VolumeCurve::Logarithmic.gain(0.0).abs() < f32::EPSILON);
+        // Halfway is -30 dB.
+        assert!((VolumeCurve::Logarithmic.gain(0.5) - 0.031_622_78).abs() < 1e-6);
+        assert!((VolumeCurve::Logarithmic.gain(1.0) - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn logarithmic_curve_applies_to_sink_volume_only() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut player = Player::new(0.5, tx);
+        player.set_volume_curve(VolumeCurve::Logarithmic);
+
+        assert!((player.volume() - 0.5).abs() < f32::EPSILON);
+        assert!((player.effective_volume() - 0.031_622_78).abs() < 1e-6);
+    }
+
+    #[test]
+    fn track_offset_is_applied_on_top_of_volume() {
+        assert!((apply_track_offset(0.5, 0) - 0.5).abs() < f32::EPSILON);
+        assert!((apply_track_offset(0.5, -20) - 0.3).abs() < 1e-6);
+        assert!((apply_track_offset(0.5, 10) - 0.6).abs() < 1e-6);
+        assert!((apply_track_offset(0.9, 50) - MAX_VOLUME).abs() < f32::EPSILON);
+        assert!((apply_track_offset(0.1, -50) - MIN_VOLUME).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn volume_changes_while_muted_update_the_stashed_volume() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut player = Player::new(0.5, tx);
+
+        player.toggle_mute();
+        assert!(player.is_muted());
+        assert!(player.effective_volume().abs() < f32::EPSILON);
+
+        player.volume_up();
+        assert!(player.is_muted());
+        assert!(player.effective_volume().abs() < f32::EPSILON);
+        assert!((player.volume() - 0.55).abs() < 1e-6);
+
+        player.toggle_mute();
+        assert!(!player.is_muted());
+        assert!((player.effective_volume() - 0.55).abs() < 1e-6);
+    }
+
+    #[test]
+    fn speed_is_clamped_to_a_sane_range() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut player = Player::new(0.5, tx);
+        assert!((player.speed() - 1.0).abs() < f32::EPSILON);
+
+        player.set_speed(0.8);
+        assert!((player.speed() - 0.8).abs() < f32::EPSILON);
+        player.set_speed(0.1);
+        assert!((player.speed() - MIN_SPEED).abs() < f32::EPSILON);
+        player.set_speed(5.0);
+        assert!((player.speed() - MAX_SPEED).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn fade_out_ramps_volume_to_zero() {
+        let (sink, _output) = Sink::new();
+        sink.set_volume(0.8);
+
+        fade_out(&sink, Duration::from_millis(60));
+
+        assert!(sink.volume().abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn stop_with_fade_detaches_the_sink_right_away() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut player = Player::new(0.5, tx);
+        let (sink, _output) = Sink::new();
+        player.sink = Some(sink);
+        let generation = player.generation.load(Ordering::SeqCst);
+
+        player.stop_with_fade(Duration::from_millis(200));
+
+        assert!(!player.has_sink());
+        assert_eq!(player.generation.load(Ordering::SeqCst), generation + 1);
+        assert!((player.volume() - 0.5).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn appended_tracks_notify_at_each_boundary() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let generation = Arc::new(AtomicU64::new(0));
+        let notifier = || TrackEndNotifier {
+            generation: 0,
+            current: Arc::clone(&generation),
+            sender: tx.clone(),
+        };
+        let (sink, mut output) = Sink::new();
+        append_track(&sink, samples(10), Some(notifier()));
+        append_track(&sink, samples(10), Some(notifier()));
+
+        output.by_ref().take(10).for_each(drop);
+        assert!(rx.try_recv().is_err(), "first track is still playing");
+
+        // The second track follows straight on, with a notification in between.
+        assert_eq!(output.by_ref().take(10).filter(|s| *s != 0.0).count(), 10);
+        assert!(matches!(rx.try_recv(), Ok(Command::TrackDone)));
+
+        output.by_ref().take(1).for_each(drop);
+        assert!(matches!(rx.try_recv(), Ok(Command::TrackDone)));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn track_end_waits_for_room_in_a_full_channel() {
+        let (tx, mut rx) = mpsc::channel(1);
+        tx.try_send(Command::Next).unwrap();
+        let notifier = TrackEndNotifier {
+            generation: 0,
+            current: Arc::new(AtomicU64::new(0)),
+            sender: tx,
+        };
+
+        let notifying = thread::spawn(move || notifier.notify());
+        assert!(matches!(rx.blocking_recv(), Some(Command::Next)));
+        notifying.join().unwrap();
+        assert!(matches!(rx.try_recv(), Ok(Command::TrackDone)));
+    }
+
+    #[test]
+    fn replaced_sink_does_not_notify() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let generation = Arc::new(AtomicU64::new(0));
+        let (sink, mut output) = Sink::new();
+        append_track(
+            &sink,
+            samples(10),
+            Some(TrackEndNotifier {
+                generation: 0,
+                current: Arc::clone(&generation),
+                sender: tx,
+            }),
+        );
+
+        generation.fetch_add(1, Ordering::SeqCst);
+        output.by_ref().take(20).for_each(drop);
+
+        assert!(rx.try_recv().is_err());
+    }
+}
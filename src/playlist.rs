@@ -0,0 +1,155 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Reads an M3U (`.m3u`, `.m3u8`) or PLS (`.pls`) playlist and returns its entries in
+/// order, with relative entries resolved against the playlist's directory.
+pub fn load(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read playlist {}: {err}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let is_pls = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+
+    let entries = if is_pls {
+        parse_pls(&contents)
+    } else {
+        parse_m3u(&contents)
+    };
+    Ok(entries
+        .into_iter()
+        .map(|entry| resolve(base, entry))
+        .collect())
+}
+
+/// Entries of a plain or extended M3U playlist; `#` lines are comments or `#EXT` tags.
+pub fn parse_m3u(contents: &str) -> Vec<&str> {
+    contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Entries of a PLS playlist, ordered by their `FileN` number.
+pub fn parse_pls(contents: &str) -> Vec<&str> {
+    let mut entries: Vec<(u32, &str)> = contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let number = key.trim().strip_prefix("File")?.parse().ok()?;
+            Some((number, value.trim()))
+        })
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    entries.sort_by_key(|(number, _)| *number);
+    entries.into_iter().map(|(_, value)| value).collect()
+}
+
+/// Formats tracks as an extended M3U playlist with one absolute path per line.
+pub fn to_m3u(tracks: &[PathBuf]) -> String {
+    tracks
+        .iter()
+        .fold(String::from("#EXTM3U\n"), |mut m3u, track| {
+            m3u.push_str(&track.to_string_lossy());
+            m3u.push('\n');
+            m3u
+        })
+}
+
+/// Joins `entry` onto `base` and folds `.` and `..` components, so entries pointing outside
+/// the music directories can be recognized by prefix.
+fn resolve(base: &Path, entry: &str) -> PathBuf {
+    let entry = entry.strip_prefix("file://").unwrap_or(entry);
+    let mut resolved = PathBuf::new();
+    for component in base.join(entry).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use tempfile::tempdir;
+
+    use super::{load, parse_m3u, parse_pls, to_m3u};
+
+    #[test]
+    fn parses_extended_m3u_with_comments() {
+        let contents = "\u{feff}#EXTM3U\n\
+            #EXTINF:123,Band - First\n\
+            album/01 First.mp3\n\
+            \n\
+            # a plain comment\n\
+            /music/other/02.flac\r\n";
+
+        assert_eq!(
+            parse_m3u(contents),
+            vec!["album/01 First.mp3", "/music/other/02.flac"]
+        );
+    }
+
+    #[test]
+    fn parses_pls_in_file_number_order() {
+        let contents = "[playlist]\n\
+            File2=b.mp3\n\
+            Title2=B\n\
+            File1=a.mp3\n\
+            File10=j.mp3\n\
+            NumberOfEntries=3\n\
+            Version=2\n";
+
+        assert_eq!(parse_pls(contents), vec!["a.mp3", "b.mp3", "j.mp3"]);
+    }
+
+    #[test]
+    fn serializes_a_queue_as_m3u_that_parses_back() {
+        let queue = vec![
+            PathBuf::from("/music/Kids Songs/01.mp3"),
+            PathBuf::from("/music/b.flac"),
+        ];
+
+        let m3u = to_m3u(&queue);
+
+        assert_eq!(m3u, "#EXTM3U\n/music/Kids Songs/01.mp3\n/music/b.flac\n");
+        assert_eq!(
+            parse_m3u(&m3u),
+            vec!["/music/Kids Songs/01.mp3", "/music/b.flac"]
+        );
+        assert_eq!(to_m3u(&[]), "#EXTM3U\n");
+    }
+
+    #[test]
+    fn resolves_entries_relative_to_the_playlist() {
+        let tmp = tempdir().expect("tempdir");
+        let lists = tmp.path().join("music/lists");
+        fs::create_dir_all(&lists).expect("create lists dir");
+        let playlist = lists.join("mix.m3u");
+        fs::write(
+            &playlist,
+            "#EXTM3U\n../album/a.mp3\n./b.mp3\n/elsewhere/c.mp3\n",
+        )
+        .expect("write playlist");
+
+        assert_eq!(
+            load(&playlist).expect("load playlist"),
+            vec![
+                tmp.path().join("music/album/a.mp3"),
+                lists.join("b.mp3"),
+                PathBuf::from("/elsewhere/c.mp3"),
+            ]
+        );
+    }
+}
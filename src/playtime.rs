@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDate;
+
+/// Tracks how long music has played today against an optional daily limit.
+pub struct Playtime {
+    limit: Option<Duration>,
+    date: NaiveDate,
+    used: Duration,
+    running_since: Option<Instant>,
+}
+
+impl Playtime {
+    pub fn new(limit: Option<Duration>, date: NaiveDate, used: Duration) -> Self {
+        Self {
+            limit,
+            date,
+            used,
+            running_since: None,
+        }
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    pub fn limit(&self) -> Option<Duration> {
+        self.limit
+    }
+
+    /// Starts or stops the clock depending on whether audio is currently playing.
+    pub fn set_playing(&mut self, playing: bool, today: NaiveDate, now: Instant) {
+        self.roll_over(today, now);
+        match (playing, self.running_since) {
+            (true, None) => self.running_since = Some(now),
+            (false, Some(since)) => {
+                self.used += now.saturating_duration_since(since);
+                self.running_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn used(&self, now: Instant) -> Duration {
+        self.used
+            + self
+                .running_since
+                .map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+    }
+
+    pub fn is_exhausted(&mut self, today: NaiveDate, now: Instant) -> bool {
+        self.roll_over(today, now);
+        self.limit.is_some_and(|limit| self.used(now) >= limit)
+    }
+
+    pub fn reset(&mut self, now: Instant) {
+        self.used = Duration::ZERO;
+        if self.running_since.is_some() {
+            self.running_since = Some(now);
+        }
+    }
+
+    fn roll_over(&mut self, today: NaiveDate, now: Instant) {
+        if today != self.date {
+            self.date = today;
+            self.reset(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    #[test]
+    fn accumulates_playing_time_until_limit() {
+        let start = Instant::now();
+        let mut playtime = Playtime::new(Some(Duration::from_mins(1)), day(1), Duration::ZERO);
+
+        playtime.set_playing(true, day(1), start);
+        assert!(!playtime.is_exhausted(day(1), start + Duration::from_secs(30)));
+        playtime.set_playing(false, day(1), start + Duration::from_secs(40));
+
+        // Paused time doesn't count.
+        assert!(!playtime.is_exhausted(day(1), start + Duration::from_mins(10)));
+        assert_eq!(
+            playtime.used(start + Duration::from_mins(10)),
+            Duration::from_secs(40)
+        );
+
+        playtime.set_playing(true, day(1), start + Duration::from_mins(10));
+        assert!(playtime.is_exhausted(day(1), start + Duration::from_secs(620)));
+    }
+
+    #[test]
+    fn resets_at_midnight_and_on_demand() {
+        let start = Instant::now();
+        let mut playtime =
+            Playtime::new(Some(Duration::from_mins(1)), day(1), Duration::from_mins(1));
+        assert!(playtime.is_exhausted(day(1), start));
+
+        assert!(!playtime.is_exhausted(day(2), start));
+        assert_eq!(playtime.date(), day(2));
+
+        playtime.set_playing(true, day(2), start);
+        assert!(playtime.is_exhausted(day(2), start + Duration::from_secs(61)));
+        playtime.reset(start + Duration::from_secs(61));
+        assert!(!playtime.is_exhausted(day(2), start + Duration::from_secs(62)));
+    }
+
+    #[test]
+    fn no_limit_is_never_exhausted() {
+        let start = Instant::now();
+        let mut playtime = Playtime::new(None, day(1), Duration::from_hours(24));
+        assert!(!playtime.is_exhausted(day(1), start));
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     error::Error,
     sync::{Arc, Mutex, mpsc as std_mpsc},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use rppal::{
@@ -13,7 +13,14 @@ use tracing::{debug, error, info};
 
 use tokio::sync::mpsc;
 
-use crate::{commands::Command, config::RfidConfig, tag::TagId};
+use crate::{
+    commands::Command,
+    config::RfidConfig,
+    tag::{TagDebouncer, TagId, TagPresence},
+};
+
+/// Clock speeds the Raspberry Pi SPI controller accepts.
+const SPI_SPEED_RANGE_HZ: std::ops::RangeInclusive<u32> = 3_815..=125_000_000;
 
 const PCD_TRANSCEIVE: u8 = 0x0C;
 const PCD_RESETPHASE: u8 = 0x0F;
@@ -47,8 +54,8 @@ impl Reader {
         config: &RfidConfig,
         command_tx: mpsc::Sender<Command>,
     ) -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let (bus, slave_select) = resolve_spi(config)?;
-        let spi = Spi::new(bus, slave_select, 1_000_000, Mode::Mode0)?;
+        let (bus, slave_select, speed_hz) = resolve_spi(config)?;
+        let spi = Spi::new(bus, slave_select, speed_hz, Mode::Mode0)?;
         let spi = Arc::new(Mutex::new(spi));
 
         let gpio = Gpio::new()?;
@@ -73,6 +80,10 @@ impl Reader {
         // Kick off an initial poll in case the IRQ line is already low.
         let _ = tx.send(());
 
+        let poll_interval = Duration::from_millis(config.poll_interval_ms);
+        let mut debouncer = TagDebouncer::new(Duration::from_millis(config.tag_debounce_ms));
+        let mut presence = TagPresence::new(config.remove_after_misses);
+        let on_remove = config.on_remove.clone();
         let worker = thread::spawn({
             move || {
                 let mut rc522 = Rc522::new(spi);
@@ -82,18 +93,33 @@ impl Reader {
                 }
 
                 while let Ok(()) | Err(std_mpsc::RecvTimeoutError::Timeout) =
-                    rx.recv_timeout(Duration::from_millis(500))
+                    rx.recv_timeout(poll_interval)
                 {
                     match rc522.poll_for_tag() {
-                        Ok(Some(uid)) => handle_tag(uid, &command_tx),
-                        Ok(None) => {}
+                        Ok(Some(uid)) => {
+                            let tag_id = TagId::from_uid(uid);
+                            presence.seen(tag_id);
+                            if debouncer.should_fire(tag_id, Instant::now()) {
+                                handle_tag(tag_id, &command_tx);
+                            } else {
+                                debug!("RFID tag {tag_id} still present; ignoring repeat read");
+                            }
+                        }
+                        Ok(None) => {
+                            if let Some(tag_id) = presence.missed() {
+                                handle_removal(tag_id, on_remove.as_ref(), &command_tx);
+                            }
+                        }
                         Err(err) => error!("RFID poll failed: {err}"),
                     }
                 }
             }
         });
 
-        info!("RFID SPI initialized on {} {}", bus, slave_select);
+        info!(
+            "RFID SPI initialized on {bus} {slave_select} (CE{}) at {speed_hz} Hz",
+            config.slave_select
+        );
 
         Ok(Self {
             _irq_pin: irq_pin,
@@ -103,8 +129,7 @@ impl Reader {
     }
 }
 
-fn handle_tag(uid: [u8; 4], command_tx: &mpsc::Sender<Command>) {
-    let tag_id = TagId::from_uid(uid);
+fn handle_tag(tag_id: TagId, command_tx: &mpsc::Sender<Command>) {
     info!("RFID tag detected UID {tag_id}");
 
     if let Err(err) = command_tx.blocking_send(Command::Tag { id: tag_id }) {
@@ -112,6 +137,16 @@ fn handle_tag(uid: [u8; 4], command_tx: &mpsc::Sender<Command>) {
     }
 }
 
+fn handle_removal(tag_id: TagId, on_remove: Option<&Command>, command_tx: &mpsc::Sender<Command>) {
+    info!("RFID tag {tag_id} removed");
+
+    if let Some(command) = on_remove
+        && let Err(err) = command_tx.blocking_send(command.clone())
+    {
+        error!("Failed to send RFID removal command for {tag_id}: {err}");
+    }
+}
+
 impl Drop for Reader {
     fn drop(&mut self) {
         if let Some(handle) = self.worker.take() {
@@ -274,7 +309,9 @@ impl Rc522 {
     }
 }
 
-fn resolve_spi(config: &RfidConfig) -> Result<(Bus, SlaveSelect), Box<dyn Error + Send + Sync>> {
+fn resolve_spi(
+    config: &RfidConfig,
+) -> Result<(Bus, SlaveSelect, u32), Box<dyn Error + Send + Sync>> {
     let bus = match config.bus {
         0 => Bus::Spi0,
         1 => Bus::Spi1,
@@ -290,5 +327,49 @@ fn resolve_spi(config: &RfidConfig) -> Result<(Bus, SlaveSelect), Box<dyn Error
         }
     };
 
-    Ok((bus, SlaveSelect::Ss0))
+    if !SPI_SPEED_RANGE_HZ.contains(&config.spi_speed_hz) {
+        return Err(format!(
+            "Unsupported SPI clock speed {} Hz. Supported speeds are {} through {} Hz.",
+            config.spi_speed_hz,
+            SPI_SPEED_RANGE_HZ.start(),
+            SPI_SPEED_RANGE_HZ.end()
+        )
+        .into());
+    }
+
+    Ok((
+        bus,
+        resolve_slave_select(config.slave_select)?,
+        config.spi_speed_hz,
+    ))
+}
+
+/// Maps the chip-enable line the reader is wired to (CE0, CE1, CE2) to its slave select.
+fn resolve_slave_select(index: u8) -> Result<SlaveSelect, Box<dyn Error + Send + Sync>> {
+    match index {
+        0 => Ok(SlaveSelect::Ss0),
+        1 => Ok(SlaveSelect::Ss1),
+        2 => Ok(SlaveSelect::Ss2),
+        other => Err(format!(
+            "Unsupported SPI slave select {other}. Supported values are 0 through 2."
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rppal::spi::SlaveSelect;
+
+    use super::resolve_slave_select;
+
+    #[test]
+    fn maps_slave_select_lines() {
+        assert_eq!(resolve_slave_select(0).unwrap(), SlaveSelect::Ss0);
+        assert_eq!(resolve_slave_select(1).unwrap(), SlaveSelect::Ss1);
+        assert_eq!(resolve_slave_select(2).unwrap(), SlaveSelect::Ss2);
+
+        let err = resolve_slave_select(3).unwrap_err();
+        assert!(err.to_string().contains("slave select 3"), "{err}");
+    }
 }
@@ -0,0 +1,112 @@
+use std::{
+    future::{Future, pending},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use tokio::signal::{
+    self,
+    unix::{SignalKind, signal},
+};
+use tracing::{info, warn};
+
+use crate::{crabbox::Crabbox, pipe};
+
+/// Resolves on the first SIGTERM (e.g. `systemctl stop`) or SIGINT (Ctrl+C).
+pub async fn termination_signal() {
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(err) => {
+                warn!("Failed to listen for SIGTERM: {err}");
+                pending::<()>().await;
+            }
+        }
+    };
+    let interrupt = async {
+        if let Err(err) = signal::ctrl_c().await {
+            warn!("Failed to listen for SIGINT: {err}");
+            pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        () = terminate => info!("Received SIGTERM"),
+        () = interrupt => info!("Received SIGINT"),
+    }
+}
+
+/// Waits for `signal`, then saves playback state and removes the control pipes. The web
+/// server stops accepting connections once this resolves.
+pub async fn on_shutdown(
+    signal: impl Future<Output = ()>,
+    crabbox: Arc<Mutex<Crabbox>>,
+    pipes: Vec<PathBuf>,
+) {
+    signal.await;
+    info!("Shutting down");
+    if let Ok(crabbox) = crabbox.lock() {
+        crabbox.save_state();
+    } else {
+        warn!("Failed to lock crabbox; playback state not saved");
+    }
+    for path in &pipes {
+        pipe::remove_fifo(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use tempfile::tempdir;
+    use tokio::{sync::oneshot, time::timeout};
+
+    use super::on_shutdown;
+    use crate::{config::Config, crabbox::Crabbox, pipe, state::State};
+
+    #[tokio::test]
+    async fn completes_when_signalled_and_cleans_up() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music dir");
+        let fifo = tmp.path().join("crabbox.pipe");
+        pipe::create_fifo(&fifo).expect("create fifo");
+        let state_file = tmp.path().join("state.json");
+        let config_path = tmp.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "state_file = {state_file:?}\n\n[[music]]\ndir = {music:?}\n\n\
+                 [server]\nweb = \"127.0.0.1:8080\"\n"
+            ),
+        )
+        .expect("write config");
+        let config = Config::load(&config_path).expect("load config");
+        let crabbox = Crabbox::new(&config);
+
+        let (fire, signal) = oneshot::channel::<()>();
+        let shutdown = tokio::spawn(on_shutdown(
+            async {
+                let _ = signal.await;
+            },
+            crabbox,
+            vec![fifo.clone()],
+        ));
+        tokio::task::yield_now().await;
+        assert!(!shutdown.is_finished());
+        assert!(fifo.exists());
+
+        let _ = fs::remove_file(&state_file);
+        fire.send(()).expect("shutdown future is waiting");
+        timeout(Duration::from_secs(5), shutdown)
+            .await
+            .expect("shutdown completes after the signal")
+            .expect("shutdown task");
+
+        assert!(!fifo.exists());
+        State::load(&state_file).expect("state saved on shutdown");
+    }
+}
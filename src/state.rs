@@ -1,11 +1,30 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
+use crate::{commands::RepeatMode, tag::TagId};
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct State {
     pub queue: Vec<PathBuf>,
     pub position: Option<usize>,
+    /// Local date (`YYYY-MM-DD`) that `playtime_secs` was accumulated on.
+    #[serde(default)]
+    pub playtime_date: Option<String>,
+    #[serde(default)]
+    pub playtime_secs: u64,
+    /// Playback speed; normal speed when missing.
+    #[serde(default)]
+    pub speed: Option<f32>,
+    /// Most recently scanned tag, so the web UI can still offer to assign it after a restart.
+    #[serde(default)]
+    pub last_tag: Option<TagId>,
+    /// Repeat mode; the default (`ALL`) when missing.
+    #[serde(default)]
+    pub repeat_mode: Option<RepeatMode>,
 }
 
 impl State {
@@ -15,9 +34,47 @@ impl State {
         Ok(())
     }
 
-    pub fn load(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let data = fs::read_to_string(path)?;
         let state = serde_json::from_str(&data)?;
         Ok(state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use tempfile::tempdir;
+
+    use super::State;
+    use crate::{commands::RepeatMode, tag::TagId};
+
+    #[test]
+    fn last_tag_and_repeat_mode_round_trip_and_are_optional() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("state.json");
+        let state = State {
+            queue: vec![PathBuf::from("/music/a.mp3")],
+            position: Some(0),
+            last_tag: Some(TagId::from_hex_str("0a1b2c3d").expect("valid tag")),
+            repeat_mode: Some(RepeatMode::One),
+            ..State::default()
+        };
+
+        state.save(&path).expect("save state");
+        let loaded = State::load(&path).expect("load state");
+        assert_eq!(loaded.last_tag, state.last_tag);
+        assert_eq!(loaded.repeat_mode, Some(RepeatMode::One));
+        assert!(
+            fs::read_to_string(&path)
+                .expect("read state")
+                .contains("\"last_tag\": \"0A1B2C3D\"")
+        );
+
+        fs::write(&path, r#"{"queue": [], "position": null}"#).expect("write old state");
+        let old = State::load(&path).expect("load old state");
+        assert_eq!(old.last_tag, None);
+        assert_eq!(old.repeat_mode, None);
+    }
+}
@@ -1,6 +1,9 @@
-use std::str::FromStr;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TagId([u8; 4]);
@@ -53,6 +56,15 @@ impl<'de> Deserialize<'de> for TagId {
     }
 }
 
+impl Serialize for TagId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 impl FromStr for TagId {
     type Err = String;
 
@@ -61,10 +73,115 @@ impl FromStr for TagId {
     }
 }
 
+/// Drops repeat reads of a tag held at the reader: a tag fires again only once it has gone
+/// unseen for `window`, or when a different tag shows up.
+#[derive(Debug)]
+pub struct TagDebouncer {
+    window: Duration,
+    last_seen: Option<(TagId, Instant)>,
+}
+
+impl TagDebouncer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_seen: None,
+        }
+    }
+
+    /// Records a read of `id` at `now` and returns whether it should run the tag's command.
+    pub fn should_fire(&mut self, id: TagId, now: Instant) -> bool {
+        let repeat = self.last_seen.is_some_and(|(last, seen)| {
+            last == id && now.saturating_duration_since(seen) < self.window
+        });
+        self.last_seen = Some((id, now));
+        !repeat
+    }
+}
+
+/// Notices when the tag at the reader is taken away: after a tag was read, that many polls
+/// in a row without a tag count as a removal.
+#[derive(Debug)]
+pub struct TagPresence {
+    misses_to_remove: u32,
+    present: Option<TagId>,
+    misses: u32,
+}
+
+impl TagPresence {
+    pub fn new(misses_to_remove: u32) -> Self {
+        Self {
+            misses_to_remove: misses_to_remove.max(1),
+            present: None,
+            misses: 0,
+        }
+    }
+
+    /// Records a poll that read `id`.
+    pub fn seen(&mut self, id: TagId) {
+        self.present = Some(id);
+        self.misses = 0;
+    }
+
+    /// Records a poll without a tag and returns the tag that was removed, once it has been
+    /// missing for enough polls in a row.
+    pub fn missed(&mut self) -> Option<TagId> {
+        self.present?;
+        self.misses += 1;
+        if self.misses < self.misses_to_remove {
+            return None;
+        }
+        self.misses = 0;
+        self.present.take()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn debouncer_suppresses_a_held_tag() {
+        let window = Duration::from_secs(1);
+        let mut debouncer = TagDebouncer::new(window);
+        let tag = TagId::from_uid([1, 2, 3, 4]);
+        let other = TagId::from_uid([5, 6, 7, 8]);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert!(debouncer.should_fire(tag, at(0)));
+        // Held at the reader: each read extends the window.
+        assert!(!debouncer.should_fire(tag, at(500)));
+        assert!(!debouncer.should_fire(tag, at(1_400)));
+        // A different tag fires right away.
+        assert!(debouncer.should_fire(other, at(1_500)));
+        assert!(debouncer.should_fire(tag, at(1_600)));
+        // Taken away for longer than the window and presented again.
+        assert!(debouncer.should_fire(tag, at(2_600)));
+    }
+
+    #[test]
+    fn presence_reports_removal_after_consecutive_misses() {
+        let tag = TagId::from_uid([1, 2, 3, 4]);
+        let mut presence = TagPresence::new(3);
+
+        // Nothing was present, so nothing can be removed.
+        assert_eq!(presence.missed(), None);
+
+        presence.seen(tag);
+        assert_eq!(presence.missed(), None);
+        assert_eq!(presence.missed(), None);
+        // A read in between (a flaky poll) starts the count over.
+        presence.seen(tag);
+        assert_eq!(presence.missed(), None);
+        assert_eq!(presence.missed(), None);
+        assert_eq!(presence.missed(), Some(tag));
+        // Removal is reported once.
+        assert_eq!(presence.missed(), None);
+        assert_eq!(presence.missed(), None);
+        assert_eq!(presence.missed(), None);
+    }
+
     #[test]
     fn tag_id_parses_hex() {
         let tag = TagId::from_hex_str("0a1b2c3d").expect("valid hex");
@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{task, time::sleep};
+use tracing::{info, warn};
+
+pub const SAVE_DELAY: Duration = Duration::from_secs(2);
+pub const MAX_OFFSET: i32 = 100;
+
+/// Manual per-track volume adjustments in percentage points, keyed by track path.
+pub struct TrackVolumes {
+    file: Option<PathBuf>,
+    offsets: BTreeMap<PathBuf, i32>,
+    generation: Arc<AtomicU64>,
+    save_delay: Duration,
+}
+
+impl TrackVolumes {
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let offsets = file
+            .as_deref()
+            .filter(|path| path.exists())
+            .map(|path| match read_offsets(path) {
+                Ok(offsets) => offsets,
+                Err(err) => {
+                    warn!(?path, "Failed to load track volumes: {err}");
+                    BTreeMap::new()
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            file,
+            offsets,
+            generation: Arc::new(AtomicU64::new(0)),
+            save_delay: SAVE_DELAY,
+        }
+    }
+
+    pub fn offset(&self, track: &Path) -> i32 {
+        self.offsets.get(track).copied().unwrap_or(0)
+    }
+
+    /// Adds `delta` to the track's offset and schedules a debounced save.
+    /// Must be called from within a tokio runtime.
+    pub fn adjust(&mut self, track: &Path, delta: i32) -> i32 {
+        let offset = (self.offset(track) + delta).clamp(-MAX_OFFSET, MAX_OFFSET);
+        if offset == 0 {
+            self.offsets.remove(track);
+        } else {
+            self.offsets.insert(track.to_path_buf(), offset);
+        }
+        info!(?track, offset, "Track volume offset updated");
+
+        self.schedule_save();
+        offset
+    }
+
+    fn schedule_save(&self) {
+        let Some(file) = self.file.clone() else {
+            return;
+        };
+
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let latest = Arc::clone(&self.generation);
+        let offsets = self.offsets.clone();
+        let delay = self.save_delay;
+
+        task::spawn(async move {
+            sleep(delay).await;
+            if latest.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            if let Err(err) = write_offsets(&file, offsets) {
+                warn!(path = ?file, "Failed to save track volumes: {err}");
+            }
+        });
+    }
+}
+
+fn read_offsets(
+    path: &Path,
+) -> Result<BTreeMap<PathBuf, i32>, Box<dyn std::error::Error + Send + Sync>> {
+    let data = fs::read_to_string(path)?;
+    let offsets: BTreeMap<PathBuf, i32> = serde_json::from_str(&data)?;
+    Ok(offsets
+        .into_iter()
+        .filter(|(track, _)| track.exists())
+        .collect())
+}
+
+/// Writes offsets for tracks that still exist; stale entries for deleted files are dropped.
+fn write_offsets(
+    path: &Path,
+    mut offsets: BTreeMap<PathBuf, i32>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    offsets.retain(|track, _| track.exists());
+    let data = serde_json::to_string_pretty(&offsets)?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn adjust_accumulates_and_clamps() {
+        let mut volumes = TrackVolumes::load(None);
+        let track = Path::new("/music/loud.mp3");
+
+        assert_eq!(volumes.adjust(track, -10), -10);
+        assert_eq!(volumes.adjust(track, -5), -15);
+        assert_eq!(volumes.offset(track), -15);
+        assert_eq!(volumes.adjust(track, 500), MAX_OFFSET);
+        assert_eq!(volumes.adjust(track, -MAX_OFFSET), 0);
+        assert!(volumes.offsets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn saves_after_debounce_and_skips_deleted_tracks() {
+        let tmp = tempdir().expect("tempdir");
+        let file = tmp.path().join("track_volumes.json");
+        let kept = tmp.path().join("kept.mp3");
+        let deleted = tmp.path().join("deleted.mp3");
+        fs::write(&kept, "audio").expect("write kept");
+
+        let mut volumes = TrackVolumes::load(Some(file.clone()));
+        volumes.save_delay = Duration::from_millis(20);
+        volumes.adjust(&kept, -10);
+        volumes.adjust(&kept, -10);
+        volumes.adjust(&deleted, 5);
+        assert!(!file.exists(), "save should be debounced");
+
+        sleep(Duration::from_millis(100)).await;
+
+        let reloaded = TrackVolumes::load(Some(file));
+        assert_eq!(reloaded.offset(&kept), -20);
+        assert_eq!(reloaded.offset(&deleted), 0);
+    }
+
+    #[test]
+    fn load_ignores_stale_entries() {
+        let tmp = tempdir().expect("tempdir");
+        let file = tmp.path().join("track_volumes.json");
+        let kept = tmp.path().join("kept.mp3");
+        fs::write(&kept, "audio").expect("write kept");
+        let data = serde_json::json!({
+            kept.display().to_string(): 15,
+            tmp.path().join("gone.mp3").display().to_string(): -30,
+        });
+        fs::write(&file, data.to_string()).expect("write offsets");
+
+        let volumes = TrackVolumes::load(Some(file));
+
+        assert_eq!(volumes.offsets.len(), 1);
+        assert_eq!(volumes.offset(&kept), 15);
+    }
+}
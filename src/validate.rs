@@ -0,0 +1,202 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{
+    config::{Config, TagMapping},
+    tag::TagId,
+};
+
+/// Checks the config at `path` the way the server would load it, plus things that only fail
+/// later at runtime, and returns every problem found. Bad `[tags]` entries are reported one
+/// by one and left out, so they don't hide problems elsewhere.
+pub fn validate(path: &Path) -> Vec<String> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) => return vec![format!("Failed to read {}: {err}", path.display())],
+    };
+    let mut table: toml::Table = match raw.parse() {
+        Ok(table) => table,
+        Err(err) => return vec![format!("Invalid TOML: {err}")],
+    };
+
+    let mut problems = Vec::new();
+    if let Some(toml::Value::Table(tags)) = table.get_mut("tags") {
+        tags.retain(|id, value| match tag_problem(id, value) {
+            Some(problem) => {
+                problems.push(problem);
+                false
+            }
+            None => true,
+        });
+    }
+
+    let config = match table
+        .try_into::<Config>()
+        .map_err(|err| err.to_string())
+        .and_then(|config| config.checked(path).map_err(|err| err.to_string()))
+    {
+        Ok(config) => config,
+        Err(err) => {
+            problems.push(err);
+            return problems;
+        }
+    };
+
+    for music in &config.music {
+        if music.url().is_none() && !music.dir.is_dir() {
+            problems.push(format!(
+                "Music directory {} does not exist",
+                music.dir.display()
+            ));
+        }
+    }
+    let mut tags: Vec<_> = config.tags.iter().collect();
+    tags.sort_by_key(|(id, _)| id.to_string());
+    for (id, mapping) in tags {
+        problems.extend(
+            mapping
+                .command
+                .filters()
+                .iter()
+                .filter_map(|filter| filter.split_path_warning())
+                .map(|warning| format!("Tag {id}: {warning}")),
+        );
+    }
+    problems.extend(pin_collisions(&configured_pins(&config)));
+    problems
+}
+
+fn tag_problem(id: &str, value: &toml::Value) -> Option<String> {
+    if let Err(err) = TagId::from_hex_str(id) {
+        return Some(format!("Tag {id}: {err}"));
+    }
+    value
+        .clone()
+        .try_into::<TagMapping>()
+        .err()
+        .map(|err| format!("Tag {id}: {}", err.message().trim()))
+}
+
+/// Every GPIO pin the config claims, with the setting that claims it.
+#[cfg(feature = "rpi")]
+fn configured_pins(config: &Config) -> Vec<(&'static str, u8)> {
+    let mut pins = Vec::new();
+    if let Some(gpio) = &config.gpio {
+        pins.extend(
+            [
+                ("gpio.play", gpio.play),
+                ("gpio.next", gpio.next),
+                ("gpio.prev", gpio.prev),
+                ("gpio.volume_up", gpio.volume_up),
+                ("gpio.volume_down", gpio.volume_down),
+                ("gpio.shutdown", gpio.shutdown),
+                ("gpio.panic.pin", gpio.panic.as_ref().map(|panic| panic.pin)),
+                ("gpio.led", gpio.led),
+                ("gpio.volume_encoder_clk", gpio.volume_encoder_clk),
+                ("gpio.volume_encoder_dt", gpio.volume_encoder_dt),
+            ]
+            .into_iter()
+            .filter_map(|(name, pin)| Some((name, pin?))),
+        );
+    }
+    if let Some(rfid) = &config.rfid {
+        pins.push(("rfid.irq", rfid.irq));
+        pins.extend(rfid.reset.map(|pin| ("rfid.reset", pin)));
+    }
+    pins
+}
+
+/// Without the `rpi` feature GPIO and RFID settings aren't read, so no pins are in use.
+#[cfg(not(feature = "rpi"))]
+fn configured_pins(_config: &Config) -> Vec<(&'static str, u8)> {
+    Vec::new()
+}
+
+/// One message per pin claimed by more than one setting.
+fn pin_collisions(pins: &[(&str, u8)]) -> Vec<String> {
+    let mut users: BTreeMap<u8, Vec<&str>> = BTreeMap::new();
+    for &(name, pin) in pins {
+        users.entry(pin).or_default().push(name);
+    }
+    users
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(pin, names)| format!("GPIO pin {pin} is used by {}", names.join(", ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::{pin_collisions, validate};
+
+    #[test]
+    fn reports_pins_claimed_twice() {
+        let pins = [
+            ("gpio.play", 17),
+            ("gpio.next", 27),
+            ("gpio.led", 17),
+            ("rfid.irq", 27),
+            ("rfid.reset", 25),
+        ];
+
+        assert_eq!(
+            pin_collisions(&pins),
+            vec![
+                "GPIO pin 17 is used by gpio.play, gpio.led",
+                "GPIO pin 27 is used by gpio.next, rfid.irq",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_bad_tags_and_missing_music_dirs_together() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music dir");
+        let missing = tmp.path().join("missing");
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "[[music]]\ndir = {music:?}\n\n[[music]]\ndir = {missing:?}\n\n\
+                 [server]\nweb = \"127.0.0.1:8080\"\n\n\
+                 [tags]\n0A1B2C3D = \"PLAY\"\n11223344 = \"DANCE\"\nXYZ = \"STOP\"\n\
+                 55667788 = \"PLAY kids/Peter and the Wolf/*\"\n"
+            ),
+        )
+        .expect("write config");
+
+        let problems = validate(&path);
+
+        assert_eq!(problems.len(), 4, "{problems:?}");
+        assert!(problems[0].starts_with("Tag 11223344: "), "{problems:?}");
+        assert!(problems[1].starts_with("Tag XYZ: "), "{problems:?}");
+        assert_eq!(
+            problems[2],
+            format!("Music directory {} does not exist", missing.display())
+        );
+        assert!(
+            problems[3].starts_with("Tag 55667788: filter `kids/Peter and the Wolf/*`"),
+            "{problems:?}"
+        );
+    }
+
+    #[test]
+    fn a_valid_config_has_no_problems() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "[[music]]\ndir = {:?}\n\n[server]\nweb = \"127.0.0.1:8080\"\n\n[tags]\n0A1B2C3D = \"PLAY\"\n",
+                tmp.path()
+            ),
+        )
+        .expect("write config");
+
+        assert_eq!(validate(&path), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::AuthConfig;
+
+const SESSION_COOKIE: &str = "crabbox_session";
+
+/// Credentials for the web interface, plus the sessions that expire after inactivity when
+/// `session_timeout_mins` is set.
+pub(super) struct Auth {
+    config: AuthConfig,
+    sessions: Option<Sessions>,
+}
+
+impl Auth {
+    pub(super) fn new(config: AuthConfig) -> Self {
+        let sessions = config
+            .session_timeout_mins
+            .filter(|mins| *mins > 0)
+            .map(|mins| Sessions::new(Duration::from_mins(mins)));
+        Self { config, sessions }
+    }
+}
+
+/// Browser sessions keyed by cookie value, with the time of their last request.
+struct Sessions {
+    timeout: Duration,
+    last_seen: Mutex<HashMap<String, Instant>>,
+}
+
+impl Sessions {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts a session and returns its id.
+    fn start(&self, now: Instant) -> String {
+        let id = format!("{:032x}", rand::rng().random::<u128>());
+        if let Ok(mut last_seen) = self.last_seen.lock() {
+            last_seen.insert(id.clone(), now);
+        }
+        id
+    }
+
+    /// Records a request in session `id`. False once it has been idle for `timeout`, or if it
+    /// is unknown, e.g. from before a restart.
+    fn touch(&self, id: &str, now: Instant) -> bool {
+        let Ok(mut last_seen) = self.last_seen.lock() else {
+            return false;
+        };
+        last_seen.retain(|_, seen| now.saturating_duration_since(*seen) < self.timeout);
+        match last_seen.get_mut(id) {
+            Some(seen) => {
+                *seen = now;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Rejects requests without HTTP Basic credentials matching `auth`. With a session timeout,
+/// a request from an expired session is rejected too, so the browser asks for the password
+/// again instead of resending the one it remembered.
+pub(super) async fn require_basic_auth(
+    State(auth): State<Arc<Auth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_authorized(&auth.config, request.headers()) {
+        if request.headers().contains_key(header::AUTHORIZATION) {
+            warn!(uri = %request.uri(), "Rejected web request with wrong credentials");
+        }
+        return unauthorized();
+    }
+    let Some(sessions) = &auth.sessions else {
+        return next.run(request).await;
+    };
+
+    let now = Instant::now();
+    match session_cookie(request.headers()) {
+        Some(id) if sessions.touch(&id, now) => next.run(request).await,
+        Some(_) => {
+            info!("Web session expired; asking for credentials again");
+            let mut response = unauthorized();
+            response.headers_mut().append(
+                header::SET_COOKIE,
+                HeaderValue::from_static("crabbox_session=; Path=/; Max-Age=0; HttpOnly"),
+            );
+            response
+        }
+        None => {
+            let id = sessions.start(now);
+            let mut response = next.run(request).await;
+            if let Ok(cookie) = HeaderValue::from_str(&format!(
+                "{SESSION_COOKIE}={id}; Path=/; HttpOnly; SameSite=Strict"
+            )) {
+                response.headers_mut().append(header::SET_COOKIE, cookie);
+            }
+            response
+        }
+    }
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"crabbox\", charset=\"UTF-8\""),
+        )],
+        "Authentication required",
+    )
+        .into_response()
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then(|| value.to_string())
+        })
+}
+
+fn is_authorized(auth: &AuthConfig, headers: &HeaderMap) -> bool {
+    let Some((username, password)) = basic_credentials(headers) else {
+        return false;
+    };
+    let password_hash = hex_sha256(password.as_bytes());
+    // Evaluate both so a wrong username takes as long as a wrong password.
+    let username_ok = constant_time_eq(username.as_bytes(), auth.username.as_bytes());
+    let password_ok = constant_time_eq(
+        password_hash.as_bytes(),
+        auth.password_sha256.to_ascii_lowercase().as_bytes(),
+    );
+    username_ok & password_ok
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode, header},
+        middleware::from_fn_with_state,
+        routing::get,
+    };
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use tower::ServiceExt;
+
+    use super::{Auth, Sessions, hex_sha256, require_basic_auth};
+    use crate::config::AuthConfig;
+
+    fn app() -> Router {
+        app_with_sessions(None)
+    }
+
+    fn app_with_sessions(session_timeout_mins: Option<u64>) -> Router {
+        let auth = Arc::new(Auth::new(AuthConfig {
+            username: "parent".to_string(),
+            password_sha256: hex_sha256(b"hunter2"),
+            session_timeout_mins,
+        }));
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(from_fn_with_state(auth, require_basic_auth))
+    }
+
+    async fn status_with(authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().uri("/");
+        if let Some(value) = authorization {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[test]
+    fn hashes_passwords_as_lowercase_hex() {
+        assert_eq!(
+            hex_sha256(b"hunter2"),
+            "f52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7"
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_matching_credentials() {
+        assert_eq!(
+            status_with(Some(&basic("parent:hunter2"))).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_wrong_credentials() {
+        let response = app()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(
+            response.headers()[header::WWW_AUTHENTICATE]
+                .to_str()
+                .unwrap()
+                .starts_with("Basic")
+        );
+
+        assert_eq!(
+            status_with(Some(&basic("parent:wrong"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status_with(Some(&basic("kid:hunter2"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status_with(Some("Bearer hunter2")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn sessions_expire_after_the_idle_timeout() {
+        let sessions = Sessions::new(Duration::from_mins(10));
+        let start = Instant::now();
+        let id = sessions.start(start);
+
+        assert!(sessions.touch(&id, start + Duration::from_mins(9)));
+        assert!(sessions.touch(&id, start + Duration::from_mins(18)));
+        assert!(!sessions.touch(&id, start + Duration::from_mins(28)));
+        assert!(!sessions.touch(&id, start + Duration::from_mins(29)));
+        assert!(!sessions.touch("unknown", start));
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_must_log_in_again() {
+        let app = app_with_sessions(Some(30));
+        let credentials = basic("parent:hunter2");
+        let request = |cookie: Option<&str>| {
+            let mut request = Request::builder()
+                .uri("/")
+                .header(header::AUTHORIZATION, &credentials);
+            if let Some(cookie) = cookie {
+                request = request.header(header::COOKIE, cookie);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let first = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let cookie = first.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+        assert!(cookie.starts_with("crabbox_session="), "{cookie}");
+
+        let again = app.clone().oneshot(request(Some(&cookie))).await.unwrap();
+        assert_eq!(again.status(), StatusCode::OK);
+        assert!(!again.headers().contains_key(header::SET_COOKIE));
+
+        let expired = app
+            .clone()
+            .oneshot(request(Some("crabbox_session=0123")))
+            .await
+            .unwrap();
+        assert_eq!(expired.status(), StatusCode::UNAUTHORIZED);
+        assert!(
+            expired.headers()[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .contains("Max-Age=0")
+        );
+
+        let relogin = app.oneshot(request(None)).await.unwrap();
+        assert_eq!(relogin.status(), StatusCode::OK);
+        assert!(relogin.headers().contains_key(header::SET_COOKIE));
+    }
+}
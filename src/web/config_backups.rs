@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Form, State},
+    response::{Json, Redirect},
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{AppState, api_error::ApiError, error_redirect};
+
+#[derive(Serialize)]
+pub(super) struct ConfigBackupResponse {
+    name: String,
+    file: String,
+    created: String,
+}
+
+/// Lists the config and tags file backups, newest first.
+pub(super) async fn config_backups(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ConfigBackupResponse>>, ApiError> {
+    let crabbox = state
+        .crabbox
+        .lock()
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+    Ok(Json(
+        crabbox
+            .config_backups()
+            .into_iter()
+            .map(|backup| ConfigBackupResponse {
+                name: backup.name,
+                file: backup.file.display().to_string(),
+                created: backup.created.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub(super) struct RestoreConfigForm {
+    name: String,
+}
+
+pub(super) async fn restore_config(
+    State(state): State<AppState>,
+    Form(form): Form<RestoreConfigForm>,
+) -> Redirect {
+    let result = match state.crabbox.lock() {
+        Ok(mut crabbox) => crabbox.restore_config_backup(&form.name),
+        Err(_) => Err("Failed to access crabbox".to_string()),
+    };
+    match result {
+        Ok(()) => Redirect::to("/"),
+        Err(err) => {
+            warn!(backup = form.name, "Failed to restore config backup: {err}");
+            error_redirect(&err)
+        }
+    }
+}
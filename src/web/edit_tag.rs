@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use axum::{
-    extract::{Form, Path, State},
+    extract::{Form, Path, Query, State},
     response::{Html, Redirect},
 };
 use serde::{Deserialize, Serialize};
@@ -16,11 +16,21 @@ pub(super) struct AssignTagForm {
     tag_id: String,
     command: Option<String>,
     filter: Option<String>,
+    sequence: Option<String>,
     action: String,
+    /// Command the user already saw a warning for and chose to save anyway.
+    confirm: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct EditTagQuery {
+    warning: Option<String>,
+    confirm: Option<String>,
 }
 
 pub(super) async fn edit_tag(
     Path(tag_id): Path<String>,
+    Query(query): Query<EditTagQuery>,
     State(state): State<AppState>,
 ) -> Html<String> {
     let snapshot = state.crabbox.lock().ok().map(|c| c.snapshot());
@@ -32,18 +42,32 @@ pub(super) async fn edit_tag(
             tag: None,
             error: None,
             tag_id: requested_tag_id.clone(),
+            config_warning: None,
+            warning: None,
+            confirm: None,
         },
         |snapshot| match TagId::from_str(&requested_tag_id) {
             Ok(id) => {
-                let command = snapshot
-                    .tags
-                    .iter()
-                    .find(|(tag, _)| *tag == id)
-                    .map(|(_, command)| command.clone());
+                // After a held-back save, show the command that was entered.
+                let command = query
+                    .confirm
+                    .as_deref()
+                    .and_then(|command| Command::from_str(command).ok())
+                    .or_else(|| {
+                        snapshot
+                            .tags
+                            .iter()
+                            .find(|(tag, _)| *tag == id)
+                            .map(|(_, command)| command.clone())
+                    });
                 let (selected_command, filter) = command.as_ref().map_or_else(
                     || ("PLAY".to_string(), None),
                     |command| (command.name().to_string(), command_filter(command)),
                 );
+                let sequence = command
+                    .as_ref()
+                    .filter(|command| matches!(command, Command::Sequence(_)))
+                    .map(ToString::to_string);
 
                 EditTagTemplateContext {
                     available: true,
@@ -51,10 +75,14 @@ pub(super) async fn edit_tag(
                         id: id.to_string(),
                         selected_command: selected_command.clone(),
                         filter,
+                        sequence,
                         command_options: command_options(&selected_command),
                     }),
                     error: None,
                     tag_id: requested_tag_id.clone(),
+                    config_warning: snapshot.config_warning.clone(),
+                    warning: query.warning,
+                    confirm: query.confirm,
                 }
             }
             Err(err) => EditTagTemplateContext {
@@ -62,6 +90,9 @@ pub(super) async fn edit_tag(
                 tag: None,
                 error: Some(err),
                 tag_id: requested_tag_id,
+                config_warning: snapshot.config_warning.clone(),
+                warning: None,
+                confirm: None,
             },
         },
     );
@@ -77,6 +108,12 @@ pub(super) async fn assign_tag(
         Ok(tag_id) => {
             let command_text = match form.action.as_str() {
                 "delete" => None,
+                _ if form.command.as_deref() == Some(SEQUENCE) => form
+                    .sequence
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(ToString::to_string),
                 _ => form
                     .command
                     .as_deref()
@@ -99,6 +136,12 @@ pub(super) async fn assign_tag(
                         composed
                     }),
             };
+            if let Some(command) = command_text.as_deref()
+                && form.confirm.as_deref() != Some(command)
+                && let Err(warning) = check_filters(&state, command).await
+            {
+                return warning_redirect(tag_id, &warning, command);
+            }
             send_command(
                 &state,
                 Command::AssignTag {
@@ -114,11 +157,34 @@ pub(super) async fn assign_tag(
     Redirect::to("/edit_tag")
 }
 
+/// Checks that the filters in `command` match tracks. Unparseable commands pass, so
+/// `Crabbox::assign_tag` reports them as before.
+async fn check_filters(state: &AppState, command: &str) -> Result<(), String> {
+    let Ok(command) = Command::from_str(command) else {
+        return Ok(());
+    };
+    let Ok(library) = state.crabbox.lock().map(|crabbox| crabbox.library.clone()) else {
+        return Ok(());
+    };
+    tokio::task::spawn_blocking(move || library.check_filters(&command))
+        .await
+        .unwrap_or(Ok(()))
+}
+
+/// Sends the user back to the tag with `warning`, offering to save `command` anyway.
+fn warning_redirect(tag_id: TagId, warning: &str, command: &str) -> Redirect {
+    match serde_urlencoded::to_string([("warning", warning), ("confirm", command)]) {
+        Ok(query) => Redirect::to(&format!("/edit_tag/{tag_id}?{query}")),
+        Err(_) => Redirect::to(&format!("/edit_tag/{tag_id}")),
+    }
+}
+
 #[derive(Serialize)]
 struct TagTemplateContext {
     id: String,
     selected_command: String,
     filter: Option<String>,
+    sequence: Option<String>,
     command_options: Vec<CommandOptionContext>,
 }
 
@@ -128,6 +194,11 @@ struct EditTagTemplateContext {
     tag: Option<TagTemplateContext>,
     error: Option<String>,
     tag_id: String,
+    config_warning: Option<String>,
+    /// Why the last save was held back.
+    warning: Option<String>,
+    /// Command to save anyway when the form is submitted again.
+    confirm: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -138,17 +209,21 @@ struct CommandOptionContext {
     selected: bool,
 }
 
+/// Select value for entering several `;`-separated commands as free text.
+const SEQUENCE: &str = "SEQUENCE";
+
 fn command_filter(command: &Command) -> Option<String> {
     match command {
-        Command::Play { filter } | Command::PlayPause { filter } | Command::Shuffle { filter } => {
-            filter.clone()
-        }
+        Command::Play { filter }
+        | Command::PlayPause { filter }
+        | Command::Shuffle { filter }
+        | Command::Enqueue { filter } => filter.as_ref().map(ToString::to_string),
         _ => None,
     }
 }
 
 fn command_requires_filter(name: &str) -> bool {
-    matches!(name, "PLAY" | "PLAYPAUSE" | "SHUFFLE")
+    matches!(name, "PLAY" | "PLAYPAUSE" | "SHUFFLE" | "ENQUEUE")
 }
 
 fn command_options(selected_command: &str) -> Vec<CommandOptionContext> {
@@ -156,12 +231,18 @@ fn command_options(selected_command: &str) -> Vec<CommandOptionContext> {
         Command::Play { filter: None },
         Command::PlayPause { filter: None },
         Command::Shuffle { filter: None },
+        Command::Enqueue { filter: None },
         Command::Stop,
+        Command::Pause,
+        Command::Resume,
         Command::Next,
         Command::Prev,
+        Command::NextDir,
+        Command::PrevDir,
         Command::VolumeUp,
         Command::VolumeDown,
         Command::Shutdown,
+        Command::ResetLimit,
     ];
 
     commands
@@ -175,5 +256,11 @@ fn command_options(selected_command: &str) -> Vec<CommandOptionContext> {
                 value: name,
             }
         })
+        .chain(std::iter::once(CommandOptionContext {
+            value: SEQUENCE.to_string(),
+            label: "SEQUENCE (several commands)".to_string(),
+            requires_filter: false,
+            selected: selected_command == SEQUENCE,
+        }))
         .collect()
 }
@@ -0,0 +1,123 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tracing::warn;
+
+use crate::crabbox::StatusEvent;
+
+use super::{AppState, status::StatusResponse};
+
+/// Items sent to SSE and WebSocket subscribers: live updates, or a full snapshot after
+/// connecting or falling behind.
+#[derive(Debug, PartialEq)]
+pub(super) enum StreamItem {
+    Update(StatusEvent),
+    Snapshot(StatusResponse),
+}
+
+pub(super) async fn events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = subscribe(&state).map(|item| {
+        let event = match item {
+            StreamItem::Update(update) => Event::default().event("status").json_data(update),
+            StreamItem::Snapshot(snapshot) => {
+                Event::default().event("snapshot").json_data(snapshot)
+            }
+        };
+        Ok(event.unwrap_or_else(|err| Event::default().comment(format!("error: {err}"))))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Subscribes to status changes, starting with a snapshot of the current state.
+pub(super) fn subscribe(state: &AppState) -> impl Stream<Item = StreamItem> + use<> {
+    let receiver = state.crabbox.lock().ok().map(|crabbox| crabbox.subscribe());
+    let crabbox = state.crabbox.clone();
+    let snapshot = move || {
+        crabbox
+            .lock()
+            .ok()
+            .map(|crabbox| StatusResponse::from(crabbox.snapshot()))
+    };
+
+    status_stream(receiver, snapshot)
+}
+
+fn status_stream(
+    receiver: Option<Receiver<StatusEvent>>,
+    snapshot: impl Fn() -> Option<StatusResponse>,
+) -> impl Stream<Item = StreamItem> {
+    let initial = stream::iter(snapshot().map(StreamItem::Snapshot));
+    let updates = stream::unfold((receiver, snapshot), |(receiver, snapshot)| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((StreamItem::Update(event), (Some(receiver), snapshot))),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Status subscriber lagged; resyncing");
+                    if let Some(resync) = snapshot() {
+                        return Some((StreamItem::Snapshot(resync), (Some(receiver), snapshot)));
+                    }
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    initial.chain(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StreamItem, status_stream};
+    use crate::{
+        crabbox::{CrabboxSnapshot, StatusEvent},
+        web::status::StatusResponse,
+    };
+    use futures_util::StreamExt;
+    use tokio::sync::broadcast;
+
+    fn snapshot() -> StatusResponse {
+        CrabboxSnapshot::default().into()
+    }
+
+    #[tokio::test]
+    async fn sends_snapshot_then_updates() {
+        let (tx, rx) = broadcast::channel(4);
+        let mut stream = Box::pin(status_stream(Some(rx), || Some(snapshot())));
+
+        assert_eq!(stream.next().await, Some(StreamItem::Snapshot(snapshot())));
+        tx.send(StatusEvent::Stopped).unwrap();
+        assert_eq!(
+            stream.next().await,
+            Some(StreamItem::Update(StatusEvent::Stopped))
+        );
+        drop(tx);
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn lagged_subscribers_get_a_snapshot() {
+        let (tx, rx) = broadcast::channel(1);
+        let mut stream = Box::pin(status_stream(Some(rx), || Some(snapshot())));
+        stream.next().await;
+
+        tx.send(StatusEvent::Stopped).unwrap();
+        tx.send(StatusEvent::PauseChanged { paused: true }).unwrap();
+
+        assert_eq!(stream.next().await, Some(StreamItem::Snapshot(snapshot())));
+        assert_eq!(
+            stream.next().await,
+            Some(StreamItem::Update(StatusEvent::PauseChanged {
+                paused: true
+            }))
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Form, State},
+    http::StatusCode,
+    response::Redirect,
+};
+use serde::Deserialize;
+
+use super::AppState;
+
+#[derive(Deserialize)]
+pub(super) struct FavoriteForm {
+    path: String,
+}
+
+pub(super) async fn add_favorite(
+    State(state): State<AppState>,
+    Form(form): Form<FavoriteForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let path = PathBuf::from(form.path.trim());
+    let mut crabbox = state.crabbox.lock().map_err(|_| crabbox_unavailable())?;
+    crabbox
+        .add_favorite(path)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    Ok(Redirect::to("/library"))
+}
+
+pub(super) async fn remove_favorite(
+    State(state): State<AppState>,
+    Form(form): Form<FavoriteForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let path = PathBuf::from(form.path.trim());
+    let mut crabbox = state.crabbox.lock().map_err(|_| crabbox_unavailable())?;
+    crabbox
+        .remove_favorite(&path)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    Ok(Redirect::to("/library"))
+}
+
+fn crabbox_unavailable() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Failed to access crabbox".to_string(),
+    )
+}
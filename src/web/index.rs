@@ -1,17 +1,45 @@
-use axum::{extract::State, response::Html};
-use serde::Serialize;
+use axum::{
+    extract::{Query, State},
+    response::Html,
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::TrackMetadata;
 
 use super::AppState;
 
+/// Played tracks listed on the page; `/api/history` has the rest.
+const RECENTLY_PLAYED_SHOWN: usize = 10;
+
+#[derive(Deserialize)]
+pub(super) struct IndexQuery {
+    error: Option<String>,
+}
+
 #[allow(clippy::too_many_lines)]
-pub(super) async fn index(State(state): State<AppState>) -> Html<String> {
+pub(super) async fn index(
+    State(state): State<AppState>,
+    Query(query): Query<IndexQuery>,
+) -> Html<String> {
     let snapshot = state.crabbox.lock().ok().map(|c| c.snapshot());
+    let library = state
+        .crabbox
+        .lock()
+        .map(|c| c.library.clone())
+        .unwrap_or_default();
 
     let (current, queue, queue_position, last_tag, last_tag_command) = match snapshot {
         Some(ref snapshot) => (
             snapshot.current.as_ref().map_or_else(
                 || "Nothing playing".to_string(),
-                |p| p.display().to_string(),
+                |p| {
+                    snapshot
+                        .current_metadata
+                        .as_ref()
+                        .and_then(TrackMetadata::display_name)
+                        .unwrap_or_else(|| library.display_path(p))
+                },
             ),
             snapshot.queue.clone(),
             snapshot.queue_position,
@@ -25,7 +53,8 @@ pub(super) async fn index(State(state): State<AppState>) -> Html<String> {
         .into_iter()
         .enumerate()
         .map(|(idx, track)| QueueItem {
-            name: track.display().to_string(),
+            name: library.display_path(&track),
+            path: track.display().to_string(),
             is_current: queue_position == Some(idx),
         })
         .collect();
@@ -49,13 +78,136 @@ pub(super) async fn index(State(state): State<AppState>) -> Html<String> {
         })
         .unwrap_or_default();
 
+    let current_track = snapshot.as_ref().and_then(|snapshot| {
+        snapshot.current.as_ref().map(|path| CurrentTrackContext {
+            path: path.display().to_string(),
+            volume_offset: snapshot.current_volume_offset,
+            paused: snapshot.paused,
+        })
+    });
+
+    let tag_history = snapshot
+        .as_ref()
+        .map(|snapshot| {
+            snapshot
+                .tag_history
+                .iter()
+                .map(|scan| TagScanContext {
+                    id: scan.id.to_string(),
+                    time: scan.at.with_timezone(&Local).format("%H:%M:%S").to_string(),
+                    mapped: scan.mapped,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let history = snapshot
+        .as_ref()
+        .map(|snapshot| {
+            snapshot
+                .history
+                .iter()
+                .take(RECENTLY_PLAYED_SHOWN)
+                .map(|played| PlayedTrackContext {
+                    name: library.display_path(&played.path),
+                    path: played.path.display().to_string(),
+                    time: played
+                        .at
+                        .with_timezone(&Local)
+                        .format("%H:%M:%S")
+                        .to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let playtime = snapshot.as_ref().and_then(|snapshot| {
+        snapshot.playtime_limit.map(|limit| PlaytimeContext {
+            used_minutes: snapshot.playtime_used.as_secs() / 60,
+            limit_minutes: limit.as_secs() / 60,
+        })
+    });
+
+    let sleep_minutes = snapshot
+        .as_ref()
+        .and_then(|snapshot| snapshot.sleep_remaining)
+        .map(|left| left.as_secs().div_ceil(60));
+
+    let idle_minutes = snapshot
+        .as_ref()
+        .and_then(|snapshot| snapshot.idle_remaining)
+        .map(|left| left.as_secs().div_ceil(60));
+
+    let volume_percent = snapshot
+        .as_ref()
+        .map_or(0, |snapshot| volume_percent(snapshot.volume));
+
+    let muted = snapshot.as_ref().is_some_and(|snapshot| snapshot.muted);
+
+    let speed = snapshot
+        .as_ref()
+        .map_or_else(|| "1".to_string(), |snapshot| snapshot.speed.to_string());
+
+    let repeat = snapshot
+        .as_ref()
+        .map(|snapshot| snapshot.repeat.to_string())
+        .unwrap_or_default();
+
+    let config_warning = snapshot
+        .as_ref()
+        .and_then(|snapshot| snapshot.config_warning.clone());
+
+    let config_backups = state
+        .crabbox
+        .lock()
+        .map(|crabbox| crabbox.config_backups())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|backup| ConfigBackupContext {
+            label: format!(
+                "{} · {}",
+                backup.created.format("%Y-%m-%d %H:%M:%S"),
+                backup
+                    .file
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default()
+            ),
+            name: backup.name,
+        })
+        .collect();
+
+    let sounds = state
+        .crabbox
+        .lock()
+        .map(|crabbox| crabbox.configured_sounds())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
     state.render(
         "index.html",
         IndexContext {
             current,
+            current_track,
+            volume_percent,
+            muted,
+            speed,
+            repeat,
             queue: queue_items,
             last_tag,
             tags,
+            tag_history,
+            history,
+            playtime,
+            sleep_minutes,
+            idle_minutes,
+            sounds,
+            config_backups,
+            config_warning,
+            error: query.error,
+            shutdown_token: state.shutdown_token.to_string(),
         },
     )
 }
@@ -63,19 +215,75 @@ pub(super) async fn index(State(state): State<AppState>) -> Html<String> {
 #[derive(Serialize)]
 struct QueueItem {
     name: String,
+    path: String,
     is_current: bool,
 }
 
+#[derive(Serialize)]
+struct CurrentTrackContext {
+    path: String,
+    volume_offset: i32,
+    paused: bool,
+}
+
 #[derive(Serialize)]
 struct TagAssignmentContext {
     id: String,
     command: Option<String>,
 }
 
+#[derive(Serialize)]
+struct TagScanContext {
+    id: String,
+    time: String,
+    mapped: bool,
+}
+
+#[derive(Serialize)]
+struct PlayedTrackContext {
+    name: String,
+    path: String,
+    time: String,
+}
+
+#[derive(Serialize)]
+struct ConfigBackupContext {
+    name: String,
+    label: String,
+}
+
+#[derive(Serialize)]
+struct PlaytimeContext {
+    used_minutes: u64,
+    limit_minutes: u64,
+}
+
 #[derive(Serialize)]
 struct IndexContext {
     current: String,
+    current_track: Option<CurrentTrackContext>,
+    volume_percent: u32,
+    muted: bool,
+    speed: String,
+    repeat: String,
     queue: Vec<QueueItem>,
     last_tag: Option<TagAssignmentContext>,
     tags: Vec<TagAssignmentContext>,
+    tag_history: Vec<TagScanContext>,
+    /// Recently played tracks, newest first.
+    history: Vec<PlayedTrackContext>,
+    playtime: Option<PlaytimeContext>,
+    /// Minutes left on the sleep timer, rounded up.
+    sleep_minutes: Option<u64>,
+    idle_minutes: Option<u64>,
+    sounds: Vec<String>,
+    config_backups: Vec<ConfigBackupContext>,
+    config_warning: Option<String>,
+    error: Option<String>,
+    shutdown_token: String,
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn volume_percent(volume: f32) -> u32 {
+    (volume.clamp(0.0, 1.0) * 100.0).round() as u32
 }
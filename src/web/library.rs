@@ -1,22 +1,268 @@
-use axum::{extract::State, response::Html};
-use serde::Serialize;
+use std::path::{Path, PathBuf};
 
-use super::AppState;
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+    response::{Html, Json},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
 
-pub(super) async fn library_page(State(state): State<AppState>) -> Html<String> {
-    let library = state
+use crate::{
+    crabbox::{PageRequest, SortOrder, TrackSort, is_library_track},
+    metadata::TrackMetadata,
+};
+
+use super::{AppState, api_error::ApiError};
+
+const PAGE_SIZE: usize = 200;
+
+#[derive(Deserialize)]
+pub(super) struct LibraryQuery {
+    #[serde(default)]
+    sort: TrackSort,
+    #[serde(default)]
+    order: SortOrder,
+    #[serde(default)]
+    offset: usize,
+}
+
+pub(super) async fn library_page(
+    State(state): State<AppState>,
+    Query(query): Query<LibraryQuery>,
+) -> Html<String> {
+    let (library, favorites) = state
+        .crabbox
+        .lock()
+        .map(|c| (c.library.clone(), c.favorites().to_vec()))
+        .unwrap_or_default();
+    let (total, offset, tracks) = tokio::task::spawn_blocking(move || {
+        let page = library.list_tracks_page(
+            None,
+            PageRequest {
+                sort: query.sort,
+                order: query.order,
+                offset: query.offset,
+                limit: Some(PAGE_SIZE),
+            },
+        );
+        let tracks = page
+            .tracks
+            .iter()
+            .map(|path| {
+                let metadata = library.metadata(path);
+                LibraryTrack {
+                    name: metadata
+                        .as_ref()
+                        .and_then(TrackMetadata::display_name)
+                        .unwrap_or_else(|| library.display_path(path)),
+                    album: metadata.and_then(|metadata| metadata.album),
+                    favorite: favorites.contains(path),
+                    path: path.display().to_string(),
+                }
+            })
+            .collect::<Vec<_>>();
+        (page.total, page.offset, tracks)
+    })
+    .await
+    .unwrap_or_default();
+
+    let end = offset + tracks.len();
+    state.render(
+        "library.html",
+        LibraryContext {
+            library: tracks,
+            total,
+            first: offset + 1,
+            last: end,
+            sort: sort_name(query.sort),
+            order: order_name(query.order),
+            page_size: PAGE_SIZE,
+            prev_offset: (offset > 0).then(|| offset.saturating_sub(PAGE_SIZE)),
+            next_offset: (end < total).then_some(end),
+        },
+    )
+}
+
+#[derive(Deserialize)]
+pub(super) struct DeleteForm {
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct DeleteResponse {
+    deleted: String,
+    /// Tracks left in the library.
+    total: usize,
+}
+
+/// Removes a track file from a music directory, refusing the one that is playing.
+pub(super) async fn delete_track(
+    State(state): State<AppState>,
+    Form(form): Form<DeleteForm>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    let path = PathBuf::from(form.path.trim());
+    let (library, directories, current) = state
+        .crabbox
+        .lock()
+        .map(|crabbox| {
+            (
+                crabbox.library.clone(),
+                crabbox.music_directories(),
+                crabbox.current().map(Path::to_path_buf),
+            )
+        })
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+
+    delete_library_file(&path, &directories, current.as_deref()).await?;
+    library.refresh();
+    let total = tokio::task::spawn_blocking(move || library.list_tracks(None).len())
+        .await
+        .map_err(|err| ApiError::internal(format!("Failed to rescan library: {err}")))?;
+
+    Ok(Json(DeleteResponse {
+        deleted: path.display().to_string(),
+        total,
+    }))
+}
+
+#[derive(Deserialize)]
+pub(super) struct RenameForm {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct RenameResponse {
+    renamed: String,
+}
+
+/// Moves a track within its music directory; `to` may be relative to that directory.
+pub(super) async fn rename_track(
+    State(state): State<AppState>,
+    Form(form): Form<RenameForm>,
+) -> Result<Json<RenameResponse>, ApiError> {
+    let from = PathBuf::from(form.from.trim());
+    let to = PathBuf::from(form.to.trim());
+    let renamed = state
         .crabbox
         .lock()
-        .map(|c| c.library.list_tracks(None))
-        .unwrap_or_default()
-        .into_iter()
-        .map(|path| path.display().to_string())
-        .collect();
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?
+        .rename_track(&from, &to)
+        .map_err(ApiError::bad_request)?;
+
+    Ok(Json(RenameResponse {
+        renamed: renamed.display().to_string(),
+    }))
+}
+
+async fn delete_library_file(
+    path: &Path,
+    directories: &[PathBuf],
+    current: Option<&Path>,
+) -> Result<(), ApiError> {
+    if !is_library_track(path, directories) {
+        return Err(ApiError::bad_request(
+            "Track must be a file inside a music directory",
+        ));
+    }
+    if current == Some(path) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "Track is playing; stop it before deleting",
+        ));
+    }
+
+    tokio::fs::remove_file(path)
+        .await
+        .map_err(|err| ApiError::internal(format!("Failed to delete {}: {err}", path.display())))?;
+    info!(?path, "Deleted track from library");
+    Ok(())
+}
+
+fn sort_name(sort: TrackSort) -> &'static str {
+    match sort {
+        TrackSort::Path => "path",
+        TrackSort::Name => "name",
+        TrackSort::Mtime => "mtime",
+    }
+}
+
+fn order_name(order: SortOrder) -> &'static str {
+    match order {
+        SortOrder::Asc => "asc",
+        SortOrder::Desc => "desc",
+    }
+}
 
-    state.render("library.html", LibraryContext { library })
+#[derive(Serialize)]
+struct LibraryTrack {
+    name: String,
+    album: Option<String>,
+    path: String,
+    favorite: bool,
 }
 
 #[derive(Serialize)]
 struct LibraryContext {
-    library: Vec<String>,
+    library: Vec<LibraryTrack>,
+    total: usize,
+    first: usize,
+    last: usize,
+    sort: &'static str,
+    order: &'static str,
+    page_size: usize,
+    prev_offset: Option<usize>,
+    next_offset: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{http::StatusCode, response::IntoResponse};
+    use tempfile::tempdir;
+
+    use super::delete_library_file;
+
+    #[tokio::test]
+    async fn deletes_files_inside_music_directories() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let track = music.join("bad upload.mp3");
+        fs::write(&track, "audio").expect("write track");
+
+        delete_library_file(&track, &[music], None)
+            .await
+            .expect("delete");
+
+        assert!(!track.exists());
+    }
+
+    #[tokio::test]
+    async fn refuses_escapes_and_the_playing_track() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let secret = tmp.path().join("secret.mp3");
+        fs::write(&secret, "keep").expect("write secret");
+        let playing = music.join("playing.mp3");
+        fs::write(&playing, "audio").expect("write track");
+        let directories = [music.clone()];
+
+        for path in [music.join("../secret.mp3"), secret.clone()] {
+            let err = delete_library_file(&path, &directories, None)
+                .await
+                .expect_err("outside the library");
+            assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+        }
+        let err = delete_library_file(&playing, &directories, Some(&playing))
+            .await
+            .expect_err("playing");
+        assert_eq!(err.into_response().status(), StatusCode::CONFLICT);
+
+        assert!(secret.exists());
+        assert!(playing.exists());
+    }
 }
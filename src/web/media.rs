@@ -0,0 +1,197 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures_util::stream;
+use tokio::{fs::File, io::AsyncReadExt, task};
+
+use crate::{cue, library::source};
+
+use super::AppState;
+
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+/// Image names looked for next to the current track, in order of preference.
+const COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// The audio file of the current track, e.g. to listen along on another device.
+pub(super) async fn stream_current(
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, String)> {
+    let path = current_audio_file(&state).await?;
+    let file = File::open(&path).await.map_err(|err| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Failed to open {}: {err}", path.display()),
+        )
+    })?;
+    let length = file.metadata().await.map(|meta| meta.len()).ok();
+
+    let mut response = Response::builder().header(header::CONTENT_TYPE, audio_content_type(&path));
+    if let Some(length) = length {
+        response = response.header(header::CONTENT_LENGTH, length);
+    }
+    response
+        .body(Body::from_stream(chunks(file)))
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+/// The cover image in the current track's folder.
+pub(super) async fn cover(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let track = current_audio_file(&state).await?;
+    let cover = task::spawn_blocking(move || find_cover(&track))
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "The current track's folder has no cover image".to_string(),
+            )
+        })?;
+    let image = tokio::fs::read(&cover).await.map_err(|err| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Failed to read {}: {err}", cover.display()),
+        )
+    })?;
+    Ok(([(header::CONTENT_TYPE, image_content_type(&cover))], image))
+}
+
+/// The local file holding the current track; the whole file for cue sheet tracks.
+async fn current_audio_file(state: &AppState) -> Result<PathBuf, (StatusCode, String)> {
+    let current = state
+        .crabbox
+        .lock()
+        .map(|crabbox| crabbox.snapshot().current)
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to access crabbox".to_string(),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Nothing is playing".to_string()))?;
+    if source::is_remote(&current) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "The current track isn't a local file".to_string(),
+        ));
+    }
+    let lookup = task::spawn_blocking({
+        let current = current.clone();
+        move || cue::lookup(&current)
+    })
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    match lookup {
+        Some(Ok(track)) => Ok(track.file),
+        Some(Err(err)) => Err((StatusCode::NOT_FOUND, err)),
+        None => Ok(current),
+    }
+}
+
+fn chunks(file: File) -> impl futures_util::Stream<Item = io::Result<Bytes>> {
+    stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = vec![0; STREAM_CHUNK_BYTES];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buf.truncate(read);
+                Some((Ok(Bytes::from(buf)), Some(file)))
+            }
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn audio_content_type(path: &Path) -> &'static str {
+    match extension(path).as_str() {
+        "mp3" => "audio/mpeg",
+        "flac" => "audio/flac",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        "m4a" | "m4b" | "aac" | "mp4" => "audio/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+fn image_content_type(path: &Path) -> &'static str {
+    if extension(path) == "png" {
+        "image/png"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// The preferred cover image next to `track`, matching names case-insensitively.
+fn find_cover(track: &Path) -> Option<PathBuf> {
+    let images: Vec<(String, PathBuf)> = fs::read_dir(track.parent()?)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && COVER_EXTENSIONS.contains(&extension(path).as_str()))
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_str()?.to_ascii_lowercase();
+            Some((stem, path))
+        })
+        .collect();
+    COVER_NAMES.iter().find_map(|name| {
+        images
+            .iter()
+            .filter(|(stem, _)| stem == name)
+            .map(|(_, path)| path.clone())
+            .min()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tempfile::tempdir;
+
+    use super::{audio_content_type, find_cover};
+
+    #[test]
+    fn finds_the_preferred_cover_next_to_a_track() {
+        let tmp = tempdir().unwrap();
+        let track = tmp.path().join("01.mp3");
+        for name in ["01.mp3", "Folder.JPG", "notes.txt"] {
+            fs::write(tmp.path().join(name), "x").unwrap();
+        }
+        assert_eq!(find_cover(&track), Some(tmp.path().join("Folder.JPG")));
+
+        fs::write(tmp.path().join("cover.png"), "x").unwrap();
+        assert_eq!(find_cover(&track), Some(tmp.path().join("cover.png")));
+
+        let bare = tempdir().unwrap();
+        assert_eq!(find_cover(&bare.path().join("01.mp3")), None);
+    }
+
+    #[test]
+    fn audio_types_follow_the_extension() {
+        assert_eq!(audio_content_type(Path::new("/m/a.MP3")), "audio/mpeg");
+        assert_eq!(audio_content_type(Path::new("/m/book.m4b")), "audio/mp4");
+        assert_eq!(
+            audio_content_type(Path::new("/m/a.xyz")),
+            "application/octet-stream"
+        );
+    }
+}
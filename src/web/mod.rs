@@ -1,65 +1,230 @@
 use std::{
+    collections::BTreeMap,
+    future::{Future, IntoFuture},
     net::SocketAddr,
-    path::PathBuf,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, atomic::AtomicBool},
     time::Duration,
 };
 
 use axum::{
     Router,
-    extract::{Form, Path, Query, State},
+    extract::{DefaultBodyLimit, Form, Path, Query, State, rejection::QueryRejection},
+    http::StatusCode,
+    middleware::{from_fn, from_fn_with_state},
     response::{Html, Json, Redirect},
     routing::{get, post},
 };
 use minijinja::{Environment, value::Value};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use tokio::{net::TcpListener, time::sleep};
+use tokio::{
+    net::TcpListener,
+    sync::oneshot,
+    time::{sleep, timeout},
+};
 use tracing::{info, warn};
 
-use crate::{AnyResult, BUILD_INFO, BuildInfo, commands::Command, crabbox::Crabbox, tag::TagId};
+use crate::{
+    AnyResult, BUILD_INFO, BuildInfo,
+    commands::{Command, parse_sleep, parse_volume},
+    config::AuthConfig,
+    crabbox::{
+        Crabbox, Heartbeat, Library, LibraryDiagnostics, PageRequest, ShuffleRecord, SortOrder,
+        TrackSort,
+    },
+    filter::Filter,
+    tag::TagId,
+};
 
+mod api_error;
+mod auth;
+mod config_backups;
 mod edit_tag;
+mod events;
+mod favorites;
 mod index;
 mod library;
+mod media;
+mod preview;
+mod share;
+mod status;
+mod timers;
+mod track_volume;
 mod upload;
+mod ws;
 
+use api_error::ApiError;
+use config_backups::{config_backups, restore_config};
 use edit_tag::{assign_tag, edit_tag};
+use events::events;
+use favorites::{add_favorite, remove_favorite};
 use index::index;
-use library::library_page;
-use upload::{upload_files, upload_form};
+use library::{delete_track, library_page, rename_track};
+use media::{cover, stream_current};
+use preview::preview_sound;
+use share::{ShareAccess, ShareSigner, share};
+use status::{queue_m3u, status};
+use timers::{cancel_timers, timers};
+use track_volume::{play_file, track_volume};
+use upload::{UploadSummary, api_upload, upload_body_limit, upload_files, upload_form};
+use ws::ws;
+
+/// How long open connections, e.g. event streams, get to finish once shutdown starts.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+const SHUTDOWN_UNCONFIRMED: &str = "Shutdown must be confirmed from the crabbox page";
 
-pub async fn serve_web(addr: SocketAddr, crabbox: Arc<Mutex<Crabbox>>) -> AnyResult<()> {
+pub async fn serve_web(
+    addr: SocketAddr,
+    crabbox: Arc<Mutex<Crabbox>>,
+    auth: Option<AuthConfig>,
+    share_secret: Option<String>,
+    max_upload_bytes: u64,
+    stop_signal: impl Future<Output = ()> + Send + 'static,
+) -> AnyResult<()> {
     let templates = build_templates(BUILD_INFO)?;
 
+    let health = {
+        let crabbox = crabbox.lock().expect("failed to lock crabbox");
+        HealthState {
+            heartbeat: crabbox.heartbeat(),
+            library: crabbox.library.clone(),
+        }
+    };
     let state = AppState {
         crabbox,
-        last_uploaded: Arc::new(Mutex::new(Vec::new())),
+        last_uploaded: Arc::new(Mutex::new(UploadSummary::default())),
+        previewing: Arc::new(AtomicBool::new(false)),
+        shutdown_token: Arc::from(format!("{:032x}", rand::rng().random::<u128>())),
+        share_signer: share_secret
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| Arc::new(ShareSigner::new(&secret))),
+        max_upload_bytes,
         templates,
     };
+    let upload_limit = DefaultBodyLimit::max(upload_body_limit(max_upload_bytes));
 
     let app = Router::new()
         .route("/", get(index))
         .route("/play", post(play))
         .route("/playpause", post(play_pause))
         .route("/stop", post(stop))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
         .route("/next", post(next))
         .route("/prev", post(prev))
         .route("/volume-up", post(volume_up))
         .route("/volume-down", post(volume_down))
+        .route("/volume", post(set_volume))
+        .route("/sleep", post(sleep_timer))
+        .route("/timers", get(timers))
+        .route("/timers/cancel", post(cancel_timers))
+        .route("/mute", post(mute))
         .route("/clear-queue", post(clear_queue))
+        .route("/rescan", post(rescan))
+        .route("/jump/{index}", post(jump))
+        .route("/remove-from-queue/{index}", post(remove_from_queue))
+        .route("/move-queue", post(move_queue))
         .route("/activate_tag/{id}", post(activate_tag))
         .route("/shutdown", post(shutdown))
+        .route("/preview-sound", post(preview_sound))
+        .route("/track-volume", post(track_volume))
+        .route("/play-file", post(play_file))
+        .route("/favorites/add", post(add_favorite))
+        .route("/favorites/remove", post(remove_favorite))
         .route("/command", post(run_command))
+        .route("/restore_config", post(restore_config))
         .route("/list_files", get(list_files))
+        .route("/api/preview", get(preview_command))
+        .route("/api/library/diagnostics", get(library_diagnostics))
+        .route("/api/status", get(status))
+        .route("/api/queue.m3u", get(queue_m3u))
+        .route("/api/version", get(version))
+        .route("/api/history", get(history))
+        .route("/share", post(share))
+        .route("/api/tags", get(tags))
+        .route("/tags/clear", post(clear_tags))
+        .route("/api/config/backups", get(config_backups))
+        .route("/api/events", get(events))
+        .route("/ws", get(ws).layer(from_fn(ws::require_same_origin)))
+        .route("/api/debug/shuffle", get(debug_shuffle))
         .route("/edit_tag/{id}", get(edit_tag))
         .route("/assign_tag", post(assign_tag))
         .route("/library", get(library_page))
+        .route("/library/delete", post(delete_track))
+        .route("/library/rename", post(rename_track))
         .route("/upload", get(upload_form))
-        .route("/do_upload", post(upload_files))
-        .with_state(state);
+        .route("/do_upload", post(upload_files).layer(upload_limit))
+        .route("/api/upload", post(api_upload).layer(upload_limit))
+        .with_state(state.clone());
+    let app = with_auth(app, &state, auth);
+    // Probes stay reachable without credentials.
+    let app = app.merge(
+        Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .with_state(health),
+    );
     let listener = bind_with_retry(addr).await?;
-    axum::serve(listener, app).await?;
+    serve_until(listener, app, stop_signal).await
+}
+
+/// Puts `app` behind `auth`, and adds the routes that share links can reach without it.
+fn with_auth(app: Router, state: &AppState, auth: Option<AuthConfig>) -> Router {
+    let auth = auth.map(|auth| {
+        info!(
+            username = auth.username,
+            "Web interface requires authentication"
+        );
+        Arc::new(auth::Auth::new(auth))
+    });
+    let app = match &auth {
+        Some(auth) => app.layer(from_fn_with_state(
+            Arc::clone(auth),
+            auth::require_basic_auth,
+        )),
+        None => app,
+    };
+    let shared = Router::new()
+        .route(share::SHAREABLE[0], get(stream_current))
+        .route(share::SHAREABLE[1], get(cover))
+        .with_state(state.clone())
+        .layer(from_fn_with_state(
+            ShareAccess {
+                signer: state.share_signer.clone(),
+                auth,
+            },
+            share::allow_signed,
+        ));
+    app.merge(shared)
+}
+
+/// Serves `app` until `stop_signal` resolves, then gives open connections `SHUTDOWN_GRACE`
+/// to finish before returning.
+async fn serve_until(
+    listener: TcpListener,
+    app: Router,
+    stop_signal: impl Future<Output = ()> + Send + 'static,
+) -> AnyResult<()> {
+    let (stopping_tx, stopping) = oneshot::channel();
+    let serve = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            stop_signal.await;
+            let _ = stopping_tx.send(());
+        })
+        .into_future();
+    tokio::pin!(serve);
+    tokio::select! {
+        result = &mut serve => result?,
+        Ok(()) = stopping => {
+            if let Ok(result) = timeout(SHUTDOWN_GRACE, serve).await {
+                result?;
+            } else {
+                warn!("Open web connections did not close in time; stopping anyway");
+            }
+        }
+    }
+    info!("Web server stopped");
     Ok(())
 }
 
@@ -89,10 +254,52 @@ async fn bind_with_retry(addr: SocketAddr) -> AnyResult<TcpListener> {
 #[derive(Clone)]
 pub(super) struct AppState {
     pub(super) crabbox: Arc<Mutex<Crabbox>>,
-    pub(super) last_uploaded: Arc<Mutex<Vec<PathBuf>>>,
+    pub(super) last_uploaded: Arc<Mutex<UploadSummary>>,
+    pub(super) previewing: Arc<AtomicBool>,
+    /// Random per-process token the index page embeds in the shutdown form.
+    pub(super) shutdown_token: Arc<str>,
+    /// Signs `/share` links; sharing is off without a `[server].share_secret`.
+    pub(super) share_signer: Option<Arc<ShareSigner>>,
+    /// Cap on the bytes written by a single upload request.
+    pub(super) max_upload_bytes: u64,
     templates: Environment<'static>,
 }
 
+#[derive(Clone)]
+struct HealthState {
+    heartbeat: Heartbeat,
+    library: Library,
+}
+
+#[derive(Serialize)]
+struct Health {
+    uptime_secs: u64,
+    playback_thread_alive: bool,
+}
+
+async fn healthz(State(health): State<HealthState>) -> Json<Health> {
+    Json(Health {
+        uptime_secs: health.heartbeat.uptime().as_secs(),
+        playback_thread_alive: health.heartbeat.is_alive(),
+    })
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    library_scanned: bool,
+}
+
+/// Ready once the music library has been enumerated.
+async fn readyz(State(health): State<HealthState>) -> (StatusCode, Json<Readiness>) {
+    let library_scanned = health.library.is_scanned();
+    let status = if library_scanned {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(Readiness { library_scanned }))
+}
+
 impl AppState {
     pub(super) fn render<C: Serialize>(&self, name: &str, context: C) -> Html<String> {
         let rendered = self
@@ -120,6 +327,16 @@ async fn play_pause(State(state): State<AppState>) -> Redirect {
     Redirect::to("/")
 }
 
+async fn pause(State(state): State<AppState>) -> Redirect {
+    send_command(&state, Command::Pause).await;
+    Redirect::to("/")
+}
+
+async fn resume(State(state): State<AppState>) -> Redirect {
+    send_command(&state, Command::Resume).await;
+    Redirect::to("/")
+}
+
 async fn next(State(state): State<AppState>) -> Redirect {
     send_command(&state, Command::Next).await;
     Redirect::to("/")
@@ -140,22 +357,132 @@ async fn volume_down(State(state): State<AppState>) -> Redirect {
     Redirect::to("/")
 }
 
-async fn shutdown(State(state): State<AppState>) -> Redirect {
-    send_command(&state, Command::Shutdown).await;
+async fn mute(State(state): State<AppState>) -> Redirect {
+    send_command(&state, Command::Mute).await;
     Redirect::to("/")
 }
 
+#[derive(Deserialize)]
+struct VolumeForm {
+    level: String,
+}
+
+async fn set_volume(
+    State(state): State<AppState>,
+    Form(form): Form<VolumeForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let level = parse_volume(&form.level).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    send_command(&state, Command::SetVolume { level }).await;
+    Ok(Redirect::to("/"))
+}
+
+#[derive(Deserialize)]
+struct SleepForm {
+    minutes: String,
+}
+
+async fn sleep_timer(
+    State(state): State<AppState>,
+    Form(form): Form<SleepForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let duration = parse_sleep(&form.minutes).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    send_command(&state, Command::SleepTimer { duration }).await;
+    Ok(Redirect::to("/"))
+}
+
+#[derive(Deserialize)]
+struct ShutdownForm {
+    #[serde(default)]
+    token: String,
+}
+
+async fn shutdown(
+    State(state): State<AppState>,
+    Form(form): Form<ShutdownForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    check_shutdown_token(&state.shutdown_token, &form.token)?;
+    send_command(&state, Command::Shutdown).await;
+    Ok(Redirect::to("/"))
+}
+
+fn check_shutdown_token(expected: &str, provided: &str) -> Result<(), (StatusCode, String)> {
+    if auth::constant_time_eq(expected.as_bytes(), provided.as_bytes()) {
+        Ok(())
+    } else {
+        warn!("Rejected shutdown request without a valid token");
+        Err((StatusCode::FORBIDDEN, SHUTDOWN_UNCONFIRMED.to_string()))
+    }
+}
+
+/// Commands from the web may only power the box off through `/shutdown`, which checks the
+/// page token.
+pub(super) fn refuse_shutdown(command: &Command) -> Result<(), String> {
+    if command.shuts_down() {
+        warn!(%command, "Rejected shutdown outside the shutdown form");
+        Err(SHUTDOWN_UNCONFIRMED.to_string())
+    } else {
+        Ok(())
+    }
+}
+
 async fn clear_queue(State(state): State<AppState>) -> Redirect {
     send_command(&state, Command::ClearQueue).await;
     Redirect::to("/")
 }
 
+async fn rescan(State(state): State<AppState>) -> Redirect {
+    send_command(&state, Command::Rescan).await;
+    Redirect::to("/library")
+}
+
+async fn jump(Path(index): Path<usize>, State(state): State<AppState>) -> Redirect {
+    send_command(&state, Command::JumpTo { index }).await;
+    Redirect::to("/")
+}
+
+async fn remove_from_queue(Path(index): Path<usize>, State(state): State<AppState>) -> Redirect {
+    send_command(&state, Command::RemoveFromQueue { index }).await;
+    Redirect::to("/")
+}
+
+#[derive(Deserialize)]
+struct MoveQueueForm {
+    from: usize,
+    to: usize,
+}
+
+async fn move_queue(State(state): State<AppState>, Form(form): Form<MoveQueueForm>) -> Redirect {
+    send_command(
+        &state,
+        Command::MoveInQueue {
+            from: form.from,
+            to: form.to,
+        },
+    )
+    .await;
+    Redirect::to("/")
+}
+
 async fn activate_tag(Path(id): Path<String>, State(state): State<AppState>) -> Redirect {
-    match TagId::from_str(&id) {
-        Ok(tag_id) => send_command(&state, Command::Tag { id: tag_id }).await,
-        Err(err) => warn!(%id, "Invalid tag id: {err}"),
+    let tag_id = match TagId::from_str(&id) {
+        Ok(tag_id) => tag_id,
+        Err(err) => {
+            warn!(%id, "Invalid tag id: {err}");
+            return Redirect::to("/");
+        }
+    };
+    let mapped = state
+        .crabbox
+        .lock()
+        .ok()
+        .and_then(|crabbox| crabbox.tag_command(tag_id).cloned());
+    if let Some(command) = mapped
+        && let Err(err) = refuse_shutdown(&command)
+    {
+        return error_redirect(&err);
     }
 
+    send_command(&state, Command::Tag { id: tag_id }).await;
     Redirect::to("/")
 }
 
@@ -165,13 +492,29 @@ struct CommandForm {
 }
 
 async fn run_command(State(state): State<AppState>, Form(form): Form<CommandForm>) -> Redirect {
-    match Command::from_str(&form.command) {
-        Ok(cmd) => send_command(&state, cmd).await,
-        Err(err) => warn!(command = form.command, "Invalid command from web: {err}"),
+    let cmd = match Command::from_str(&form.command) {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            warn!(command = form.command, "Invalid command from web: {err}");
+            return error_redirect(&err);
+        }
+    };
+    if let Err(err) = refuse_shutdown(&cmd) {
+        return error_redirect(&err);
     }
+
+    send_command(&state, cmd).await;
     Redirect::to("/")
 }
 
+/// Redirects to the index page with `message` shown as an error banner.
+fn error_redirect(message: &str) -> Redirect {
+    match serde_urlencoded::to_string([("error", message)]) {
+        Ok(query) => Redirect::to(&format!("/?{query}")),
+        Err(_) => Redirect::to("/"),
+    }
+}
+
 pub(super) async fn send_command(state: &AppState, command: Command) {
     let sender = state.crabbox.lock().ok().map(|c| c.sender());
 
@@ -183,22 +526,219 @@ pub(super) async fn send_command(state: &AppState, command: Command) {
 #[derive(Deserialize)]
 struct ListFilesQuery {
     filter: Option<String>,
+    /// Return paths relative to their music directory instead of absolute paths.
+    #[serde(default)]
+    relative: bool,
+    #[serde(default)]
+    sort: TrackSort,
+    #[serde(default)]
+    order: SortOrder,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ListFilesResponse {
+    total: usize,
+    offset: usize,
+    tracks: Vec<String>,
 }
 
 async fn list_files(
-    Query(query): Query<ListFilesQuery>,
+    query: Result<Query<ListFilesQuery>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<ListFilesResponse>, ApiError> {
+    let Query(query) = query?;
+    let filter = parse_list_filter(query.filter.as_deref())?;
+    let library = state
+        .crabbox
+        .lock()
+        .map(|c| c.library.clone())
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+    tokio::task::spawn_blocking(move || {
+        let page = library.list_tracks_page(
+            filter.as_ref(),
+            PageRequest {
+                sort: query.sort,
+                order: query.order,
+                offset: query.offset,
+                limit: query.limit,
+            },
+        );
+        let tracks = page
+            .tracks
+            .iter()
+            .map(|path| {
+                if query.relative {
+                    library.display_path(path)
+                } else {
+                    path.display().to_string()
+                }
+            })
+            .collect();
+
+        Json(ListFilesResponse {
+            total: page.total,
+            offset: page.offset,
+            tracks,
+        })
+    })
+    .await
+    .map_err(|_| ApiError::internal("Failed to scan the library"))
+}
+
+#[derive(Deserialize)]
+struct PreviewQuery {
+    command: String,
+}
+
+#[derive(Serialize)]
+struct PreviewResponse {
+    command: String,
+    count: usize,
+    tracks: Vec<String>,
+}
+
+/// Lists the tracks a filtered command would queue, without touching playback.
+async fn preview_command(
+    query: Result<Query<PreviewQuery>, QueryRejection>,
+    State(state): State<AppState>,
+) -> Result<Json<PreviewResponse>, ApiError> {
+    let Query(query) = query?;
+    let command = Command::from_str(&query.command).map_err(ApiError::bad_request)?;
+    let filter = command_filter(&command)?.cloned();
+    let library = state
+        .crabbox
+        .lock()
+        .map(|c| c.library.clone())
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+    let tracks: Vec<String> = tokio::task::spawn_blocking(move || {
+        library
+            .list_tracks(filter.as_ref())
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    })
+    .await
+    .map_err(|_| ApiError::internal("Failed to scan the library"))?;
+
+    Ok(Json(PreviewResponse {
+        command: command.to_string(),
+        count: tracks.len(),
+        tracks,
+    }))
+}
+
+fn command_filter(command: &Command) -> Result<Option<&Filter>, ApiError> {
+    let filter = match command {
+        Command::Play { filter }
+        | Command::PlayPause { filter }
+        | Command::Shuffle { filter }
+        | Command::Enqueue { filter } => filter.as_ref(),
+        _ => {
+            return Err(ApiError::bad_request(format!(
+                "{} does not take a filter",
+                command.name()
+            )));
+        }
+    };
+    if let Some(filter) = filter {
+        filter
+            .matcher()
+            .map_err(|err| ApiError::bad_request(format!("Invalid filter: {err}")))?;
+    }
+    Ok(filter)
+}
+
+async fn library_diagnostics(
+    State(state): State<AppState>,
+) -> Result<Json<LibraryDiagnostics>, ApiError> {
+    let library = state
+        .crabbox
+        .lock()
+        .map(|c| c.library.clone())
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+    tokio::task::spawn_blocking(move || library.diagnostics())
+        .await
+        .map(Json)
+        .map_err(|_| ApiError::internal("Failed to scan the library"))
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    path: String,
+    /// RFC 3339 time the track started.
+    played_at: String,
+}
+
+async fn history(State(state): State<AppState>) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let history = state
+        .crabbox
+        .lock()
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?
+        .history();
+    Ok(Json(
+        history
+            .into_iter()
+            .map(|played| HistoryEntry {
+                path: played.path.display().to_string(),
+                played_at: played.at.to_rfc3339(),
+            })
+            .collect(),
+    ))
+}
+
+/// Tag mappings keyed by id, in the same shape as a JSON `tags_file`.
+async fn tags(
     State(state): State<AppState>,
-) -> Json<Vec<String>> {
-    let files = state
+) -> Result<Json<BTreeMap<String, serde_json::Value>>, ApiError> {
+    state
         .crabbox
         .lock()
-        .map(|c| c.library.list_tracks(query.filter))
-        .unwrap_or_default()
-        .into_iter()
-        .map(|path| path.display().to_string())
-        .collect();
+        .map(|crabbox| Json(crabbox.tags_json()))
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))
+}
+
+async fn clear_tags(State(state): State<AppState>) -> Redirect {
+    let result = match state.crabbox.lock() {
+        Ok(mut crabbox) => crabbox.clear_tags(),
+        Err(_) => Err("Failed to access crabbox".to_string()),
+    };
+    match result {
+        Ok(()) => Redirect::to("/"),
+        Err(err) => error_redirect(&format!("Tags were cleared but not saved: {err}")),
+    }
+}
 
-    Json(files)
+async fn version() -> Json<BuildInfo> {
+    Json(BUILD_INFO)
+}
+
+async fn debug_shuffle(State(state): State<AppState>) -> Result<Json<ShuffleRecord>, ApiError> {
+    state
+        .crabbox
+        .lock()
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?
+        .last_shuffle()
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "No shuffle recorded; enable debug_shuffle in the config",
+            )
+        })
+}
+
+fn parse_list_filter(filter: Option<&str>) -> Result<Option<Filter>, ApiError> {
+    let Some(filter) = filter.map(str::trim).filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+    let filter = Filter::parse(filter);
+    filter
+        .matcher()
+        .map_err(|err| ApiError::bad_request(format!("Invalid filter: {err}")))?;
+    Ok(Some(filter))
 }
 
 fn build_templates(build_info: BuildInfo) -> AnyResult<Environment<'static>> {
@@ -239,3 +779,223 @@ fn build_templates(build_info: BuildInfo) -> AnyResult<Environment<'static>> {
     )?;
     Ok(env)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{
+        ApiError, AppState, ListFilesQuery, activate_tag, build_templates, check_shutdown_token,
+        command_filter, error_redirect, parse_list_filter, run_command, version,
+    };
+    use crate::crabbox::{Crabbox, SortOrder, TrackSort};
+    use crate::{BUILD_INFO, commands::Command, config::Config, filter::Filter};
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        extract::Query,
+        http::{Request, StatusCode, Uri, header},
+        response::{IntoResponse, Response},
+        routing::post,
+    };
+    use serde_json::Value;
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+    use tower::ServiceExt;
+
+    /// App state around a crabbox that doesn't play anything; the commands the web sends it
+    /// end up in the returned receiver.
+    fn test_state(dir: &Path) -> (AppState, mpsc::Receiver<Command>) {
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "[[music]]\ndir = {dir:?}\n\n[server]\nweb = \"127.0.0.1:8080\"\n\n\
+                 [tags]\nDEADBEEF = \"SHUTDOWN\"\n0A1B2C3D = \"STOP\"\n"
+            ),
+        )
+        .unwrap();
+        let (crabbox, commands) = Crabbox::build(&Config::load(&path).unwrap());
+        let state = AppState {
+            crabbox: Arc::new(Mutex::new(crabbox)),
+            last_uploaded: Arc::default(),
+            previewing: Arc::default(),
+            shutdown_token: Arc::from("0123abcd"),
+            share_signer: None,
+            max_upload_bytes: u64::MAX,
+            templates: build_templates(BUILD_INFO).unwrap(),
+        };
+        (state, commands)
+    }
+
+    async fn post_form(state: &AppState, uri: &str, form: &[(&str, &str)]) -> Response {
+        Router::new()
+            .route("/command", post(run_command))
+            .route("/activate_tag/{id}", post(activate_tag))
+            .with_state(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    fn error_location(response: &Response) -> bool {
+        response.headers()[header::LOCATION]
+            .to_str()
+            .unwrap()
+            .starts_with("/?error=")
+    }
+
+    #[tokio::test]
+    async fn version_returns_build_info() {
+        let response = version().await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("read body");
+        let body: Value = serde_json::from_slice(&bytes).expect("json body");
+
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        for field in ["profile", "target", "commit", "dirty", "rustc", "built_at"] {
+            assert!(body[field].is_string(), "missing {field}");
+        }
+    }
+
+    #[test]
+    fn shutdown_requires_the_page_token() {
+        assert!(check_shutdown_token("0123abcd", "0123abcd").is_ok());
+
+        for provided in ["", "0123abce", "0123abcd0"] {
+            let Err((status, _)) = check_shutdown_token("0123abcd", provided) else {
+                panic!("accepted token {provided:?}");
+            };
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn command_route_refuses_shutdown_without_a_token() {
+        let tmp = tempdir().unwrap();
+        let (state, mut commands) = test_state(tmp.path());
+
+        for command in ["SHUTDOWN", "STOP; SHUTDOWN"] {
+            let response = post_form(&state, "/command", &[("command", command)]).await;
+            assert!(error_location(&response), "{command}");
+        }
+        assert!(commands.try_recv().is_err());
+
+        let response = post_form(&state, "/command", &[("command", "STOP")]).await;
+        assert!(!error_location(&response));
+        assert_eq!(commands.try_recv().ok(), Some(Command::Stop));
+    }
+
+    #[tokio::test]
+    async fn web_tag_activation_refuses_shutdown_mappings() {
+        let tmp = tempdir().unwrap();
+        let (state, mut commands) = test_state(tmp.path());
+
+        let response = post_form(&state, "/activate_tag/DEADBEEF", &[]).await;
+        assert!(error_location(&response));
+        assert!(commands.try_recv().is_err());
+
+        post_form(&state, "/activate_tag/0A1B2C3D", &[]).await;
+        assert!(matches!(commands.try_recv(), Ok(Command::Tag { .. })));
+    }
+
+    #[test]
+    fn error_redirect_encodes_message_in_query() {
+        let response = error_redirect("Invalid command \"PLAY x&y\"").into_response();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()["location"],
+            "/?error=Invalid+command+%22PLAY+x%26y%22"
+        );
+    }
+
+    async fn error_json(error: ApiError) -> (StatusCode, Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("read body");
+        (status, serde_json::from_slice(&bytes).expect("json body"))
+    }
+
+    #[tokio::test]
+    async fn list_files_rejects_invalid_query_with_json_error() {
+        let uri: Uri = "/list_files?relative=maybe".parse().unwrap();
+        let Err(rejection) = Query::<ListFilesQuery>::try_from_uri(&uri) else {
+            panic!("expected query rejection");
+        };
+
+        let (status, body) = error_json(rejection.into()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 400);
+        assert!(body["error"].as_str().unwrap().contains("relative"));
+    }
+
+    #[tokio::test]
+    async fn list_files_rejects_oversized_filter_with_json_error() {
+        let filter = "?".repeat(100_000);
+
+        let (status, body) = error_json(parse_list_filter(Some(&filter)).unwrap_err()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 400);
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid filter")
+        );
+    }
+
+    #[test]
+    fn list_files_parses_sort_and_page_parameters() {
+        let uri: Uri = "/list_files?sort=mtime&order=desc&offset=20&limit=10"
+            .parse()
+            .unwrap();
+        let Query(query) = Query::<ListFilesQuery>::try_from_uri(&uri).expect("valid query");
+        assert_eq!(query.sort, TrackSort::Mtime);
+        assert_eq!(query.order, SortOrder::Desc);
+        assert_eq!((query.offset, query.limit), (20, Some(10)));
+
+        let uri: Uri = "/list_files?sort=size".parse().unwrap();
+        assert!(Query::<ListFilesQuery>::try_from_uri(&uri).is_err());
+    }
+
+    #[test]
+    fn list_files_ignores_blank_filter() {
+        assert!(parse_list_filter(Some("   ")).unwrap().is_none());
+        assert!(parse_list_filter(None).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn preview_extracts_filters_and_rejects_other_commands() {
+        let shuffle: Command = "SHUFFLE **/kids/**".parse().unwrap();
+        assert_eq!(
+            command_filter(&shuffle).unwrap(),
+            Some(&Filter::parse("**/kids/**"))
+        );
+        assert_eq!(
+            command_filter(&Command::Play { filter: None }).unwrap(),
+            None
+        );
+
+        let (status, body) = error_json(command_filter(&Command::Next).unwrap_err()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "NEXT does not take a filter");
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    thread,
+};
+
+use axum::{
+    extract::{Form, State},
+    http::StatusCode,
+    response::Redirect,
+};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::player::play_blocking;
+
+use super::AppState;
+
+#[derive(Deserialize)]
+pub(super) struct PreviewSoundForm {
+    path: String,
+}
+
+pub(super) async fn preview_sound(
+    State(state): State<AppState>,
+    Form(form): Form<PreviewSoundForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let (directories, sounds, volume, curve, output_device) = state
+        .crabbox
+        .lock()
+        .map(|crabbox| {
+            (
+                crabbox.music_directories(),
+                crabbox.configured_sounds(),
+                crabbox.volume(),
+                crabbox.volume_curve(),
+                crabbox.output_device().map(str::to_string),
+            )
+        })
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to access crabbox".to_string(),
+            )
+        })?;
+
+    let path = resolve_preview_path(Path::new(form.path.trim()), &directories, &sounds).ok_or((
+        StatusCode::BAD_REQUEST,
+        "Sound must be a configured sound or a file inside a music directory".to_string(),
+    ))?;
+
+    if state
+        .previewing
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            "A sound preview is already playing".to_string(),
+        ));
+    }
+
+    let previewing = state.previewing.clone();
+    thread::spawn(move || {
+        info!("Previewing sound {}", path.display());
+        if let Err(err) = play_blocking(&path, volume, curve, output_device.as_deref()) {
+            error!("Failed to preview sound {}: {err}", path.display());
+        }
+        previewing.store(false, Ordering::SeqCst);
+    });
+
+    Ok(Redirect::to("/"))
+}
+
+fn resolve_preview_path(
+    requested: &Path,
+    directories: &[PathBuf],
+    sounds: &[PathBuf],
+) -> Option<PathBuf> {
+    let requested = requested.canonicalize().ok()?;
+    if !requested.is_file() {
+        return None;
+    }
+
+    let is_sound = sounds
+        .iter()
+        .filter_map(|sound| sound.canonicalize().ok())
+        .any(|sound| sound == requested);
+    let in_library = directories
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| requested.starts_with(dir));
+
+    (is_sound || in_library).then_some(requested)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_preview_path;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn accepts_library_files_and_configured_sounds() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music dir");
+        let track = music.join("jingle.mp3");
+        let sound = tmp.path().join("startup.mp3");
+        fs::write(&track, "audio").expect("write track");
+        fs::write(&sound, "audio").expect("write sound");
+
+        let directories = vec![music.clone()];
+        let sounds = vec![sound.clone()];
+
+        assert_eq!(
+            resolve_preview_path(&track, &directories, &sounds),
+            Some(track.canonicalize().unwrap())
+        );
+        assert_eq!(
+            resolve_preview_path(&sound, &directories, &sounds),
+            Some(sound.canonicalize().unwrap())
+        );
+    }
+
+    #[test]
+    fn rejects_paths_outside_allowed_locations() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("create music dir");
+        let secret = tmp.path().join("secret.mp3");
+        fs::write(&secret, "audio").expect("write secret");
+
+        let directories = vec![music.clone()];
+
+        assert_eq!(
+            resolve_preview_path(&music.join("../secret.mp3"), &directories, &[]),
+            None
+        );
+        assert_eq!(
+            resolve_preview_path(&music.join("missing.mp3"), &directories, &[]),
+            None
+        );
+        assert_eq!(resolve_preview_path(&music, &directories, &[]), None);
+    }
+}
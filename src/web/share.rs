@@ -0,0 +1,246 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Form, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::warn;
+
+use super::{AppState, api_error::ApiError, auth};
+
+/// Routes a share link can point at.
+pub(super) const SHAREABLE: [&str; 2] = ["/stream/current", "/cover"];
+const DEFAULT_SHARE_MINS: u64 = 60;
+const MAX_SHARE_MINS: u64 = 7 * 24 * 60;
+
+/// Signs and checks time-limited links with the `[server].share_secret`.
+pub struct ShareSigner {
+    secret: Vec<u8>,
+}
+
+impl ShareSigner {
+    pub(super) fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self, path: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC takes keys of any length");
+        mac.update(format!("{path}\n{expires}").as_bytes());
+        mac
+    }
+
+    /// `path` with the query that lets it through until `expires` (Unix seconds).
+    fn link(&self, path: &str, expires: u64) -> String {
+        let sig = URL_SAFE_NO_PAD.encode(self.mac(path, expires).finalize().into_bytes());
+        format!("{path}?expires={expires}&sig={sig}")
+    }
+
+    fn verify(&self, path: &str, expires: u64, sig: &str, now: u64) -> Result<(), &'static str> {
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig)
+            .map_err(|_| "Invalid signature")?;
+        self.mac(path, expires)
+            .verify_slice(&sig)
+            .map_err(|_| "Invalid signature")?;
+        if now >= expires {
+            return Err("Share link has expired");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub(super) struct ShareForm {
+    /// `/stream/current` or `/cover`.
+    path: String,
+    #[serde(default)]
+    minutes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ShareLink {
+    url: String,
+    expires_at: String,
+}
+
+/// Returns a link to `path` that works without credentials until it expires.
+pub(super) async fn share(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<ShareForm>,
+) -> Result<Json<ShareLink>, ApiError> {
+    let signer = state.share_signer.as_ref().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "Sharing is off; set [server].share_secret to enable it",
+        )
+    })?;
+    if !SHAREABLE.contains(&form.path.as_str()) {
+        return Err(ApiError::bad_request(format!(
+            "Only {} can be shared",
+            SHAREABLE.join(" and ")
+        )));
+    }
+    let minutes = form.minutes.unwrap_or(DEFAULT_SHARE_MINS);
+    if !(1..=MAX_SHARE_MINS).contains(&minutes) {
+        return Err(ApiError::bad_request(format!(
+            "Share links last between 1 and {MAX_SHARE_MINS} minutes"
+        )));
+    }
+
+    let expires = unix_now() + Duration::from_mins(minutes).as_secs();
+    let link = signer.link(&form.path, expires);
+    let url = match headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+    {
+        Some(host) => format!("http://{host}{link}"),
+        None => link,
+    };
+    let expires_at = i64::try_from(expires)
+        .ok()
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+        .map(|at| at.to_rfc3339())
+        .unwrap_or_default();
+    Ok(Json(ShareLink { url, expires_at }))
+}
+
+#[derive(Deserialize)]
+struct SignedQuery {
+    #[serde(default)]
+    expires: u64,
+    sig: Option<String>,
+}
+
+/// Who may fetch the shareable routes: holders of a valid share link, or whoever passes the
+/// normal web auth.
+#[derive(Clone)]
+pub(super) struct ShareAccess {
+    pub(super) signer: Option<Arc<ShareSigner>>,
+    pub(super) auth: Option<Arc<auth::Auth>>,
+}
+
+/// Lets requests with a valid, unexpired `sig` through without credentials and rejects
+/// expired or tampered ones with 403. Unsigned requests go through the normal auth.
+pub(super) async fn allow_signed(
+    State(access): State<ShareAccess>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let presented = request
+        .uri()
+        .query()
+        .and_then(|query| serde_urlencoded::from_str::<SignedQuery>(query).ok())
+        .and_then(|query| Some((query.expires, query.sig?)));
+    let Some((expires, signature)) = presented else {
+        return match access.auth {
+            Some(auth) => auth::require_basic_auth(State(auth), request, next).await,
+            None => next.run(request).await,
+        };
+    };
+
+    let verified = match &access.signer {
+        Some(signer) => signer.verify(request.uri().path(), expires, &signature, unix_now()),
+        None => Err("Sharing is off"),
+    };
+    match verified {
+        Ok(()) => next.run(request).await,
+        Err(reason) => {
+            warn!(uri = %request.uri().path(), "Rejected share link: {reason}");
+            (StatusCode::FORBIDDEN, reason).into_response()
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode},
+        middleware::from_fn_with_state,
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    use super::{ShareAccess, ShareSigner, allow_signed, unix_now};
+    use crate::{config::AuthConfig, web::auth::Auth};
+
+    fn app() -> Router {
+        let access = ShareAccess {
+            signer: Some(Arc::new(ShareSigner::new("s3cret"))),
+            auth: Some(Arc::new(Auth::new(AuthConfig {
+                username: "crab".to_string(),
+                password_sha256: "0".repeat(64),
+                session_timeout_mins: None,
+            }))),
+        };
+        Router::new()
+            .route("/cover", get(|| async { "image" }))
+            .layer(from_fn_with_state(access, allow_signed))
+    }
+
+    async fn get_status(uri: &str) -> StatusCode {
+        app()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn signatures_cover_the_path_and_expiry() {
+        let signer = ShareSigner::new("s3cret");
+        let link = signer.link("/cover", 2_000);
+        let sig = link.rsplit_once("sig=").unwrap().1;
+
+        assert_eq!(signer.verify("/cover", 2_000, sig, 1_000), Ok(()));
+        assert!(signer.verify("/cover", 2_000, sig, 2_000).is_err());
+        assert!(signer.verify("/cover", 3_000, sig, 1_000).is_err());
+        assert!(signer.verify("/stream/current", 2_000, sig, 1_000).is_err());
+        assert!(
+            ShareSigner::new("other")
+                .verify("/cover", 2_000, sig, 1_000)
+                .is_err()
+        );
+        assert!(
+            signer
+                .verify("/cover", 2_000, "not base64!", 1_000)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn signed_links_bypass_auth_until_they_expire() {
+        let signer = ShareSigner::new("s3cret");
+        let valid = signer.link("/cover", unix_now() + 60);
+        let expired = signer.link("/cover", unix_now() - 1);
+        let tampered = valid.replace("/cover?expires=", "/cover?expires=9");
+
+        assert_eq!(get_status(&valid).await, StatusCode::OK);
+        assert_eq!(get_status(&expired).await, StatusCode::FORBIDDEN);
+        assert_eq!(get_status(&tampered).await, StatusCode::FORBIDDEN);
+        assert_eq!(get_status("/cover").await, StatusCode::UNAUTHORIZED);
+    }
+}
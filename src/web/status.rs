@@ -0,0 +1,171 @@
+use std::sync::TryLockError;
+
+use axum::{
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json},
+};
+use serde::Serialize;
+
+use crate::{crabbox::CrabboxSnapshot, metadata::TrackMetadata, playlist};
+
+use super::{AppState, api_error::ApiError};
+
+#[derive(Debug, Serialize, PartialEq)]
+pub(super) struct StatusResponse {
+    current: Option<String>,
+    /// "Artist – Title" from the current track's tags, when it has any.
+    current_title: Option<String>,
+    paused: bool,
+    queue: Vec<String>,
+    queue_position: Option<usize>,
+    tags: Vec<TagStatus>,
+    last_tag: Option<String>,
+    last_tag_command: Option<String>,
+    repeat: String,
+    /// Volume from 0.0 to 1.0, as in `volume_changed` events.
+    volume: f32,
+    muted: bool,
+    /// Playback rate; 1.0 is normal.
+    speed: f32,
+    /// Seconds until the sleep timer stops playback.
+    sleep_remaining_secs: Option<u64>,
+    /// Seconds until paused or idle playback is stopped.
+    idle_remaining_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct TagStatus {
+    id: String,
+    command: String,
+}
+
+impl From<CrabboxSnapshot> for StatusResponse {
+    fn from(snapshot: CrabboxSnapshot) -> Self {
+        Self {
+            current: snapshot.current.map(|path| path.display().to_string()),
+            current_title: snapshot
+                .current_metadata
+                .as_ref()
+                .and_then(TrackMetadata::display_name),
+            paused: snapshot.paused,
+            queue: snapshot
+                .queue
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            queue_position: snapshot.queue_position,
+            tags: snapshot
+                .tags
+                .iter()
+                .map(|(id, command)| TagStatus {
+                    id: id.to_string(),
+                    command: command.to_string(),
+                })
+                .collect(),
+            last_tag: snapshot.last_tag.map(|id| id.to_string()),
+            last_tag_command: snapshot.last_tag_command.map(|command| command.to_string()),
+            repeat: snapshot.repeat.to_string(),
+            volume: snapshot.volume,
+            muted: snapshot.muted,
+            speed: snapshot.speed,
+            sleep_remaining_secs: snapshot.sleep_remaining.map(|left| left.as_secs()),
+            idle_remaining_secs: snapshot.idle_remaining.map(|left| left.as_secs()),
+        }
+    }
+}
+
+pub(super) async fn status(
+    State(state): State<AppState>,
+) -> Result<Json<StatusResponse>, ApiError> {
+    let snapshot = match state.crabbox.try_lock() {
+        Ok(crabbox) => crabbox.snapshot(),
+        Err(TryLockError::WouldBlock) => {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Crabbox is busy, try again",
+            ));
+        }
+        Err(TryLockError::Poisoned(_)) => {
+            return Err(ApiError::internal("Failed to access crabbox"));
+        }
+    };
+
+    Ok(Json(snapshot.into()))
+}
+
+/// The current queue as a downloadable M3U playlist, e.g. to keep a good shuffle.
+pub(super) async fn queue_m3u(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let queue = state
+        .crabbox
+        .lock()
+        .map(|crabbox| crabbox.snapshot().queue)
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "audio/x-mpegurl; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"queue.m3u\"",
+            ),
+        ],
+        playlist::to_m3u(&queue),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatusResponse;
+    use crate::{commands::Command, crabbox::CrabboxSnapshot, metadata::TrackMetadata, tag::TagId};
+    use serde_json::json;
+    use std::{path::PathBuf, time::Duration};
+
+    #[test]
+    fn serializes_paths_and_tags_as_strings() {
+        let id = TagId::from_hex_str("ABCD1234").unwrap();
+        let snapshot = CrabboxSnapshot {
+            current: Some(PathBuf::from("/music/a.mp3")),
+            current_metadata: Some(TrackMetadata {
+                title: Some("Anthem".to_string()),
+                artist: Some("The Crabs".to_string()),
+                ..TrackMetadata::default()
+            }),
+            paused: true,
+            queue: vec![PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b.mp3")],
+            queue_position: Some(0),
+            tags: vec![(id, Command::Stop)],
+            last_tag: Some(id),
+            last_tag_command: Some(Command::Stop),
+            volume: 0.25,
+            speed: 1.5,
+            sleep_remaining: Some(Duration::from_secs(90)),
+            idle_remaining: Some(Duration::from_secs(45)),
+            ..CrabboxSnapshot::default()
+        };
+
+        let value = serde_json::to_value(StatusResponse::from(snapshot)).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "current": "/music/a.mp3",
+                "current_title": "The Crabs – Anthem",
+                "paused": true,
+                "queue": ["/music/a.mp3", "/music/b.mp3"],
+                "queue_position": 0,
+                "tags": [{ "id": "ABCD1234", "command": "STOP" }],
+                "last_tag": "ABCD1234",
+                "last_tag_command": "STOP",
+                "repeat": "ALL",
+                "volume": 0.25,
+                "muted": false,
+                "speed": 1.5,
+                "sleep_remaining_secs": 90,
+                "idle_remaining_secs": 45,
+            })
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Form, State},
+    response::{Json, Redirect},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::crabbox::TimerKind;
+
+use super::{AppState, api_error::ApiError};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub(super) struct TimerStatus {
+    name: TimerKind,
+    remaining_secs: u64,
+}
+
+impl From<(TimerKind, Duration)> for TimerStatus {
+    fn from((name, remaining): (TimerKind, Duration)) -> Self {
+        Self {
+            name,
+            remaining_secs: remaining.as_secs(),
+        }
+    }
+}
+
+/// The pending timers that will stop playback, soonest first.
+pub(super) async fn timers(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TimerStatus>>, ApiError> {
+    let mut timers = state
+        .crabbox
+        .lock()
+        .map(|crabbox| crabbox.timers())
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+    timers.sort_by_key(|(_, remaining)| *remaining);
+    Ok(Json(timers.into_iter().map(TimerStatus::from).collect()))
+}
+
+#[derive(Deserialize)]
+pub(super) struct CancelTimersForm {
+    /// `sleep` or `idle`; every pending timer when missing.
+    #[serde(default)]
+    timer: Option<TimerKind>,
+}
+
+pub(super) async fn cancel_timers(
+    State(state): State<AppState>,
+    Form(form): Form<CancelTimersForm>,
+) -> Result<Redirect, ApiError> {
+    state
+        .crabbox
+        .lock()
+        .map(|mut crabbox| crabbox.cancel_timers(form.timer))
+        .map_err(|_| ApiError::internal("Failed to access crabbox"))?;
+    Ok(Redirect::to("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{CancelTimersForm, TimerStatus};
+    use crate::crabbox::TimerKind;
+
+    #[test]
+    fn timers_serialize_with_their_names() {
+        let status = TimerStatus::from((TimerKind::Sleep, Duration::from_millis(90_400)));
+        assert_eq!(
+            serde_json::to_value(status).unwrap(),
+            json!({ "name": "sleep", "remaining_secs": 90 })
+        );
+    }
+
+    #[test]
+    fn cancel_form_names_one_timer_or_none() {
+        let idle: CancelTimersForm = serde_urlencoded::from_str("timer=idle").unwrap();
+        assert_eq!(idle.timer, Some(TimerKind::Idle));
+        let all: CancelTimersForm = serde_urlencoded::from_str("").unwrap();
+        assert_eq!(all.timer, None);
+        assert!(serde_urlencoded::from_str::<CancelTimersForm>("timer=alarm").is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Form, State},
+    http::StatusCode,
+    response::Redirect,
+};
+use serde::Deserialize;
+
+use crate::{commands::Command, crabbox::is_library_track, track_volume::MAX_OFFSET};
+
+use super::{AppState, send_command};
+
+#[derive(Deserialize)]
+pub(super) struct TrackVolumeForm {
+    path: String,
+    delta: i32,
+}
+
+pub(super) async fn track_volume(
+    State(state): State<AppState>,
+    Form(form): Form<TrackVolumeForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let path = library_track(&state, &form.path)?;
+    let delta = form.delta.clamp(-MAX_OFFSET, MAX_OFFSET);
+    send_command(&state, Command::AdjustTrackVolume { path, delta }).await;
+
+    Ok(Redirect::to("/"))
+}
+
+#[derive(Deserialize)]
+pub(super) struct PlayFileForm {
+    path: String,
+}
+
+pub(super) async fn play_file(
+    State(state): State<AppState>,
+    Form(form): Form<PlayFileForm>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let path = library_track(&state, &form.path)?;
+    send_command(&state, Command::PlayFile { path }).await;
+    Ok(Redirect::to("/"))
+}
+
+fn library_track(state: &AppState, path: &str) -> Result<PathBuf, (StatusCode, String)> {
+    let directories = state
+        .crabbox
+        .lock()
+        .map(|crabbox| crabbox.music_directories())
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to access crabbox".to_string(),
+            )
+        })?;
+
+    let path = PathBuf::from(path.trim());
+    if is_library_track(&path, &directories) {
+        Ok(path)
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            "Track must be a file inside a music directory".to_string(),
+        ))
+    }
+}
@@ -1,33 +1,58 @@
-use std::path::{Component, Path, PathBuf};
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
 
 use axum::{
-    extract::{Multipart, State},
+    body::Bytes,
+    extract::{Multipart, State, multipart::MultipartError},
     http::StatusCode,
-    response::{Html, Redirect},
+    response::{Html, Json, Redirect},
 };
+use futures_util::{Stream, StreamExt};
 use serde::Serialize;
 use tokio::{fs, io::AsyncWriteExt};
 
-use super::AppState;
+use crate::{commands::Command, config::MusicDirectory, glob, tag::TagId};
+
+use super::{AppState, api_error::ApiError, send_command};
+
+/// Room for the multipart boundaries and form fields around the uploaded files.
+const FORM_OVERHEAD_BYTES: u64 = 64 * 1024;
+
+#[derive(Default)]
+pub struct UploadSummary {
+    paths: Vec<PathBuf>,
+    /// Uploaded files that weren't saved, with the reason.
+    skipped: Vec<String>,
+    tag_notice: Option<String>,
+}
 
 pub async fn upload_form(State(state): State<AppState>) -> Html<String> {
     let destinations = state
         .crabbox
         .lock()
-        .map(|crabbox| crabbox.music_directories())
+        .map(|crabbox| crabbox.library.directories().to_vec())
         .unwrap_or_default()
         .into_iter()
-        .map(|dir| dir.display().to_string())
+        .filter(|dir| dir.url().is_none())
+        .map(|dir| dir.dir.display().to_string())
         .collect();
 
-    let last_uploaded = state
+    let (last_uploaded, last_skipped, tag_notice) = state
         .last_uploaded
         .lock()
-        .map(|paths| {
-            paths
-                .iter()
-                .map(|path| path.display().to_string())
-                .collect::<Vec<_>>()
+        .map(|summary| {
+            (
+                summary
+                    .paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>(),
+                summary.skipped.clone(),
+                summary.tag_notice.clone(),
+            )
         })
         .unwrap_or_default();
 
@@ -36,6 +61,8 @@ pub async fn upload_form(State(state): State<AppState>) -> Html<String> {
         UploadTemplateContext {
             destinations,
             last_uploaded,
+            last_skipped,
+            tag_notice,
         },
     )
 }
@@ -44,28 +71,167 @@ pub async fn upload_files(
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<Redirect, (StatusCode, String)> {
-    let available_directories = state
+    let directories = music_directories(&state)?;
+    let upload = receive_upload(&mut multipart, &directories, state.max_upload_bytes).await?;
+    if upload.saved.is_empty() {
+        let message = if upload.skipped.is_empty() {
+            "No files were uploaded".to_string()
+        } else {
+            format!(
+                "No music files were uploaded; skipped {}",
+                upload
+                    .skipped
+                    .iter()
+                    .map(SkippedUpload::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        return Err((StatusCode::BAD_REQUEST, message));
+    }
+    finish_upload(&state, upload).await;
+
+    Ok(Redirect::to("/upload"))
+}
+
+/// Same form fields as `/do_upload`, answering with what was saved or skipped as JSON.
+pub async fn api_upload(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<UploadReport>, ApiError> {
+    let directories =
+        music_directories(&state).map_err(|(status, err)| ApiError::new(status, err))?;
+    let upload = receive_upload(&mut multipart, &directories, state.max_upload_bytes)
+        .await
+        .map_err(|(status, err)| ApiError::new(status, err))?;
+    if upload.saved.is_empty() && upload.skipped.is_empty() {
+        return Err(ApiError::bad_request("No files were uploaded"));
+    }
+
+    let saved: Vec<SavedFileReport> = upload
+        .saved
+        .iter()
+        .map(|file| SavedFileReport {
+            path: file.path.display().to_string(),
+            bytes: file.bytes,
+        })
+        .collect();
+    let total_bytes = upload.saved.iter().map(|file| file.bytes).sum();
+    let skipped = upload.skipped.clone();
+    let tag_notice = if upload.saved.is_empty() {
+        None
+    } else {
+        finish_upload(&state, upload).await
+    };
+
+    Ok(Json(UploadReport {
+        saved,
+        skipped,
+        total_bytes,
+        tag_notice,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadReport {
+    saved: Vec<SavedFileReport>,
+    skipped: Vec<SkippedUpload>,
+    total_bytes: u64,
+    tag_notice: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SavedFileReport {
+    path: String,
+    bytes: u64,
+}
+
+/// What a multipart upload form asked for and which of its files were written.
+#[derive(Debug, Default)]
+struct ReceivedUpload {
+    target_dir: Option<String>,
+    create_tag: bool,
+    tag_id: Option<TagId>,
+    saved: Vec<SavedFile>,
+    skipped: Vec<SkippedUpload>,
+}
+
+#[derive(Debug)]
+struct SavedFile {
+    /// Relative to the target music directory.
+    path: PathBuf,
+    bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct SkippedUpload {
+    filename: String,
+    reason: String,
+}
+
+impl fmt::Display for SkippedUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.filename, self.reason)
+    }
+}
+
+fn music_directories(state: &AppState) -> Result<Vec<MusicDirectory>, (StatusCode, String)> {
+    state
         .crabbox
         .lock()
-        .map(|crabbox| crabbox.music_directories())
+        .map(|crabbox| crabbox.library.directories().to_vec())
         .map_err(|_| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Failed to access music directories".to_string(),
             )
-        })?;
+        })
+}
 
-    let mut target_dir_value: Option<String> = None;
-    let mut saved_files = 0usize;
-    let mut uploaded_paths: Vec<PathBuf> = Vec::new();
+/// Request body limit for the upload routes: the file limit plus the form around the files.
+pub fn upload_body_limit(max_upload_bytes: u64) -> usize {
+    usize::try_from(max_upload_bytes.saturating_add(FORM_OVERHEAD_BYTES)).unwrap_or(usize::MAX)
+}
 
-    while let Some(field) = multipart.next_field().await.map_err(internal_error)? {
+/// Writes the uploaded files into the chosen music directory, skipping names that can't be
+/// stored safely or aren't music for that directory. Fails with 413 once the files add up
+/// to more than `max_bytes`.
+async fn receive_upload(
+    multipart: &mut Multipart,
+    directories: &[MusicDirectory],
+    max_bytes: u64,
+) -> Result<ReceivedUpload, (StatusCode, String)> {
+    let mut upload = ReceivedUpload::default();
+    let mut total_bytes = 0u64;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| multipart_error(&err))?
+    {
         let Some(name) = field.name().map(str::to_owned) else {
             continue;
         };
 
         if name == "target_dir" {
-            target_dir_value = Some(field.text().await.map_err(internal_error)?);
+            upload.target_dir = Some(field.text().await.map_err(|err| multipart_error(&err))?);
+            continue;
+        }
+
+        if name == "create_tag" {
+            upload.create_tag = true;
+            continue;
+        }
+
+        if name == "tag_id" {
+            let value = field.text().await.map_err(|err| multipart_error(&err))?;
+            let value = value.trim();
+            if !value.is_empty() {
+                upload.tag_id =
+                    Some(TagId::from_str(value).map_err(|err| {
+                        (StatusCode::BAD_REQUEST, format!("Invalid tag id: {err}"))
+                    })?);
+            }
             continue;
         }
 
@@ -73,8 +239,7 @@ pub async fn upload_files(
             continue;
         }
 
-        let target_root = resolve_target_dir(&available_directories, target_dir_value.as_deref())
-            .ok_or((
+        let target = resolve_target_dir(directories, upload.target_dir.as_deref()).ok_or((
             StatusCode::BAD_REQUEST,
             "Invalid target directory".to_string(),
         ))?;
@@ -84,46 +249,191 @@ pub async fn upload_files(
         };
 
         let Some(relative_path) = sanitize_relative_path(&filename) else {
+            upload.skipped.push(SkippedUpload {
+                filename,
+                reason: "invalid file name".to_string(),
+            });
             continue;
         };
 
-        let destination = target_root.join(&relative_path);
+        let accepted = relative_path
+            .extension()
+            .and_then(|os| os.to_str())
+            .is_some_and(|ext| target.accepts_extension(ext));
+        if !accepted {
+            upload.skipped.push(SkippedUpload {
+                filename,
+                reason: "unsupported file type".to_string(),
+            });
+            continue;
+        }
+
+        let destination = target.dir.join(&relative_path);
 
         if let Some(parent) = destination.parent() {
             fs::create_dir_all(parent).await.map_err(internal_error)?;
         }
 
-        let mut file = fs::File::create(&destination)
+        let chunks = field.map(|chunk| chunk.map_err(|err| multipart_error(&err)));
+        let bytes = write_chunks(chunks, &destination, max_bytes - total_bytes)
             .await
-            .map_err(internal_error)?;
+            .map_err(|err| match err.0 {
+                StatusCode::PAYLOAD_TOO_LARGE => (
+                    err.0,
+                    format!("Uploads are limited to {max_bytes} bytes in total"),
+                ),
+                _ => err,
+            })?;
+        total_bytes += bytes;
+
+        upload.saved.push(SavedFile {
+            path: relative_path,
+            bytes,
+        });
+    }
+
+    Ok(upload)
+}
+
+/// Streams the upload `chunks` into `destination`. The data goes to a hidden `.part` file
+/// that is renamed into place once complete, so a failed or oversized upload (more than
+/// `limit` bytes) never leaves a half-written track behind.
+async fn write_chunks<S>(
+    mut chunks: S,
+    destination: &Path,
+    limit: u64,
+) -> Result<u64, (StatusCode, String)>
+where
+    S: Stream<Item = Result<Bytes, (StatusCode, String)>> + Unpin,
+{
+    let partial = partial_path(destination);
+    let mut file = fs::File::create(&partial).await.map_err(internal_error)?;
 
-        let mut field = field;
-        while let Some(chunk) = field.chunk().await.map_err(internal_error)? {
-            file.write_all(&chunk).await.map_err(internal_error)?;
+    let mut bytes = 0u64;
+    let result = loop {
+        let chunk = match chunks.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(err)) => break Err(err),
+            None => break file.flush().await.map_err(internal_error),
+        };
+        bytes += chunk.len() as u64;
+        if bytes > limit {
+            break Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Upload is too large".to_string(),
+            ));
+        }
+        if let Err(err) = file.write_all(&chunk).await {
+            break Err(internal_error(err));
         }
+    };
+    drop(file);
 
-        saved_files += 1;
-        uploaded_paths.push(relative_path);
+    let result = match result {
+        Ok(()) => fs::rename(&partial, destination)
+            .await
+            .map_err(internal_error),
+        Err(err) => Err(err),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(&partial).await;
     }
+    result.map(|()| bytes)
+}
 
-    if saved_files == 0 {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "No files were uploaded".to_string(),
-        ));
-    }
+/// Where an upload to `destination` is written until it completes.
+fn partial_path(destination: &Path) -> PathBuf {
+    let name = destination
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    destination.with_file_name(format!(".{name}.part"))
+}
+
+/// Rescans the library, assigns the folder tag when asked, and remembers the upload for
+/// the upload page. Returns the tag notice, if a tag was requested.
+async fn finish_upload(state: &AppState, upload: ReceivedUpload) -> Option<String> {
+    send_command(state, Command::Rescan).await;
+
+    let uploaded_paths: Vec<PathBuf> = upload.saved.into_iter().map(|file| file.path).collect();
+    let tag_notice = if upload.create_tag {
+        Some(
+            assign_folder_tag(
+                state,
+                upload.tag_id,
+                upload.target_dir.as_deref(),
+                &uploaded_paths,
+            )
+            .await,
+        )
+    } else {
+        None
+    };
 
     if let Ok(mut last_uploaded) = state.last_uploaded.lock() {
-        *last_uploaded = uploaded_paths;
+        *last_uploaded = UploadSummary {
+            paths: uploaded_paths,
+            skipped: upload.skipped.iter().map(ToString::to_string).collect(),
+            tag_notice: tag_notice.clone(),
+        };
     }
 
-    Ok(Redirect::to("/upload"))
+    tag_notice
+}
+
+/// Maps `tag_id` to play the uploaded folder and returns a message describing the outcome.
+async fn assign_folder_tag(
+    state: &AppState,
+    tag_id: Option<TagId>,
+    target_dir: Option<&str>,
+    uploaded_paths: &[PathBuf],
+) -> String {
+    let Some(tag_id) = tag_id else {
+        return "No tag id given; no tag was created".to_string();
+    };
+    let (Some(target_dir), Some(folder)) = (target_dir, uploaded_folder(uploaded_paths)) else {
+        return "Upload was not a single folder; no tag was created".to_string();
+    };
+
+    let folder_path = Path::new(target_dir).join(folder);
+    let command = format!("PLAY {}/**", glob::escape(&folder_path.to_string_lossy()));
+
+    send_command(
+        state,
+        Command::AssignTag {
+            id: tag_id,
+            command: Some(command.clone()),
+        },
+    )
+    .await;
+
+    format!("Tag {tag_id} now runs: {command}")
+}
+
+/// Returns the top-level folder shared by all uploaded paths, if they came from one folder.
+fn uploaded_folder(paths: &[PathBuf]) -> Option<PathBuf> {
+    let mut folder: Option<&Path> = None;
+
+    for path in paths {
+        let mut components = path.components();
+        let first = Path::new(components.next()?.as_os_str());
+        components.next()?;
+
+        match folder {
+            Some(existing) if existing != first => return None,
+            _ => folder = Some(first),
+        }
+    }
+
+    folder.map(Path::to_path_buf)
 }
 
 #[derive(Serialize)]
 struct UploadTemplateContext {
     destinations: Vec<String>,
     last_uploaded: Vec<String>,
+    last_skipped: Vec<String>,
+    tag_notice: Option<String>,
 }
 
 fn sanitize_relative_path(filename: &str) -> Option<PathBuf> {
@@ -142,12 +452,21 @@ fn sanitize_relative_path(filename: &str) -> Option<PathBuf> {
     }
 }
 
-fn resolve_target_dir(directories: &[PathBuf], selected: Option<&str>) -> Option<PathBuf> {
+/// The local music directory named `selected`; remote sources can't take uploads.
+fn resolve_target_dir<'a>(
+    directories: &'a [MusicDirectory],
+    selected: Option<&str>,
+) -> Option<&'a MusicDirectory> {
     let selected = selected?;
     directories
         .iter()
-        .find(|dir| dir.to_string_lossy() == selected)
-        .cloned()
+        .filter(|dir| dir.url().is_none())
+        .find(|dir| dir.dir.to_string_lossy() == selected)
+}
+
+/// Keeps the multipart status, so a body over the size limit answers 413.
+fn multipart_error(err: &MultipartError) -> (StatusCode, String) {
+    (err.status(), err.body_text())
 }
 
 fn internal_error<E>(err: E) -> (StatusCode, String)
@@ -156,3 +475,224 @@ where
 {
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{SkippedUpload, partial_path, receive_upload, uploaded_folder, write_chunks};
+    use crate::config::MusicDirectory;
+    use axum::{
+        body::{Body, Bytes},
+        extract::{FromRequest, Multipart},
+        http::{Request, StatusCode, header},
+    };
+    use std::{fmt::Write, fs, path::PathBuf};
+    use tempfile::tempdir;
+
+    const BOUNDARY: &str = "crabbox-test-boundary";
+
+    /// Builds a multipart form with a `target_dir` field followed by `files`.
+    async fn multipart(target_dir: &str, files: &[(&str, &str)]) -> Multipart {
+        let mut body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"target_dir\"\r\n\r\n{target_dir}\r\n"
+        );
+        for (filename, contents) in files {
+            let _ = write!(
+                body,
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"files\"; filename=\"{filename}\"\r\nContent-Type: application/octet-stream\r\n\r\n{contents}\r\n"
+            );
+        }
+        let _ = write!(body, "--{BOUNDARY}--\r\n");
+
+        let request = Request::builder()
+            .method("POST")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn saves_music_and_reports_skipped_files() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let directories = [MusicDirectory::new(music.clone())];
+        let mut form = multipart(
+            &music.display().to_string(),
+            &[
+                ("Album/01.mp3", "abc"),
+                ("Album/notes.txt", "hello"),
+                ("../escape.flac", "xy"),
+                ("..", "nothing"),
+            ],
+        )
+        .await;
+
+        let upload = receive_upload(&mut form, &directories, u64::MAX)
+            .await
+            .expect("upload");
+
+        let saved: Vec<_> = upload
+            .saved
+            .iter()
+            .map(|file| (file.path.clone(), file.bytes))
+            .collect();
+        assert_eq!(
+            saved,
+            vec![
+                (PathBuf::from("Album/01.mp3"), 3),
+                (PathBuf::from("escape.flac"), 2),
+            ]
+        );
+        assert_eq!(
+            upload.skipped,
+            vec![
+                SkippedUpload {
+                    filename: "Album/notes.txt".to_string(),
+                    reason: "unsupported file type".to_string(),
+                },
+                SkippedUpload {
+                    filename: "..".to_string(),
+                    reason: "invalid file name".to_string(),
+                },
+            ]
+        );
+        assert!(music.join("Album/01.mp3").is_file());
+        assert!(!music.join("Album/notes.txt").exists());
+        assert!(!tmp.path().join("escape.flac").exists());
+    }
+
+    #[tokio::test]
+    async fn directory_uploads_drop_non_audio_files() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let directories = [MusicDirectory::new(music.clone())];
+        let mut form = multipart(
+            &music.display().to_string(),
+            &[
+                ("Album/.DS_Store", "junk"),
+                ("Album/01 Intro.mp3", "abc"),
+                ("Album/cover.jpg", "jpeg"),
+                ("Album/readme.txt", "hi"),
+                ("Album/02 Song.MP3", "def"),
+            ],
+        )
+        .await;
+
+        let upload = receive_upload(&mut form, &directories, u64::MAX)
+            .await
+            .expect("upload");
+
+        let mut on_disk: Vec<_> = fs::read_dir(music.join("Album"))
+            .expect("album dir")
+            .map(|entry| entry.expect("entry").file_name())
+            .collect();
+        on_disk.sort();
+        assert_eq!(on_disk, ["01 Intro.mp3", "02 Song.MP3"]);
+        assert_eq!(upload.saved.len(), 2);
+        assert_eq!(
+            upload
+                .skipped
+                .iter()
+                .map(|skipped| skipped.filename.as_str())
+                .collect::<Vec<_>>(),
+            ["Album/.DS_Store", "Album/cover.jpg", "Album/readme.txt"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_uploads_over_the_size_limit() {
+        let tmp = tempdir().expect("tempdir");
+        let music = tmp.path().join("music");
+        fs::create_dir_all(&music).expect("music dir");
+        let directories = [MusicDirectory::new(music.clone())];
+        let mut form = multipart(
+            &music.display().to_string(),
+            &[("Album/01.mp3", "abc"), ("Album/02.mp3", "defgh")],
+        )
+        .await;
+
+        let err = receive_upload(&mut form, &directories, 6)
+            .await
+            .expect_err("over the limit");
+
+        assert_eq!(err.0, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(err.1.contains("6 bytes"), "{}", err.1);
+        assert!(!music.join("Album/02.mp3").exists());
+        assert!(!partial_path(&music.join("Album/02.mp3")).exists());
+    }
+
+    #[tokio::test]
+    async fn interrupted_writes_leave_no_partial_file() {
+        let tmp = tempdir().expect("tempdir");
+        let destination = tmp.path().join("01.mp3");
+        fs::write(&destination, "old").expect("existing track");
+        let chunks = futures_util::stream::iter([
+            Ok(Bytes::from_static(b"abc")),
+            Err((StatusCode::BAD_REQUEST, "connection reset".to_string())),
+        ]);
+
+        let err = write_chunks(chunks, &destination, u64::MAX)
+            .await
+            .expect_err("interrupted");
+
+        assert_eq!(err.1, "connection reset");
+        assert!(!partial_path(&destination).exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+
+        let chunks = futures_util::stream::iter([Ok(Bytes::from_static(b"new"))]);
+        assert_eq!(
+            write_chunks(chunks, &destination, u64::MAX).await.unwrap(),
+            3
+        );
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+        assert!(!partial_path(&destination).exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_target_directory() {
+        let tmp = tempdir().expect("tempdir");
+        let directories = [MusicDirectory::new(tmp.path().join("music"))];
+        let mut form = multipart("/etc", &[("a.mp3", "abc")]).await;
+
+        let err = receive_upload(&mut form, &directories, u64::MAX)
+            .await
+            .expect_err("unknown target");
+
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn rejects_remote_target_directory() {
+        let directories = [MusicDirectory::new(PathBuf::from("http://nas:8000/music"))];
+        let mut form = multipart("http://nas:8000/music", &[("a.mp3", "abc")]).await;
+
+        let err = receive_upload(&mut form, &directories, u64::MAX)
+            .await
+            .expect_err("remote target");
+
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        assert!(!PathBuf::from("http:").exists());
+    }
+
+    #[test]
+    fn uploaded_folder_requires_single_top_level_folder() {
+        let paths = vec![
+            PathBuf::from("Album/01.mp3"),
+            PathBuf::from("Album/CD2/01.mp3"),
+        ];
+        assert_eq!(uploaded_folder(&paths), Some(PathBuf::from("Album")));
+
+        let mixed = vec![PathBuf::from("Album/01.mp3"), PathBuf::from("Other/01.mp3")];
+        assert_eq!(uploaded_folder(&mixed), None);
+
+        let loose = vec![PathBuf::from("01.mp3")];
+        assert_eq!(uploaded_folder(&loose), None);
+
+        assert_eq!(uploaded_folder(&[]), None);
+    }
+}
@@ -0,0 +1,261 @@
+use std::str::FromStr;
+
+use axum::{
+    extract::{
+        Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::{commands::Command, crabbox::StatusEvent};
+
+use super::{
+    AppState,
+    events::{StreamItem, subscribe},
+    refuse_shutdown, send_command,
+    status::StatusResponse,
+};
+
+/// Messages pushed to WebSocket clients, tagged like the SSE event names.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+enum Outgoing {
+    Status(StatusEvent),
+    Snapshot(StatusResponse),
+    Error(String),
+}
+
+impl From<StreamItem> for Outgoing {
+    fn from(item: StreamItem) -> Self {
+        match item {
+            StreamItem::Update(update) => Outgoing::Status(update),
+            StreamItem::Snapshot(snapshot) => Outgoing::Snapshot(snapshot),
+        }
+    }
+}
+
+/// What to do with a frame received from the client.
+#[derive(Debug, PartialEq)]
+enum Incoming {
+    Command(Command),
+    Invalid(String),
+    Close,
+    Ignore,
+}
+
+/// Refuses WebSocket upgrades started by pages from another site. Browsers don't preflight
+/// them, so without this any page the user visits could send commands. Requests without an
+/// `Origin`, e.g. from scripts, are let through.
+pub(super) async fn require_same_origin(request: Request, next: Next) -> Response {
+    if is_same_origin(request.headers()) {
+        next.run(request).await
+    } else {
+        warn!(
+            origin = ?request.headers().get(header::ORIGIN),
+            "Rejected cross-site WebSocket upgrade"
+        );
+        (
+            StatusCode::FORBIDDEN,
+            "WebSocket connections must come from the crabbox page",
+        )
+            .into_response()
+    }
+}
+
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let origin_host = origin.to_str().ok().and_then(|origin| {
+        origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"))
+    });
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    matches!((origin_host, host), (Some(origin), Some(host)) if origin.eq_ignore_ascii_case(host))
+}
+
+pub(super) async fn ws(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState) {
+    let mut updates = Box::pin(subscribe(&state));
+
+    loop {
+        let outgoing = tokio::select! {
+            item = updates.next() => match item {
+                Some(item) => Outgoing::from(item),
+                None => break,
+            },
+            frame = socket.recv() => {
+                let frame = match frame {
+                    Some(Ok(frame)) => frame,
+                    Some(Err(err)) => {
+                        debug!("WebSocket receive failed: {err}");
+                        break;
+                    }
+                    None => break,
+                };
+                match parse_frame(&frame) {
+                    Incoming::Command(command) => {
+                        debug!(%command, "Command received over WebSocket");
+                        send_command(&state, command).await;
+                        continue;
+                    }
+                    Incoming::Invalid(err) => Outgoing::Error(err),
+                    Incoming::Ignore => continue,
+                    Incoming::Close => break,
+                }
+            }
+        };
+
+        let text = match serde_json::to_string(&outgoing) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("Failed to serialize WebSocket message: {err}");
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn parse_frame(frame: &Message) -> Incoming {
+    match frame {
+        Message::Text(text) => match Command::from_str(text.as_str()) {
+            Ok(command) => match refuse_shutdown(&command) {
+                Ok(()) => Incoming::Command(command),
+                Err(err) => Incoming::Invalid(err),
+            },
+            Err(err) => Incoming::Invalid(err),
+        },
+        Message::Binary(_) => Incoming::Invalid("Expected a text frame with a command".to_string()),
+        Message::Close(_) => Incoming::Close,
+        Message::Ping(_) | Message::Pong(_) => Incoming::Ignore,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Incoming, Outgoing, is_same_origin, parse_frame, require_same_origin};
+    use crate::{commands::Command, crabbox::StatusEvent};
+    use axum::{
+        Router,
+        body::Body,
+        extract::ws::Message,
+        http::{HeaderMap, HeaderValue, Request, StatusCode, header},
+        middleware::from_fn,
+        routing::get,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    fn headers(host: &str, origin: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_str(host).unwrap());
+        if let Some(origin) = origin {
+            headers.insert(header::ORIGIN, HeaderValue::from_str(origin).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn only_pages_from_the_same_host_may_connect() {
+        assert!(is_same_origin(&headers("crabbox:8080", None)));
+        assert!(is_same_origin(&headers(
+            "crabbox:8080",
+            Some("http://crabbox:8080")
+        )));
+        assert!(is_same_origin(&headers(
+            "CrabBox.local",
+            Some("https://crabbox.local")
+        )));
+        for origin in [
+            "http://evil.example",
+            "http://crabbox:9090",
+            "http://crabbox:8080.evil.example",
+            "null",
+            "crabbox:8080",
+        ] {
+            assert!(
+                !is_same_origin(&headers("crabbox:8080", Some(origin))),
+                "{origin}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn cross_site_upgrades_are_forbidden() {
+        let app = Router::new()
+            .route("/ws", get(|| async { "upgraded" }))
+            .layer(from_fn(require_same_origin));
+        let request = |origin: &str| {
+            Request::builder()
+                .uri("/ws")
+                .header(header::HOST, "crabbox:8080")
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let cross = app
+            .clone()
+            .oneshot(request("http://evil.example"))
+            .await
+            .unwrap();
+        assert_eq!(cross.status(), StatusCode::FORBIDDEN);
+        let same = app.oneshot(request("http://crabbox:8080")).await.unwrap();
+        assert_eq!(same.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn parses_commands_and_reports_malformed_frames() {
+        assert_eq!(
+            parse_frame(&Message::Text("next".into())),
+            Incoming::Command(Command::Next)
+        );
+        assert_eq!(
+            parse_frame(&Message::Text("dance".into())),
+            Incoming::Invalid("Invalid command 'dance'".to_string())
+        );
+        assert!(matches!(
+            parse_frame(&Message::Binary(vec![1, 2].into())),
+            Incoming::Invalid(_)
+        ));
+        assert_eq!(parse_frame(&Message::Close(None)), Incoming::Close);
+    }
+
+    #[test]
+    fn shutdown_is_refused_over_the_socket() {
+        for text in ["SHUTDOWN", "STOP; SHUTDOWN"] {
+            assert_eq!(
+                parse_frame(&Message::Text(text.into())),
+                Incoming::Invalid("Shutdown must be confirmed from the crabbox page".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn outgoing_messages_are_tagged_json() {
+        assert_eq!(
+            serde_json::to_value(Outgoing::Status(StatusEvent::PauseChanged { paused: true }))
+                .unwrap(),
+            json!({ "event": "status", "data": { "type": "pause_changed", "paused": true } })
+        );
+        assert_eq!(
+            serde_json::to_value(Outgoing::Error("Invalid command 'x'".to_string())).unwrap(),
+            json!({ "event": "error", "data": "Invalid command 'x'" })
+        );
+    }
+}